@@ -0,0 +1,41 @@
+#version 460
+#extension GL_EXT_multiview : require
+
+//Output
+layout(location=0) out vec4 out_color;
+
+//Descriptors
+struct View {
+	mat4 view;
+	mat4 projection;
+	vec4 camera_pos;
+};
+layout(set=0, binding=0) uniform camera {
+	mat4 view;
+	mat4 projection;
+	vec4 camera_pos;
+	mat4 environment;
+	uint scene_color_valid;
+	uint ibl_specular_enabled;
+	uint view_count;
+	View views[4]; //MAX_VIEWS
+};
+struct Billboard {
+	vec3 pos;
+	float size;
+	vec4 color;
+};
+layout(std430, set=0, binding=1) restrict readonly buffer billboard_buffer {
+	Billboard billboards[];
+};
+
+void main() {
+	const Billboard billboard = billboards[gl_InstanceIndex];
+	//Triangle strip corner
+	const vec2 corner = vec2(gl_VertexIndex & 1, gl_VertexIndex >> 1);
+	//Expand in view space, facing the camera
+	vec4 pos = views[gl_ViewIndex].view * vec4(billboard.pos, 1.0);
+	pos.xy += (corner - 0.5) * billboard.size;
+	gl_Position = views[gl_ViewIndex].projection * pos;
+	out_color = billboard.color;
+}
@@ -0,0 +1,126 @@
+#version 460
+#define EDGE_THRESHOLD_MIN 0.0312
+#define EDGE_THRESHOLD_MAX 0.125
+#define SUBPIXEL_QUALITY 0.75
+#define ITERATIONS 12
+
+//Input
+layout(location=0) in vec2 in_texcoords;
+
+//Output
+layout(location=0) out vec4 out_color;
+
+//Descriptors
+layout(set=0, binding=0) uniform sampler2D image;
+
+const float quality[ITERATIONS] = {1.0, 1.0, 1.0, 1.0, 1.0, 1.5, 2.0, 2.0, 2.0, 2.0, 4.0, 8.0};
+
+//Perceptual luma of a linear color
+float luma(vec3 color) {
+	return sqrt(dot(color, vec3(0.299, 0.587, 0.114)));
+}
+
+float luma_at(vec2 uv) {
+	return luma(textureLod(image, uv, 0).rgb);
+}
+
+void main() {
+	const vec2 texel = 1.0 / vec2(textureSize(image, 0));
+	const vec3 color = textureLod(image, in_texcoords, 0).rgb;
+	//Local contrast
+	const float luma_center = luma(color);
+	const float luma_down = luma_at(in_texcoords + vec2(0, 1) * texel);
+	const float luma_up = luma_at(in_texcoords + vec2(0, -1) * texel);
+	const float luma_left = luma_at(in_texcoords + vec2(-1, 0) * texel);
+	const float luma_right = luma_at(in_texcoords + vec2(1, 0) * texel);
+	const float luma_min = min(luma_center, min(min(luma_down, luma_up), min(luma_left, luma_right)));
+	const float luma_max = max(luma_center, max(max(luma_down, luma_up), max(luma_left, luma_right)));
+	const float luma_range = luma_max - luma_min;
+	if (luma_range < max(EDGE_THRESHOLD_MIN, luma_max * EDGE_THRESHOLD_MAX)) {
+		out_color = vec4(color, 1.0);
+		return;
+	}
+	//Corners
+	const float luma_down_left = luma_at(in_texcoords + vec2(-1, 1) * texel);
+	const float luma_up_right = luma_at(in_texcoords + vec2(1, -1) * texel);
+	const float luma_up_left = luma_at(in_texcoords + vec2(-1, -1) * texel);
+	const float luma_down_right = luma_at(in_texcoords + vec2(1, 1) * texel);
+	const float luma_down_up = luma_down + luma_up;
+	const float luma_left_right = luma_left + luma_right;
+	const float luma_left_corners = luma_down_left + luma_up_left;
+	const float luma_down_corners = luma_down_left + luma_down_right;
+	const float luma_right_corners = luma_down_right + luma_up_right;
+	const float luma_up_corners = luma_up_right + luma_up_left;
+	//Edge orientation
+	const float edge_horizontal = abs(-2.0 * luma_left + luma_left_corners)
+		+ abs(-2.0 * luma_center + luma_down_up) * 2.0
+		+ abs(-2.0 * luma_right + luma_right_corners);
+	const float edge_vertical = abs(-2.0 * luma_up + luma_up_corners)
+		+ abs(-2.0 * luma_center + luma_left_right) * 2.0
+		+ abs(-2.0 * luma_down + luma_down_corners);
+	const bool horizontal = edge_horizontal >= edge_vertical;
+	//Edge side
+	const float luma_1 = horizontal ? luma_up : luma_left;
+	const float luma_2 = horizontal ? luma_down : luma_right;
+	const float gradient_1 = luma_1 - luma_center;
+	const float gradient_2 = luma_2 - luma_center;
+	const bool steepest_1 = abs(gradient_1) >= abs(gradient_2);
+	const float gradient_scaled = 0.25 * max(abs(gradient_1), abs(gradient_2));
+	float step_length = horizontal ? texel.y : texel.x;
+	float luma_local_average = 0.0;
+	if (steepest_1) {
+		step_length = -step_length;
+		luma_local_average = 0.5 * (luma_1 + luma_center);
+	} else {
+		luma_local_average = 0.5 * (luma_2 + luma_center);
+	}
+	vec2 current_uv = in_texcoords;
+	if (horizontal) {
+		current_uv.y += step_length * 0.5;
+	} else {
+		current_uv.x += step_length * 0.5;
+	}
+	//Edge exploration
+	const vec2 offset = horizontal ? vec2(texel.x, 0.0) : vec2(0.0, texel.y);
+	vec2 uv_1 = current_uv - offset;
+	vec2 uv_2 = current_uv + offset;
+	float luma_end_1 = luma_at(uv_1) - luma_local_average;
+	float luma_end_2 = luma_at(uv_2) - luma_local_average;
+	bool reached_1 = abs(luma_end_1) >= gradient_scaled;
+	bool reached_2 = abs(luma_end_2) >= gradient_scaled;
+	for (uint i = 0; i < ITERATIONS && !(reached_1 && reached_2); ++i) {
+		if (!reached_1) {
+			uv_1 -= offset * quality[i];
+			luma_end_1 = luma_at(uv_1) - luma_local_average;
+			reached_1 = abs(luma_end_1) >= gradient_scaled;
+		}
+		if (!reached_2) {
+			uv_2 += offset * quality[i];
+			luma_end_2 = luma_at(uv_2) - luma_local_average;
+			reached_2 = abs(luma_end_2) >= gradient_scaled;
+		}
+	}
+	//Edge offset
+	const float distance_1 = horizontal ? (in_texcoords.x - uv_1.x) : (in_texcoords.y - uv_1.y);
+	const float distance_2 = horizontal ? (uv_2.x - in_texcoords.x) : (uv_2.y - in_texcoords.y);
+	const bool direction_1 = distance_1 < distance_2;
+	const float distance_final = min(distance_1, distance_2);
+	const float edge_length = distance_1 + distance_2;
+	const bool center_smaller = luma_center < luma_local_average;
+	const bool correct_variation = ((direction_1 ? luma_end_1 : luma_end_2) < 0.0) != center_smaller;
+	const float edge_offset = correct_variation ? (-distance_final / edge_length + 0.5) : 0.0;
+	//Subpixel offset
+	const float luma_average = (1.0 / 12.0) * (2.0 * (luma_down_up + luma_left_right) + luma_left_corners + luma_right_corners);
+	const float subpixel_1 = clamp(abs(luma_average - luma_center) / luma_range, 0.0, 1.0);
+	const float subpixel_2 = (-2.0 * subpixel_1 + 3.0) * subpixel_1 * subpixel_1;
+	const float subpixel_offset = subpixel_2 * subpixel_2 * SUBPIXEL_QUALITY;
+	//Final sample
+	const float final_offset = max(edge_offset, subpixel_offset);
+	vec2 final_uv = in_texcoords;
+	if (horizontal) {
+		final_uv.y += final_offset * step_length;
+	} else {
+		final_uv.x += final_offset * step_length;
+	}
+	out_color = vec4(textureLod(image, final_uv, 0).rgb, 1.0);
+}
@@ -0,0 +1,10 @@
+#version 460
+
+//Output
+layout(location=0) out vec2 out_texcoords;
+
+//Fullscreen triangle
+void main() {
+	out_texcoords = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+	gl_Position = vec4(out_texcoords * 2.0 - 1.0, 0.0, 1.0);
+}
@@ -0,0 +1,19 @@
+#version 460
+
+//Input
+layout(location=4) in flat uint in_mirrored;
+
+//Output
+layout(location=0) out vec4 out_color;
+
+//Outline color
+layout(push_constant) uniform highlight {
+	vec4 color;
+};
+
+void main() {
+	//Only back faces of the hull, so the node itself covers its front faces
+	if (gl_FrontFacing != bool(in_mirrored))
+		discard;
+	out_color = color;
+}
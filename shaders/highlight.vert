@@ -0,0 +1,94 @@
+#version 460
+#extension GL_EXT_multiview : require
+
+//Input
+layout(location=0) in vec3 in_pos;
+layout(location=1) in vec3 in_normal; //Octahedral-encoded in xy if packed
+
+//Specialization
+layout(constant_id=0) const bool PACKED_VERTICES = false;
+
+//Output
+layout(location=4) out uint out_mirrored; //Winding is reversed
+
+//Descriptors
+struct View {
+	mat4 view;
+	mat4 projection;
+	vec4 camera_pos;
+};
+layout(set=0, binding=0) uniform camera {
+	mat4 view;
+	mat4 projection;
+	vec4 camera_pos;
+	mat4 environment;
+	uint scene_color_valid;
+	uint ibl_specular_enabled;
+	uint view_count;
+	View views[4]; //MAX_VIEWS
+};
+struct Mesh {
+	vec4 lower_bounds;
+	vec4 upper_bounds;
+	vec4 bounding_sphere;
+	uint material;
+	int morph_base;
+	uint morph_count;
+	uint vertex_count;
+};
+layout(std430, set=0, binding=1) restrict readonly buffer mesh_storage {
+	Mesh meshes[];
+};
+struct Node {
+	mat4 transform;
+	mat4 inverse_transform;
+	uint mesh;
+	uint flags;
+	float weights[8]; //MAX_MORPH_TARGETS
+};
+layout(std430, set=0, binding=3) restrict readonly buffer node_storage {
+	Node nodes[];
+};
+struct MorphDelta {
+	vec4 pos;
+	vec4 normal;
+};
+layout(std430, set=0, binding=13) restrict readonly buffer morph_storage {
+	MorphDelta morph_deltas[];
+};
+
+//Outline size
+layout(push_constant) uniform outline {
+	layout(offset=32) vec2 pixel_size; //Of the viewport in NDC (SHADOW_PUSH_CONSTANT_OFFSET)
+	float thickness; //In pixels
+};
+
+vec3 oct_decode(vec2 e) {
+	vec3 n = vec3(e, 1.0 - abs(e.x) - abs(e.y));
+	if (n.z < 0.0)
+		n.xy = (1.0 - abs(n.yx)) * vec2(n.x >= 0.0 ? 1.0 : -1.0, n.y >= 0.0 ? 1.0 : -1.0);
+	return normalize(n);
+}
+
+void main() {
+	//One instance per node
+	const Node node = nodes[gl_InstanceIndex];
+	const Mesh mesh = meshes[node.mesh];
+	//Morph targets
+	vec3 model_pos = in_pos;
+	vec3 normal = PACKED_VERTICES ? oct_decode(in_normal.xy) : in_normal;
+	for (uint i = 0; i < mesh.morph_count; ++i) {
+		const MorphDelta delta = morph_deltas[mesh.morph_base + int(i * mesh.vertex_count) + gl_VertexIndex];
+		model_pos += node.weights[i] * delta.pos.xyz;
+		normal += node.weights[i] * delta.normal.xyz;
+	}
+	//Position
+	const mat4 view_projection = views[gl_ViewIndex].projection * views[gl_ViewIndex].view;
+	gl_Position = view_projection * node.transform * vec4(model_pos, 1.0);
+	//Push the hull out along the screen-space normal, by a constant width in pixels
+	const vec3 world_normal = vec3(transpose(node.inverse_transform) * vec4(normal, 0.0));
+	const vec2 clip_normal = (view_projection * vec4(world_normal, 0.0)).xy;
+	if (dot(clip_normal, clip_normal) > 0.0)
+		gl_Position.xy += normalize(clip_normal) * pixel_size * thickness * gl_Position.w;
+	out_mirrored = (node.flags >> 6) & 1;
+}
@@ -0,0 +1,33 @@
+#version 460
+#extension GL_EXT_multiview : require
+
+//Input (per line)
+layout(location=0) in vec3 start;
+layout(location=1) in vec3 end;
+layout(location=2) in vec4 color;
+
+//Output
+layout(location=0) out vec4 out_color;
+
+//Descriptors
+struct View {
+	mat4 view;
+	mat4 projection;
+	vec4 camera_pos;
+};
+layout(set=0, binding=0) uniform camera {
+	mat4 view;
+	mat4 projection;
+	vec4 camera_pos;
+	mat4 environment;
+	uint scene_color_valid;
+	uint ibl_specular_enabled;
+	uint view_count;
+	View views[4]; //MAX_VIEWS
+};
+
+void main() {
+	const vec3 pos = gl_VertexIndex == 0 ? start : end;
+	gl_Position = views[gl_ViewIndex].projection * views[gl_ViewIndex].view * vec4(pos, 1.0);
+	out_color = color;
+}
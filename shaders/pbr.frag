@@ -1,4 +1,5 @@
 #version 460
+#extension GL_EXT_multiview : require
 #define PI 3.14159
 
 //Input
@@ -6,15 +7,35 @@ layout(location=0) in vec3 in_pos;
 layout(location=1) in vec3 in_normal;
 layout(location=2) in vec2 in_texcoords;
 layout(location=3) in flat uint in_material;
+layout(location=4) in flat uint in_mirrored;
 
 //Output
 layout(location=0) out vec4 out_color;
 
+//Specialization
+layout(constant_id=0) const bool FLAT_SHADING = false; //Use face normals instead of interpolated vertex normals
+layout(constant_id=1) const bool BACKFACES = false; //Tint back faces instead of culling them (`DebugView::Backfaces`)
+layout(constant_id=2) const bool ALPHA_TO_COVERAGE = false; //Output masked materials' coverage as alpha (otherwise alpha test)
+
 //Descriptors
+struct View {
+	mat4 view;
+	mat4 projection;
+	vec4 camera_pos;
+};
 layout(set=0, binding=0) uniform camera {
 	mat4 view;
 	mat4 projection;
 	vec4 camera_pos;
+	mat4 environment; //World to environment rotation
+	uint scene_color_valid; //Scene color holds view 0 of the previous frame
+	uint ibl_specular_enabled;
+	uint view_count;
+	View views[4]; //MAX_VIEWS
+	vec4 sky_top; //w is 1 with a gradient environment (instead of cubemaps)
+	vec4 sky_horizon;
+	vec4 sky_bottom;
+	vec4 environment_color; //Tint times intensity of the skybox & image-based lighting (w unused)
 };
 struct Material {
 	vec4 color;
@@ -22,23 +43,144 @@ struct Material {
 	uint metal_rough_tex;
 	float metal;
 	float rough;
+	uint height_tex;
+	float height_scale;
+	uint emissive_tex;
+	float emissive_strength;
+	vec4 emissive;
+	uint transmission_tex;
+	float transmission;
+	float ior;
+	float clearcoat;
+	uint clearcoat_tex;
+	float clearcoat_rough;
+	uint clearcoat_rough_tex;
+	uint workflow; //Unused (metallic-roughness only)
+	vec4 specular_color;
+	uint specular_color_tex;
+	float specular;
+	uint specular_tex;
+	uint alpha_mode; //0: opaque, 1: mask
+	float alpha_cutoff;
 };
 layout(std430, set=0, binding=2) restrict readonly buffer material_buffer {
 	Material materials[];
 };
 layout(set=0, binding=5) uniform sampler s;
 layout(set=0, binding=6) uniform texture2D textures[64];
+layout(set=0, binding=14) uniform texture2DArray texture_arrays[64]; //With `TexturePacking::Arrays`
 struct PointLight {
 	vec4 pos;
 	vec4 color;
+	vec4 shadow_rect; //Atlas UV offset of the light's tile & size of one face
 	float intensity;
 	float range;
+	uint casts_shadow;
 };
 layout(std430, set=0, binding=7) restrict readonly buffer light_buffer {
-	PointLight point_lights[64];
+	uint light_count;
+	PointLight point_lights[]; //`SceneSet::light_capacity`
 };
 layout(set=0, binding=8) uniform samplerCube cubes[2];
 layout(set=0, binding=9) uniform sampler2D dfgLUT;
+layout(set=0, binding=10) uniform sampler2D scene_color; //Previous frame, tonemapped
+layout(set=0, binding=11) uniform sampler2DShadow shadow_atlas; //3 x 2 cube faces per shadow-casting light
+
+//Shadow atlas cube faces (must match `shadow_atlas.rs`)
+const float SHADOW_NEAR = 0.05;
+const vec3 FACE_DIRS[6] = vec3[](
+	vec3(1, 0, 0), vec3(-1, 0, 0),
+	vec3(0, 1, 0), vec3(0, -1, 0),
+	vec3(0, 0, 1), vec3(0, 0, -1)
+);
+const vec3 FACE_UPS[6] = vec3[](
+	vec3(0, 1, 0), vec3(0, 1, 0),
+	vec3(0, 0, 1), vec3(0, 0, 1),
+	vec3(0, 1, 0), vec3(0, 1, 0)
+);
+
+//Texture indices with bit 31 set are a layer (bits 16-30) of a texture array (bits 0-15)
+//(indices come from the draw's material, so are dynamically uniform without `nonuniformEXT`)
+vec4 sample_texture(uint index, vec2 uv) {
+	if ((index & 0x80000000u) != 0)
+		return texture(sampler2DArray(texture_arrays[index & 0xFFFFu], s), vec3(uv, (index >> 16) & 0x7FFFu));
+	return texture(sampler2D(textures[index], s), uv);
+}
+
+vec4 sample_texture_grad(uint index, vec2 uv, vec2 dx, vec2 dy) {
+	if ((index & 0x80000000u) != 0)
+		return textureGrad(
+			sampler2DArray(texture_arrays[index & 0xFFFFu], s), vec3(uv, (index >> 16) & 0x7FFFu), dx, dy
+		);
+	return textureGrad(sampler2D(textures[index], s), uv, dx, dy);
+}
+
+//Tangent frame from screen-space derivatives (vertices don't carry tangents)
+mat3 cotangent_frame(vec3 n, vec3 p, vec2 uv) {
+	const vec3 dp1 = dFdx(p);
+	const vec3 dp2 = dFdy(p);
+	const vec2 duv1 = dFdx(uv);
+	const vec2 duv2 = dFdy(uv);
+	const vec3 dp2perp = cross(dp2, n);
+	const vec3 dp1perp = cross(n, dp1);
+	const vec3 t = dp2perp * duv1.x + dp1perp * duv2.x;
+	const vec3 b = dp2perp * duv1.y + dp1perp * duv2.y;
+	const float scale = inversesqrt(max(max(dot(t, t), dot(b, b)), 1e-12));
+	return mat3(t * scale, b * scale, n);
+}
+
+//Parallax occlusion mapping: march the view ray through the height field
+vec2 parallax_occlusion(uint height_tex, float height_scale, vec2 uv, vec3 v_tangent) {
+	const int MAX_LAYERS = 32;
+	const float layers = mix(float(MAX_LAYERS), 8.0, abs(v_tangent.z));
+	const float layer_depth = 1.0 / layers;
+	const vec2 delta = v_tangent.xy / max(v_tangent.z, 0.05) * height_scale / layers;
+	//Explicit gradients, as derivatives are undefined in non-uniform control flow
+	const vec2 uv_dx = dFdx(uv);
+	const vec2 uv_dy = dFdy(uv);
+	vec2 current_uv = uv;
+	float current_depth = 0.0;
+	float map_depth = 1.0 - sample_texture_grad(height_tex, current_uv, uv_dx, uv_dy).r;
+	for (int i = 0; i < MAX_LAYERS && current_depth < map_depth; ++i) {
+		current_uv -= delta;
+		map_depth = 1.0 - sample_texture_grad(height_tex, current_uv, uv_dx, uv_dy).r;
+		current_depth += layer_depth;
+	}
+	//Interpolate between the layers before & after the intersection
+	const vec2 previous_uv = current_uv + delta;
+	const float after = map_depth - current_depth;
+	const float before = 1.0 - sample_texture_grad(height_tex, previous_uv, uv_dx, uv_dy).r
+		- (current_depth - layer_depth);
+	const float weight = after / min(after - before, -1e-5);
+	return mix(current_uv, previous_uv, clamp(weight, 0.0, 1.0));
+}
+
+//Fraction of a point light reaching a position, from the light's shadow atlas tile
+float point_shadow(PointLight light, vec3 pos) {
+	//Cube face along the major axis
+	const vec3 d = pos - light.pos.xyz;
+	const vec3 a = abs(d);
+	uint face;
+	if (a.x >= a.y && a.x >= a.z)
+		face = d.x > 0 ? 0u : 1u;
+	else if (a.y >= a.z)
+		face = d.y > 0 ? 2u : 3u;
+	else
+		face = d.z > 0 ? 4u : 5u;
+	//Project with the face's view-projection (see `Camera::view` & `Camera::projection`)
+	const vec3 f = FACE_DIRS[face];
+	const vec3 r = normalize(cross(f, FACE_UPS[face]));
+	const vec3 dn = cross(f, r);
+	const float z = dot(d, f);
+	const vec2 ndc = vec2(dot(d, r), dot(d, dn)) / z;
+	const float far = light.range;
+	const float depth = far / (far - SHADOW_NEAR) - far * SHADOW_NEAR / ((far - SHADOW_NEAR) * z);
+	//Keep filtering within the face
+	const vec2 half_texel = 0.5 / (light.shadow_rect.zw * vec2(textureSize(shadow_atlas, 0)));
+	const vec2 face_uv = clamp(ndc * 0.5 + 0.5, half_texel, 1.0 - half_texel);
+	const vec2 uv = light.shadow_rect.xy + (vec2(face % 3, face / 3) + face_uv) * light.shadow_rect.zw;
+	return textureLod(shadow_atlas, vec3(uv, depth), 0);
+}
 
 // Remapped and clamped roughness
 float alpha(float roughness) {
@@ -63,10 +205,27 @@ float geometry(vec3 l, vec3 v, vec3 n, float roughness) {
 }
 
 //Fresnel term
+//Gradient environment color in an environment direction
+vec3 sky_gradient(vec3 dir) {
+	const float elevation = normalize(dir).y;
+	return elevation >= 0.0
+		? mix(sky_horizon.rgb, sky_top.rgb, elevation)
+		: mix(sky_horizon.rgb, sky_bottom.rgb, -elevation);
+}
+
+//Pre-filtered specular radiance in an environment direction (gradients aren't blurred by roughness)
+vec3 specular_radiance(vec3 dir, float roughness) {
+	return environment_color.rgb * (sky_top.w != 0.0 ? sky_gradient(dir) : textureLod(cubes[1], dir, roughness * 11).xyz);
+}
+
 vec3 fresnel(float vh, vec3 f0) {
 	return f0 + (1 - f0) * pow(1 - vh, 5);
 }
 
+vec3 fresnel(float vh, vec3 f0, float f90) {
+	return f0 + (f90 - f0) * pow(1 - vh, 5);
+}
+
 vec3 aces_tonemap(vec3 hdr) {
 	vec3 aces = hdr * 0.6;
 	float a = 2.51;
@@ -78,33 +237,64 @@ vec3 aces_tonemap(vec3 hdr) {
 }
 
 void main() {
+	//Face normal from screen-space derivatives of the world position (before any invocation is discarded)
+	const vec3 face_normal = normalize(cross(dFdy(in_pos), dFdx(in_pos)));
+	//Back-face culling (front faces of mirrored nodes are clockwise)
+	if (gl_FrontFacing == bool(in_mirrored)) {
+		if (!BACKFACES)
+			discard;
+		//Magenta, shaded by the angle to the camera to show the shape
+		const vec3 view_dir = normalize(views[gl_ViewIndex].camera_pos.xyz - in_pos);
+		out_color = vec4(vec3(1.0, 0.0, 1.0) * (0.3 + 0.7 * abs(dot(face_normal, view_dir))), 1.0);
+		return;
+	}
 	//Material
 	const Material material = materials[in_material];
-	const vec3 albedo = vec3(material.color * texture(
-		sampler2D(textures[material.color_tex], s),
-		in_texcoords
-	));
-	const vec4 metal_rough_map = texture(
-		sampler2D(textures[material.metal_rough_tex], s),
-		in_texcoords
+	const vec3 cameraPos = views[gl_ViewIndex].camera_pos.xyz;
+	vec2 texcoords = in_texcoords;
+	if (material.height_scale > 0.0) {
+		const mat3 tbn = cotangent_frame(normalize(in_normal), in_pos, in_texcoords);
+		const vec3 v_tangent = normalize(transpose(tbn) * (cameraPos - in_pos));
+		texcoords = parallax_occlusion(material.height_tex, material.height_scale, in_texcoords, v_tangent);
+	}
+	const vec4 base_color = material.color * sample_texture(material.color_tex, texcoords);
+	const vec3 albedo = base_color.rgb;
+	//Alpha mask: coverage sharpened to about a pixel around the cutoff with alpha-to-coverage
+	const bool masked = material.alpha_mode == 1;
+	const float coverage = clamp(
+		(base_color.a - material.alpha_cutoff) / max(fwidth(base_color.a), 0.0001) + 0.5, 0.0, 1.0
 	);
+	if (masked && !ALPHA_TO_COVERAGE && base_color.a < material.alpha_cutoff)
+		discard;
+	const vec4 metal_rough_map = sample_texture(material.metal_rough_tex, texcoords);
 	const float metallic = material.metal * metal_rough_map.b;
 	const float roughness = material.rough * metal_rough_map.g;
 	const float a = alpha(roughness);
+	//Clearcoat layer (dielectric, f0 = 0.04)
+	const float clearcoat = material.clearcoat * sample_texture(material.clearcoat_tex, texcoords).r;
+	const float clearcoat_roughness = material.clearcoat_rough * sample_texture(material.clearcoat_rough_tex, texcoords).g;
+	const float clearcoat_a = alpha(clearcoat_roughness);
 	//Lighting vectors
-	const vec3 cameraPos = camera_pos.xyz;
 	const vec3 v = normalize(cameraPos - in_pos);
-	const vec3 n = in_normal;
+	vec3 n = in_normal;
+	if (FLAT_SHADING)
+		n = dot(face_normal, v) < 0.0 ? -face_normal : face_normal;
 	const float nv = max(dot(n, v), 0);
 	//Diffuse & specular
 	const vec3 diffColor = (1 - metallic) * albedo;
-	const vec3 f0 = mix(vec3(0.04), albedo, metallic);
+	//Dielectric F0 from the IOR (0.04 at the default 1.5), tinted & weighted by KHR_materials_specular
+	const float specular_weight = material.specular * sample_texture(material.specular_tex, texcoords).a;
+	const vec3 specular_color = material.specular_color.rgb * sample_texture(material.specular_color_tex, texcoords).rgb;
+	const vec3 dielectric_f0 = min(pow((material.ior - 1) / (material.ior + 1), 2) * specular_color, vec3(1.0))
+		* specular_weight;
+	const vec3 f0 = mix(dielectric_f0, albedo, metallic);
+	const float f90 = mix(specular_weight, 1.0, metallic);
 	//IBL
 	const vec2 dfg = textureLod(dfgLUT, vec2(nv, roughness), 0).xy;
 	vec3 multiscatter = 1.0f + f0 * (1.0f / dfg.y - 1.0f);
 	//Reflectance equation
 	vec3 outgoing = vec3(0.0);
-	for (uint i = 0; i < 64; ++i) {
+	for (uint i = 0; i < light_count; ++i) {
 		//Light
 		const PointLight light = point_lights[i];
 		const vec3 l = normalize(light.pos.xyz - in_pos);
@@ -114,21 +304,61 @@ void main() {
 		const float vh = max(dot(v, h), 0);
 		const float light_dist = distance(light.pos.xyz, in_pos);
 		const float attenuation = max(min(1 - pow(light_dist / light.range, 4), 1), 0) / pow(light_dist, 2);
-		const vec3 radiance = attenuation * light.intensity * vec3(light.color);
+		const float shadow = light.casts_shadow != 0 ? point_shadow(light, in_pos) : 1.0;
+		const vec3 radiance = shadow * attenuation * light.intensity * vec3(light.color);
 		//Specular
 		const float d = distribution(nh, a);
 		const float g = geometry(l, v, n, roughness);
-		const vec3 f = fresnel(vh, f0);
+		const vec3 f = fresnel(vh, f0, f90);
 		const vec3 specular = d * g * f / (4 * nl * nv + 0.0001);
 		//Diffuse
 		const vec3 diffuse = diffColor / PI;
-		//BDRF
-		const vec3 reflectance = multiscatter * specular + (1 - f) * diffuse;
+		//Clearcoat
+		const float clearcoat_f = fresnel(vh, vec3(0.04)).x;
+		const float clearcoat_specular = distribution(nh, clearcoat_a) * geometry(l, v, n, clearcoat_roughness)
+			* clearcoat_f / (4 * nl * nv + 0.0001);
+		//BDRF (the base is attenuated by the clearcoat's reflection)
+		const vec3 reflectance = (multiscatter * specular + (1 - f) * diffuse) * (1 - clearcoat * clearcoat_f)
+			+ clearcoat * clearcoat_specular;
 		outgoing += reflectance * radiance * nl;
 	}
 	//IBL
-	const vec3 f = fresnel(nv, f0);
-	const vec3 ibl_diffuse = diffColor * textureLod(cubes[0], n, 0).xyz;
-	const vec3 ibl_specular = textureLod(cubes[1], reflect(-v, n), roughness * 11).xyz * mix(dfg.xxx, dfg.yyy, f0);
-	out_color = vec4(aces_tonemap(outgoing + ibl_specular + (1 - f) * ibl_diffuse), 1.0);
+	const vec3 f = fresnel(nv, f0, f90);
+	const mat3 env = mat3(environment);
+	const vec3 ibl_diffuse = diffColor * environment_color.rgb
+		* (sky_top.w != 0.0 ? sky_gradient(env * n) : textureLod(cubes[0], env * n, 0).xyz);
+	const vec3 ibl_specular = ibl_specular_enabled != 0
+		? specular_radiance(env * reflect(-v, n), roughness) * (f0 * (dfg.y - dfg.x) + f90 * dfg.x)
+		: vec3(0.0);
+	//Emission
+	const vec3 emission = material.emissive_strength * material.emissive.rgb * sample_texture(material.emissive_tex, texcoords).rgb;
+	//Clearcoat IBL, attenuating everything beneath it
+	const float clearcoat_f = fresnel(nv, vec3(0.04)).x;
+	const vec2 clearcoat_dfg = textureLod(dfgLUT, vec2(nv, clearcoat_roughness), 0).xy;
+	const vec3 clearcoat_ibl = ibl_specular_enabled != 0
+		? specular_radiance(env * reflect(-v, n), clearcoat_roughness)
+			* mix(clearcoat_dfg.x, clearcoat_dfg.y, 0.04)
+		: vec3(0.0);
+	const vec3 base = ibl_specular + (1 - f) * ibl_diffuse + emission;
+	const vec3 color = aces_tonemap(
+		outgoing + base * (1 - clearcoat * clearcoat_f) + clearcoat * clearcoat_ibl
+	);
+	//Transmission
+	const float transmission = material.transmission * sample_texture(material.transmission_tex, texcoords).r;
+	if (transmission <= 0.0) {
+		out_color = vec4(color, 1.0);
+	} else if (scene_color_valid != 0 && gl_ViewIndex == 0) {
+		//Refract through a nominal thickness & sample what's behind
+		const float THICKNESS = 0.1;
+		const vec3 refracted = refract(-v, n, 1.0 / material.ior);
+		const vec4 clip = projection * view * vec4(in_pos + refracted * THICKNESS, 1.0);
+		const vec2 uv = clip.xy / clip.w * 0.5 + 0.5;
+		const vec3 behind = albedo * textureLod(scene_color, uv, 0).rgb;
+		out_color = vec4(mix(color, behind, transmission * (1 - max(f.r, max(f.g, f.b)))), 1.0);
+	} else {
+		//No scene color (yet, or for this view), fall back to blending (or coverage, with alpha-to-coverage)
+		out_color = vec4(color, 1.0 - transmission);
+	}
+	if (masked && ALPHA_TO_COVERAGE)
+		out_color.a = coverage;
 }
@@ -1,26 +1,47 @@
 #version 460
+#extension GL_EXT_multiview : require
 
 //Input
 layout(location=0) in vec3 in_pos;
-layout(location=1) in vec3 in_normal;
+layout(location=1) in vec3 in_normal; //Octahedral-encoded in xy if packed
 layout(location=2) in vec2 in_texcoords;
+layout(location=5) in mat4 in_transform; //Node transform (instance rate, locations 5-8)
+
+//Specialization
+layout(constant_id=0) const bool PACKED_VERTICES = false;
+layout(constant_id=1) const bool INSTANCED_TRANSFORMS = false; //Read the transform from `in_transform` (requires drawIndirectFirstInstance)
 
 //Output
 layout(location=0) out vec3 out_pos;
 layout(location=1) out vec3 out_normal;
 layout(location=2) out vec2 out_texcoords;
 layout(location=3) out uint out_material;
+layout(location=4) out uint out_mirrored; //Winding is reversed
 
 //Descriptors
+struct View {
+	mat4 view;
+	mat4 projection;
+	vec4 camera_pos;
+};
 layout(set=0, binding=0) uniform camera {
 	mat4 view;
 	mat4 projection;
 	vec4 camera_pos;
+	mat4 environment;
+	uint scene_color_valid;
+	uint ibl_specular_enabled;
+	uint view_count;
+	View views[4]; //MAX_VIEWS
 };
 struct Mesh {
 	vec4 lower_bounds;
 	vec4 upper_bounds;
+	vec4 bounding_sphere; //Center in xyz, radius in w
 	uint material;
+	int morph_base; //First morph delta minus the vertex offset
+	uint morph_count;
+	uint vertex_count;
 };
 layout(std430, set=0, binding=1) restrict readonly buffer mesh_storage {
 	Mesh meshes[];
@@ -30,6 +51,7 @@ struct Node {
 	mat4 inverse_transform;
 	uint mesh;
 	uint flags;
+	float weights[8]; //MAX_MORPH_TARGETS
 };
 layout(std430, set=0, binding=3) restrict readonly buffer node_storage {
 	Node nodes[];
@@ -41,19 +63,43 @@ struct Extra {
 layout(std430, set=0, binding=4) restrict readonly buffer extra_storage {
 	Extra extras[];
 };
+struct MorphDelta {
+	vec4 pos;
+	vec4 normal;
+};
+layout(std430, set=0, binding=13) restrict readonly buffer morph_storage {
+	MorphDelta morph_deltas[];
+};
+
+vec3 oct_decode(vec2 e) {
+	vec3 n = vec3(e, 1.0 - abs(e.x) - abs(e.y));
+	if (n.z < 0.0)
+		n.xy = (1.0 - abs(n.yx)) * vec2(n.x >= 0.0 ? 1.0 : -1.0, n.y >= 0.0 ? 1.0 : -1.0);
+	return normalize(n);
+}
 
 void main() {
 	//Inputs
 	const Extra extra = extras[gl_DrawID];
 	const Node node = nodes[extra.node];
 	const Mesh mesh = meshes[extra.mesh];
+	//Morph targets
+	vec3 model_pos = in_pos;
+	vec3 normal = PACKED_VERTICES ? oct_decode(in_normal.xy) : in_normal;
+	for (uint i = 0; i < mesh.morph_count; ++i) {
+		const MorphDelta delta = morph_deltas[mesh.morph_base + int(i * mesh.vertex_count) + gl_VertexIndex];
+		model_pos += node.weights[i] * delta.pos.xyz;
+		normal += node.weights[i] * delta.normal.xyz;
+	}
 	//Position
-	const vec4 pos = vec4(in_pos, 1.0); //Model-space position
-	const vec4 world_pos = node.transform * pos;
-	gl_Position = projection * view * world_pos;
+	const vec4 pos = vec4(model_pos, 1.0); //Model-space position
+	const vec4 world_pos = (INSTANCED_TRANSFORMS ? in_transform : node.transform) * pos;
+	gl_Position = views[gl_ViewIndex].projection * views[gl_ViewIndex].view * world_pos;
 	//Outputs
 	out_pos = vec3(world_pos);
-	out_normal = normalize(vec3(transpose(node.inverse_transform) * vec4(in_normal, 0.0)));
+	//Inverse-transpose of the upper-left 3x3, correct under non-uniform scale
+	out_normal = normalize(vec3(transpose(node.inverse_transform) * vec4(normal, 0.0)));
 	out_texcoords = in_texcoords;
 	out_material = mesh.material;
+	out_mirrored = (node.flags >> 6) & 1;
 }
@@ -0,0 +1,26 @@
+#version 460
+
+//Input
+layout(location=0) in vec2 in_texcoords;
+
+//Output
+layout(location=0) out vec4 out_color;
+
+//Descriptors
+layout(set=0, binding=0) uniform sampler2D drawn_image; //sRGB, decoded to linear before filtering
+
+//Swapchain format
+layout(push_constant) uniform present {
+	uint encode_srgb; //The color space is sRGB, but the format doesn't encode on write
+};
+
+vec3 encode(vec3 linear) {
+	const vec3 low = 12.92 * linear;
+	const vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+	return mix(low, high, greaterThan(linear, vec3(0.0031308)));
+}
+
+void main() {
+	const vec4 color = texture(drawn_image, in_texcoords);
+	out_color = encode_srgb != 0 ? vec4(encode(clamp(color.rgb, 0.0, 1.0)), color.a) : color;
+}
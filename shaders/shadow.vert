@@ -0,0 +1,50 @@
+#version 460
+
+//Input
+layout(location=0) in vec3 in_pos;
+
+//Descriptors
+struct Node {
+	mat4 transform;
+	mat4 inverse_transform;
+	uint mesh;
+	uint flags;
+	float weights[8]; //MAX_MORPH_TARGETS
+};
+layout(std430, set=0, binding=3) restrict readonly buffer node_storage {
+	Node nodes[];
+};
+struct Mesh {
+	vec4 lower_bounds;
+	vec4 upper_bounds;
+	vec4 bounding_sphere;
+	uint material;
+	int morph_base;
+	uint morph_count;
+	uint vertex_count;
+};
+layout(std430, set=0, binding=1) restrict readonly buffer mesh_storage {
+	Mesh meshes[];
+};
+struct MorphDelta {
+	vec4 pos;
+	vec4 normal;
+};
+layout(std430, set=0, binding=13) restrict readonly buffer morph_storage {
+	MorphDelta morph_deltas[];
+};
+
+//Shadow map face
+layout(push_constant) uniform shadow_face {
+	layout(offset=32) mat4 view_projection; //SHADOW_PUSH_CONSTANT_OFFSET
+};
+
+void main() {
+	//One instance per node
+	const Node node = nodes[gl_InstanceIndex];
+	const Mesh mesh = meshes[node.mesh];
+	vec3 pos = in_pos;
+	for (uint i = 0; i < mesh.morph_count; ++i)
+		pos += node.weights[i] * morph_deltas[mesh.morph_base + int(i * mesh.vertex_count) + gl_VertexIndex].pos.xyz;
+	gl_Position = view_projection * node.transform * vec4(pos, 1.0);
+}
@@ -2,7 +2,36 @@
 
 layout(location=0) in vec3 in_pos;
 layout(location=0) out vec4 out_color;
-layout(set=0, binding=1) uniform samplerCube cube;
+
+//Descriptors
+struct View {
+	mat4 view;
+	mat4 projection;
+	vec4 camera_pos;
+};
+layout(set=0, binding=0) uniform camera {
+	mat4 view;
+	mat4 projection;
+	vec4 camera_pos;
+	mat4 environment; //World to environment rotation
+	uint scene_color_valid;
+	uint ibl_specular_enabled;
+	uint view_count;
+	View views[4]; //MAX_VIEWS
+	vec4 sky_top; //w is 1 with a gradient environment (instead of a cubemap)
+	vec4 sky_horizon;
+	vec4 sky_bottom;
+	vec4 environment_color; //Tint times intensity of the skybox & image-based lighting (w unused)
+};
+layout(set=0, binding=1) uniform samplerCube cube; //Unwritten with a gradient environment
+
+//Gradient environment color in an environment direction
+vec3 sky_gradient(vec3 dir) {
+	const float elevation = normalize(dir).y;
+	return elevation >= 0.0
+		? mix(sky_horizon.rgb, sky_top.rgb, elevation)
+		: mix(sky_horizon.rgb, sky_bottom.rgb, -elevation);
+}
 
 vec3 aces_tonemap(vec3 hdr) {
 	vec3 aces = hdr * 0.6;
@@ -15,7 +44,8 @@ vec3 aces_tonemap(vec3 hdr) {
 }
 
 void main() {
-	vec3 color = textureLod(cube, in_pos, 0).xyz;
+	vec3 color = sky_top.w != 0.0 ? sky_gradient(in_pos) : textureLod(cube, in_pos, 0).xyz;
+	color *= environment_color.rgb;
 	color = aces_tonemap(color);
 	out_color = vec4(color, 1.0);
 }
@@ -1,19 +1,30 @@
 #version 460
+#extension GL_EXT_multiview : require
 
 layout(location=0) in vec3 in_pos;
 layout(location=0) out vec3 out_pos;
 
-//Push constants
+//Descriptors
+struct View {
+	mat4 view;
+	mat4 projection;
+	vec4 camera_pos;
+};
 layout(set=0, binding=0) uniform camera {
 	mat4 view;
 	mat4 projection;
-	//vec4 camera_pos;
+	vec4 camera_pos;
+	mat4 environment; //World to environment rotation
+	uint scene_color_valid;
+	uint ibl_specular_enabled;
+	uint view_count;
+	View views[4]; //MAX_VIEWS
 };
 
 void main() {
-	vec4 pos = view * vec4(in_pos, 0.0);
+	vec4 pos = views[gl_ViewIndex].view * vec4(in_pos, 0.0);
 	pos.w = 1.0;
-	pos = projection * pos;
-	gl_Position = pos.xyww;
-	out_pos = in_pos;
+	pos = views[gl_ViewIndex].projection * pos;
+	gl_Position = pos.xyww; //z == w, so depth is exactly 1 (the far plane & depth clear value)
+	out_pos = mat3(environment) * in_pos;
 }
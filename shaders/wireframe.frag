@@ -0,0 +1,29 @@
+#version 460
+#extension GL_EXT_fragment_shader_barycentric : require
+
+//Input
+layout(location=4) in flat uint in_mirrored;
+
+//Output
+layout(location=0) out vec4 out_color;
+
+//Edge style
+layout(push_constant) uniform wireframe {
+	vec4 color;
+	float thickness; //Edge width in pixels
+};
+
+void main() {
+	//Distance to the nearest triangle edge in pixels (before any invocation is discarded)
+	const vec3 b = gl_BaryCoordEXT;
+	const vec3 d = b / max(fwidth(b), vec3(1e-6));
+	const float edge_distance = min(min(d.x, d.y), d.z);
+	//Back-face culling (front faces of mirrored nodes are clockwise)
+	if (gl_FrontFacing == bool(in_mirrored))
+		discard;
+	//Each triangle draws half of a shared edge
+	const float coverage = 1.0 - smoothstep(thickness * 0.5 - 0.5, thickness * 0.5 + 0.5, edge_distance);
+	if (coverage <= 0.0)
+		discard;
+	out_color = vec4(color.rgb, color.a * coverage);
+}
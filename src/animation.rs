@@ -0,0 +1,200 @@
+use nalgebra as na;
+use nalgebra::geometry as na_geo;
+use super::scene::{Scene, Node};
+
+///Keyframe interpolation of an animation channel (glTF sampler `interpolation`).
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Interpolation {
+    Linear, //Spherical for rotations
+    Step,
+    CubicSpline //Hermite spline, with in & out tangents stored around each keyframe value
+}
+
+///Node property driven by an animation channel.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AnimatedProperty {
+    Translation, //xyz
+    Rotation, //Quaternion xyzw
+    Scale, //xyz
+    Weights //One per morph target
+}
+
+#[derive(Clone, Debug)]
+pub struct Channel {
+    pub node: u32,
+    pub property: AnimatedProperty,
+    pub interpolation: Interpolation,
+    pub times: Vec<f32>, //Keyframe times in seconds, increasing
+    //Keyframe value components, flattened
+    //(in-tangent, value & out-tangent of each keyframe with cubic splines)
+    pub values: Vec<f32>
+}
+
+impl Channel {
+    ///Components of each value (e.g. 4 for rotations).
+    pub fn width(&self) -> usize {
+        let elements = if self.interpolation == Interpolation::CubicSpline {3} else {1};
+        self.values.len() / (elements * self.times.len()).max(1)
+    }
+
+    ///Interpolate the channel's value at `time`, clamped to its first & last keyframes.
+    pub fn sample(&self, time: f32) -> Vec<f32> {
+        let width = self.width();
+        let cubic = self.interpolation == Interpolation::CubicSpline;
+        //Value, in-tangent & out-tangent of a keyframe
+        let element = |keyframe: usize, element: usize| {
+            let start = if cubic {(3 * keyframe + element) * width} else {keyframe * width};
+            &self.values[start..start + width]
+        };
+        let (first, last) = (self.times[0], self.times[self.times.len() - 1]);
+        if time <= first || self.times.len() == 1 {
+            return element(0, 1).to_vec();
+        }
+        if time >= last {
+            return element(self.times.len() - 1, 1).to_vec();
+        }
+        let k = self.times.partition_point(|&t| t <= time) - 1;
+        let delta = self.times[k + 1] - self.times[k];
+        let s = (time - self.times[k]) / delta;
+        match self.interpolation {
+            Interpolation::Step => element(k, 1).to_vec(),
+            Interpolation::Linear if self.property == AnimatedProperty::Rotation => {
+                let [a, b] = [element(k, 1), element(k + 1, 1)].map(quaternion);
+                let q = a.slerp(&b, s);
+                vec![q.i, q.j, q.k, q.w]
+            },
+            Interpolation::Linear => std::iter::zip(element(k, 1), element(k + 1, 1)).map(
+                |(a, b)| a + (b - a) * s
+            ).collect(),
+            Interpolation::CubicSpline => {
+                //Hermite basis, with tangents scaled by the keyframe interval
+                let (s2, s3) = (s * s, s * s * s);
+                let value = (0..width).map(|i| {
+                    (2.0 * s3 - 3.0 * s2 + 1.0) * element(k, 1)[i]
+                        + delta * (s3 - 2.0 * s2 + s) * element(k, 2)[i]
+                        + (-2.0 * s3 + 3.0 * s2) * element(k + 1, 1)[i]
+                        + delta * (s3 - s2) * element(k + 1, 0)[i]
+                }).collect::<Vec<f32>>();
+                if self.property == AnimatedProperty::Rotation {
+                    let q = quaternion(&value);
+                    vec![q.i, q.j, q.k, q.w]
+                } else {
+                    value
+                }
+            }
+        }
+    }
+}
+
+///Normalized quaternion from xyzw components.
+fn quaternion(xyzw: &[f32]) -> na::UnitQuaternion<f32> {
+    na::UnitQuaternion::from_quaternion(na::Quaternion::new(xyzw[3], xyzw[0], xyzw[1], xyzw[2]))
+}
+
+#[derive(Clone, Debug)]
+pub struct Animation {
+    pub name: Option<String>,
+    pub channels: Vec<Channel>
+}
+
+impl Animation {
+    ///Time of the last keyframe of any channel, in seconds.
+    pub fn duration(&self) -> f32 {
+        self.channels.iter().filter_map(|channel| channel.times.last()).fold(0.0, |a, &b| a.max(b))
+    }
+
+    ///Set the animated properties of a scene's nodes to their values at `time`.
+    pub fn apply(&self, nodes: &mut [Node], time: f32) {
+        for channel in self.channels.iter().filter(|channel| !channel.times.is_empty()) {
+            let value = channel.sample(time);
+            let node = &mut nodes[channel.node as usize];
+            match channel.property {
+                AnimatedProperty::Translation => node.translation = na_geo::Translation3::new(value[0], value[1], value[2]),
+                AnimatedProperty::Rotation => node.rotation = quaternion(&value).to_rotation_matrix(),
+                AnimatedProperty::Scale => node.scale = na_geo::Scale3::new(value[0], value[1], value[2]),
+                AnimatedProperty::Weights => node.weights = value
+            }
+        }
+    }
+}
+
+/**
+    Playback clock for a scene's animations (see `Scene::animations`), advanced in the application's main loop.
+    Playing animations set node transforms & morph weights of the CPU-side scene;
+    when `update` or `set_time` report a change, upload the scene with `SceneSet::update_scene`.
+*/
+#[derive(Clone, Debug)]
+pub struct Animator {
+    animation: Option<usize>,
+    time: f32,
+    pub speed: f32, //Playback rate (1 by default)
+    pub looping: bool //Wrap around at the end of the animation (otherwise it stops on its last keyframe)
+}
+
+impl Default for Animator {
+    fn default() -> Self {
+        Self {
+            animation: None,
+            time: 0.0,
+            speed: 1.0,
+            looping: true
+        }
+    }
+}
+
+impl Animator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///Play a scene's animation by name from the start. Returns false, stopping playback, if there is no such animation.
+    pub fn play(&mut self, scene: &Scene, name: &str) -> bool {
+        self.animation = scene.animations.iter().position(|animation| animation.name.as_deref() == Some(name));
+        self.time = 0.0;
+        self.animation.is_some()
+    }
+
+    ///Play a scene's animation by index from the start.
+    pub fn play_index(&mut self, scene: &Scene, index: usize) {
+        assert!(index < scene.animations.len());
+        self.animation = Some(index);
+        self.time = 0.0;
+    }
+
+    ///Stop playback, leaving nodes as last animated.
+    pub fn stop(&mut self) {
+        self.animation = None;
+    }
+
+    ///Index of the playing animation.
+    pub fn animation(&self) -> Option<usize> {
+        self.animation
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    ///Seek to `time` in seconds & apply the animation. Returns whether the scene was changed.
+    pub fn set_time(&mut self, scene: &mut Scene, time: f32) -> bool {
+        self.time = time;
+        self.update(scene, 0.0)
+    }
+
+    /**
+        Advance the clock by `dt` seconds (scaled by `speed`) & apply the playing animation to the scene's nodes.
+        Returns whether the scene was changed, i.e. needs uploading with `SceneSet::update_scene`.
+    */
+    pub fn update(&mut self, scene: &mut Scene, dt: f32) -> bool {
+        let Some(animation) = self.animation.and_then(|index| scene.animations.get(index)) else {return false};
+        let duration = animation.duration();
+        self.time += dt * self.speed;
+        self.time = if self.looping && duration > 0.0 {
+            self.time.rem_euclid(duration)
+        } else {
+            self.time.clamp(0.0, duration)
+        };
+        animation.apply(&mut scene.nodes, self.time);
+        true
+    }
+}
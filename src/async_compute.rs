@@ -0,0 +1,149 @@
+use ash::vk;
+use super::FRAME_COUNT;
+use super::base::Base;
+use std::sync::Arc;
+
+/**
+    Submission of the cull pass on `Base::compute_queue`, overlapping the previous frame's graphics work.
+    Synchronization uses two timeline semaphores:
+    * Cull: signaled by each cull submission, waited by the frame's graphics submission
+      before reading draw commands (also ordering the depth pyramid rebuild after culling reads it).
+    * Graphics: signaled by each frame's graphics submission once its depth pyramid is built,
+      waited by the next cull submission when it reads the pyramid (occlusion culling),
+      so culling only overlaps the end of the previous frame (post-processing & presentation).
+      Without occlusion culling, culling doesn't wait for graphics at all.
+
+    Per-frame buffers written by culling are protected by the frame fence, as with graphics culling.
+*/
+pub struct AsyncCompute {
+    base: Arc<Base>,
+    queue: vk::Queue,
+    command_buffers: [vk::CommandBuffer; FRAME_COUNT],
+    cull_semaphore: vk::Semaphore,
+    cull_value: u64,
+    graphics_semaphore: vk::Semaphore,
+    graphics_value: u64 //Of the last submitted graphics work
+}
+
+impl AsyncCompute {
+    ///Fails with `ERROR_FEATURE_NOT_PRESENT` without a compute queue.
+    pub fn new(base: Arc<Base>) -> Result<Self, vk::Result> {
+        let queue = base.compute_queue.ok_or(vk::Result::ERROR_FEATURE_NOT_PRESENT)?;
+        unsafe {
+            //Command buffers (the compute queue is in the graphics family)
+            let allocate_info = vk::CommandBufferAllocateInfo::builder()
+                .command_pool(base.command_pool)
+                .level(vk::CommandBufferLevel::PRIMARY)
+                .command_buffer_count(FRAME_COUNT as u32);
+            let command_buffers = base.device.allocate_command_buffers(&allocate_info)?;
+            //Semaphores
+            let mut type_info = vk::SemaphoreTypeCreateInfo::builder()
+                .semaphore_type(vk::SemaphoreType::TIMELINE)
+                .initial_value(0);
+            let create_info = vk::SemaphoreCreateInfo::builder()
+                .push_next(&mut type_info);
+            let cull_semaphore = base.device.create_semaphore(&create_info, None)?;
+            let graphics_semaphore = base.device.create_semaphore(&create_info, None)?;
+            Ok(Self {
+                base,
+                queue,
+                command_buffers: command_buffers.try_into().unwrap(),
+                cull_semaphore,
+                cull_value: 0,
+                graphics_semaphore,
+                graphics_value: 0
+            })
+        }
+    }
+
+    ///Begin recording a frame's cull command buffer.
+    pub fn begin(&self, frame: usize) -> Result<vk::CommandBuffer, vk::Result> {
+        let command_buffer = self.command_buffers[frame];
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        unsafe {
+            self.base.device.begin_command_buffer(command_buffer, &begin_info)?;
+        }
+        Ok(command_buffer)
+    }
+
+    /**
+        End & submit a frame's cull command buffer after the transfer semaphore value,
+        & after the last graphics submission if `reads_depth_pyramid`.
+        Returns the wait for the frame's graphics submission.
+    */
+    pub fn submit(
+        &mut self,
+        frame: usize,
+        transfer_semaphore: vk::Semaphore,
+        transfer_semaphore_value: u64,
+        reads_depth_pyramid: bool
+    ) -> Result<vk::SemaphoreSubmitInfo, vk::Result> {
+        let command_buffer = self.command_buffers[frame];
+        let wait_semaphore_infos = [
+            *vk::SemaphoreSubmitInfo::builder()
+                .semaphore(transfer_semaphore)
+                .value(transfer_semaphore_value)
+                .stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER),
+            *vk::SemaphoreSubmitInfo::builder()
+                .semaphore(self.graphics_semaphore)
+                .value(self.graphics_value)
+                .stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+        ];
+        let wait_count = if reads_depth_pyramid {2} else {1};
+        let command_buffer_info = vk::CommandBufferSubmitInfo::builder()
+            .command_buffer(command_buffer);
+        let signal_semaphore_info = vk::SemaphoreSubmitInfo::builder()
+            .semaphore(self.cull_semaphore)
+            .value(self.cull_value + 1)
+            .stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER);
+        let submit_info = vk::SubmitInfo2::builder()
+            .wait_semaphore_infos(&wait_semaphore_infos[..wait_count])
+            .command_buffer_infos(std::slice::from_ref(&command_buffer_info))
+            .signal_semaphore_infos(std::slice::from_ref(&signal_semaphore_info));
+        unsafe {
+            self.base.device.end_command_buffer(command_buffer)?;
+            self.base.device.queue_submit2(
+                self.queue,
+                std::slice::from_ref(&submit_info),
+                vk::Fence::null()
+            )?;
+        }
+        self.cull_value += 1;
+        //Draw commands are read indirectly & by vertex shaders, cull statistics by copies
+        Ok(*vk::SemaphoreSubmitInfo::builder()
+            .semaphore(self.cull_semaphore)
+            .value(self.cull_value)
+            .stage_mask(
+                vk::PipelineStageFlags2::DRAW_INDIRECT
+                | vk::PipelineStageFlags2::VERTEX_SHADER
+                | vk::PipelineStageFlags2::COMPUTE_SHADER
+                | vk::PipelineStageFlags2::COPY
+            )
+        )
+    }
+
+    ///Signal for a frame's graphics submission, after its depth pyramid is built.
+    ///Call `graphics_submitted` once the submission succeeds.
+    pub fn graphics_signal(&self) -> vk::SemaphoreSubmitInfo {
+        *vk::SemaphoreSubmitInfo::builder()
+            .semaphore(self.graphics_semaphore)
+            .value(self.graphics_value + 1)
+            .stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+    }
+
+    pub fn graphics_submitted(&mut self) {
+        self.graphics_value += 1;
+    }
+}
+
+impl Drop for AsyncCompute {
+    fn drop(&mut self) {
+        unsafe {
+            self.base.device.queue_wait_idle(self.queue).unwrap();
+            self.base.device.destroy_semaphore(self.cull_semaphore, None);
+            self.base.device.destroy_semaphore(self.graphics_semaphore, None);
+            self.base.device.free_command_buffers(self.base.command_pool, &self.command_buffers);
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use ash::vk;
+
+///How an image is used: the synchronization scope & layout of its accesses.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ImageState {
+    pub stage: vk::PipelineStageFlags2,
+    pub access: vk::AccessFlags2,
+    pub layout: vk::ImageLayout
+}
+
+impl ImageState {
+    ///Contents are undefined (e.g. a newly acquired swapchain image).
+    pub const UNDEFINED: Self = Self::new(
+        vk::PipelineStageFlags2::NONE,
+        vk::AccessFlags2::NONE,
+        vk::ImageLayout::UNDEFINED
+    );
+    ///Color attachment of a render pass (as left by its final layout).
+    pub const COLOR_ATTACHMENT: Self = Self::new(
+        vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        vk::AccessFlags2::from_raw(
+            vk::AccessFlags2::COLOR_ATTACHMENT_READ.as_raw() | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE.as_raw()
+        ),
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+    );
+    ///Depth attachment of a render pass (as left by its final layout).
+    pub const DEPTH_ATTACHMENT: Self = Self::new(
+        vk::PipelineStageFlags2::from_raw(
+            vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS.as_raw() | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS.as_raw()
+        ),
+        vk::AccessFlags2::from_raw(
+            vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ.as_raw()
+            | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE.as_raw()
+        ),
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+    );
+    ///Sampled by fragment shaders.
+    pub const FRAGMENT_SAMPLED: Self = Self::new(
+        vk::PipelineStageFlags2::FRAGMENT_SHADER,
+        vk::AccessFlags2::SHADER_READ,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+    );
+    ///Sampled by compute shaders.
+    pub const COMPUTE_SAMPLED: Self = Self::new(
+        vk::PipelineStageFlags2::COMPUTE_SHADER,
+        vk::AccessFlags2::SHADER_READ,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+    );
+    ///Read by compute shaders in the general layout (as storage or sampled images).
+    pub const COMPUTE_READ: Self = Self::new(
+        vk::PipelineStageFlags2::COMPUTE_SHADER,
+        vk::AccessFlags2::SHADER_READ,
+        vk::ImageLayout::GENERAL
+    );
+    ///Written by compute shaders in the general layout.
+    pub const COMPUTE_WRITE: Self = Self::new(
+        vk::PipelineStageFlags2::COMPUTE_SHADER,
+        vk::AccessFlags2::SHADER_WRITE,
+        vk::ImageLayout::GENERAL
+    );
+    ///Source of copies (including copies to buffers).
+    pub const COPY_SRC: Self = Self::new(
+        vk::PipelineStageFlags2::COPY,
+        vk::AccessFlags2::TRANSFER_READ,
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+    );
+    ///Destination of copies.
+    pub const COPY_DST: Self = Self::new(
+        vk::PipelineStageFlags2::COPY,
+        vk::AccessFlags2::TRANSFER_WRITE,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL
+    );
+    ///Source of blits.
+    pub const BLIT_SRC: Self = Self::new(
+        vk::PipelineStageFlags2::BLIT,
+        vk::AccessFlags2::TRANSFER_READ,
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+    );
+    ///Destination of blits.
+    pub const BLIT_DST: Self = Self::new(
+        vk::PipelineStageFlags2::BLIT,
+        vk::AccessFlags2::TRANSFER_WRITE,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL
+    );
+    ///Swapchain image ready to present (the submission's semaphore signal orders presentation).
+    pub const PRESENT: Self = Self::new(
+        vk::PipelineStageFlags2::ALL_COMMANDS,
+        vk::AccessFlags2::NONE,
+        vk::ImageLayout::PRESENT_SRC_KHR
+    );
+
+    pub const fn new(stage: vk::PipelineStageFlags2, access: vk::AccessFlags2, layout: vk::ImageLayout) -> Self {
+        Self {stage, access, layout}
+    }
+
+    ///Accesses which must be made available to later accesses.
+    fn writes(self) -> vk::AccessFlags2 {
+        self.access & (
+            vk::AccessFlags2::SHADER_WRITE
+            | vk::AccessFlags2::SHADER_STORAGE_WRITE
+            | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE
+            | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE
+            | vk::AccessFlags2::TRANSFER_WRITE
+            | vk::AccessFlags2::HOST_WRITE
+            | vk::AccessFlags2::MEMORY_WRITE
+        )
+    }
+}
+
+/**
+    Tracks the last known state of images (or subresources of them) while recording a command buffer,
+    & generates the barriers for their state transitions:
+    the source scope is the previous state's stages & writes (reads only need an execution dependency),
+    & read-only transitions within a layout need no barrier.
+    Queued barriers are recorded together by `record`, so transitions of several images share one dependency.
+*/
+pub struct ImageTracker {
+    queue_family: u32,
+    images: Vec<TrackedImage>,
+    barriers: Vec<vk::ImageMemoryBarrier2>
+}
+
+struct TrackedImage {
+    image: vk::Image,
+    subresource_range: vk::ImageSubresourceRange,
+    state: ImageState
+}
+
+impl ImageTracker {
+    ///Tracker for images used on one queue family (so no ownership transfers are needed).
+    pub fn new(queue_family: u32) -> Self {
+        Self {
+            queue_family,
+            images: Vec::new(),
+            barriers: Vec::new()
+        }
+    }
+
+    ///Start tracking a subresource range of an image in a known state. Returns its index for transitions.
+    pub fn track(
+        &mut self,
+        image: vk::Image,
+        subresource_range: vk::ImageSubresourceRange,
+        state: ImageState
+    ) -> usize {
+        self.images.push(TrackedImage {image, subresource_range, state});
+        self.images.len() - 1
+    }
+
+    ///Queue the barrier from a tracked image's current state to `state`, preserving its contents.
+    pub fn transition(&mut self, index: usize, state: ImageState) {
+        let old_layout = self.images[index].state.layout;
+        self.queue_barrier(index, old_layout, state);
+    }
+
+    ///Queue the barrier from a tracked image's current state to `state`, discarding its contents.
+    pub fn discard(&mut self, index: usize, state: ImageState) {
+        self.queue_barrier(index, vk::ImageLayout::UNDEFINED, state);
+    }
+
+    fn queue_barrier(&mut self, index: usize, old_layout: vk::ImageLayout, state: ImageState) {
+        let tracked = &mut self.images[index];
+        let old_state = std::mem::replace(&mut tracked.state, state);
+        if old_layout == state.layout && old_state.writes().is_empty() && state.writes().is_empty() {
+            return;
+        }
+        self.barriers.push(*vk::ImageMemoryBarrier2::builder()
+            .src_stage_mask(old_state.stage)
+            .src_access_mask(old_state.writes())
+            .dst_stage_mask(state.stage)
+            .dst_access_mask(state.access)
+            .old_layout(old_layout)
+            .new_layout(state.layout)
+            .src_queue_family_index(self.queue_family)
+            .dst_queue_family_index(self.queue_family)
+            .image(tracked.image)
+            .subresource_range(tracked.subresource_range)
+        );
+    }
+
+    ///Record the queued barriers as one pipeline barrier (if any).
+    pub unsafe fn record(&mut self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        if !self.barriers.is_empty() {
+            let dependency = vk::DependencyInfo::builder()
+                .image_memory_barriers(&self.barriers);
+            device.cmd_pipeline_barrier2(command_buffer, &dependency);
+            self.barriers.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::Handle;
+
+    fn range(base_mip_level: u32, level_count: u32) -> vk::ImageSubresourceRange {
+        *vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(base_mip_level)
+            .level_count(level_count)
+            .base_array_layer(0)
+            .layer_count(1)
+    }
+
+    #[test]
+    fn transition_scopes() {
+        let image = vk::Image::from_raw(1);
+        let mut tracker = ImageTracker::new(0);
+        let index = tracker.track(image, range(0, 1), ImageState::COLOR_ATTACHMENT);
+        tracker.transition(index, ImageState::FRAGMENT_SAMPLED);
+        tracker.transition(index, ImageState::COPY_DST);
+        assert_eq!(tracker.barriers.len(), 2);
+        //Attachment writes are made available to fragment shader reads
+        let barrier = &tracker.barriers[0];
+        assert_eq!(barrier.image, image);
+        assert_eq!(barrier.old_layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        assert_eq!(barrier.new_layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        assert_eq!(barrier.src_stage_mask, vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT);
+        assert_eq!(barrier.src_access_mask, vk::AccessFlags2::COLOR_ATTACHMENT_WRITE);
+        assert_eq!(barrier.dst_stage_mask, vk::PipelineStageFlags2::FRAGMENT_SHADER);
+        assert_eq!(barrier.dst_access_mask, vk::AccessFlags2::SHADER_READ);
+        //Reads only need an execution dependency
+        let barrier = &tracker.barriers[1];
+        assert_eq!(barrier.old_layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        assert_eq!(barrier.new_layout, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+        assert_eq!(barrier.src_stage_mask, vk::PipelineStageFlags2::FRAGMENT_SHADER);
+        assert_eq!(barrier.src_access_mask, vk::AccessFlags2::NONE);
+        assert_eq!(barrier.dst_stage_mask, vk::PipelineStageFlags2::COPY);
+        assert_eq!(barrier.dst_access_mask, vk::AccessFlags2::TRANSFER_WRITE);
+    }
+
+    #[test]
+    fn discard_from_undefined() {
+        let mut tracker = ImageTracker::new(0);
+        let index = tracker.track(vk::Image::from_raw(1), range(0, 1), ImageState::FRAGMENT_SAMPLED);
+        tracker.discard(index, ImageState::BLIT_DST);
+        assert_eq!(tracker.barriers.len(), 1);
+        assert_eq!(tracker.barriers[0].old_layout, vk::ImageLayout::UNDEFINED);
+        assert_eq!(tracker.barriers[0].new_layout, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+    }
+
+    #[test]
+    fn same_state_needs_no_barrier() {
+        let mut tracker = ImageTracker::new(0);
+        let index = tracker.track(vk::Image::from_raw(1), range(0, 1), ImageState::COMPUTE_SAMPLED);
+        tracker.transition(index, ImageState::COMPUTE_SAMPLED);
+        //Read-only in the same layout
+        tracker.transition(index, ImageState::FRAGMENT_SAMPLED);
+        assert!(tracker.barriers.is_empty());
+        //Writes in the same layout still need a barrier
+        let index = tracker.track(vk::Image::from_raw(2), range(0, 1), ImageState::COMPUTE_WRITE);
+        tracker.transition(index, ImageState::COMPUTE_WRITE);
+        assert_eq!(tracker.barriers.len(), 1);
+    }
+
+    #[test]
+    fn subresource_ranges() {
+        //Downsample mip chain: each level is written, then read to write the next
+        let image = vk::Image::from_raw(1);
+        let mut tracker = ImageTracker::new(0);
+        let levels: Vec<_> = (0..3).map(
+            |level| tracker.track(image, range(level, 1), ImageState::UNDEFINED)
+        ).collect();
+        tracker.discard(levels[0], ImageState::COPY_DST);
+        for level in 1..3 {
+            tracker.transition(levels[level - 1], ImageState::BLIT_SRC);
+            tracker.discard(levels[level], ImageState::BLIT_DST);
+        }
+        tracker.transition(levels[2], ImageState::FRAGMENT_SAMPLED);
+        let transitions: Vec<_> = tracker.barriers.iter().map(|barrier| (
+            barrier.subresource_range.base_mip_level,
+            barrier.old_layout,
+            barrier.new_layout
+        )).collect();
+        assert_eq!(transitions, [
+            (0, vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL),
+            (0, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+            (1, vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL),
+            (1, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+            (2, vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL),
+            (2, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        ]);
+        //Every level has its own range
+        assert!(tracker.barriers.iter().all(|barrier| barrier.subresource_range.level_count == 1));
+    }
+}
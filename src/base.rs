@@ -1,11 +1,14 @@
 use ash::vk;
 use ash::extensions::khr;
-use std::fs::File;
-use std::io::Read;
-use std::io::Write;
+use super::memory::{MemoryCategory, MemoryUsage, HeapUsage, MemoryTypeError, AllocationError};
+use super::ResourceSharing;
+use std::sync::Mutex;
+use std::collections::HashMap;
 
 ///Container for persistent Vulkan objects (created once and never reassigned).
 ///Used to create transient Vulkan objects.
+///Shared through `Arc` & `Sync`, so renderers & scene sets can move to other threads.
+///Vulkan objects which need external synchronization (queues, pools) are only used by their owning renderer.
 pub struct Base {
     pub entry: ash::Entry,
     pub instance: ash::Instance,
@@ -13,17 +16,48 @@ pub struct Base {
     pub surface_loader: khr::Surface,
     pub physical_device: vk::PhysicalDevice,
     pub physical_device_properties: vk::PhysicalDeviceProperties,
+    pub physical_device_features: vk::PhysicalDeviceFeatures,
+    pub draw_indirect_count: bool, //Whether `cmd_draw_indexed_indirect_count` is supported
+    pub memory_budget: bool, //Whether `VK_EXT_memory_budget` is enabled
+    pub fragment_shader_barycentric: bool, //Whether `VK_KHR_fragment_shader_barycentric` is enabled
+    pub wide_lines: bool, //Whether line widths other than 1 are supported (`wideLines`)
+    pub draw_indirect_first_instance: bool, //Whether indirect draws may set `first_instance`
+    //Whether shaders may index sampled image arrays non-uniformly (`nonuniformEXT`);
+    //the default shaders index textures by the draw's materials, which is dynamically uniform
+    pub non_uniform_indexing: bool,
     pub device: ash::Device,
     //Command submission
     pub graphics_queue_family: u32,
     pub transfer_queue_family: u32,
     pub graphics_queue: vk::Queue,
+    //Second queue of the graphics family for async compute, if the family has one
+    //(the same family needs no ownership transfers of resources shared with graphics)
+    pub compute_queue: Option<vk::Queue>,
     pub command_pool: vk::CommandPool,
     pub pipeline_cache: vk::PipelineCache,
+    //Sharing mode for resources uploaded on the transfer queue
+    resource_sharing: Mutex<ResourceSharing>,
+    //Live allocations: (size, memory type, category)
+    allocations: Mutex<HashMap<vk::DeviceMemory, (vk::DeviceSize, u32, MemoryCategory)>>
 }
 
 impl Base {
     pub fn new(window: &sdl2::video::Window) -> Result<Self, vk::Result> {
+        Self::with_pipeline_cache(window, None)
+    }
+
+    /**
+        Create a base whose pipeline cache starts from `pipeline_cache` (e.g. a previous run's
+        `pipeline_cache_data`), or empty. Nothing is read from or written to disk;
+        the driver ignores data from another device or driver version.
+        Fails with `ERROR_FEATURE_NOT_PRESENT` if the device lacks a required feature
+        (dynamic indexing of sampled image arrays, partially bound descriptors, multiview,
+        timeline semaphores, synchronization2 or multi-draw indirect).
+    */
+    pub fn with_pipeline_cache(
+        window: &sdl2::video::Window,
+        pipeline_cache: Option<&[u8]>
+    ) -> Result<Self, vk::Result> {
         //TODO: Vulkan portability subset support (needed for MoltenVK)
         //TODO: Debug utils messenger support
         unsafe {
@@ -75,6 +109,32 @@ impl Base {
                 return Err(vk::Result::ERROR_UNKNOWN);
             };
             let physical_device_properties = instance.get_physical_device_properties(physical_device);
+            let physical_device_features = instance.get_physical_device_features(physical_device);
+            let mut supported_vk11_features = vk::PhysicalDeviceVulkan11Features::default();
+            let mut supported_vk12_features = vk::PhysicalDeviceVulkan12Features::default();
+            let mut supported_vk13_features = vk::PhysicalDeviceVulkan13Features::default();
+            let mut supported_barycentric_features = vk::PhysicalDeviceFragmentShaderBarycentricFeaturesKHR::default();
+            {
+                let mut features = vk::PhysicalDeviceFeatures2::builder()
+                    .push_next(&mut supported_vk11_features)
+                    .push_next(&mut supported_vk12_features)
+                    .push_next(&mut supported_vk13_features)
+                    .push_next(&mut supported_barycentric_features);
+                instance.get_physical_device_features2(physical_device, &mut features);
+            }
+            let draw_indirect_count = supported_vk12_features.draw_indirect_count == vk::TRUE;
+            let non_uniform_indexing = supported_vk12_features.shader_sampled_image_array_non_uniform_indexing == vk::TRUE;
+            let supported = physical_device_features.shader_sampled_image_array_dynamic_indexing == vk::TRUE
+                && physical_device_features.multi_draw_indirect == vk::TRUE
+                && supported_vk11_features.multiview == vk::TRUE
+                && supported_vk12_features.descriptor_binding_partially_bound == vk::TRUE
+                && supported_vk12_features.timeline_semaphore == vk::TRUE
+                && supported_vk13_features.synchronization2 == vk::TRUE;
+            if !supported {
+                surface_loader.destroy_surface(surface, None);
+                instance.destroy_instance(None);
+                return Err(vk::Result::ERROR_FEATURE_NOT_PRESENT);
+            }
             //Queue families
             let properties = instance.get_physical_device_queue_family_properties(physical_device);
             let graphics_queue_family = properties.iter().enumerate().position(
@@ -86,12 +146,14 @@ impl Base {
                 |props| props.queue_flags.contains(vk::QueueFlags::TRANSFER)
                     && !props.queue_flags.contains(vk::QueueFlags::GRAPHICS)
             ) {i as u32} else {graphics_queue_family};
+            let async_compute = properties[graphics_queue_family as usize].queue_count > 1;
+            let graphics_priorities: &[f32] = if async_compute {&[1.0, 1.0]} else {&[1.0]};
             //Device
             let queue_create_info = if graphics_queue_family != transfer_queue_family {
                 vec![
                     *vk::DeviceQueueCreateInfo::builder()
                         .queue_family_index(graphics_queue_family)
-                        .queue_priorities(&[1.0]),
+                        .queue_priorities(graphics_priorities),
                     *vk::DeviceQueueCreateInfo::builder()
                         .queue_family_index(transfer_queue_family)
                         .queue_priorities(&[1.0])
@@ -100,49 +162,67 @@ impl Base {
                 vec![
                     *vk::DeviceQueueCreateInfo::builder()
                         .queue_family_index(graphics_queue_family)
-                        .queue_priorities(&[1.0])
+                        .queue_priorities(graphics_priorities)
                 ]
             };
-            let extensions = [
+            let device_extensions = instance.enumerate_device_extension_properties(physical_device)?;
+            let has_extension = |name: &std::ffi::CStr| device_extensions.iter().any(
+                |extension| std::ffi::CStr::from_ptr(extension.extension_name.as_ptr()) == name
+            );
+            let memory_budget = has_extension(vk::ExtMemoryBudgetFn::name());
+            let fragment_shader_barycentric = has_extension(vk::KhrFragmentShaderBarycentricFn::name())
+                && supported_barycentric_features.fragment_shader_barycentric == vk::TRUE;
+            let wide_lines = physical_device_features.wide_lines == vk::TRUE;
+            let draw_indirect_first_instance = physical_device_features.draw_indirect_first_instance == vk::TRUE;
+            let mut extensions = vec![
                 khr::Swapchain::name().as_ptr(),
                 vk::KhrShaderDrawParametersFn::name().as_ptr()
             ];
+            if memory_budget {
+                extensions.push(vk::ExtMemoryBudgetFn::name().as_ptr());
+            }
+            if fragment_shader_barycentric {
+                extensions.push(vk::KhrFragmentShaderBarycentricFn::name().as_ptr());
+            }
             let features = vk::PhysicalDeviceFeatures::builder()
-                .multi_draw_indirect(true);
+                .multi_draw_indirect(true)
+                .shader_sampled_image_array_dynamic_indexing(true)
+                .wide_lines(wide_lines)
+                .draw_indirect_first_instance(draw_indirect_first_instance)
+                .sampler_anisotropy(physical_device_features.sampler_anisotropy == vk::TRUE);
             let mut synchronization2 = vk::PhysicalDeviceSynchronization2Features::builder()
                 .synchronization2(true);
+            let mut vk11_features = vk::PhysicalDeviceVulkan11Features::builder()
+                .multiview(true);
             let mut vk12_features = vk::PhysicalDeviceVulkan12Features::builder()
-                .draw_indirect_count(true)
-                .descriptor_indexing(true)
-                .shader_sampled_image_array_non_uniform_indexing(true)
-                .shader_storage_buffer_array_non_uniform_indexing(true)
+                .draw_indirect_count(draw_indirect_count)
+                .descriptor_binding_partially_bound(true)
+                .shader_sampled_image_array_non_uniform_indexing(non_uniform_indexing)
                 .timeline_semaphore(true);
-            let create_info = vk::DeviceCreateInfo::builder()
+            let mut barycentric_features = vk::PhysicalDeviceFragmentShaderBarycentricFeaturesKHR::builder()
+                .fragment_shader_barycentric(true);
+            let mut create_info = vk::DeviceCreateInfo::builder()
                 .queue_create_infos(&queue_create_info)
                 .enabled_extension_names(&extensions)
                 .enabled_features(&features)
                 .push_next(&mut synchronization2)
+                .push_next(&mut vk11_features)
                 .push_next(&mut vk12_features);
+            if fragment_shader_barycentric {
+                create_info = create_info.push_next(&mut barycentric_features);
+            }
             let device = instance.create_device(physical_device, &create_info, None)?;
-            //Queue
+            //Queues
             let graphics_queue = device.get_device_queue(graphics_queue_family, 0);
+            let compute_queue = async_compute.then(|| device.get_device_queue(graphics_queue_family, 1));
             //Command pool
             let create_info = vk::CommandPoolCreateInfo::builder()
                 .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
                 .queue_family_index(graphics_queue_family);
             let command_pool = device.create_command_pool(&create_info, None)?;
             //Pipeline cache
-            let mut pipeline_cache_path = std::env::current_exe().unwrap();
-            pipeline_cache_path.pop();
-            pipeline_cache_path.push("pipeline-cache.bin");
-            let mut data = Vec::new();
-            let create_info = if pipeline_cache_path.exists() {
-                let mut file = File::open(pipeline_cache_path).unwrap();
-                file.read_to_end(&mut data).unwrap();
-                vk::PipelineCacheCreateInfo::builder().initial_data(&data)
-            } else {
-                vk::PipelineCacheCreateInfo::builder()
-            };
+            let create_info = vk::PipelineCacheCreateInfo::builder()
+                .initial_data(pipeline_cache.unwrap_or(&[]));
             let pipeline_cache = device.create_pipeline_cache(&create_info, None)?;
             Ok(Self {
                 entry,
@@ -151,23 +231,69 @@ impl Base {
                 surface_loader,
                 physical_device,
                 physical_device_properties,
+                physical_device_features,
+                draw_indirect_count,
+                memory_budget,
+                fragment_shader_barycentric,
+                wide_lines,
+                draw_indirect_first_instance,
+                non_uniform_indexing,
                 device,
                 graphics_queue_family,
                 transfer_queue_family,
                 graphics_queue,
+                compute_queue,
                 command_pool,
-                pipeline_cache
+                pipeline_cache,
+                resource_sharing: Mutex::new(ResourceSharing::Exclusive),
+                allocations: Mutex::new(HashMap::new())
             })
         }
     }
 
-    ///Allocate a memory block which satisfies the given memory requirements.
-    ///Note that buffers & images cannot share a memory block.
+    ///Current pipeline cache contents, for the caller to persist.
+    pub fn pipeline_cache_data(&self) -> Result<Vec<u8>, vk::Result> {
+        unsafe {
+            self.device.get_pipeline_cache_data(self.pipeline_cache)
+        }
+    }
+
+    ///Set the sharing mode of scene & environment resources created from now on.
+    pub fn set_resource_sharing(&self, sharing: ResourceSharing) {
+        *self.resource_sharing.lock().unwrap() = sharing;
+    }
+
+    ///Effective sharing mode (always exclusive with a single queue family).
+    pub fn resource_sharing(&self) -> ResourceSharing {
+        if self.graphics_queue_family == self.transfer_queue_family {
+            ResourceSharing::Exclusive
+        } else {
+            *self.resource_sharing.lock().unwrap()
+        }
+    }
+
+    ///Whether resources of a category are created with concurrent sharing.
+    fn concurrent(&self, category: MemoryCategory) -> bool {
+        self.resource_sharing() == ResourceSharing::Concurrent && matches!(
+            category,
+            MemoryCategory::Scenes | MemoryCategory::Environment
+        )
+    }
+
+    /**
+        Allocate a memory block which satisfies the given memory requirements.
+        Note that buffers & images cannot share a memory block.
+        Host-visible allocations prefer host-coherent memory types.
+        If no memory type has all the requested properties, `DEVICE_LOCAL` is dropped from them,
+        since it only affects performance (check `memory_properties` for the actual properties).
+        Fails with `AllocationError::NoMemoryType` if there is still no memory type.
+    */
     fn allocate(
         &self,
         requirements: &[vk::MemoryRequirements],
-        properties: vk::MemoryPropertyFlags
-    ) -> Result<(vk::DeviceMemory, Vec<vk::DeviceSize>), vk::Result> {
+        properties: vk::MemoryPropertyFlags,
+        category: MemoryCategory
+    ) -> Result<(vk::DeviceMemory, Vec<vk::DeviceSize>), AllocationError> {
         //Determine offsets
         let mut offsets = Vec::<vk::DeviceSize>::new();
         let mut size: vk::DeviceSize = 0;
@@ -183,38 +309,124 @@ impl Base {
         let device_memory = unsafe {
             self.instance.get_physical_device_memory_properties(self.physical_device)
         };
-        let Some((memory_type_index, _)) = device_memory
-            .memory_types[..(device_memory.memory_type_count as usize)]
+        let memory_types = &device_memory.memory_types[..(device_memory.memory_type_count as usize)];
+        let find_type = |properties: vk::MemoryPropertyFlags| memory_types
             .iter().enumerate()
             .find(|(i, mem_type)|
                 mem_type.property_flags.contains(properties)
                 && (supported_memory_types >> i) & 1 == 1
-            ) else {return Err(vk::Result::ERROR_UNKNOWN)};
+            );
+        let preferred = if properties.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+            find_type(properties | vk::MemoryPropertyFlags::HOST_COHERENT)
+        } else {None};
+        let relaxed = properties & !vk::MemoryPropertyFlags::DEVICE_LOCAL;
+        let Some((memory_type_index, _)) = preferred
+            .or_else(|| find_type(properties))
+            .or_else(|| find_type(relaxed))
+            else {
+                return Err(AllocationError::NoMemoryType(Box::new(MemoryTypeError {
+                    category,
+                    properties,
+                    supported_memory_types,
+                    memory_types: memory_types.to_vec(),
+                    memory_heaps: device_memory.memory_heaps[..(device_memory.memory_heap_count as usize)].to_vec()
+                })));
+            };
         //Allocate
         let create_info = vk::MemoryAllocateInfo::builder()
             .allocation_size(size)
             .memory_type_index(memory_type_index as u32);
         let allocation = unsafe {self.device.allocate_memory(&create_info, None)}?;
+        self.allocations.lock().unwrap().insert(
+            allocation,
+            (size, memory_type_index as u32, category)
+        );
         Ok((allocation, offsets))
     }
 
+    ///Property flags of the memory type of a block allocated by `create_buffers` or `create_images`.
+    pub fn memory_properties(&self, allocation: vk::DeviceMemory) -> vk::MemoryPropertyFlags {
+        let Some(&(_, memory_type, _)) = self.allocations.lock().unwrap().get(&allocation)
+            else {return vk::MemoryPropertyFlags::empty()};
+        let device_memory = unsafe {
+            self.instance.get_physical_device_memory_properties(self.physical_device)
+        };
+        device_memory.memory_types[memory_type as usize].property_flags
+    }
+
+    ///Free a memory block allocated by `create_buffers` or `create_images`.
+    pub unsafe fn free_memory(&self, allocation: vk::DeviceMemory) {
+        self.allocations.lock().unwrap().remove(&allocation);
+        self.device.free_memory(allocation, None);
+    }
+
+    ///Device memory currently allocated, by category & by heap.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut budget_properties = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+        let mut properties = vk::PhysicalDeviceMemoryProperties2::builder();
+        if self.memory_budget {
+            properties = properties.push_next(&mut budget_properties);
+        }
+        unsafe {
+            self.instance.get_physical_device_memory_properties2(self.physical_device, &mut properties);
+        }
+        let device_memory = properties.memory_properties;
+        let mut usage = MemoryUsage {
+            heaps: device_memory.memory_heaps[..(device_memory.memory_heap_count as usize)]
+                .iter().enumerate().map(|(i, heap)| HeapUsage {
+                    flags: heap.flags,
+                    size: heap.size,
+                    allocated: 0,
+                    budget: self.memory_budget.then_some(budget_properties.heap_budget[i]),
+                    usage: self.memory_budget.then_some(budget_properties.heap_usage[i])
+                }).collect(),
+            max_allocation_count: self.physical_device_properties.limits.max_memory_allocation_count,
+            ..MemoryUsage::default()
+        };
+        for &(size, memory_type, category) in self.allocations.lock().unwrap().values() {
+            match category {
+                MemoryCategory::Scenes => usage.scenes += size,
+                MemoryCategory::Environment => usage.environment += size,
+                MemoryCategory::Framebuffer => usage.framebuffer += size,
+                MemoryCategory::Staging => usage.staging += size
+            }
+            let heap = device_memory.memory_types[memory_type as usize].heap_index as usize;
+            usage.heaps[heap].allocated += size;
+            usage.allocation_count += 1;
+        }
+        usage
+    }
+
     ///Create buffers bound to a shared memory allocation.
     pub fn create_buffers(
         &self,
         create_infos: &[vk::BufferCreateInfo],
-        properties: vk::MemoryPropertyFlags
-        ) -> Result<(Vec<vk::Buffer>, vk::DeviceMemory), vk::Result> {
+        properties: vk::MemoryPropertyFlags,
+        category: MemoryCategory
+        ) -> Result<(Vec<vk::Buffer>, vk::DeviceMemory), AllocationError> {
+        let queue_families = [self.graphics_queue_family, self.transfer_queue_family];
+        let concurrent = self.concurrent(category);
         unsafe {
             //Create buffers
-            let buffers: Vec<_> = create_infos.iter().map(
-                |create_info| self.device.create_buffer(create_info, None)
+            let buffers: Vec<_> = create_infos.iter().map(|create_info| {
+                let mut create_info = *create_info;
+                if concurrent {
+                    create_info.sharing_mode = vk::SharingMode::CONCURRENT;
+                    create_info.queue_family_index_count = queue_families.len() as u32;
+                    create_info.p_queue_family_indices = queue_families.as_ptr();
+                }
+                self.device.create_buffer(&create_info, None)
                     .expect("Buffer creation error")
-            ).collect();
+            }).collect();
             //Allocate memory
             let requirements: Vec<_> = buffers.iter().map(
                 |buffer| self.device.get_buffer_memory_requirements(*buffer)
             ).collect();
-            let (allocation, offsets) = self.allocate(&requirements, properties)?;
+            let (allocation, offsets) = self.allocate(&requirements, properties, category).inspect_err(|_| {
+                for &buffer in &buffers {
+                    self.device.destroy_buffer(buffer, None);
+                }
+            })?;
             //Bind buffers to memory
             let bind_infos: Vec<_> = buffers.iter().zip(offsets).map(
                 |(buffer, offset)| vk::BindBufferMemoryInfo::builder()
@@ -229,19 +441,32 @@ impl Base {
     pub fn create_images(
         &self,
         create_infos: &[vk::ImageCreateInfo],
-        properties: vk::MemoryPropertyFlags
-    ) -> Result<(Vec<vk::Image>, vk::DeviceMemory), vk::Result> {
+        properties: vk::MemoryPropertyFlags,
+        category: MemoryCategory
+    ) -> Result<(Vec<vk::Image>, vk::DeviceMemory), AllocationError> {
+        let queue_families = [self.graphics_queue_family, self.transfer_queue_family];
+        let concurrent = self.concurrent(category);
         //Create images
         unsafe {
-            let images: Vec<_> = create_infos.iter().map(
-                |create_info| self.device.create_image(create_info, None)
+            let images: Vec<_> = create_infos.iter().map(|create_info| {
+                let mut create_info = *create_info;
+                if concurrent {
+                    create_info.sharing_mode = vk::SharingMode::CONCURRENT;
+                    create_info.queue_family_index_count = queue_families.len() as u32;
+                    create_info.p_queue_family_indices = queue_families.as_ptr();
+                }
+                self.device.create_image(&create_info, None)
                     .expect("Image creation error")
-            ).collect();
+            }).collect();
             //Allocate memory
             let requirements: Vec<_> = images.iter().map(
                 |image| self.device.get_image_memory_requirements(*image)
             ).collect();
-            let (allocation, offsets) = self.allocate(&requirements, properties)?;
+            let (allocation, offsets) = self.allocate(&requirements, properties, category).inspect_err(|_| {
+                for &image in &images {
+                    self.device.destroy_image(image, None);
+                }
+            })?;
             //Bind images to memory
             let bind_infos: Vec<_> = std::iter::zip(&images, &offsets).map(
                 |(image , offset)| *vk::BindImageMemoryInfo::builder()
@@ -253,18 +478,135 @@ impl Base {
             Ok((images, allocation))
         }
     }
+
+    /**
+        Create a device-local buffer holding `data`, e.g. for tools & tests building their own GPU data.
+        The data is staged & copied synchronously, with a transient command buffer & fence
+        (independent of renderers' frames & `Transfer`), so this blocks until the copy completes.
+        `TRANSFER_DST` is added to `usage`. The buffer is owned by the graphics queue family
+        & counted as scene memory; destroy it & free its memory (`free_memory`) when done.
+        Fails with `ERROR_INITIALIZATION_FAILED` for empty data, as buffers can't be empty.
+        The copy is submitted to the graphics queue, which needs external synchronization,
+        so don't call this while another thread draws with a renderer of this base.
+    */
+    pub fn upload_buffer<T: Copy>(
+        &self,
+        data: &[T],
+        usage: vk::BufferUsageFlags
+    ) -> Result<(vk::Buffer, vk::DeviceMemory), AllocationError> {
+        let size = std::mem::size_of_val(data) as u64;
+        if size == 0 {
+            return Err(vk::Result::ERROR_INITIALIZATION_FAILED.into());
+        }
+        //Staging buffer
+        let create_info = vk::BufferCreateInfo::builder()
+            .size(size)
+            .usage(vk::BufferUsageFlags::TRANSFER_SRC)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let (staging_buffers, staging_alloc) = self.create_buffers(
+            std::slice::from_ref(&create_info),
+            vk::MemoryPropertyFlags::HOST_VISIBLE,
+            MemoryCategory::Staging
+        )?;
+        //Device-local buffer
+        let create_info = vk::BufferCreateInfo::builder()
+            .size(size)
+            .usage(usage | vk::BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let buffer = self.create_buffers(
+            std::slice::from_ref(&create_info),
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            MemoryCategory::Scenes
+        );
+        let result = buffer.and_then(|(buffers, alloc)| unsafe {
+            //Write staging memory
+            let written = self.device.map_memory(
+                staging_alloc,
+                0,
+                vk::WHOLE_SIZE,
+                vk::MemoryMapFlags::empty()
+            ).and_then(|ptr| {
+                std::ptr::copy_nonoverlapping(data.as_ptr() as *const u8, ptr as *mut u8, size as usize);
+                let flushed = if self.memory_properties(staging_alloc).contains(vk::MemoryPropertyFlags::HOST_COHERENT) {
+                    Ok(())
+                } else {
+                    let range = vk::MappedMemoryRange::builder()
+                        .memory(staging_alloc)
+                        .offset(0)
+                        .size(vk::WHOLE_SIZE);
+                    self.device.flush_mapped_memory_ranges(std::slice::from_ref(&range))
+                };
+                self.device.unmap_memory(staging_alloc);
+                flushed
+            });
+            //Copy, making the data visible to later commands
+            let copied = written.and_then(|_| self.submit_once(|command_buffer| {
+                let region = vk::BufferCopy2::builder().size(size);
+                let copy_info = vk::CopyBufferInfo2::builder()
+                    .src_buffer(staging_buffers[0])
+                    .dst_buffer(buffers[0])
+                    .regions(std::slice::from_ref(&region));
+                self.device.cmd_copy_buffer2(command_buffer, &copy_info);
+                let memory_barrier = vk::MemoryBarrier2::builder()
+                    .src_stage_mask(vk::PipelineStageFlags2::COPY)
+                    .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                    .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                    .dst_access_mask(vk::AccessFlags2::MEMORY_READ);
+                let dependency = vk::DependencyInfo::builder()
+                    .memory_barriers(std::slice::from_ref(&memory_barrier));
+                self.device.cmd_pipeline_barrier2(command_buffer, &dependency);
+            }));
+            //Every failure frees the device-local buffer (the staging buffer is freed below)
+            if copied.is_err() {
+                self.device.destroy_buffer(buffers[0], None);
+                self.free_memory(alloc);
+            }
+            copied.map(|_| (buffers[0], alloc)).map_err(AllocationError::from)
+        });
+        unsafe {
+            self.device.destroy_buffer(staging_buffers[0], None);
+            self.free_memory(staging_alloc);
+        }
+        result
+    }
+
+    ///Record commands into a transient command buffer, submit them to the graphics queue & wait for completion.
+    pub unsafe fn submit_once(&self, record: impl FnOnce(vk::CommandBuffer)) -> Result<(), vk::Result> {
+        let create_info = vk::CommandPoolCreateInfo::builder()
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+            .queue_family_index(self.graphics_queue_family);
+        let command_pool = self.device.create_command_pool(&create_info, None)?;
+        let fence = self.device.create_fence(&vk::FenceCreateInfo::default(), None);
+        let result = fence.and_then(|fence| {
+            let submitted = (|| {
+                let allocate_info = vk::CommandBufferAllocateInfo::builder()
+                    .command_pool(command_pool)
+                    .level(vk::CommandBufferLevel::PRIMARY)
+                    .command_buffer_count(1);
+                let command_buffer = self.device.allocate_command_buffers(&allocate_info)?[0];
+                let begin_info = vk::CommandBufferBeginInfo::builder()
+                    .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+                self.device.begin_command_buffer(command_buffer, &begin_info)?;
+                record(command_buffer);
+                self.device.end_command_buffer(command_buffer)?;
+                let command_buffer_info = vk::CommandBufferSubmitInfo::builder()
+                    .command_buffer(command_buffer);
+                let submit_info = vk::SubmitInfo2::builder()
+                    .command_buffer_infos(std::slice::from_ref(&command_buffer_info));
+                self.device.queue_submit2(self.graphics_queue, std::slice::from_ref(&submit_info), fence)?;
+                self.device.wait_for_fences(std::slice::from_ref(&fence), false, u64::MAX)
+            })();
+            self.device.destroy_fence(fence, None);
+            submitted
+        });
+        self.device.destroy_command_pool(command_pool, None);
+        result
+    }
 }
 
 impl Drop for Base {
     fn drop(&mut self) {
         unsafe {
-            //Save pipeline cache
-            let pipeline_cache_data = self.device.get_pipeline_cache_data(self.pipeline_cache).unwrap();
-            let mut pipeline_cache_path = std::env::current_exe().unwrap();
-            pipeline_cache_path.pop();
-            pipeline_cache_path.push("pipeline-cache.bin");
-            let mut pipeline_cache_file = File::create(pipeline_cache_path).unwrap();
-            pipeline_cache_file.write_all(&pipeline_cache_data).unwrap();
             //Destroy Vulkan objects
             self.device.device_wait_idle().unwrap();
             self.device.destroy_pipeline_cache(self.pipeline_cache, None);
@@ -21,6 +21,19 @@ pub struct Camera {
     pub far: f32 //Far plane distance
 }
 
+///Plain copy of a camera's state, for saving & restoring viewpoints
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CameraPose {
+    pub pos: [f32; 3],
+    pub dir: [f32; 3],
+    pub up: [f32; 3],
+    pub fov: f32,
+    pub near: f32,
+    pub far: f32,
+    pub aspect: f32
+}
+
 impl Camera {
     pub fn new() -> Camera {
         Camera {
@@ -34,6 +47,52 @@ impl Camera {
         }
     }
 
+    pub fn pose(&self) -> CameraPose {
+        CameraPose {
+            pos: self.pos.into(),
+            dir: self.dir.into_inner().into(),
+            up: self.up.into_inner().into(),
+            fov: self.fov,
+            near: self.near,
+            far: self.far,
+            aspect: self.aspect
+        }
+    }
+
+    ///Restores a pose, normalizing its directions
+    pub fn set_pose(&mut self, pose: CameraPose) {
+        self.pos = pose.pos.into();
+        self.dir = na::UnitVector3::new_normalize(pose.dir.into());
+        self.up = na::UnitVector3::new_normalize(pose.up.into());
+        self.fov = pose.fov;
+        self.near = pose.near;
+        self.far = pose.far;
+        self.aspect = pose.aspect;
+    }
+
+    /**
+        Interpolates between two cameras (`t` in [0, 1]).
+        Directions are slerped & `up` is re-orthogonalized against `dir`.
+    */
+    pub fn lerp(a: &Camera, b: &Camera, t: f32) -> Camera {
+        let dir = a.dir.try_slerp(&b.dir, t, f32::EPSILON).unwrap_or(
+            if t < 0.5 { a.dir } else { b.dir }
+        );
+        let up = a.up.into_inner().lerp(&b.up, t);
+        let up = na::UnitVector3::try_new(up - dir.into_inner() * up.dot(&dir), f32::EPSILON).unwrap_or(
+            if t < 0.5 { a.up } else { b.up }
+        );
+        Camera {
+            pos: a.pos + (b.pos - a.pos) * t,
+            dir,
+            up,
+            fov: a.fov + (b.fov - a.fov) * t,
+            aspect: a.aspect + (b.aspect - a.aspect) * t,
+            near: a.near + (b.near - a.near) * t,
+            far: a.far + (b.far - a.far) * t
+        }
+    }
+
     pub fn locomote(&mut self, forward: f32, strafe: f32, vertical: f32) {
         let right = self.dir.cross(&self.up);
         self.pos += forward * self.dir.into_inner();
@@ -78,3 +137,9 @@ impl Camera {
         ])
     }
 }
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,181 @@
+use ash::vk;
+use super::FRAME_COUNT;
+use super::base::Base;
+use super::memory::{MemoryCategory, AllocationError};
+use super::framebuffer::Framebuffer;
+use super::pipeline::{PipelineLayout, add_descriptor_counts};
+use std::sync::Arc;
+
+pub const PYRAMID_FORMAT: vk::Format = vk::Format::R32_SFLOAT;
+
+///Hierarchical depth buffer (farthest depth per texel) used for occlusion culling.
+///It is built from a frame's depth image after drawing,
+///and read by the culling pass of the following frame.
+pub struct DepthPyramid {
+    base: Arc<Base>,
+    pub extent: vk::Extent2D,
+    pub level_count: u32,
+    pub image: vk::Image,
+    pub allocation: vk::DeviceMemory,
+    pub view: vk::ImageView, //All levels
+    pub level_views: Vec<vk::ImageView>,
+    pub descriptor: vk::DescriptorImageInfo,
+    descriptor_pool: vk::DescriptorPool,
+    //Build descriptor sets: [frames: [levels: []]]
+    pub descriptor_sets: Vec<vk::DescriptorSet>
+}
+
+impl DepthPyramid {
+    pub fn new(
+        base: Arc<Base>,
+        framebuffer: &Framebuffer,
+        layout: &PipelineLayout
+    ) -> Result<Self, AllocationError> {
+        let extent = framebuffer.extent;
+        let level_count = 32 - extent.width.max(extent.height).leading_zeros();
+        //Image
+        let create_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(PYRAMID_FORMAT)
+            .extent(vk::Extent3D {width: extent.width, height: extent.height, depth: 1})
+            .mip_levels(level_count)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+        let (images, allocation) = base.create_images(
+            std::slice::from_ref(&create_info),
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            MemoryCategory::Framebuffer
+        )?;
+        let image = images[0];
+        //Image views
+        let create_view = |base_mip_level: u32, level_count: u32| {
+            let subresource_range = vk::ImageSubresourceRange::builder()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .base_mip_level(base_mip_level)
+                .level_count(level_count)
+                .base_array_layer(0)
+                .layer_count(1);
+            let create_info = vk::ImageViewCreateInfo::builder()
+                .image(image)
+                .view_type(vk::ImageViewType::TYPE_2D)
+                .format(PYRAMID_FORMAT)
+                .components(vk::ComponentMapping::default())
+                .subresource_range(*subresource_range);
+            unsafe {base.device.create_image_view(&create_info, None)}
+        };
+        let view = create_view(0, level_count)?;
+        let level_views = (0..level_count).map(
+            |level| create_view(level, 1)
+        ).collect::<Result<Vec<_>, _>>()?;
+        let descriptor = *vk::DescriptorImageInfo::builder()
+            .image_view(view)
+            .image_layout(vk::ImageLayout::GENERAL);
+        //Descriptor sets
+        let set_count = FRAME_COUNT as u32 * level_count;
+        let mut pool_sizes = vec![];
+        add_descriptor_counts(&mut pool_sizes, &layout.descriptor_counts, set_count);
+        let create_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(set_count)
+            .pool_sizes(&pool_sizes);
+        let descriptor_pool = unsafe {
+            base.device.create_descriptor_pool(&create_info, None)
+        }?;
+        let layouts = vec![layout.descriptor_set_layout; set_count as usize];
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&layouts);
+        let descriptor_sets = unsafe {
+            base.device.allocate_descriptor_sets(&allocate_info)
+        }?;
+        //Descriptor writes
+        let depth_infos = framebuffer.frames.each_ref().map(
+            |frame| *vk::DescriptorImageInfo::builder()
+                .image_view(frame.sample_views[1])
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        );
+        let level_infos: Vec<_> = level_views.iter().map(
+            |view| *vk::DescriptorImageInfo::builder()
+                .image_view(*view)
+                .image_layout(vk::ImageLayout::GENERAL)
+        ).collect();
+        let mut writes = Vec::<vk::WriteDescriptorSet>::new();
+        for frame in 0..FRAME_COUNT {
+            for level in 0..(level_count as usize) {
+                let descriptor_set = descriptor_sets[frame * level_count as usize + level];
+                writes.extend_from_slice(&[
+                    //Depth image (multisampled or not)
+                    *vk::WriteDescriptorSet::builder()
+                        .dst_set(descriptor_set)
+                        .dst_binding(if framebuffer.samples == vk::SampleCountFlags::TYPE_1 {3} else {0})
+                        .dst_array_element(0)
+                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                        .image_info(std::slice::from_ref(&depth_infos[frame])),
+                    //Source level (unused by the first level)
+                    *vk::WriteDescriptorSet::builder()
+                        .dst_set(descriptor_set)
+                        .dst_binding(1)
+                        .dst_array_element(0)
+                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                        .image_info(std::slice::from_ref(&level_infos[level.saturating_sub(1)])),
+                    //Destination level
+                    *vk::WriteDescriptorSet::builder()
+                        .dst_set(descriptor_set)
+                        .dst_binding(2)
+                        .dst_array_element(0)
+                        .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                        .image_info(std::slice::from_ref(&level_infos[level]))
+                ]);
+            }
+        }
+        //Without a depth buffer, the pyramid is never built
+        if framebuffer.depth_buffer {
+            unsafe {
+                base.device.update_descriptor_sets(&writes, &[]);
+            }
+        }
+        Ok(Self {
+            base,
+            extent,
+            level_count,
+            image,
+            allocation,
+            view,
+            level_views,
+            descriptor,
+            descriptor_pool,
+            descriptor_sets
+        })
+    }
+
+    pub fn build_descriptors(&self, frame: usize, level: u32) -> vk::DescriptorSet {
+        assert!(frame < FRAME_COUNT);
+        assert!(level < self.level_count);
+        self.descriptor_sets[frame * self.level_count as usize + level as usize]
+    }
+
+    ///Extent of a pyramid level.
+    pub fn level_extent(&self, level: u32) -> vk::Extent2D {
+        vk::Extent2D {
+            width: (self.extent.width >> level).max(1),
+            height: (self.extent.height >> level).max(1)
+        }
+    }
+}
+
+impl Drop for DepthPyramid {
+    fn drop(&mut self) {
+        unsafe {
+            self.base.device.destroy_descriptor_pool(self.descriptor_pool, None);
+            for view in &self.level_views {
+                self.base.device.destroy_image_view(*view, None);
+            }
+            self.base.device.destroy_image_view(self.view, None);
+            self.base.device.destroy_image(self.image, None);
+            self.base.free_memory(self.allocation);
+        }
+    }
+}
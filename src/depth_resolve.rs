@@ -0,0 +1,168 @@
+use ash::vk;
+use super::base::Base;
+use super::memory::{MemoryCategory, AllocationError};
+use super::framebuffer::Framebuffer;
+use super::pipeline::{PipelineLayout, add_descriptor_counts};
+use super::barrier::{ImageTracker, ImageState};
+use std::sync::Arc;
+
+pub const RESOLVE_FORMAT: vk::Format = vk::Format::R32_SFLOAT;
+
+/**
+    Single-sampled copy of a frame's depth (view 0), resolved by a compute pass
+    which keeps each pixel's first sample, like `VK_RESOLVE_MODE_SAMPLE_ZERO_BIT`.
+    Created on demand to read depth back (see `Renderer::capture_depth`).
+*/
+pub struct DepthResolve {
+    base: Arc<Base>,
+    layout: PipelineLayout,
+    pipeline: vk::Pipeline,
+    pub extent: vk::Extent2D,
+    pub image: vk::Image,
+    allocation: vk::DeviceMemory,
+    view: vk::ImageView,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_set: vk::DescriptorSet
+}
+
+impl DepthResolve {
+    ///Prepare to resolve the depth image of `framebuffer.frames[frame]`.
+    pub fn new(base: Arc<Base>, framebuffer: &Framebuffer, frame: usize) -> Result<Self, AllocationError> {
+        let layout = super::pipeline::depth_resolve::create_layout(base.clone(), framebuffer.samples)?;
+        //Handles stay null until created, so dropping a partially created resolve destroys only what exists
+        let mut resolve = Self {
+            base: base.clone(),
+            layout,
+            pipeline: vk::Pipeline::null(),
+            extent: framebuffer.extent,
+            image: vk::Image::null(),
+            allocation: vk::DeviceMemory::null(),
+            view: vk::ImageView::null(),
+            descriptor_pool: vk::DescriptorPool::null(),
+            descriptor_set: vk::DescriptorSet::null()
+        };
+        resolve.pipeline = (resolve.layout.create_pipeline)(&resolve.layout, vk::RenderPass::null())?;
+        //Image
+        let create_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(RESOLVE_FORMAT)
+            .extent(vk::Extent3D {width: resolve.extent.width, height: resolve.extent.height, depth: 1})
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_SRC)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+        let (images, allocation) = base.create_images(
+            std::slice::from_ref(&create_info),
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            MemoryCategory::Framebuffer
+        )?;
+        resolve.image = images[0];
+        resolve.allocation = allocation;
+        //Image view
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1);
+        let create_info = vk::ImageViewCreateInfo::builder()
+            .image(resolve.image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(RESOLVE_FORMAT)
+            .components(vk::ComponentMapping::default())
+            .subresource_range(*subresource_range);
+        resolve.view = unsafe {base.device.create_image_view(&create_info, None)}?;
+        //Descriptor set
+        let mut pool_sizes = vec![];
+        add_descriptor_counts(&mut pool_sizes, &resolve.layout.descriptor_counts, 1);
+        let create_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(1)
+            .pool_sizes(&pool_sizes);
+        resolve.descriptor_pool = unsafe {
+            base.device.create_descriptor_pool(&create_info, None)
+        }?;
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(resolve.descriptor_pool)
+            .set_layouts(std::slice::from_ref(&resolve.layout.descriptor_set_layout));
+        resolve.descriptor_set = unsafe {
+            base.device.allocate_descriptor_sets(&allocate_info)
+        }?[0];
+        //Descriptor writes
+        let depth_info = vk::DescriptorImageInfo::builder()
+            .image_view(framebuffer.frames[frame].sample_views[1])
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        let resolve_info = vk::DescriptorImageInfo::builder()
+            .image_view(resolve.view)
+            .image_layout(vk::ImageLayout::GENERAL);
+        let writes = [
+            //Depth image (multisampled or not)
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(resolve.descriptor_set)
+                .dst_binding(if framebuffer.samples == vk::SampleCountFlags::TYPE_1 {1} else {0})
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(std::slice::from_ref(&depth_info)),
+            //Resolved image
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(resolve.descriptor_set)
+                .dst_binding(2)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .image_info(std::slice::from_ref(&resolve_info))
+        ];
+        unsafe {
+            base.device.update_descriptor_sets(&writes, &[]);
+        }
+        Ok(resolve)
+    }
+
+    /**
+        Record the resolve. The depth image must be sampled by compute shaders,
+        as the depth pyramid build leaves it (see `ImageState::COMPUTE_SAMPLED`).
+        The resolved image is left ready to copy from.
+    */
+    pub unsafe fn record(&self, command_buffer: vk::CommandBuffer) {
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1);
+        let mut images = ImageTracker::new(self.base.graphics_queue_family);
+        let image = images.track(self.image, *subresource_range, ImageState::UNDEFINED);
+        images.discard(image, ImageState::COMPUTE_WRITE);
+        images.record(&self.base.device, command_buffer);
+        self.base.device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, self.pipeline);
+        self.base.device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            self.layout.pipeline_layout,
+            0,
+            std::slice::from_ref(&self.descriptor_set),
+            &[]
+        );
+        self.base.device.cmd_dispatch(
+            command_buffer,
+            self.extent.width.div_ceil(8),
+            self.extent.height.div_ceil(8),
+            1
+        );
+        images.transition(image, ImageState::COPY_SRC);
+        images.record(&self.base.device, command_buffer);
+    }
+}
+
+impl Drop for DepthResolve {
+    fn drop(&mut self) {
+        unsafe {
+            self.base.device.destroy_descriptor_pool(self.descriptor_pool, None);
+            self.base.device.destroy_image_view(self.view, None);
+            self.base.device.destroy_image(self.image, None);
+            self.base.free_memory(self.allocation);
+            self.base.device.destroy_pipeline(self.pipeline, None);
+        }
+    }
+}
@@ -1,11 +1,17 @@
 use ash::vk;
 use nalgebra as na;
 
-use super::{FRAME_COUNT, MAX_TEXTURES};
+use super::{FRAME_COUNT, MAX_TEXTURES, MAX_MORPH_TARGETS, ShaderId, DepthBias};
 use super::base::Base;
-use super::scene::{Vertex, Material, Scene};
+use super::memory::{MemoryCategory, AllocationError};
+use super::resource::{Buffer, Image, ImageView, Allocation};
+use super::scene_set::CullStats;
+use super::scene::{Vertex, VertexFormat, TexturePacking, Material, AlphaMode, Node, Scene};
+use super::geometry::GeometryBuilder;
 use super::transfer::transaction::Transaction;
-use std::rc::Rc;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::Arc;
 
 //Device-local structures must obey GLSL std430 layout alignment rules
 
@@ -14,7 +20,11 @@ use std::rc::Rc;
 pub struct DeviceMesh {
     pub lower_bounds: na::Vector4<f32>,
     pub upper_bounds: na::Vector4<f32>,
+    pub bounding_sphere: na::Vector4<f32>, //Center in xyz, radius in w
     pub material: u32,
+    pub morph_base: i32, //First morph delta minus the vertex offset (so vertex indices address it)
+    pub morph_count: u32,
+    pub vertex_count: u32 //Deltas per morph target
 }
 
 #[repr(C, align(16))]
@@ -23,14 +33,169 @@ pub struct DeviceNode {
     pub transform: na::Matrix4<f32>,
     pub inverse_transform: na::Matrix4<f32>,
     pub mesh: u32,
-    pub flags: u32 //LSB is visibility
+    pub flags: u32, //LSB is visibility, bits 1-5 are the layer, bit 6 is mirroring
+    pub weights: [f32; MAX_MORPH_TARGETS]
+}
+
+///Displacement of a vertex by a morph target.
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+pub struct MorphDelta {
+    pub pos: na::Vector4<f32>,
+    pub normal: na::Vector4<f32>
+}
+
+///Frame slot of the cull readback buffer: the draw count followed by the cull statistics.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CullReadback {
+    draw_count: u32,
+    stats: CullStats
+}
+
+///Vertex of `VertexFormat::Packed`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PackedVertex {
+    pub pos: [f32; 3],
+    pub normal: [i16; 2], //Octahedral encoding (SNORM)
+    pub tex: [u16; 2] //f16
+}
+
+impl From<&Vertex> for PackedVertex {
+    fn from(vertex: &Vertex) -> Self {
+        Self {
+            pos: vertex.pos.into(),
+            normal: oct_encode(&vertex.normal),
+            tex: [f16_bits(vertex.tex.x), f16_bits(vertex.tex.y)]
+        }
+    }
+}
+
+///Map a direction onto the octahedron, unfolded to [-1, 1]^2.
+fn oct_encode(normal: &na::Vector3<f32>) -> [i16; 2] {
+    let sum = normal.x.abs() + normal.y.abs() + normal.z.abs();
+    if sum == 0.0 {
+        return [0, 0];
+    }
+    let n = normal / sum;
+    let sign = |x: f32| if x >= 0.0 {1.0} else {-1.0};
+    let (x, y) = if n.z >= 0.0 {
+        (n.x, n.y)
+    } else {
+        ((1.0 - n.y.abs()) * sign(n.x), (1.0 - n.x.abs()) * sign(n.y))
+    };
+    [(x * 32767.0).round() as i16, (y * 32767.0).round() as i16]
+}
+
+///Convert to half precision, rounding to nearest.
+fn f16_bits(x: f32) -> u16 {
+    let bits = x.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xFF) as i32;
+    let mantissa = bits & 0x7F_FFFF;
+    if exponent == 0xFF {
+        //Infinity or NaN
+        return sign | 0x7C00 | if mantissa != 0 {0x200} else {0};
+    }
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1F {
+        //Overflow
+        sign | 0x7C00
+    } else if exponent <= 0 {
+        //Subnormal or zero
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        let round = (mantissa >> (shift - 1)) & 1;
+        sign | ((mantissa >> shift) + round) as u16
+    } else {
+        //Rounding may carry into the exponent
+        let round = (mantissa >> 12) & 1;
+        sign | ((((exponent as u32) << 10) | (mantissa >> 13)) + round) as u16
+    }
+}
+
+//Shader texture indices with this bit are (layer << 16) | array
+const TEXTURE_ARRAY_BIT: u32 = 1 << 31;
+
+const VISIBLE: u32 = 1;
+const LAYER_SHIFT: u32 = 1;
+const LAYER_BITS: u32 = 0x1F << LAYER_SHIFT;
+const MIRRORED: u32 = 1 << 6; //Negative determinant, so triangle winding is reversed
+
+///Morph weights of a node, zero for missing weights.
+fn node_weights(node: &Node) -> [f32; MAX_MORPH_TARGETS] {
+    std::array::from_fn(|i| node.weights.get(i).copied().unwrap_or(0.0))
+}
+
+///Inverse of a node transform, or identity if it's singular (e.g. zero scale) so normals stay finite.
+fn inverse_transform(transform: &na::Affine3<f32>) -> na::Matrix4<f32> {
+    transform.to_homogeneous().try_inverse().unwrap_or_else(na::Matrix4::identity)
+}
+
+///Initial flags of a visible node.
+fn node_flags(transform: &na::Matrix4<f32>) -> u32 {
+    if transform.fixed_view::<3, 3>(0, 0).determinant() < 0.0 {
+        VISIBLE | MIRRORED
+    } else {
+        VISIBLE
+    }
+}
+
+///First device node of each scene node, followed by the device node count.
+fn node_offsets(scene: &Scene) -> Vec<usize> {
+    let mut offsets = vec![0];
+    for node in &scene.nodes {
+        let primitives = node.mesh.map_or(0, |mesh| scene.meshes[mesh as usize].primitives.len());
+        offsets.push(offsets.last().unwrap() + primitives);
+    }
+    offsets
+}
+
+impl DeviceNode {
+    ///Layer (0-31), drawn when its bit is set in `SceneSet::set_layer_mask`.
+    pub fn layer(&self) -> u32 {
+        (self.flags & LAYER_BITS) >> LAYER_SHIFT
+    }
+
+    pub fn set_layer(&mut self, layer: u32) {
+        assert!(layer < 32, "Layer {} out of range", layer);
+        self.flags = (self.flags & !LAYER_BITS) | (layer << LAYER_SHIFT);
+    }
+
+    ///Hidden nodes are culled (and skipped by shadows & picking).
+    pub fn visible(&self) -> bool {
+        self.flags & VISIBLE != 0
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.flags = if visible {self.flags | VISIBLE} else {self.flags & !VISIBLE};
+    }
 }
 
 pub struct DeviceScene {
-    base: Rc<Base>,
+    base: Arc<Base>,
+    pub shader: ShaderId,
+    pub depth_bias: DepthBias,
+    pub wireframe: Option<bool>, //Overrides `Renderer::set_wireframe_overlay` (see `SceneSet::set_scene_wireframe`)
+    pub vertex_format: VertexFormat,
+    pub masked: bool, //Has `AlphaMode::Mask` materials, so is drawn with alpha-to-coverage
     //Dynamic data
     pub nodes: Vec<DeviceNode>,
+    pub node_capacity: usize, //Nodes which fit in the node, draw command & extras buffers
+    pub node_indices: Vec<usize>, //Source scene node of each device node
+    /*
+        Device nodes are scene nodes expanded in order, one per primitive of the node's mesh:
+        scene node `n`'s primitive `p` is device node `node_offsets[n] + p`
+        (see `primitive_node_index`). Nodes without a mesh have no device nodes.
+    */
+    pub node_offsets: Vec<usize>,
     pub mesh_offsets: Vec<usize>,
+    pub meshes: Vec<DeviceMesh>,
+    pub mesh_commands: Vec<vk::DrawIndexedIndirectCommand>,
     //Buffers
     /*
         Buffers:
@@ -43,87 +208,121 @@ pub struct DeviceScene {
         6. Draw commands (duplicated)
         7. Draw extras [node, primitive] (duplicated)
         8. Draw command count (duplicated)
+        9. Morph deltas
+        10. Cull statistics (duplicated)
     */
-    pub buffers: [vk::Buffer; 9],
-    pub buffer_alloc: vk::DeviceMemory,
-    pub buffer_sizes: [usize; 9],
-    pub buffer_descriptors: [vk::DescriptorBufferInfo; 3 + 4 * FRAME_COUNT],
-    //Images
-    pub images: Vec<vk::Image>,
-    pub image_views: Vec<vk::ImageView>,
-    pub image_alloc: vk::DeviceMemory,
-    pub image_descriptors: [vk::DescriptorImageInfo; MAX_TEXTURES]
+    pub buffers: [Buffer; 11],
+    pub buffer_alloc: Allocation,
+    pub buffer_sizes: [usize; 11],
+    pub buffer_descriptors: [vk::DescriptorBufferInfo; 4 + 5 * FRAME_COUNT],
+    //Images (views drop first)
+    pub image_views: Vec<ImageView>,
+    pub images: Vec<Image>,
+    pub image_alloc: Allocation,
+    pub image_descriptors: [vk::DescriptorImageInfo; MAX_TEXTURES],
+    pub array_descriptors: [vk::DescriptorImageInfo; MAX_TEXTURES], //With `TexturePacking::Arrays`
+    //Draw count & cull statistics readback (for each frame)
+    cull_readback_buffer: Buffer,
+    cull_readback_alloc: Allocation,
+    cull_readback_ptr: *const CullReadback,
+    cull_readback_coherent: bool,
+    cull_count: Cell<u32>, //Of the last completed frame
+    cull_stats: Cell<CullStats>, //Of the last completed frame
+    pub source: Option<Arc<Scene>> //CPU-side scene, if retained (see `SceneSet::set_scene_retention`)
 }
 
+//The readback mapping is only accessed through its owner
+unsafe impl Send for DeviceScene {}
+
 impl DeviceScene {
+    /**
+        Upload a scene, with room for at least `node_capacity` device nodes (one per node primitive).
+        Fails with `ERROR_TOO_MANY_OBJECTS` if the scene's textures need more than `MAX_TEXTURES` images
+        with its texture packing.
+    */
     pub fn new(
-        base: Rc<Base>,
+        base: Arc<Base>,
         transaction: &mut Transaction,
-        scene: &Scene
-    ) -> Result<Self, vk::Result> {
+        scene: &Scene,
+        node_capacity: usize
+    ) -> Result<Self, AllocationError> {
+        //Color textures are sRGB encoded & decoded when sampled, other textures hold linear data
+        let formats: Vec<_> = (0..scene.textures.len() as u32).map(|i| {
+            if scene.is_color_texture(i) {
+                vk::Format::R8G8B8A8_SRGB
+            } else {
+                vk::Format::R8G8B8A8_UNORM
+            }
+        }).collect();
+        //Group textures into images (the default texture is never in an array)
+        let groups: Vec<Vec<usize>> = match scene.texture_packing {
+            TexturePacking::Separate => (0..scene.textures.len()).map(|i| vec![i]).collect(),
+            TexturePacking::Arrays => {
+                let mut groups = vec![vec![0]];
+                let mut group_indices = HashMap::<(u32, u32, vk::Format), usize>::new();
+                for (i, texture) in scene.textures.iter().enumerate().skip(1) {
+                    let key = (texture.width(), texture.height(), formats[i]);
+                    let group = *group_indices.entry(key).or_insert_with(|| {
+                        groups.push(vec![]);
+                        groups.len() - 1
+                    });
+                    groups[group].push(i);
+                }
+                groups
+            }
+        };
+        //Checked before anything is uploaded, so failing leaves no pending writes to freed buffers
+        if groups.len() > MAX_TEXTURES {
+            return Err(vk::Result::ERROR_TOO_MANY_OBJECTS.into());
+        }
         //Meshes
-        let mut vertices = Vec::<Vertex>::new();
-        let mut indices = Vec::<u16>::new();
-        let mut meshes = Vec::<DeviceMesh>::new();
+        let mut geometry = GeometryBuilder::new();
         let mut mesh_commands = Vec::<vk::DrawIndexedIndirectCommand>::new();
         let mut mesh_offsets = Vec::<usize>::new();
         for mesh in &scene.meshes {
-            mesh_offsets.push(meshes.len());
+            mesh_offsets.push(mesh_commands.len());
             for primitive in &mesh.primitives {
-                //Geometry
-                mesh_commands.push(*vk::DrawIndexedIndirectCommand::builder()
-                    .index_count(primitive.indices.len() as u32)
-                    .instance_count(1)
-                    .first_index(indices.len() as u32)
-                    .vertex_offset(vertices.len() as i32)
-                    .first_instance(0)
-                );
-                vertices.extend_from_slice(&primitive.vertices);
-                indices.extend_from_slice(&primitive.indices);
-                //Bounds
-                let mut lower_bounds = na::Point3::<f32>::new(f32::MAX, f32::MAX, f32::MAX);
-                let mut upper_bounds = na::Point3::<f32>::new(f32::MIN, f32::MIN, f32::MIN);
-                for vertex in &primitive.vertices {
-                    lower_bounds.x = lower_bounds.x.min(vertex.pos.x);
-                    lower_bounds.y = lower_bounds.y.min(vertex.pos.y);
-                    lower_bounds.z = lower_bounds.z.min(vertex.pos.z);
-                    upper_bounds.x = upper_bounds.x.max(vertex.pos.x);
-                    upper_bounds.y = upper_bounds.y.max(vertex.pos.y);
-                    upper_bounds.z = upper_bounds.z.max(vertex.pos.z);
-                }
-                //Device mesh
-                meshes.push(DeviceMesh {
-                    upper_bounds: upper_bounds.into(),
-                    lower_bounds: lower_bounds.into(),
-                    material: primitive.material
-                });
+                mesh_commands.push(geometry.push_primitive(primitive).draw_command());
             }
         }
+        let meshes = std::mem::take(&mut geometry.meshes);
+        let morph_deltas = std::mem::take(&mut geometry.morph_deltas);
+        let (vertices, indices) = (geometry.vertices(), geometry.indices());
         //Nodes
         let mut nodes = Vec::<DeviceNode>::new();
-        for (node, transform) in std::iter::zip(&scene.nodes, scene.transformations()) {
+        let mut node_indices = Vec::<usize>::new();
+        for (j, (node, transform)) in std::iter::zip(&scene.nodes, scene.transformations()).enumerate() {
             if let Some(mesh) = node.mesh {
                 for i in 0..(scene.meshes[mesh as usize].primitives.len()) {
                     nodes.push(DeviceNode {
                         transform: transform.to_homogeneous(),
-                        inverse_transform: transform.inverse().to_homogeneous(),
+                        inverse_transform: inverse_transform(&transform),
                         mesh: (mesh_offsets[mesh as usize] + i) as u32,
-                        flags: 1
+                        flags: node_flags(&transform.to_homogeneous()),
+                        weights: node_weights(node)
                     });
+                    node_indices.push(j);
                 }
             }
         }
+        let node_capacity = node_capacity.max(nodes.len());
         //Create device-local buffers
+        let vertex_size = match scene.vertex_format {
+            VertexFormat::Full => std::mem::size_of::<Vertex>(),
+            VertexFormat::Packed => std::mem::size_of::<PackedVertex>()
+        };
         let buffer_sizes = [
-            vertices.len() * std::mem::size_of::<Vertex>(),
-            indices.len() * std::mem::size_of::<u16>(),
+            vertices.len() * vertex_size,
+            std::mem::size_of_val(indices),
             meshes.len() * std::mem::size_of::<DeviceMesh>(),
             scene.materials.len() * std::mem::size_of::<Material>(),
             mesh_commands.len() * std::mem::size_of::<vk::DrawIndexedIndirectCommand>(),
-            nodes.len() * std::mem::size_of::<DeviceNode>(),
-            nodes.len() * std::mem::size_of::<vk::DrawIndexedIndirectCommand>(),
-            nodes.len() * std::mem::size_of::<[u32; 2]>(),
-            std::mem::size_of::<u32>()
+            node_capacity * std::mem::size_of::<DeviceNode>(),
+            node_capacity * std::mem::size_of::<vk::DrawIndexedIndirectCommand>(),
+            node_capacity * std::mem::size_of::<[u32; 2]>(),
+            std::mem::size_of::<u32>(),
+            morph_deltas.len().max(1) * std::mem::size_of::<MorphDelta>(), //Never empty
+            std::mem::size_of::<CullStats>()
         ];
         let create_infos = [
             //Vertices
@@ -151,11 +350,14 @@ impl DeviceScene {
                 .size(buffer_sizes[4] as u64)
                 .usage(vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST)
                 .sharing_mode(vk::SharingMode::EXCLUSIVE),
-            //Nodes
+            //Nodes (also the instance-rate vertex buffer of mesh pipelines)
             *vk::BufferCreateInfo::builder()
                 .size((FRAME_COUNT * buffer_sizes[5]) as u64)
-                .usage(vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST)
-                .sharing_mode(vk::SharingMode::EXCLUSIVE),
+                .usage(
+                    vk::BufferUsageFlags::VERTEX_BUFFER
+                    | vk::BufferUsageFlags::STORAGE_BUFFER
+                    | vk::BufferUsageFlags::TRANSFER_DST
+                ).sharing_mode(vk::SharingMode::EXCLUSIVE),
             //Draw commands
             *vk::BufferCreateInfo::builder()
                 .size((FRAME_COUNT * buffer_sizes[6]) as u64)
@@ -175,26 +377,83 @@ impl DeviceScene {
                 .usage(
                     vk::BufferUsageFlags::INDIRECT_BUFFER
                     | vk::BufferUsageFlags::STORAGE_BUFFER
+                    | vk::BufferUsageFlags::TRANSFER_SRC
+                    | vk::BufferUsageFlags::TRANSFER_DST
+                ).sharing_mode(vk::SharingMode::EXCLUSIVE),
+            //Morph deltas
+            *vk::BufferCreateInfo::builder()
+                .size(buffer_sizes[9] as u64)
+                .usage(vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE),
+            //Cull statistics
+            *vk::BufferCreateInfo::builder()
+                .size((FRAME_COUNT * buffer_sizes[10]) as u64)
+                .usage(
+                    vk::BufferUsageFlags::STORAGE_BUFFER
+                    | vk::BufferUsageFlags::TRANSFER_SRC
                     | vk::BufferUsageFlags::TRANSFER_DST
                 ).sharing_mode(vk::SharingMode::EXCLUSIVE)
         ];
         let (buffers, buffer_alloc) = base.create_buffers(
             &create_infos,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            MemoryCategory::Scenes
         )?;
+        let buffers: [Buffer; 11] = std::array::from_fn(|i| Buffer::new(base.clone(), buffers[i]));
+        let buffer_alloc = Allocation::new(base.clone(), buffer_alloc);
         //Write to buffers
-        transaction.buffer_write(&vertices, buffers[0], 0);
-        transaction.buffer_write(&indices, buffers[1], 0);
-        transaction.buffer_write(&meshes, buffers[2], 0);
-        transaction.buffer_write(&scene.materials, buffers[3], 0);
-        transaction.buffer_write(&mesh_commands, buffers[4], 0);
+        match scene.vertex_format {
+            VertexFormat::Full => transaction.buffer_write(vertices, buffers[0].handle(), 0),
+            VertexFormat::Packed => {
+                let packed: Vec<PackedVertex> = vertices.iter().map(PackedVertex::from).collect();
+                transaction.buffer_write(&packed, buffers[0].handle(), 0);
+            }
+        }
+        transaction.buffer_write(indices, buffers[1].handle(), 0);
+        transaction.buffer_write(&meshes, buffers[2].handle(), 0);
+        transaction.buffer_write(&mesh_commands, buffers[4].handle(), 0);
+        if !morph_deltas.is_empty() {
+            transaction.buffer_write(&morph_deltas, buffers[9].handle(), 0);
+        }
+        //Draw count & cull statistics readback
+        let create_info = vk::BufferCreateInfo::builder()
+            .size((FRAME_COUNT * std::mem::size_of::<CullReadback>()) as u64)
+            .usage(vk::BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let (cull_readback_buffers, cull_readback_alloc) = base.create_buffers(
+            std::slice::from_ref(&create_info),
+            vk::MemoryPropertyFlags::HOST_VISIBLE,
+            MemoryCategory::Scenes
+        )?;
+        let cull_readback_buffer = Buffer::new(base.clone(), cull_readback_buffers[0]);
+        let cull_readback_alloc = Allocation::new(base.clone(), cull_readback_alloc);
+        let cull_readback_coherent = base.memory_properties(cull_readback_alloc.handle())
+            .contains(vk::MemoryPropertyFlags::HOST_COHERENT);
+        let cull_readback_ptr = unsafe {
+            let ptr = base.device.map_memory(
+                cull_readback_alloc.handle(),
+                0,
+                vk::WHOLE_SIZE,
+                vk::MemoryMapFlags::empty()
+            )? as *mut CullReadback;
+            //Frames which haven't completed yet report no draws
+            ptr.write_bytes(0, FRAME_COUNT);
+            if !cull_readback_coherent {
+                let range = vk::MappedMemoryRange::builder()
+                    .memory(cull_readback_alloc.handle())
+                    .offset(0)
+                    .size(vk::WHOLE_SIZE);
+                base.device.flush_mapped_memory_ranges(std::slice::from_ref(&range))?;
+            }
+            ptr as *const CullReadback
+        };
 
         //Buffer descriptors
         let mut buffer_descriptors = Vec::<vk::DescriptorBufferInfo>::new();
         //Static descriptors
         for i in 2..=4 {
             buffer_descriptors.push(*vk::DescriptorBufferInfo::builder()
-                .buffer(buffers[i])
+                .buffer(buffers[i].handle())
                 .offset(0)
                 .range(vk::WHOLE_SIZE)
             );
@@ -204,27 +463,65 @@ impl DeviceScene {
             let size = buffer_sizes[i];
             for j in 0..FRAME_COUNT {
                 buffer_descriptors.push(*vk::DescriptorBufferInfo::builder()
-                    .buffer(buffers[i])
+                    .buffer(buffers[i].handle())
                     .offset((j * size) as u64)
                     .range(size as u64)
                 );
             }
         }
+        buffer_descriptors.push(*vk::DescriptorBufferInfo::builder()
+            .buffer(buffers[9].handle())
+            .offset(0)
+            .range(vk::WHOLE_SIZE)
+        );
+        for j in 0..FRAME_COUNT {
+            buffer_descriptors.push(*vk::DescriptorBufferInfo::builder()
+                .buffer(buffers[10].handle())
+                .offset((j * buffer_sizes[10]) as u64)
+                .range(buffer_sizes[10] as u64)
+            );
+        }
 
         //Textures
-        let format = vk::Format::R8G8B8A8_SRGB;
+        let is_array = |group: usize| scene.texture_packing == TexturePacking::Arrays && group > 0;
+        //Shader texture indices: an image of the texture array, or an array & layer (see `pbr.frag`)
+        let mut texture_indices = vec![0; scene.textures.len()];
+        for (group, textures) in groups.iter().enumerate() {
+            for (layer, &texture) in textures.iter().enumerate() {
+                texture_indices[texture] = if is_array(group) {
+                    TEXTURE_ARRAY_BIT | (layer as u32) << 16 | (group - 1) as u32
+                } else {
+                    group as u32
+                };
+            }
+        }
+        let remap = |texture: u32| texture_indices.get(texture as usize).copied().unwrap_or(0);
+        let materials: Vec<Material> = scene.materials.iter().map(|material| Material {
+            color_texture: remap(material.color_texture),
+            metal_rough_texture: remap(material.metal_rough_texture),
+            height_texture: remap(material.height_texture),
+            emissive_texture: remap(material.emissive_texture),
+            transmission_texture: remap(material.transmission_texture),
+            clearcoat_texture: remap(material.clearcoat_texture),
+            clearcoat_roughness_texture: remap(material.clearcoat_roughness_texture),
+            specular_color_texture: remap(material.specular_color_texture),
+            specular_texture: remap(material.specular_texture),
+            ..*material
+        }).collect();
+        transaction.buffer_write(&materials, buffers[3].handle(), 0);
         //Create images
-        let create_infos: Vec<_> = scene.textures.iter().map(|asset| {
+        let create_infos: Vec<_> = groups.iter().map(|textures| {
+            let asset = &scene.textures[textures[0]];
             let extent = vk::Extent3D::builder()
                 .width(asset.width())
                 .height(asset.height())
                 .depth(1);
             *vk::ImageCreateInfo::builder()
                 .image_type(vk::ImageType::TYPE_2D)
-                .format(format)
+                .format(formats[textures[0]])
                 .extent(*extent)
                 .mip_levels(1)
-                .array_layers(1)
+                .array_layers(textures.len() as u32)
                 .samples(vk::SampleCountFlags::TYPE_1)
                 .tiling(vk::ImageTiling::OPTIMAL)
                 .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST)
@@ -233,40 +530,46 @@ impl DeviceScene {
         }).collect();
         let (images, image_alloc) = base.create_images(
             &create_infos,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            MemoryCategory::Scenes
         )?;
-        //Write to images
-        for (asset, image) in std::iter::zip(&scene.textures, &images) {
-            let subresource_range = vk::ImageSubresourceRange::builder()
-                .aspect_mask(vk::ImageAspectFlags::COLOR)
-                .base_mip_level(0)
-                .level_count(1)
-                .base_array_layer(0)
-                .layer_count(1);
-            let subresource = vk::ImageSubresourceLayers::builder()
-                .aspect_mask(vk::ImageAspectFlags::COLOR)
-                .mip_level(0)
-                .base_array_layer(0)
-                .layer_count(1);
-            let extent = vk::Extent3D::builder()
-                .width(asset.width())
-                .height(asset.height())
-                .depth(1);
-            let region = vk::BufferImageCopy2::builder()
-                .buffer_offset(0)
-                .image_subresource(*subresource)
-                .image_offset(vk::Offset3D::default())
-                .image_extent(*extent);
-            transaction.image_write(
-                asset.as_raw(),
-                *image,
-                *subresource_range,
-                std::slice::from_ref(&region),
-                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
-            );
+        let images: Vec<_> = images.into_iter().map(|image| Image::new(base.clone(), image)).collect();
+        let image_alloc = Allocation::new(base.clone(), image_alloc);
+        //Write to images, a layer at a time
+        for (textures, image) in std::iter::zip(&groups, &images) {
+            for (layer, &texture) in textures.iter().enumerate() {
+                let asset = &scene.textures[texture];
+                let subresource_range = vk::ImageSubresourceRange::builder()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .base_mip_level(0)
+                    .level_count(1)
+                    .base_array_layer(layer as u32)
+                    .layer_count(1);
+                let subresource = vk::ImageSubresourceLayers::builder()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .mip_level(0)
+                    .base_array_layer(layer as u32)
+                    .layer_count(1);
+                let extent = vk::Extent3D::builder()
+                    .width(asset.width())
+                    .height(asset.height())
+                    .depth(1);
+                let region = vk::BufferImageCopy2::builder()
+                    .buffer_offset(0)
+                    .image_subresource(*subresource)
+                    .image_offset(vk::Offset3D::default())
+                    .image_extent(*extent);
+                transaction.image_write(
+                    asset.as_raw(),
+                    image.handle(),
+                    *subresource_range,
+                    std::slice::from_ref(&region),
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+                );
+            }
         }
         //Create image views
-        let image_views: Vec<_> = images.iter().map(|image| {
+        let create_view = |image: &Image, view_type: vk::ImageViewType, format: vk::Format, layers: usize| {
             let component_mapping = vk::ComponentMapping::builder()
                 .r(vk::ComponentSwizzle::IDENTITY)
                 .g(vk::ComponentSwizzle::IDENTITY)
@@ -277,80 +580,198 @@ impl DeviceScene {
                 .base_mip_level(0)
                 .level_count(1)
                 .base_array_layer(0)
-                .layer_count(1);
+                .layer_count(layers as u32);
             let create_info = vk::ImageViewCreateInfo::builder()
-                .image(*image)
-                .view_type(vk::ImageViewType::TYPE_2D)
+                .image(image.handle())
+                .view_type(view_type)
                 .format(format)
                 .components(*component_mapping)
                 .subresource_range(*subresource_range);
-            unsafe {
-                base.device.create_image_view(&create_info, None).unwrap()
-            }
-        }).collect();
+            ImageView::new(base.clone(), &create_info)
+        };
+        let mut image_views = std::iter::zip(&groups, &images).enumerate().map(|(group, (textures, image))| {
+            let view_type = if is_array(group) {
+                vk::ImageViewType::TYPE_2D_ARRAY
+            } else {
+                vk::ImageViewType::TYPE_2D
+            };
+            create_view(image, view_type, formats[textures[0]], textures.len())
+        }).collect::<Result<Vec<_>, _>>()?;
+        //Array view of the default texture, filling unused texture array descriptors
+        image_views.push(create_view(&images[0], vk::ImageViewType::TYPE_2D_ARRAY, formats[0], 1)?);
 
         //Image descriptors
         let mut image_descriptors = [
             *vk::DescriptorImageInfo::builder()
-                .image_view(image_views[0])
+                .image_view(image_views[0].handle())
                 .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
             MAX_TEXTURES
         ];
-        for i in 0..image_views.len() {
-            image_descriptors[i] = *vk::DescriptorImageInfo::builder()
-                .image_view(image_views[i])
+        let mut array_descriptors = [
+            *vk::DescriptorImageInfo::builder()
+                .image_view(image_views[groups.len()].handle())
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+            MAX_TEXTURES
+        ];
+        for group in 0..groups.len() {
+            let descriptor = if is_array(group) {
+                &mut array_descriptors[group - 1]
+            } else {
+                &mut image_descriptors[group]
+            };
+            *descriptor = *vk::DescriptorImageInfo::builder()
+                .image_view(image_views[group].handle())
                 .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
         }
 
         //Result
         Ok(Self {
             base,
+            shader: ShaderId::DEFAULT,
+            depth_bias: DepthBias::default(),
+            wireframe: None,
+            vertex_format: scene.vertex_format,
+            masked: scene.materials.iter().any(|material| material.alpha_mode == AlphaMode::Mask),
             nodes,
+            node_capacity,
+            node_indices,
+            node_offsets: node_offsets(scene),
             mesh_offsets,
-            buffers: buffers.try_into().unwrap(),
+            meshes,
+            mesh_commands,
+            buffers,
             buffer_alloc,
             buffer_sizes,
             buffer_descriptors: buffer_descriptors.try_into().unwrap(),
-            images,
             image_views,
+            images,
             image_alloc,
-            image_descriptors
+            image_descriptors,
+            array_descriptors,
+            cull_readback_buffer,
+            cull_readback_alloc,
+            cull_readback_ptr,
+            cull_readback_coherent,
+            cull_count: Cell::new(0),
+            cull_stats: Cell::new(CullStats::default()),
+            source: None
         })
     }
 
+    ///Device nodes needed for a scene (one per primitive of each node's mesh).
+    pub fn node_count(scene: &Scene) -> usize {
+        scene.nodes.iter().filter_map(|node| node.mesh).map(
+            |mesh| scene.meshes[mesh as usize].primitives.len()
+        ).sum()
+    }
+
+    ///Device node drawing a primitive of a scene node's mesh, if the node has that primitive.
+    pub fn primitive_node_index(&self, node: usize, primitive: usize) -> Option<usize> {
+        let first = *self.node_offsets.get(node)?;
+        let end = *self.node_offsets.get(node + 1)?;
+        (first + primitive < end).then_some(first + primitive)
+    }
+
+    ///Copy a frame's draw count & cull statistics to the readback buffer, after culling.
+    pub(crate) fn record_cull_readback(&self, command_buffer: vk::CommandBuffer, frame: usize) {
+        let offset = frame * std::mem::size_of::<CullReadback>();
+        for (buffer, dst_offset) in [
+            (8, offset),
+            (10, offset + std::mem::size_of::<u32>())
+        ] {
+            let region = vk::BufferCopy::builder()
+                .src_offset((frame * self.buffer_sizes[buffer]) as u64)
+                .dst_offset(dst_offset as u64)
+                .size(self.buffer_sizes[buffer] as u64);
+            unsafe {
+                self.base.device.cmd_copy_buffer(
+                    command_buffer,
+                    self.buffers[buffer].handle(),
+                    self.cull_readback_buffer.handle(),
+                    std::slice::from_ref(&region)
+                );
+            }
+        }
+    }
+
+    ///Read back a frame's draw count & cull statistics, once its submission has completed.
+    pub(crate) fn read_cull_readback(&self, frame: usize) {
+        unsafe {
+            if !self.cull_readback_coherent {
+                let range = vk::MappedMemoryRange::builder()
+                    .memory(self.cull_readback_alloc.handle())
+                    .offset(0)
+                    .size(vk::WHOLE_SIZE);
+                self.base.device.invalidate_mapped_memory_ranges(std::slice::from_ref(&range)).unwrap();
+            }
+            let readback = self.cull_readback_ptr.add(frame).read_volatile();
+            self.cull_count.set(readback.draw_count);
+            self.cull_stats.set(readback.stats);
+        }
+    }
+
+    ///Nodes which passed culling in the last completed frame.
+    pub fn cull_count(&self) -> u32 {
+        self.cull_count.get()
+    }
+
+    ///Cull statistics of the last completed frame.
+    pub fn cull_stats(&self) -> CullStats {
+        self.cull_stats.get()
+    }
+
     pub fn update(&mut self, scene: &Scene) {
-        let old_len = self.nodes.len();
-        self.nodes.clear();
-        for (node, transform) in std::iter::zip(&scene.nodes, scene.transformations()) {
+        let old_nodes = std::mem::take(&mut self.nodes);
+        self.node_indices.clear();
+        for (j, (node, transform)) in std::iter::zip(&scene.nodes, scene.transformations()).enumerate() {
             if let Some(mesh) = node.mesh {
                 for i in 0..(scene.meshes[mesh as usize].primitives.len()) {
                     self.nodes.push(DeviceNode {
                         transform: transform.to_homogeneous(),
-                        inverse_transform: transform.inverse().to_homogeneous(),
+                        inverse_transform: inverse_transform(&transform),
                         mesh: (self.mesh_offsets[mesh as usize] + i) as u32,
-                        flags: 1
+                        flags: node_flags(&transform.to_homogeneous()),
+                        weights: node_weights(node)
                     });
+                    self.node_indices.push(j);
                 }
             }
         }
-        assert!(self.nodes.len() == old_len);
+        assert!(self.nodes.len() == old_nodes.len());
+        self.node_offsets = node_offsets(scene);
+        //Keep layers & visibility
+        for (node, old_node) in std::iter::zip(&mut self.nodes, &old_nodes) {
+            node.set_layer(old_node.layer());
+            node.set_visible(old_node.visible());
+        }
     }
 }
 
-impl Drop for DeviceScene {
-    fn drop(&mut self) {
-        unsafe {
-            for buffer in self.buffers {
-                self.base.device.destroy_buffer(buffer, None);
-            }
-            self.base.device.free_memory(self.buffer_alloc, None);
-            for image_view in &self.image_views {
-                self.base.device.destroy_image_view(*image_view, None);
-            }
-            for image in &self.images {
-                self.base.device.destroy_image(*image, None);
-            }
-            self.base.device.free_memory(self.image_alloc, None);
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn singular_inverse_is_identity() {
+        let transform = na::Affine3::from_matrix_unchecked(
+            na::Matrix4::new_nonuniform_scaling(&na::Vector3::new(1.0, 0.0, 1.0))
+        );
+        assert_eq!(inverse_transform(&transform), na::Matrix4::identity());
+    }
+
+    #[test]
+    fn normal_matrix_is_inverse_transpose() {
+        let transform = na::Affine3::from_matrix_unchecked(
+            na::Matrix4::new_translation(&na::Vector3::new(1.0, 2.0, 3.0))
+            * na::Matrix4::new_nonuniform_scaling(&na::Vector3::new(2.0, 1.0, 1.0))
+        );
+        //As pbr.vert transforms normals
+        let normal_matrix = inverse_transform(&transform).transpose();
+        let expected = na::Matrix3::from_diagonal(&na::Vector3::new(0.5, 1.0, 1.0));
+        assert!((normal_matrix.fixed_view::<3, 3>(0, 0) - expected).abs().max() < 1e-6);
+        //The normal of a 45 degree plane stays perpendicular to it
+        let tangent = transform.transform_vector(&na::Vector3::new(1.0, 1.0, 0.0));
+        let normal = normal_matrix.transform_vector(&na::Vector3::new(1.0, -1.0, 0.0));
+        assert!(tangent.dot(&normal).abs() < 1e-6);
     }
 }
@@ -1,29 +1,77 @@
 use ash::vk::{self, BufferImageCopy2};
+use nalgebra as na;
 use ktx2::Reader;
 use super::base::Base;
+use super::memory::{MemoryCategory, AllocationError};
+use super::resource::{Image, ImageView, Allocation};
 use super::transfer::transaction::Transaction;
-use std::rc::Rc;
+use std::sync::Arc;
 
+///Skybox & image-based lighting of a scene set.
 pub struct Environment {
-    base: Rc<Base>,
-    pub images: [vk::Image; 3],
-    pub image_views: [vk::ImageView; 3],
+    pub kind: EnvironmentKind
+}
+
+pub enum EnvironmentKind {
+    Cubemap(Cubemaps),
+    /**
+        Procedural sky blending linear colors by elevation (world +Y after the environment rotation),
+        used for the skybox & a cheap ambient term (diffuse from the normal's direction,
+        specular from the reflection's, without roughness blurring). No images are allocated.
+    */
+    Gradient {
+        top: na::Vector3<f32>,
+        horizon: na::Vector3<f32>,
+        bottom: na::Vector3<f32>
+    }
+}
+
+///Skybox, diffuse & specular cubemaps.
+pub struct Cubemaps {
+    base: Arc<Base>,
+    //Views drop before images
+    pub image_views: [ImageView; 3],
+    pub images: [Image; 3],
     pub sampler: vk::Sampler,
-    pub allocation: vk::DeviceMemory,
+    pub allocation: Allocation,
     pub descriptors: [vk::DescriptorImageInfo; 3]
 }
 
 impl Environment {
+    /**
+        Load the skybox, diffuse & specular cubemaps from KTX2 files.
+        Fails with `ERROR_FORMAT_NOT_SUPPORTED` for files without a Vulkan format
+        & `ERROR_INITIALIZATION_FAILED` for invalid files (including levels whose size doesn't match their extent).
+    */
 	pub fn new(
-        base: Rc<Base>, 
+        base: Arc<Base>, 
         transaction: &mut Transaction,
         skybox: &[u8],
         diffuse: &[u8],
         specular: &[u8]
-    ) -> Result<Environment, vk::Result> {
+    ) -> Result<Environment, AllocationError> {
 		//Read files to buffer
 		let files = [skybox, diffuse, specular];
-		let readers = files.map(|file| Reader::new(file).unwrap());
+		let mut readers = Vec::with_capacity(3);
+		let mut formats = [vk::Format::UNDEFINED; 3];
+		for (i, file) in files.into_iter().enumerate() {
+			let reader = Reader::new(file).map_err(|_| vk::Result::ERROR_INITIALIZATION_FAILED)?;
+			let header = reader.header();
+			formats[i] = vk::Format::from_raw(
+				u32::from(header.format.ok_or(vk::Result::ERROR_FORMAT_NOT_SUPPORTED)?.0) as i32
+			);
+			//Levels are stored whole, so their sizes follow from the format & extent
+			if header.supercompression_scheme.is_none() {
+				let extent = vk::Extent3D {width: header.pixel_width, height: header.pixel_height, depth: header.pixel_depth};
+				let images = header.face_count.max(1) * header.layer_count.max(1);
+				for (level, data) in reader.levels().enumerate() {
+					if level_size(formats[i], extent, images, level as u32).is_some_and(|size| data.len() != size) {
+						return Err(vk::Result::ERROR_INITIALIZATION_FAILED.into());
+					}
+				}
+			}
+			readers.push(reader);
+		}
 		//Create images
 		let create_infos = [0, 1, 2].map(|i| {
 			let header = readers[i].header();
@@ -34,7 +82,7 @@ impl Environment {
             *vk::ImageCreateInfo::builder()
 				.flags(vk::ImageCreateFlags::CUBE_COMPATIBLE)
                 .image_type(vk::ImageType::TYPE_2D)
-                .format(vk::Format::from_raw(u32::from(header.format.unwrap().0) as i32))
+                .format(formats[i])
                 .extent(*extent)
                 .mip_levels(header.level_count)
                 .array_layers(6)
@@ -46,8 +94,11 @@ impl Environment {
 		});
         let (images, allocation) = base.create_images(
             &create_infos,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            MemoryCategory::Environment
         )?;
+        let images = [0, 1, 2].map(|i| Image::new(base.clone(), images[i]));
+        let allocation = Allocation::new(base.clone(), allocation);
         //Write to images
         for i in 0..3 {
             //Read image
@@ -55,9 +106,9 @@ impl Environment {
             let header = reader.header();
             let mut texels = Vec::<u8>::new();
             let mut offsets = Vec::<usize>::new();
-            for level in reader.levels() {
+            for data in reader.levels() {
                 offsets.push(texels.len());
-                texels.extend_from_slice(level);
+                texels.extend_from_slice(data);
             }
             //Write
             let subresource_range = vk::ImageSubresourceRange::builder()
@@ -74,8 +125,8 @@ impl Environment {
                     .base_array_layer(0)
                     .layer_count(6);
                 let extent = vk::Extent3D::builder()
-                    .width(header.pixel_width >> level)
-                    .height(header.pixel_height >> level)
+                    .width(level_extent(header.pixel_width, level))
+                    .height(level_extent(header.pixel_height, level))
                     .depth(1);
                 *vk::BufferImageCopy2::builder()
                     .buffer_offset(offsets[level as usize] as u64)
@@ -86,7 +137,7 @@ impl Environment {
             //Layout
             transaction.image_write(
                 &texels,
-                images[i],
+                images[i].handle(),
                 *subresource_range,
                 &regions,
                 vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
@@ -107,14 +158,12 @@ impl Environment {
                 .base_array_layer(0)
                 .layer_count(6);
             let create_info = vk::ImageViewCreateInfo::builder()
-                .image(images[i])
+                .image(images[i].handle())
                 .view_type(vk::ImageViewType::CUBE)
                 .format(create_infos[i].format)
                 .components(*component_mapping)
                 .subresource_range(*subresource_range);
-            unsafe {
-                base.device.create_image_view(&create_info, None).unwrap()
-            }
+            ImageView::new(base.clone(), &create_info).unwrap()
 		});
         //Samplers
         let create_info = vk::SamplerCreateInfo::builder()
@@ -131,39 +180,122 @@ impl Environment {
         let descriptors = [
             *vk::DescriptorImageInfo::builder()
                 .sampler(sampler)
-                .image_view(image_views[0])
+                .image_view(image_views[0].handle())
                 .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
             *vk::DescriptorImageInfo::builder()
                 .sampler(sampler)
-                .image_view(image_views[1])
+                .image_view(image_views[1].handle())
                 .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
             *vk::DescriptorImageInfo::builder()
                 .sampler(sampler)
-                .image_view(image_views[2])
+                .image_view(image_views[2].handle())
                 .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
         ];
-		Ok(Environment{
-			base,
-			images: images.try_into().unwrap(),
-			image_views,
-            sampler,
-			allocation,
-            descriptors
+		Ok(Environment {
+            kind: EnvironmentKind::Cubemap(Cubemaps {
+                base,
+                image_views,
+                images,
+                sampler,
+                allocation,
+                descriptors
+            })
 		})
 	}
+
+    pub fn gradient(top: na::Vector3<f32>, horizon: na::Vector3<f32>, bottom: na::Vector3<f32>) -> Environment {
+        Environment {
+            kind: EnvironmentKind::Gradient {top, horizon, bottom}
+        }
+    }
+
+    ///Cubemap descriptors (skybox, diffuse & specular), unless the environment is a gradient.
+    pub fn descriptors(&self) -> Option<&[vk::DescriptorImageInfo; 3]> {
+        match &self.kind {
+            EnvironmentKind::Cubemap(cubemaps) => Some(&cubemaps.descriptors),
+            EnvironmentKind::Gradient {..} => None
+        }
+    }
+
+    ///Gradient colors for the camera uniform (top, horizon & bottom; top's w is 1 with a gradient).
+    pub(crate) fn gradient_uniform(&self) -> [na::Vector4<f32>; 3] {
+        let mut uniform = [na::Vector4::zeros(); 3];
+        if let EnvironmentKind::Gradient {top, horizon, bottom} = &self.kind {
+            for (vector, color) in uniform.iter_mut().zip([top, horizon, bottom]) {
+                *vector = color.push(0.0);
+            }
+            uniform[0][3] = 1.0;
+        }
+        uniform
+    }
 }
 
-impl Drop for Environment {
+impl Drop for Cubemaps {
     fn drop(&mut self) {
         unsafe {
             self.base.device.destroy_sampler(self.sampler, None);
-            for image_view in &self.image_views {
-                self.base.device.destroy_image_view(*image_view, None);
-            }
-            for image in &self.images {
-                self.base.device.destroy_image(*image, None);
-            }
-            self.base.device.free_memory(self.allocation, None);
         }
     }
 }
+
+///Extent of a mip level, as in KTX2 & Vulkan: the base extent halved (rounding down) per level, at least 1.
+fn level_extent(base: u32, level: u32) -> u32 {
+    (base >> level).max(1)
+}
+
+/**
+    Byte size of a KTX2 level without supercompression: every one of the `images` (faces & layers)
+    at the level's extent, in whole blocks of the format. `None` for formats without a known block size,
+    whose levels aren't validated.
+*/
+fn level_size(format: vk::Format, extent: vk::Extent3D, images: u32, level: u32) -> Option<usize> {
+    //Block width & height in texels, & bytes per block
+    let (block_width, block_height, block_size) = match format {
+        vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB
+        | vk::Format::B10G11R11_UFLOAT_PACK32 | vk::Format::E5B9G9R9_UFLOAT_PACK32
+        | vk::Format::R16G16_SFLOAT | vk::Format::R32_SFLOAT => (1, 1, 4),
+        vk::Format::R16G16B16A16_SFLOAT | vk::Format::R16G16B16A16_UNORM
+        | vk::Format::R32G32_SFLOAT => (1, 1, 8),
+        vk::Format::R32G32B32_SFLOAT => (1, 1, 12),
+        vk::Format::R32G32B32A32_SFLOAT => (1, 1, 16),
+        vk::Format::BC1_RGB_UNORM_BLOCK | vk::Format::BC1_RGB_SRGB_BLOCK
+        | vk::Format::BC1_RGBA_UNORM_BLOCK | vk::Format::BC1_RGBA_SRGB_BLOCK => (4, 4, 8),
+        vk::Format::BC6H_UFLOAT_BLOCK | vk::Format::BC6H_SFLOAT_BLOCK
+        | vk::Format::BC7_UNORM_BLOCK | vk::Format::BC7_SRGB_BLOCK => (4, 4, 16),
+        _ => return None
+    };
+    let width = level_extent(extent.width, level).div_ceil(block_width);
+    let height = level_extent(extent.height, level).div_ceil(block_height);
+    let depth = level_extent(extent.depth.max(1), level);
+    Some((width * height * depth * images) as usize * block_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn npot_level_extents() {
+        let extents: Vec<_> = (0..7).map(|level| level_extent(96, level)).collect();
+        assert_eq!(extents, [96, 48, 24, 12, 6, 3, 1]);
+        //Levels past the last stay at 1 texel
+        assert_eq!(level_extent(96, 9), 1);
+    }
+
+    #[test]
+    fn npot_level_sizes() {
+        let extent = vk::Extent3D {width: 96, height: 96, depth: 0};
+        //Cubemap of 4-byte texels
+        let sizes: Vec<_> = (0..7).map(
+            |level| level_size(vk::Format::R8G8B8A8_SRGB, extent, 6, level).unwrap()
+        ).collect();
+        assert_eq!(sizes, [96 * 96, 48 * 48, 24 * 24, 12 * 12, 6 * 6, 3 * 3, 1].map(|texels| texels * 4 * 6));
+        //Block compressed levels round up to whole 4x4 blocks (3x3 & 1x1 texels take one block)
+        let sizes: Vec<_> = (0..7).map(
+            |level| level_size(vk::Format::BC6H_UFLOAT_BLOCK, extent, 6, level).unwrap()
+        ).collect();
+        assert_eq!(sizes, [24 * 24, 12 * 12, 6 * 6, 3 * 3, 2 * 2, 1, 1].map(|blocks| blocks * 16 * 6));
+        assert_eq!(level_size(vk::Format::UNDEFINED, extent, 6, 0), None);
+    }
+}
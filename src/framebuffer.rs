@@ -1,38 +1,58 @@
 use ash::vk;
-use crate::{FRAME_COUNT, COLOR_FORMAT, DEPTH_FORMAT, SAMPLE_COUNT};
+use crate::{FRAME_COUNT, COLOR_FORMAT, DEPTH_FORMAT};
 use super::base::Base;
-use super::pipeline::PipelineLayout;
-use std::rc::Rc;
+use super::memory::{MemoryCategory, AllocationError};
+use super::pipeline::{self, PipelineLayout};
+use std::sync::Arc;
 
 pub struct Framebuffer {
-    base: Rc<Base>,
+    base: Arc<Base>,
     pub extent: vk::Extent2D,
+    pub depth_buffer: bool, //Otherwise, meshes are drawn in order without depth testing (see `Renderer::without_depth_buffer`)
+    pub samples: vk::SampleCountFlags, //Of the color & depth attachments (see `RendererOptions::samples`)
     pub render_pass: vk::RenderPass,
     pub pipelines: Vec<vk::Pipeline>,
+    pub material_pipelines: Vec<vk::Pipeline>, //Mesh pipelines with custom shaders
+    pub mesh_variants: [vk::Pipeline; pipeline::mesh::VARIANTS.len()], //Default mesh pipeline variants
+    //Wireframe overlay for each vertex format (null without barycentric support)
+    pub wireframe_variants: [vk::Pipeline; 2],
+    pub highlight_variants: [vk::Pipeline; 2], //Selection highlight for each vertex format
+    pub backface_variants: [vk::Pipeline; 2], //Back-face debug view for each vertex format
+    pub coverage_variants: [vk::Pipeline; 2], //Alpha-to-coverage for each vertex format
+    pub line_pipeline: vk::Pipeline, //Debug lines (see `Renderer::draw_lines`)
+    //Post-processing
+    pub post_render_pass: vk::RenderPass,
+    pub post_pipeline: vk::Pipeline,
+    descriptor_pool: vk::DescriptorPool,
     pub image_allocation: vk::DeviceMemory,
-    pub frames: [Frame; FRAME_COUNT]
+    pub frames: [Frame; FRAME_COUNT],
+    //Copy of the last drawn opaque scene, sampled by transmissive materials
+    pub scene_color_copy: bool, //Whether the color format supports copying into the scene color image
+    pub scene_color: vk::Image,
+    pub scene_color_view: vk::ImageView,
+    pub scene_color_descriptor: vk::DescriptorImageInfo,
+    scene_color_allocation: vk::DeviceMemory
 }
 
 ///Container for data needed to independently render a frame.
 pub struct Frame {
-    base: Rc<Base>,
+    base: Arc<Base>,
     /*
         Images:
         1. Color
         2. Resolve
-        3. Depth
+        3. Depth (null without a depth buffer)
+        4. Post-processing target
     */
-    pub images: [vk::Image; 3],
-    pub image_views: [vk::ImageView; 3],
+    pub images: [vk::Image; 4],
+    pub image_views: [vk::ImageView; 4],
+    pub sample_views: [vk::ImageView; 2], //View 0's layer of the resolve & depth images
     pub framebuffer: vk::Framebuffer,
+    pub post_framebuffer: vk::Framebuffer,
+    pub post_descriptor_set: vk::DescriptorSet,
+    pub present_descriptor_sets: [vk::DescriptorSet; 2], //Present pass sampling the resolve & post-processing images
     pub command_buffer: vk::CommandBuffer,
-    //Synchronization
-    /*
-        Semaphores:
-        1. Swapchain image acquired
-        2. Presentation
-    */
-    pub semaphores: [vk::Semaphore; 2],
+    //Synchronization (presentation semaphores belong to each window, see `PresentTarget`)
     pub fence: vk::Fence
 }
 
@@ -40,15 +60,13 @@ impl Drop for Frame {
     fn drop(&mut self) {
         unsafe {
             self.base.device.destroy_fence(self.fence, None);
-            for semaphore in self.semaphores {
-                self.base.device.destroy_semaphore(semaphore, None);
-            }
             self.base.device.free_command_buffers(
                 self.base.command_pool,
                 std::slice::from_ref(&self.command_buffer)
             );
             self.base.device.destroy_framebuffer(self.framebuffer, None);
-            for image_view in self.image_views {
+            self.base.device.destroy_framebuffer(self.post_framebuffer, None);
+            for image_view in self.image_views.into_iter().chain(self.sample_views) {
                 self.base.device.destroy_image_view(image_view, None);
             }
             for image in self.images {
@@ -60,72 +78,73 @@ impl Drop for Frame {
 
 impl Framebuffer {
     pub fn new(
-        base: Rc<Base>,
+        base: Arc<Base>,
         extent: vk::Extent2D,
-        pipeline_layouts: &[PipelineLayout]
-    ) -> Result<Self, vk::Result> {
+        view_count: u32,
+        depth_buffer: bool,
+        pipeline_layouts: &[PipelineLayout], //All built for the same sample count
+        post_layout: &PipelineLayout,
+        present_layout: &PipelineLayout
+    ) -> Result<Self, AllocationError> {
         //Render pass
-        let attachments = [
-            //Color attachment
-            *vk::AttachmentDescription::builder()
-                .format(COLOR_FORMAT)
-                .samples(SAMPLE_COUNT)
-                .load_op(vk::AttachmentLoadOp::CLEAR)
-                .store_op(vk::AttachmentStoreOp::STORE)
-                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
-                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-                .initial_layout(vk::ImageLayout::UNDEFINED)
-                .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
-            //Resolve attachment
-            *vk::AttachmentDescription::builder()
-                .format(COLOR_FORMAT)
-                .samples(vk::SampleCountFlags::TYPE_1)
-                .load_op(vk::AttachmentLoadOp::CLEAR)
-                .store_op(vk::AttachmentStoreOp::STORE)
-                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
-                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-                .initial_layout(vk::ImageLayout::UNDEFINED)
-                .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
-            //Depth attachment
-            *vk::AttachmentDescription::builder()
-                .format(DEPTH_FORMAT)
-                .samples(SAMPLE_COUNT)
-                .load_op(vk::AttachmentLoadOp::CLEAR)
-                .store_op(vk::AttachmentStoreOp::STORE)
-                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
-                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-                .initial_layout(vk::ImageLayout::UNDEFINED)
-                .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
-        ];
-        let references = [
-            *vk::AttachmentReference::builder()
-                .attachment(0)
-                .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
-            *vk::AttachmentReference::builder()
-                .attachment(1)
-                .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
-            *vk::AttachmentReference::builder()
-                .attachment(2)
-                .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
-        ];
-        let subpasses = [
-            vk::SubpassDescription::builder()
-                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-                .color_attachments(&references[0..1])
-                .resolve_attachments(&references[1..2])
-                .depth_stencil_attachment(&references[2])
-                .build()
-        ];
+        let samples = pipeline_layouts[0].samples;
+        let render_pass = create_render_pass(&base, view_count, depth_buffer, samples, vk::AttachmentLoadOp::CLEAR)?;
+        //Images of each frame: [color, resolve, depth, post-processing]
+        //Single-sampled frames draw straight into the resolve image, so they have no color image
+        let multisampled = samples != vk::SampleCountFlags::TYPE_1;
+        let allocated = [multisampled, true, depth_buffer, true];
+        let image_count = allocated.iter().filter(|&&allocated| allocated).count();
+        //Post-processing render pass
+        let attachment = vk::AttachmentDescription::builder()
+            .format(COLOR_FORMAT)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        let reference = vk::AttachmentReference::builder()
+            .attachment(0)
+            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        let subpass = vk::SubpassDescription::builder()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(std::slice::from_ref(&reference));
         let create_info = vk::RenderPassCreateInfo::builder()
-            .attachments(attachments.as_slice())
-            .subpasses(subpasses.as_slice());
-        let render_pass = unsafe {
+            .attachments(std::slice::from_ref(&attachment))
+            .subpasses(std::slice::from_ref(&subpass));
+        let post_render_pass = unsafe {
             base.device.create_render_pass(&create_info, None)?
         };
         //Pipelines
         let pipelines: Vec<vk::Pipeline> = pipeline_layouts.iter().map(
-            |layout| (layout.create_pipeline)(&layout, extent, render_pass).unwrap()
+            |layout| (layout.create_pipeline)(&layout, render_pass).unwrap()
         ).collect();
+        let post_pipeline = (post_layout.create_pipeline)(post_layout, post_render_pass)?;
+        //Post-processing & present descriptor sets
+        let pool_size = vk::DescriptorPoolSize::builder()
+            .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(3 * FRAME_COUNT as u32);
+        let create_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(3 * FRAME_COUNT as u32)
+            .pool_sizes(std::slice::from_ref(&pool_size));
+        let descriptor_pool = unsafe {
+            base.device.create_descriptor_pool(&create_info, None)
+        }?;
+        let layouts = [post_layout.descriptor_set_layout; FRAME_COUNT];
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&layouts);
+        let post_descriptor_sets = unsafe {
+            base.device.allocate_descriptor_sets(&allocate_info)
+        }?;
+        let layouts = [present_layout.descriptor_set_layout; 2 * FRAME_COUNT];
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&layouts);
+        let present_descriptor_sets = unsafe {
+            base.device.allocate_descriptor_sets(&allocate_info)
+        }?;
         //Frame images
         let extent_3d = vk::Extent3D::builder()
             .width(extent.width)
@@ -138,8 +157,8 @@ impl Framebuffer {
                 .format(COLOR_FORMAT)
                 .extent(*extent_3d)
                 .mip_levels(1)
-                .array_layers(1)
-                .samples(SAMPLE_COUNT)
+                .array_layers(view_count)
+                .samples(samples)
                 .tiling(vk::ImageTiling::OPTIMAL)
                 .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
                 .sharing_mode(vk::SharingMode::EXCLUSIVE)
@@ -150,11 +169,14 @@ impl Framebuffer {
                 .format(COLOR_FORMAT)
                 .extent(*extent_3d)
                 .mip_levels(1)
-                .array_layers(1)
+                .array_layers(view_count)
                 .samples(vk::SampleCountFlags::TYPE_1)
                 .tiling(vk::ImageTiling::OPTIMAL)
-                .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC)
-                .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                .usage(
+                    vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | vk::ImageUsageFlags::TRANSFER_SRC
+                    | vk::ImageUsageFlags::SAMPLED
+                ).sharing_mode(vk::SharingMode::EXCLUSIVE)
                 .initial_layout(vk::ImageLayout::UNDEFINED),
             //Depth image
             *vk::ImageCreateInfo::builder()
@@ -162,17 +184,80 @@ impl Framebuffer {
                 .format(DEPTH_FORMAT)
                 .extent(*extent_3d)
                 .mip_levels(1)
-                .array_layers(1)
-                .samples(SAMPLE_COUNT)
+                .array_layers(view_count)
+                .samples(samples)
                 .tiling(vk::ImageTiling::OPTIMAL)
-                .usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT)
+                .usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED)
                 .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                .initial_layout(vk::ImageLayout::UNDEFINED),
+            //Post-processing image
+            *vk::ImageCreateInfo::builder()
+                .image_type(vk::ImageType::TYPE_2D)
+                .format(COLOR_FORMAT)
+                .extent(*extent_3d)
+                .mip_levels(1)
+                .array_layers(1)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .tiling(vk::ImageTiling::OPTIMAL)
+                .usage(
+                    vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | vk::ImageUsageFlags::TRANSFER_SRC
+                    | vk::ImageUsageFlags::SAMPLED
+                ).sharing_mode(vk::SharingMode::EXCLUSIVE)
                 .initial_layout(vk::ImageLayout::UNDEFINED)
-        ].into_iter().cycle().take(3 * FRAME_COUNT).collect();
+        ].into_iter().zip(allocated).filter(|(_, allocated)| *allocated).map(
+            |(create_info, _)| create_info
+        ).cycle().take(image_count * FRAME_COUNT).collect();
         let (images, image_allocation) = base.create_images(
-            &create_infos, vk::MemoryPropertyFlags::DEVICE_LOCAL
+            &create_infos, vk::MemoryPropertyFlags::DEVICE_LOCAL, MemoryCategory::Framebuffer
+        )?;
+        let mut image_chunks = images.chunks_exact(image_count);
+        //Scene color image
+        let format_features = unsafe {
+            base.instance.get_physical_device_format_properties(base.physical_device, COLOR_FORMAT)
+        }.optimal_tiling_features;
+        let scene_color_copy = format_features.contains(
+            vk::FormatFeatureFlags::TRANSFER_SRC
+            | vk::FormatFeatureFlags::TRANSFER_DST
+            | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR
+        );
+        let create_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(COLOR_FORMAT)
+            .extent(*extent_3d)
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(if scene_color_copy {
+                vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST
+            } else {
+                vk::ImageUsageFlags::SAMPLED
+            }).sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+        let (scene_color_images, scene_color_allocation) = base.create_images(
+            std::slice::from_ref(&create_info),
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            MemoryCategory::Framebuffer
         )?;
-        let mut image_chunks = images.chunks_exact(3);
+        let scene_color = scene_color_images[0];
+        let create_info = vk::ImageViewCreateInfo::builder()
+            .image(scene_color)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(COLOR_FORMAT)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1
+            });
+        let scene_color_view = unsafe {
+            base.device.create_image_view(&create_info, None)
+        }?;
+        let scene_color_descriptor = *vk::DescriptorImageInfo::builder()
+            .image_view(scene_color_view)
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
         //Command buffers
         let alloc_info = vk::CommandBufferAllocateInfo::builder()
             .command_pool(base.command_pool)
@@ -182,8 +267,10 @@ impl Framebuffer {
         //Frames
         let frames = [0, 1].map(|i| {
             //Images
-            let chunk = image_chunks.next().unwrap();
-            let images = [chunk[0], chunk[1], chunk[2]];
+            let mut chunk = image_chunks.next().unwrap().iter();
+            let images = allocated.map(
+                |allocated| if allocated {*chunk.next().unwrap()} else {vk::Image::null()}
+            );
             //Image views
             let component_mapping = vk::ComponentMapping::builder()
                 .r(vk::ComponentSwizzle::IDENTITY)
@@ -202,22 +289,66 @@ impl Framebuffer {
                 .level_count(1)
                 .base_array_layer(0)
                 .layer_count(1);
+            //Attachment views cover every view's layer
+            let attachment_view_type = if view_count > 1 {
+                vk::ImageViewType::TYPE_2D_ARRAY
+            } else {
+                vk::ImageViewType::TYPE_2D
+            };
+            let color_layers_range = vk::ImageSubresourceRange {
+                layer_count: view_count,
+                ..*color_subresource_range
+            };
+            let depth_layers_range = vk::ImageSubresourceRange {
+                layer_count: view_count,
+                ..*depth_subresource_range
+            };
             let create_infos = [
                 //Color image view
                 vk::ImageViewCreateInfo::builder()
                     .image(images[0])
-                    .view_type(vk::ImageViewType::TYPE_2D)
+                    .view_type(attachment_view_type)
                     .format(COLOR_FORMAT)
                     .components(*component_mapping)
-                    .subresource_range(*color_subresource_range),
+                    .subresource_range(color_layers_range),
                 //Resolve image view
+                vk::ImageViewCreateInfo::builder()
+                    .image(images[1])
+                    .view_type(attachment_view_type)
+                    .format(COLOR_FORMAT)
+                    .components(*component_mapping)
+                    .subresource_range(color_layers_range),
+                //Depth image view
+                vk::ImageViewCreateInfo::builder()
+                    .image(images[2])
+                    .view_type(attachment_view_type)
+                    .format(DEPTH_FORMAT)
+                    .components(*component_mapping)
+                    .subresource_range(depth_layers_range),
+                //Post-processing image view
+                vk::ImageViewCreateInfo::builder()
+                    .image(images[3])
+                    .view_type(vk::ImageViewType::TYPE_2D)
+                    .format(COLOR_FORMAT)
+                    .components(*component_mapping)
+                    .subresource_range(*color_subresource_range)
+            ];
+            let base = base.clone();
+            let create_view = |create_info: vk::ImageViewCreateInfoBuilder| if create_info.image == vk::Image::null() {
+                vk::ImageView::null()
+            } else {
+                unsafe {&base.device.create_image_view(&create_info, None)}
+                    .expect("Image view creation error")
+            };
+            let image_views = create_infos.map(create_view);
+            //Sampled views (resolve & depth, first layer)
+            let create_infos = [
                 vk::ImageViewCreateInfo::builder()
                     .image(images[1])
                     .view_type(vk::ImageViewType::TYPE_2D)
                     .format(COLOR_FORMAT)
                     .components(*component_mapping)
                     .subresource_range(*color_subresource_range),
-                //Depth image view
                 vk::ImageViewCreateInfo::builder()
                     .image(images[2])
                     .view_type(vk::ImageViewType::TYPE_2D)
@@ -225,28 +356,69 @@ impl Framebuffer {
                     .components(*component_mapping)
                     .subresource_range(*depth_subresource_range)
             ];
-            let base = base.clone();
-            let image_views = create_infos.map(
-                |create_info| unsafe {&base.device.create_image_view(&create_info, None)}
-                    .expect("Image view creation error")
-            );
-            //Framebuffer
+            let sample_views = create_infos.map(create_view);
+            //Framebuffer (attachments in render pass order, see `create_render_pass`)
+            let attachments: Vec<_> = image_views[0..3].iter().copied().filter(
+                |view| *view != vk::ImageView::null()
+            ).collect();
             let create_info = vk::FramebufferCreateInfo::builder()
                 .render_pass(render_pass)
-                .attachments(&image_views)
+                .attachments(&attachments)
                 .width(extent.width)
                 .height(extent.height)
                 .layers(1);
             let framebuffer = unsafe {
                 base.device.create_framebuffer(&create_info, None)
             }.unwrap();
+            let create_info = vk::FramebufferCreateInfo::builder()
+                .render_pass(post_render_pass)
+                .attachments(&image_views[3..4])
+                .width(extent.width)
+                .height(extent.height)
+                .layers(1);
+            let post_framebuffer = unsafe {
+                base.device.create_framebuffer(&create_info, None)
+            }.unwrap();
+            //Post-processing descriptor set (samples the resolve image)
+            let post_descriptor_set = post_descriptor_sets[i as usize];
+            let image_info = vk::DescriptorImageInfo::builder()
+                .image_view(sample_views[0])
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+            //Present descriptor sets (sample the resolve or post-processing image)
+            let present_descriptor_sets = [
+                present_descriptor_sets[2 * i as usize],
+                present_descriptor_sets[2 * i as usize + 1]
+            ];
+            let present_image_infos = [sample_views[0], image_views[3]].map(
+                |image_view| *vk::DescriptorImageInfo::builder()
+                    .image_view(image_view)
+                    .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            );
+            let writes = [
+                *vk::WriteDescriptorSet::builder()
+                    .dst_set(post_descriptor_set)
+                    .dst_binding(0)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(std::slice::from_ref(&image_info)),
+                *vk::WriteDescriptorSet::builder()
+                    .dst_set(present_descriptor_sets[0])
+                    .dst_binding(0)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(std::slice::from_ref(&present_image_infos[0])),
+                *vk::WriteDescriptorSet::builder()
+                    .dst_set(present_descriptor_sets[1])
+                    .dst_binding(0)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(std::slice::from_ref(&present_image_infos[1]))
+            ];
+            unsafe {
+                base.device.update_descriptor_sets(&writes, &[]);
+            }
             //Command buffer
             let command_buffer = command_buffers[i as usize];
-            //Semaphores
-            let semaphores = [vk::SemaphoreCreateInfo::default(); 2].map(
-                |create_info| unsafe {base.device.create_semaphore(&create_info, None)}
-                    .expect("Semaphore creation error")
-            );
             //Fence
             let create_info = vk::FenceCreateInfo::builder()
                 .flags(vk::FenceCreateFlags::SIGNALED);
@@ -257,31 +429,166 @@ impl Framebuffer {
                 base,
                 images,
                 image_views,
+                sample_views,
                 framebuffer,
+                post_framebuffer,
+                post_descriptor_set,
+                present_descriptor_sets,
                 command_buffer,
-                semaphores,
                 fence
             }
         });
+        let wireframe_variants = if base.fragment_shader_barycentric {
+            pipeline::mesh::create_wireframe_variants(&pipeline_layouts[0], render_pass)?
+        } else {
+            [vk::Pipeline::null(); 2]
+        };
+        let highlight_variants = pipeline::mesh::create_highlight_variants(&pipeline_layouts[0], render_pass)?;
+        let backface_variants = pipeline::mesh::create_backface_variants(&pipeline_layouts[0], render_pass)?;
+        let coverage_variants = pipeline::mesh::create_coverage_variants(&pipeline_layouts[0], render_pass)?;
+        let line_pipeline = pipeline::billboard::create_line_pipeline(&pipeline_layouts[2], render_pass)?;
         Ok(Self {
             base,
             extent,
+            depth_buffer,
+            samples,
             render_pass,
             pipelines,
+            material_pipelines: vec![],
+            mesh_variants: pipeline::mesh::create_variants(&pipeline_layouts[0], render_pass)?,
+            wireframe_variants,
+            highlight_variants,
+            backface_variants,
+            coverage_variants,
+            line_pipeline,
+            post_render_pass,
+            post_pipeline,
+            descriptor_pool,
             image_allocation,
-            frames
+            frames,
+            scene_color_copy,
+            scene_color,
+            scene_color_view,
+            scene_color_descriptor,
+            scene_color_allocation
         })
     }
 }
 
+impl Framebuffer {
+    ///Replace the main render pass with one loading the color & resolve attachments with `color_load`.
+    ///The render pass must not be in use by the device.
+    pub fn set_color_load(&mut self, view_count: u32, color_load: vk::AttachmentLoadOp) -> Result<(), vk::Result> {
+        let render_pass = create_render_pass(&self.base, view_count, self.depth_buffer, self.samples, color_load)?;
+        unsafe {
+            self.base.device.destroy_render_pass(self.render_pass, None);
+        }
+        self.render_pass = render_pass;
+        Ok(())
+    }
+}
+
 impl Drop for Framebuffer {
     fn drop(&mut self) {
         unsafe {
             self.base.device.destroy_render_pass(self.render_pass, None);
-            for pipeline in &self.pipelines {
+            self.base.device.destroy_render_pass(self.post_render_pass, None);
+            let variants = self.mesh_variants.iter()
+                .chain(&self.wireframe_variants)
+                .chain(&self.highlight_variants)
+                .chain(&self.backface_variants)
+                .chain(&self.coverage_variants)
+                .chain(std::slice::from_ref(&self.line_pipeline));
+            for pipeline in self.pipelines.iter().chain(&self.material_pipelines).chain(variants) {
                 self.base.device.destroy_pipeline(*pipeline, None);
             }
-            self.base.device.free_memory(self.image_allocation, None);
+            self.base.device.destroy_pipeline(self.post_pipeline, None);
+            self.base.device.destroy_descriptor_pool(self.descriptor_pool, None);
+            self.base.free_memory(self.image_allocation);
+            self.base.device.destroy_image_view(self.scene_color_view, None);
+            self.base.device.destroy_image(self.scene_color, None);
+            self.base.free_memory(self.scene_color_allocation);
         }
     }
 }
+
+/**
+    Create the main render pass, drawing every view into the color, resolve & (optionally) depth attachments.
+    Single-sampled passes draw straight into the resolve image, so their attachments are [resolve, depth].
+    The color load operation doesn't affect render pass compatibility,
+    so pipelines & framebuffers work with render passes differing only in it.
+*/
+pub fn create_render_pass(
+    base: &Base,
+    view_count: u32,
+    depth_buffer: bool,
+    samples: vk::SampleCountFlags,
+    color_load: vk::AttachmentLoadOp
+) -> Result<vk::RenderPass, vk::Result> {
+    let multisampled = samples != vk::SampleCountFlags::TYPE_1;
+    let color = *vk::AttachmentDescription::builder()
+        .format(COLOR_FORMAT)
+        .samples(samples)
+        .load_op(color_load)
+        //Only read by the resolve at the end of the subpass, after everything (including the skybox) is drawn
+        .store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+    let resolve = *vk::AttachmentDescription::builder()
+        .format(COLOR_FORMAT)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .load_op(color_load) //Fully overwritten by resolving, so it can skip the clear along with color
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+    let depth = *vk::AttachmentDescription::builder()
+        .format(DEPTH_FORMAT)
+        .samples(samples)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+    let mut attachments = if multisampled {vec![color, resolve]} else {vec![resolve]};
+    let color_references = [
+        *vk::AttachmentReference::builder()
+            .attachment(0)
+            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
+        *vk::AttachmentReference::builder()
+            .attachment(1)
+            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+    ];
+    let depth_reference = vk::AttachmentReference::builder()
+        .attachment(attachments.len() as u32)
+        .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+    let mut subpass = vk::SubpassDescription::builder()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .color_attachments(&color_references[0..1]);
+    if multisampled {
+        subpass = subpass.resolve_attachments(&color_references[1..2]);
+    }
+    //Pipelines ignore their depth state in subpasses without a depth attachment
+    if depth_buffer {
+        subpass = subpass.depth_stencil_attachment(&depth_reference);
+        attachments.push(depth);
+    }
+    //Draw every view in one pass, with views likely to overlap
+    let view_mask = (1 << view_count) - 1;
+    let mut multiview_info = vk::RenderPassMultiviewCreateInfo::builder()
+        .view_masks(std::slice::from_ref(&view_mask))
+        .correlation_masks(std::slice::from_ref(&view_mask));
+    let mut create_info = vk::RenderPassCreateInfo::builder()
+        .attachments(&attachments)
+        .subpasses(std::slice::from_ref(&subpass));
+    if view_count > 1 {
+        create_info = create_info.push_next(&mut multiview_info);
+    }
+    unsafe {
+        base.device.create_render_pass(&create_info, None)
+    }
+}
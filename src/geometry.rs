@@ -0,0 +1,113 @@
+use ash::vk;
+use nalgebra as na;
+
+use super::device_scene::{DeviceMesh, MorphDelta};
+use super::scene::{Primitive, Vertex};
+
+///Location of a packed primitive in the shared vertex & index buffers.
+#[derive(Clone, Copy, Debug)]
+pub struct PackedPrimitive {
+    pub first_index: u32,
+    pub index_count: u32,
+    pub vertex_offset: i32, //Added to each index (indices are relative to the primitive)
+    pub material: u32
+}
+
+impl PackedPrimitive {
+    ///Single-instance indirect draw of the primitive.
+    pub fn draw_command(&self) -> vk::DrawIndexedIndirectCommand {
+        *vk::DrawIndexedIndirectCommand::builder()
+            .index_count(self.index_count)
+            .instance_count(1)
+            .first_index(self.first_index)
+            .vertex_offset(self.vertex_offset)
+            .first_instance(0)
+    }
+}
+
+/**
+    Packs primitives into one vertex & one index buffer, as scenes are uploaded.
+    All geometry is bound once at offset 0; each primitive is drawn with
+    the `first_index` & `vertex_offset` returned by `push_primitive`.
+*/
+#[derive(Default)]
+pub struct GeometryBuilder {
+    vertices: Vec<Vertex>,
+    indices: Vec<u16>,
+    pub(crate) meshes: Vec<DeviceMesh>, //Bounds & morph targets of each primitive
+    pub(crate) morph_deltas: Vec<MorphDelta>
+}
+
+impl GeometryBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///Append a primitive's geometry, returning where it was placed.
+    pub fn push_primitive(&mut self, primitive: &Primitive) -> PackedPrimitive {
+        let packed = PackedPrimitive {
+            first_index: self.indices.len() as u32,
+            index_count: primitive.indices.len() as u32,
+            vertex_offset: self.vertices.len() as i32,
+            material: primitive.material
+        };
+        let morph_base = self.morph_deltas.len() as i32 - self.vertices.len() as i32;
+        self.vertices.extend_from_slice(&primitive.vertices);
+        self.indices.extend_from_slice(&primitive.indices);
+        //Bounds
+        let mut lower_bounds = na::Point3::<f32>::new(f32::MAX, f32::MAX, f32::MAX);
+        let mut upper_bounds = na::Point3::<f32>::new(f32::MIN, f32::MIN, f32::MIN);
+        for vertex in &primitive.vertices {
+            lower_bounds.x = lower_bounds.x.min(vertex.pos.x);
+            lower_bounds.y = lower_bounds.y.min(vertex.pos.y);
+            lower_bounds.z = lower_bounds.z.min(vertex.pos.z);
+            upper_bounds.x = upper_bounds.x.max(vertex.pos.x);
+            upper_bounds.y = upper_bounds.y.max(vertex.pos.y);
+            upper_bounds.z = upper_bounds.z.max(vertex.pos.z);
+        }
+        //Sphere around the box's center, enclosing only the vertices
+        let center = na::center(&lower_bounds, &upper_bounds);
+        let mut radius = primitive.vertices.iter().map(
+            |vertex| (vertex.pos - center.coords).norm()
+        ).fold(0.0, f32::max);
+        //Morph targets, growing the bounds by their largest displacements (for weights in [0, 1])
+        for target in &primitive.morph_targets {
+            let mut lower_delta = na::Vector3::<f32>::zeros();
+            let mut upper_delta = na::Vector3::<f32>::zeros();
+            let mut max_delta = 0.0f32;
+            for i in 0..primitive.vertices.len() {
+                let pos = target.positions.get(i).copied().unwrap_or_default();
+                let normal = target.normals.get(i).copied().unwrap_or_default();
+                lower_delta = lower_delta.inf(&pos);
+                upper_delta = upper_delta.sup(&pos);
+                max_delta = max_delta.max(pos.norm());
+                self.morph_deltas.push(MorphDelta {
+                    pos: pos.push(0.0),
+                    normal: normal.push(0.0)
+                });
+            }
+            lower_bounds += lower_delta;
+            upper_bounds += upper_delta;
+            radius += max_delta;
+        }
+        //Device mesh
+        self.meshes.push(DeviceMesh {
+            upper_bounds: upper_bounds.into(),
+            lower_bounds: lower_bounds.into(),
+            bounding_sphere: center.coords.push(radius),
+            material: primitive.material,
+            morph_base,
+            morph_count: primitive.morph_targets.len() as u32,
+            vertex_count: primitive.vertices.len() as u32
+        });
+        packed
+    }
+
+    pub fn vertices(&self) -> &[Vertex] {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &[u16] {
+        &self.indices
+    }
+}
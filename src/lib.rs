@@ -1,83 +1,583 @@
 use ash::vk;
+use nalgebra as na;
 
 use base::Base;
+use async_compute::AsyncCompute;
 use framebuffer::Framebuffer;
-use swapchain::Swapchain;
+use depth_pyramid::DepthPyramid;
+use depth_resolve::DepthResolve;
+use present_target::PresentTarget;
 use transfer::Transfer;
 use transfer::transaction::Transaction;
 use pipeline::PipelineLayout;
-use scene_set::SceneSet;
-use scene::PointLight;
+use scene_set::{SceneSet, DeviceLight, CullStats, CameraUniform, ViewUniform, LIGHTS_HEADER_SIZE};
+use device_scene::DeviceScene;
+use shadow_atlas::{ShadowAtlas, MAX_SHADOW_CASTERS};
+use scene::{Scene, Billboard, DebugLine, VertexFormat};
+use environment::Environment;
+use viewer::Viewer;
+use sampler::TextureFiltering;
+use quality::{QualityPreset, QualitySettings};
+use memory::{MemoryCategory, MemoryUsage, AllocationError};
+use barrier::{ImageTracker, ImageState};
 
-use std::rc::Rc;
+use std::sync::Arc;
 use std::cell::RefCell;
+use std::time::{Duration, Instant};
 
 pub mod scene;
+pub mod animation;
+pub mod camera;
+pub mod geometry;
 pub mod scene_set;
 pub mod environment;
+pub mod sampler;
+pub mod quality;
+pub mod memory;
+pub mod viewer;
 mod base;
+mod async_compute;
 mod transfer;
 mod framebuffer;
+mod depth_pyramid;
+mod depth_resolve;
 mod swapchain;
-mod camera;
+mod present_target;
 mod device_scene;
+mod shadow_atlas;
 mod pipeline;
+mod resource;
+mod barrier;
 
 pub const FRAME_COUNT: usize = 2;
 pub const COLOR_FORMAT: vk::Format = vk::Format::B8G8R8A8_SRGB;
+pub const SURFACE_FORMAT: vk::SurfaceFormatKHR = vk::SurfaceFormatKHR {
+    format: vk::Format::B8G8R8A8_SRGB,
+    color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR
+};
 pub const DEPTH_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
-pub const SAMPLE_COUNT: vk::SampleCountFlags = vk::SampleCountFlags::TYPE_4;
+pub const DEFAULT_SAMPLE_COUNT: vk::SampleCountFlags = vk::SampleCountFlags::TYPE_4; //See `RendererOptions::samples`
 pub const MAX_TEXTURES: usize = 64;
-pub const MAX_LIGHTS: usize = 64;
+pub const DEFAULT_LIGHT_CAPACITY: usize = 64; //Lights per scene set (see `SceneSet::with_light_capacity`)
+pub const MAX_BILLBOARDS: usize = 16384; //Per frame
+pub const MAX_DEBUG_LINES: usize = 16384; //Per frame
+pub const MAX_VIEWS: usize = 4;
+pub const MAX_MORPH_TARGETS: usize = 8; //Per primitive, further targets are ignored
+pub const MAX_USER_UNIFORM_SIZE: usize = 256; //Bytes (see `SceneSet::set_user_uniform`)
 pub const TIMEOUT: u64 = 1_000_000_000;
+pub const PRESENT_STATS_WINDOW: usize = 60; //Frames averaged by `PresentStats`
+///Extent drawn at a render scale of 1 (see `Renderer::set_render_scale`).
+pub const BASE_RENDER_EXTENT: vk::Extent2D = vk::Extent2D {width: 1024, height: 1024};
+pub const MIN_RENDER_SCALE: f32 = 0.25;
+pub const MAX_RENDER_SCALE: f32 = 2.0;
+pub const MAX_SUPERSAMPLING: u32 = 4; //Per axis (see `Renderer::set_supersampling`)
 
+///Identifies the shaders used to draw a scene's meshes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct ShaderId(usize);
+
+impl ShaderId {
+    ///The bundled PBR shaders.
+    pub const DEFAULT: ShaderId = ShaderId(0);
+}
+
+/**
+    How resources uploaded on the transfer queue are shared with the graphics queue.
+    Only applies when the transfer & graphics queue families differ.
+*/
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ResourceSharing {
+    ///Owned by one queue family at a time, transferred by the upload barriers
+    #[default]
+    Exclusive,
+    ///Accessible from both queue families without ownership transfers (may be slower)
+    Concurrent
+}
+
+///Reasons `Renderer::reload_shaders` can fail.
+#[cfg(feature = "hot-reload")]
+#[derive(Debug)]
+pub enum ShaderReloadError {
+    ///Compiler messages for every shader that failed to compile
+    Compile(String),
+    Vulkan(vk::Result)
+}
+
+/*
+    Built-in pipelines (see `Renderer::reload_shaders`), destroyed on drop:
+    the new pipelines if building them fails, or the ones they replaced.
+    Null until built (& wireframe variants without barycentric support).
+*/
+#[cfg(feature = "hot-reload")]
+struct BuiltinPipelines {
+    base: Arc<Base>,
+    builtin: [vk::Pipeline; 3], //Mesh, skybox & billboard (see `Framebuffer::pipelines`)
+    post: vk::Pipeline,
+    cull: vk::Pipeline,
+    hiz: vk::Pipeline,
+    present: Vec<vk::Pipeline>, //Each window's
+    line: vk::Pipeline,
+    mesh_variants: [vk::Pipeline; pipeline::mesh::VARIANTS.len()],
+    shadow: [vk::Pipeline; 2],
+    wireframe_variants: [vk::Pipeline; 2],
+    highlight_variants: [vk::Pipeline; 2],
+    backface_variants: [vk::Pipeline; 2],
+    coverage_variants: [vk::Pipeline; 2]
+}
+
+#[cfg(feature = "hot-reload")]
+impl BuiltinPipelines {
+    fn new(base: Arc<Base>) -> Self {
+        Self {
+            base,
+            builtin: [vk::Pipeline::null(); 3],
+            post: vk::Pipeline::null(),
+            cull: vk::Pipeline::null(),
+            hiz: vk::Pipeline::null(),
+            present: vec![],
+            line: vk::Pipeline::null(),
+            mesh_variants: [vk::Pipeline::null(); pipeline::mesh::VARIANTS.len()],
+            shadow: [vk::Pipeline::null(); 2],
+            wireframe_variants: [vk::Pipeline::null(); 2],
+            highlight_variants: [vk::Pipeline::null(); 2],
+            backface_variants: [vk::Pipeline::null(); 2],
+            coverage_variants: [vk::Pipeline::null(); 2]
+        }
+    }
+}
+
+#[cfg(feature = "hot-reload")]
+impl Drop for BuiltinPipelines {
+    fn drop(&mut self) {
+        let pipelines = self.builtin.into_iter()
+            .chain([self.post, self.cull, self.hiz, self.line])
+            .chain(self.present.drain(..))
+            .chain(self.mesh_variants)
+            .chain(self.shadow)
+            .chain(self.wireframe_variants)
+            .chain(self.highlight_variants)
+            .chain(self.backface_variants)
+            .chain(self.coverage_variants);
+        for pipeline in pipelines {
+            unsafe {self.base.device.destroy_pipeline(pipeline, None);}
+        }
+    }
+}
+
+///Reasons `Renderer::capture_depth` can fail.
+#[derive(Clone, Debug)]
+pub enum DepthCaptureError {
+    ///No frame was drawn with a depth buffer since the framebuffer was created or recreated
+    NoDepthFrame,
+    Allocation(AllocationError)
+}
+
+impl From<AllocationError> for DepthCaptureError {
+    fn from(error: AllocationError) -> Self {
+        Self::Allocation(error)
+    }
+}
+
+impl From<vk::Result> for DepthCaptureError {
+    fn from(result: vk::Result) -> Self {
+        Self::Allocation(result.into())
+    }
+}
+
+impl std::fmt::Display for DepthCaptureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::NoDepthFrame => write!(f, "No frame was drawn with a depth buffer"),
+            Self::Allocation(error) => error.fmt(f)
+        }
+    }
+}
+
+impl std::error::Error for DepthCaptureError {}
+
+/**
+    Depth bias applied to a scene's meshes (see `SceneSet::set_scene_depth_bias`),
+    added to depth as `constant * r + slope * max_slope`,
+    where `r` is the smallest resolvable depth difference & `max_slope` the polygon's depth slope.
+    Depth increases away from the camera, so negative factors pull geometry forward.
+    Typical values:
+    * Decals & coplanar overlays: constant -1 to -4, slope -1 to -2
+    * Shadow map casters: constant 1 to 2, slope 1.5 to 2
+*/
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct DepthBias {
+    pub constant: f32,
+    pub slope: f32
+}
+
+/**
+    Diagnostic shading of default-shaded scenes (see `Renderer::set_debug_view`).
+    * `Shaded`: normal rendering.
+    * `Backfaces`: back faces, which are otherwise culled, are drawn magenta (darker at grazing angles)
+      over the normally shaded front faces, exposing inverted or mis-wound geometry (e.g. from imports).
+      Front faces of mirrored nodes (negative determinant) are clockwise, as when culling.
+*/
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum DebugView {
+    #[default]
+    Shaded,
+    Backfaces
+}
+
+///Optional device features, as enabled when creating the renderer (see `Renderer::capabilities`).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Capabilities {
+    pub draw_indirect_count: bool, //Culling compacts draws (otherwise culled draws are empty)
+    pub draw_indirect_first_instance: bool, //Instance-rate node attributes (see `pipeline::mesh::NODE_BINDING`)
+    pub fragment_shader_barycentric: bool, //Wireframe overlays
+    pub wide_lines: bool, //Line widths other than 1 (see `Renderer::set_line_style`)
+    pub non_uniform_indexing: bool, //Custom shaders may index textures with `nonuniformEXT`
+    pub async_compute: bool //A second graphics queue is available (see `Renderer::set_async_compute`)
+}
+
+///How the main render pass initializes its color attachment each frame (see `AttachmentLoads`).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ColorLoad {
+    Clear([f32; 4]), //Linear color
+    /**
+        Leave the previous contents undefined, saving the clear's bandwidth (notably on tiled GPUs).
+        Only valid if every pixel is drawn each frame, e.g. by the skybox or a gradient environment
+        (which aren't drawn without a depth buffer) or opaque geometry covering the view.
+    */
+    DontCare
+}
+
+/**
+    Options fixed at renderer creation (see `Renderer::with_options`).
+    Defaults to one view with a depth buffer, `DEFAULT_SAMPLE_COUNT` samples, no quality preset & an empty pipeline cache.
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct RendererOptions<'a> {
+    pub view_count: usize, //See `Renderer::with_views`
+    pub depth_buffer: bool, //See `Renderer::without_depth_buffer`
+    /**
+        MSAA samples of the color & depth attachments, lowered to the highest count the device supports
+        (e.g. `TYPE_1` to rely on FXAA or supersampling instead, see `Renderer::set_fxaa`).
+    */
+    pub samples: vk::SampleCountFlags,
+    pub quality: Option<QualityPreset>, //Also selects the sample count, replacing `samples`
+    pub pipeline_cache: Option<&'a [u8]> //See `Renderer::with_pipeline_cache`
+}
+
+impl Default for RendererOptions<'_> {
+    fn default() -> Self {
+        Self {
+            view_count: 1,
+            depth_buffer: true,
+            samples: DEFAULT_SAMPLE_COUNT,
+            quality: None,
+            pipeline_cache: None
+        }
+    }
+}
+
+/**
+    Initialization of the main render pass's attachments (see `Renderer::set_attachment_loads`).
+    Defaults to clearing color to opaque black & depth to 1.
+
+    The depth attachment is always cleared: depth testing needs a known starting value,
+    & building the depth pyramid leaves the depth image in a sampled layout.
+    Loading (preserving) attachments isn't offered, since each frame slot has its own images,
+    so a loaded image would hold the frame drawn `FRAME_COUNT` frames earlier;
+    effects needing the previous frame can sample the scene color copy instead
+    (as transmissive materials do).
+*/
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AttachmentLoads {
+    pub color: ColorLoad,
+    pub clear_depth: f32
+}
+
+impl Default for AttachmentLoads {
+    fn default() -> Self {
+        Self {
+            color: ColorLoad::Clear([0.0, 0.0, 0.0, 1.0]),
+            clear_depth: 1.0
+        }
+    }
+}
+
+impl AttachmentLoads {
+    ///Load operation of the color & resolve attachments.
+    pub(crate) fn color_load_op(&self) -> vk::AttachmentLoadOp {
+        match self.color {
+            ColorLoad::Clear(_) => vk::AttachmentLoadOp::CLEAR,
+            ColorLoad::DontCare => vk::AttachmentLoadOp::DONT_CARE
+        }
+    }
+}
+
+/**
+    State of a frame's main render pass, passed to the frame hook (see `Renderer::set_frame_hook`).
+    Hook pipelines must be created for subpass 0 of `render_pass` with `samples` samples.
+*/
+pub struct FrameContext {
+    pub render_pass: vk::RenderPass,
+    pub extent: vk::Extent2D,
+    pub samples: vk::SampleCountFlags,
+    pub view_count: usize, //Draws are broadcast to every view (multiview) when above 1
+    pub depth_buffer: bool, //Whether the subpass has a depth attachment
+    pub frame: usize, //Frame slot (< FRAME_COUNT) whose previous submission has completed
+    pub camera_descriptor: vk::DescriptorBufferInfo //The frame's camera uniform (see `register_material_shader`)
+}
+
+type FrameHook = Box<dyn FnMut(vk::CommandBuffer, &FrameContext) + Send>;
+
+///CPU time spent blocked around presentation in a `Renderer::draw` call.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PresentTimes {
+    pub fence_wait: Duration, //Waiting for the frame slot's previous submission
+    pub acquire: Duration, //Acquiring the swapchain image (including swapchain recreation)
+    pub present: Duration //Queueing the present
+}
+
+/**
+    Presentation timing & swapchain events, measured on the CPU without GPU queries
+    (see `Renderer::present_statistics`). Long fence waits mean the GPU is the bottleneck,
+    long acquires that presentation is (e.g. waiting for vsync).
+*/
+#[derive(Clone, Debug, Default)]
+pub struct PresentStats {
+    pub last: PresentTimes,
+    pub average: PresentTimes, //Over the last `PRESENT_STATS_WINDOW` frames
+    pub frames: u64, //Frames presented
+    pub suboptimal: u64, //Acquires & presents reporting a suboptimal swapchain
+    pub dropped: u64, //Frames not presented, as the swapchain was out of date
+    history: std::collections::VecDeque<PresentTimes>
+}
+
+impl PresentStats {
+    fn record(&mut self, times: PresentTimes) {
+        if self.history.len() == PRESENT_STATS_WINDOW {
+            self.history.pop_front();
+        }
+        self.history.push_back(times);
+        let count = self.history.len() as u32;
+        self.last = times;
+        self.average = PresentTimes {
+            fence_wait: self.history.iter().map(|times| times.fence_wait).sum::<Duration>() / count,
+            acquire: self.history.iter().map(|times| times.acquire).sum::<Duration>() / count,
+            present: self.history.iter().map(|times| times.present).sum::<Duration>() / count
+        };
+        self.frames += 1;
+    }
+}
+
+impl std::fmt::Display for PresentStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Average fence wait: {:.2?}, acquire: {:.2?}, present: {:.2?}; frames: {}, suboptimal: {}, dropped: {}",
+            self.average.fence_wait,
+            self.average.acquire,
+            self.average.present,
+            self.frames,
+            self.suboptimal,
+            self.dropped
+        )
+    }
+}
+
+///Semaphores a recorded frame's graphics submission waits on & signals (see `Renderer::record_frame`).
+struct FrameSemaphores {
+    waits: Vec<vk::SemaphoreSubmitInfo>,
+    signals: Vec<vk::SemaphoreSubmitInfo>
+}
+
+///Custom mesh shaders registered with the renderer.
+struct MaterialShader {
+    name: String,
+    vertex_code: Vec<u32>,
+    fragment_code: Vec<u32>
+}
+
+/**
+    Renderers & scene sets are `Send` but not `Sync`:
+    they can be created on one thread & moved to a render thread,
+    but each must be used from a single thread at a time.
+*/
 pub struct Renderer {
-    pub base: Rc<Base>,
+    pub base: Arc<Base>,
     transfer: Transfer,
     pub transaction: RefCell<Transaction>,
     framebuffer: Framebuffer,
+    render_scale: f32, //Of `BASE_RENDER_EXTENT`
+    supersampling: u32, //Render extent multiplier per axis
+    attachment_loads: AttachmentLoads,
     //Layouts: [mesh, skybox]
-    layouts: [PipelineLayout; 2],
-    swapchain: Swapchain,
+    layouts: [PipelineLayout; 3],
+    view_count: usize,
+    shadow_atlas: ShadowAtlas,
+    windows: Vec<PresentTarget>, //Presented windows, the renderer's own first (see `add_window`)
+    last_window: u32, //First window presented to by the last frame (see `draw_to_windows`)
+    surface_format: vk::SurfaceFormatKHR, //Requested (see `set_surface_format`)
+    composite_alpha: vk::CompositeAlphaFlagsKHR, //Requested (see `set_composite_alpha`)
     //Scene data
     skybox_vertex_buffer: vk::Buffer,
     skybox_vertex_alloc: vk::DeviceMemory,
+    //Billboards queued for the next frame
+    billboards: Vec<Billboard>,
+    billboard_buffer: vk::Buffer,
+    billboard_alloc: vk::DeviceMemory,
+    billboard_descriptors: [vk::DescriptorBufferInfo; FRAME_COUNT],
+    //Debug lines queued for the next frame (drawn with the billboard layout's camera)
+    lines: Vec<DebugLine>,
+    line_buffer: vk::Buffer, //`MAX_DEBUG_LINES` lines per frame
+    line_alloc: vk::DeviceMemory,
+    line_style: (f32, DepthBias), //Width in pixels & depth bias (see `set_line_style`)
     dfg_lookup: vk::Image,
     dfg_lookup_view: vk::ImageView,
     dfg_lookup_sampler: vk::Sampler,
     dfg_lookup_alloc: vk::DeviceMemory,
     dfg_descriptor: vk::DescriptorImageInfo,
+    texture_filtering: TextureFiltering,
+    texture_sampler: vk::Sampler,
+    texture_descriptor: vk::DescriptorImageInfo,
+    descriptor_version: u64, //Incremented when resources in scene descriptor sets are replaced
     //Compute
     cull_layout: PipelineLayout,
     cull_pipeline: vk::Pipeline,
+    draw_indirect_count: bool, //Otherwise, draw all nodes with culled draws emitting no instances
+    culling: bool, //Otherwise, draw commands for every node are uploaded instead of dispatching culling
+    async_compute: Option<AsyncCompute>, //Culling on the compute queue (see `set_async_compute`)
+    flat_shading: bool, //Draw default-shaded scenes with face normals
+    debug_view: DebugView,
+    wireframe_overlay: bool,
+    wireframe_style: (na::Vector4<f32>, f32), //Edge color & thickness in pixels
+    //Occlusion culling
+    depth_pyramid: DepthPyramid,
+    hiz_layout: PipelineLayout,
+    hiz_pipeline: vk::Pipeline,
+    occlusion_view_projection: na::Matrix4<f32>, //View-projection of the depth pyramid's frame
+    occlusion_valid: bool,
+    depth_planes: Option<(f32, f32)>, //Near & far planes of the frame in the depth pyramid, if one was drawn
+    scene_color_valid: bool, //Scene color holds a previous frame, sampled by transmissive materials
+    deterministic: bool, //Don't carry results over from previous frames (see `set_deterministic`)
+    material_shaders: Vec<MaterialShader>,
+    frame_hook: Option<FrameHook>,
+    //Post-processing
+    fxaa_layout: PipelineLayout,
+    fxaa: bool,
+    //Present pass (when the drawn image can't be blitted to the swapchain; pipelines belong to each window)
+    present_layout: PipelineLayout,
+    present_stats: PresentStats,
+    fence_wait: Duration, //Of the last recorded frame
     current_frame: usize
 }
 
+//Renderers & scene sets can move to a render thread
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+    assert_send::<Renderer>();
+    assert_send::<SceneSet>();
+};
+
 impl<'a> Renderer {
-    pub fn new(window: &sdl2::video::Window) -> Result<Self, vk::Result> {
-        let base = Rc::new(Base::new(window)?);
-        let transfer = Transfer::new(base.clone())?;
+    pub fn new(window: &sdl2::video::Window) -> Result<Self, AllocationError> {
+        Self::with_views(window, 1)
+    }
+
+    /**
+        Create a renderer drawing `view_count` views (e.g. 2 for stereo) in each frame,
+        in a single multiview render pass. View 0 uses `SceneSet::camera`,
+        the others `SceneSet::extra_cameras`.
+        Only view 0 is post-processed & presented; read every view back with `render_views_to_images`.
+        Occlusion culling & transmission are limited to view 0, so nodes are only frustum culled
+        & other views blend transmissive materials instead.
+        Panics unless `1 <= view_count <= MAX_VIEWS`.
+    */
+    pub fn with_views(window: &sdl2::video::Window, view_count: usize) -> Result<Self, AllocationError> {
+        Self::with_pipeline_cache(window, view_count, None)
+    }
+
+    /**
+        Create a renderer (see `with_views`) whose pipeline cache starts from `pipeline_cache`.
+        The renderer never touches the file system; persist `pipeline_cache_data` to skip
+        pipeline compilation in later runs.
+    */
+    pub fn with_pipeline_cache(
+        window: &sdl2::video::Window,
+        view_count: usize,
+        pipeline_cache: Option<&[u8]>
+    ) -> Result<Self, AllocationError> {
+        Self::with_options(window, RendererOptions {view_count, pipeline_cache, ..Default::default()})
+    }
+
+    /**
+        Create a renderer for 2D content (e.g. sprites & UI drawn as billboards) without a depth buffer,
+        so no depth images are allocated or tested against. Later draws cover earlier ones:
+        the skybox isn't drawn, billboards are drawn over meshes, & meshes are drawn in
+        the order culling emits them (disable culling with `set_culling` to draw nodes in order).
+        Without depth, there's no occlusion culling.
+    */
+    pub fn without_depth_buffer(
+        window: &sdl2::video::Window,
+        pipeline_cache: Option<&[u8]>
+    ) -> Result<Self, AllocationError> {
+        Self::with_options(window, RendererOptions {depth_buffer: false, pipeline_cache, ..Default::default()})
+    }
+
+    /**
+        Create a renderer with options fixed at creation (see `RendererOptions`),
+        e.g. single-sampled to pair with FXAA: the render pass, pipelines & framebuffer are built for its sample count.
+        A quality preset is applied once created (see `set_quality_preset`), after its sample count replaced `samples`.
+    */
+    pub fn with_options(window: &sdl2::video::Window, options: RendererOptions) -> Result<Self, AllocationError> {
+        let RendererOptions {view_count, depth_buffer, pipeline_cache, quality, ..} = options;
+        let samples = quality.map_or(options.samples, |preset| preset.settings().samples);
+        assert!((1..=MAX_VIEWS).contains(&view_count), "View count must be between 1 & MAX_VIEWS");
+        let base = Arc::new(Base::with_pipeline_cache(window, pipeline_cache)?);
+        let transfer = Transfer::new(base.clone(), transfer::MIN_STAGING_SIZE)?;
         let transaction = RefCell::new(Transaction::new(
             base.transfer_queue_family,
             base.graphics_queue_family
         ));
-        let extent = vk::Extent2D {
-            width: 1024,
-            height: 1024
-        };
+        let extent = BASE_RENDER_EXTENT;
+        let samples = supported_samples(&base, samples, depth_buffer);
         let layouts = [
-            pipeline::mesh::create_layout(base.clone())?,
-            pipeline::skybox::create_layout(base.clone())?
+            pipeline::mesh::create_layout(base.clone(), samples)?,
+            pipeline::skybox::create_layout(base.clone(), samples)?,
+            pipeline::billboard::create_layout(base.clone(), samples)?
         ];
-        let framebuffer = Framebuffer::new(base.clone(), extent, &layouts)?;
-        let swapchain = Swapchain::new(base.clone(), None)?;
+        let fxaa_layout = pipeline::fxaa::create_layout(base.clone())?;
+        let present_layout = pipeline::present::create_layout(base.clone())?;
+        let framebuffer = Framebuffer::new(
+            base.clone(),
+            extent,
+            view_count as u32,
+            depth_buffer,
+            &layouts,
+            &fxaa_layout,
+            &present_layout
+        )?;
+        let main_window = PresentTarget::new(
+            base.clone(),
+            window.id(),
+            base.surface,
+            false,
+            SURFACE_FORMAT,
+            vk::CompositeAlphaFlagsKHR::OPAQUE,
+            &present_layout
+        )?;
+        let shadow_atlas = ShadowAtlas::new(base.clone(), &layouts[0])?;
         //Compute culling
         let cull_layout = pipeline::cull::create_layout(base.clone())?;
         let cull_pipeline = (cull_layout.create_pipeline)(
             &cull_layout,
-            vk::Extent2D::default(),
             vk::RenderPass::default()
         )?;
+        //Occlusion culling
+        let hiz_layout = pipeline::hiz::create_layout(base.clone(), samples)?;
+        let hiz_pipeline = (hiz_layout.create_pipeline)(
+            &hiz_layout,
+            vk::RenderPass::default()
+        )?;
+        let depth_pyramid = DepthPyramid::new(base.clone(), &framebuffer, &hiz_layout)?;
         //Skybox mesh
         let skybox_vertices: [f32; 3 * 14] = [
             1.0, -1.0, -1.0,
@@ -101,72 +601,43 @@ impl<'a> Renderer {
             .sharing_mode(vk::SharingMode::EXCLUSIVE);
         let (vertex_buffers, vertex_alloc) = base.create_buffers(
             std::slice::from_ref(&create_info),
-            vk::MemoryPropertyFlags::HOST_VISIBLE
+            vk::MemoryPropertyFlags::HOST_VISIBLE,
+            MemoryCategory::Environment
         )?;
         transaction.borrow_mut().buffer_write(&skybox_vertices, vertex_buffers[0], 0);
-        //DFG lookup texture
-        let dfg_lookup_bytes = include_bytes!("../assets/dfg_lut.bin");
-        let extent = vk::Extent3D::builder().width(256).height(256).depth(1);
-        let create_info = vk::ImageCreateInfo::builder()
-            .image_type(vk::ImageType::TYPE_2D)
-            .format(vk::Format::R16G16B16A16_SFLOAT)
-            .extent(*extent)
-            .mip_levels(1)
-            .array_layers(1)
-            .samples(vk::SampleCountFlags::TYPE_1)
-            .tiling(vk::ImageTiling::OPTIMAL)
-            .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST)
-            .sharing_mode(vk::SharingMode::EXCLUSIVE)
-            .initial_layout(vk::ImageLayout::UNDEFINED);
-        let (lut_images, lut_allocation) = base.create_images(
+        //Billboards
+        let billboard_size = MAX_BILLBOARDS * std::mem::size_of::<Billboard>();
+        let create_info = vk::BufferCreateInfo::builder()
+            .size((FRAME_COUNT * billboard_size) as u64)
+            .usage(vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let (billboard_buffers, billboard_alloc) = base.create_buffers(
             std::slice::from_ref(&create_info),
-            vk::MemoryPropertyFlags::DEVICE_LOCAL
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            MemoryCategory::Scenes
         )?;
-        //Write to DFG lookup texture
-        let subresource_range = vk::ImageSubresourceRange::builder()
-            .aspect_mask(vk::ImageAspectFlags::COLOR)
-            .base_mip_level(0)
-            .level_count(1)
-            .base_array_layer(0)
-            .layer_count(1);
-        let subresource = vk::ImageSubresourceLayers::builder()
-            .aspect_mask(vk::ImageAspectFlags::COLOR)
-            .mip_level(0)
-            .base_array_layer(0)
-            .layer_count(1);
-        let region = vk::BufferImageCopy2::builder()
-            .buffer_offset(0)
-            .image_subresource(*subresource)
-            .image_offset(vk::Offset3D::default())
-            .image_extent(*extent);
-        transaction.borrow_mut().image_write(
-            dfg_lookup_bytes,
-            lut_images[0],
-            *subresource_range, 
-            std::slice::from_ref(&region),
-            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        let billboard_descriptors = std::array::from_fn(|frame| *vk::DescriptorBufferInfo::builder()
+            .buffer(billboard_buffers[0])
+            .offset((frame * billboard_size) as u64)
+            .range(billboard_size as u64)
         );
-        //DGF lookup image view
-        let component_mapping = vk::ComponentMapping::builder()
-            .r(vk::ComponentSwizzle::IDENTITY)
-            .g(vk::ComponentSwizzle::IDENTITY)
-            .b(vk::ComponentSwizzle::IDENTITY)
-            .a(vk::ComponentSwizzle::IDENTITY);
-        let subresource_range = vk::ImageSubresourceRange::builder()
-            .aspect_mask(vk::ImageAspectFlags::COLOR)
-            .base_mip_level(0)
-            .level_count(1)
-            .base_array_layer(0)
-            .layer_count(1);
-        let create_info = vk::ImageViewCreateInfo::builder()
-            .image(lut_images[0])
-            .view_type(vk::ImageViewType::TYPE_2D)
-            .format(vk::Format::R16G16B16A16_SFLOAT)
-            .components(*component_mapping)
-            .subresource_range(*subresource_range);
-        let dfg_lookup_view = unsafe {
-            base.device.create_image_view(&create_info, None).unwrap()
-        };
+        //Debug lines
+        let create_info = vk::BufferCreateInfo::builder()
+            .size((FRAME_COUNT * MAX_DEBUG_LINES * std::mem::size_of::<DebugLine>()) as u64)
+            .usage(vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let (line_buffers, line_alloc) = base.create_buffers(
+            std::slice::from_ref(&create_info),
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            MemoryCategory::Scenes
+        )?;
+        //DFG lookup texture
+        let (dfg_lookup, dfg_lookup_view, dfg_lookup_alloc) = create_dfg_lookup(
+            &base,
+            &mut transaction.borrow_mut(),
+            include_bytes!("../assets/dfg_lut.bin"),
+            vk::Extent2D {width: 256, height: 256}
+        )?;
         //DFG lookup sampler
         let create_info = vk::SamplerCreateInfo::builder()
             .mag_filter(vk::Filter::LINEAR)
@@ -181,116 +652,1098 @@ impl<'a> Renderer {
             .sampler(dfg_lookup_sampler)
             .image_view(dfg_lookup_view)
             .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
-        Ok(Renderer {
+        //Indirect draw path
+        let draw_indirect_count = base.draw_indirect_count;
+        //Scene texture sampler
+        let texture_filtering = TextureFiltering::default();
+        let texture_sampler = texture_filtering.create_sampler(&base)?;
+        let texture_descriptor = *vk::DescriptorImageInfo::builder()
+            .sampler(texture_sampler);
+        let mut renderer = Renderer {
             base,
             transfer,
             transaction,
             layouts,
+            view_count,
+            shadow_atlas,
             framebuffer,
-            swapchain,
+            render_scale: 1.0,
+            supersampling: 1,
+            attachment_loads: AttachmentLoads::default(),
+            last_window: main_window.window_id,
+            windows: vec![main_window],
+            surface_format: SURFACE_FORMAT,
+            composite_alpha: vk::CompositeAlphaFlagsKHR::OPAQUE,
             skybox_vertex_buffer: vertex_buffers[0],
             skybox_vertex_alloc: vertex_alloc,
-            dfg_lookup: lut_images[0],
+            billboards: vec![],
+            billboard_buffer: billboard_buffers[0],
+            billboard_alloc,
+            billboard_descriptors,
+            lines: vec![],
+            line_buffer: line_buffers[0],
+            line_alloc,
+            line_style: (1.0, DepthBias {constant: -1.0, slope: -1.0}),
+            dfg_lookup,
             dfg_lookup_view,
             dfg_lookup_sampler,
-            dfg_lookup_alloc: lut_allocation,
+            dfg_lookup_alloc,
             dfg_descriptor,
+            texture_filtering,
+            texture_sampler,
+            texture_descriptor,
+            descriptor_version: 0,
             cull_layout,
             cull_pipeline,
+            draw_indirect_count,
+            culling: true,
+            async_compute: None,
+            flat_shading: false,
+            debug_view: DebugView::Shaded,
+            wireframe_overlay: false,
+            wireframe_style: (na::Vector4::new(1.0, 1.0, 1.0, 1.0), 1.0),
+            depth_pyramid,
+            hiz_layout,
+            hiz_pipeline,
+            occlusion_view_projection: na::Matrix4::identity(),
+            occlusion_valid: false,
+            depth_planes: None,
+            scene_color_valid: false,
+            deterministic: false,
+            material_shaders: vec![],
+            frame_hook: None,
+            fxaa_layout,
+            fxaa: false,
+            present_layout,
+            present_stats: PresentStats::default(),
+            fence_wait: Duration::ZERO,
             current_frame: 0
-        })
+        };
+        if let Some(preset) = quality {
+            renderer.set_quality_preset(preset)?;
+        }
+        Ok(renderer)
+    }
+
+    ///MSAA samples of the main render pass (fixed at creation, see `RendererOptions::samples`).
+    pub fn samples(&self) -> vk::SampleCountFlags {
+        self.framebuffer.samples
     }
 
     /**
-        Draw bound scenes.
-        The instructions proceed as follows:
-        1. Execute transfers
-        2. Acquire swapchain image
-        3. Record graphics command buffer
-            1. Update scene data
-            2. Draw scenes
-            3. Blit drawn image to swapchain image
+        Request a swapchain format & color space for every window.
+        If a surface doesn't support it, the closest supported format is used instead
+        (see `surface_format` for the format actually in use).
+        Note that the drawn image is always sRGB; it is converted to the swapchain format
+        (see `draw`), but not tonemapped for HDR color spaces.
+    */
+    pub fn set_surface_format(&mut self, format: vk::SurfaceFormatKHR) -> Result<(), vk::Result> {
+        self.surface_format = format;
+        self.recreate_swapchains()
+    }
+
+    /**
+        Request how the window system composites presented images with what's behind each window,
+        e.g. `PRE_MULTIPLIED` or `POST_MULTIPLIED` for transparent overlay windows
+        (`OPAQUE` by default; exactly one flag, see `supported_composite_alpha`).
+        If a surface doesn't support it, `OPAQUE` is used instead
+        (see `composite_alpha` for the mode actually in use).
+        Blending uses the presented alpha, which the skybox & opaque meshes write as 1,
+        so the window is only see-through where nothing is drawn over a transparent clear color
+        (see `set_attachment_loads`). The drawn colors aren't premultiplied.
+    */
+    pub fn set_composite_alpha(&mut self, composite_alpha: vk::CompositeAlphaFlagsKHR) -> Result<(), vk::Result> {
+        assert!(composite_alpha.as_raw().is_power_of_two(), "Exactly one composite alpha mode must be requested");
+        self.composite_alpha = composite_alpha;
+        self.recreate_swapchains()
+    }
+
+    ///The composite alpha mode of the renderer's window's swapchain.
+    pub fn composite_alpha(&self) -> vk::CompositeAlphaFlagsKHR {
+        self.windows[0].swapchain.composite_alpha
+    }
+
+    ///Composite alpha modes supported by the renderer's window's surface.
+    pub fn supported_composite_alpha(&self) -> Result<vk::CompositeAlphaFlagsKHR, vk::Result> {
+        let capabilities = unsafe {
+            self.base.surface_loader.get_physical_device_surface_capabilities(
+                self.base.physical_device,
+                self.windows[0].surface()
+            )
+        }?;
+        Ok(capabilities.supported_composite_alpha)
+    }
+
+    ///Recreate the swapchain of the window at `index` in `windows` (e.g. after a resize).
+    fn recreate_swapchain(&mut self, index: usize) -> Result<(), vk::Result> {
+        unsafe {self.base.device.queue_wait_idle(self.base.graphics_queue)?;}
+        self.windows[index].recreate_swapchain(self.surface_format, self.composite_alpha, &self.present_layout)
+    }
+
+    ///Recreate the swapchains of every window (e.g. after changing the requested format).
+    fn recreate_swapchains(&mut self) -> Result<(), vk::Result> {
+        unsafe {self.base.device.queue_wait_idle(self.base.graphics_queue)?;}
+        for window in &mut self.windows {
+            window.recreate_swapchain(self.surface_format, self.composite_alpha, &self.present_layout)?;
+        }
+        Ok(())
+    }
+
+    /**
+        Present to another window (e.g. a second editor viewport) from this renderer's device,
+        sharing its pipelines, framebuffer & scene sets. Draw to it with `draw_to_window` or `draw_to_windows`.
+        Its swapchain follows the requested surface format & composite alpha (see `set_surface_format`).
+        Fails with `ERROR_INCOMPATIBLE_DISPLAY_KHR` if the graphics queue can't present to the window.
+        Panics if the window was already added.
+    */
+    pub fn add_window(&mut self, window: &sdl2::video::Window) -> Result<(), vk::Result> {
+        assert!(
+            self.windows.iter().all(|target| target.window_id != window.id()),
+            "Window was already added to the renderer"
+        );
+        let surface: vk::SurfaceKHR = vk::Handle::from_raw(
+            window.vulkan_create_surface(
+                vk::Handle::as_raw(self.base.instance.handle()) as usize
+            ).map_err(|_| vk::Result::ERROR_INITIALIZATION_FAILED)?
+        );
+        let supported = unsafe {
+            self.base.surface_loader.get_physical_device_surface_support(
+                self.base.physical_device,
+                self.base.graphics_queue_family,
+                surface
+            )
+        }.unwrap_or_default();
+        if !supported {
+            unsafe {self.base.surface_loader.destroy_surface(surface, None);}
+            return Err(vk::Result::ERROR_INCOMPATIBLE_DISPLAY_KHR);
+        }
+        self.windows.push(PresentTarget::new(
+            self.base.clone(),
+            window.id(),
+            surface,
+            true,
+            self.surface_format,
+            self.composite_alpha,
+            &self.present_layout
+        )?);
+        Ok(())
+    }
+
+    /**
+        Stop presenting to a window added with `add_window` (e.g. before closing it),
+        waiting for the device to idle. Fails with `ERROR_SURFACE_LOST_KHR` for a window that wasn't added.
+        Panics for the renderer's own window.
     */
-    pub fn draw(&mut self, scene_set: &SceneSet) -> Result<(), vk::Result> {
+    pub fn remove_window(&mut self, window_id: u32) -> Result<(), vk::Result> {
+        let index = self.window_index(window_id)?;
+        assert!(index > 0, "The renderer's own window can't be removed");
+        unsafe {self.base.device.device_wait_idle()?;}
+        self.windows.remove(index);
+        Ok(())
+    }
+
+    ///SDL IDs of the windows presented to, the renderer's own first.
+    pub fn window_ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.windows.iter().map(|target| target.window_id)
+    }
+
+    /**
+        Recreate a window's swapchain for its current size (e.g. on a resize event).
+        Draws also recreate swapchains that acquisition reports as suboptimal, after presenting.
+        Fails with `ERROR_SURFACE_LOST_KHR` for a window that wasn't added.
+    */
+    pub fn resize_window(&mut self, window_id: u32) -> Result<(), vk::Result> {
+        let index = self.window_index(window_id)?;
+        self.recreate_swapchain(index)
+    }
+
+    ///Index in `windows` of the window with an SDL ID, failing with `ERROR_SURFACE_LOST_KHR` if it wasn't added.
+    fn window_index(&self, window_id: u32) -> Result<usize, vk::Result> {
+        self.windows.iter().position(|target| target.window_id == window_id)
+            .ok_or(vk::Result::ERROR_SURFACE_LOST_KHR)
+    }
+
+    /**
+        Scale the extent scenes are drawn at (`BASE_RENDER_EXTENT` at 1), trading quality for performance.
+        The swapchain is kept, so the drawn image is scaled to it when presented.
+        `scale` is clamped to [`MIN_RENDER_SCALE`, `MAX_RENDER_SCALE`].
+        Changing the scale waits for the device to idle & reallocates the framebuffer images
+        (scene sets then rewrite their descriptor sets as each frame begins), so avoid changing it every frame.
+    */
+    pub fn set_render_scale(&mut self, scale: f32) -> Result<(), AllocationError> {
+        let scale = scale.clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE);
+        if scale == self.render_scale {
+            return Ok(());
+        }
+        self.recreate_framebuffer(scaled_extent(scale, self.supersampling))?;
+        self.render_scale = scale;
+        Ok(())
+    }
+
+    /**
+        Supersample by an integer `factor` per axis: scenes are drawn at `factor` times the render scale's extent
+        (see `output_extent`) & filtered down, so every sample is shaded rather than only triangle edges.
+        The present pass samples the drawn image with a linear filter, which averages 2x2 blocks exactly
+        when the swapchain extent is half the drawn extent, & `render_once_to_image` & `render_views_to_images`
+        average `factor`x`factor` blocks (in linear space) back to the output extent, e.g. for screenshots.
+
+        Multisampling is orthogonal & fixed at creation (see `RendererOptions::samples`),
+        so create the renderer with `TYPE_1` for pure supersampling.
+        Framebuffer & depth pyramid memory grows with the square of the factor (4x for 2, 16x for 4),
+        as does shading cost. `factor` is clamped to [1, `MAX_SUPERSAMPLING`], & further so the drawn extent
+        fits `maxImageDimension2D`. Like `set_render_scale`, changing it waits for the device to idle.
+    */
+    pub fn set_supersampling(&mut self, factor: u32) -> Result<(), AllocationError> {
+        let max_dimension = self.base.physical_device_properties.limits.max_image_dimension2_d;
+        let output = scaled_extent(self.render_scale, 1);
+        let factor = factor.clamp(1, MAX_SUPERSAMPLING).min(
+            (max_dimension / output.width.max(output.height)).max(1)
+        );
+        if factor == self.supersampling {
+            return Ok(());
+        }
+        self.recreate_framebuffer(scaled_extent(self.render_scale, factor))?;
+        self.supersampling = factor;
+        Ok(())
+    }
+
+    pub fn supersampling(&self) -> u32 {
+        self.supersampling
+    }
+
+    //Wait for the device to idle & recreate the framebuffer & depth pyramid
+    fn recreate_framebuffer(&mut self, extent: vk::Extent2D) -> Result<(), AllocationError> {
+        unsafe {self.base.device.device_wait_idle()?;}
+        let mut framebuffer = Framebuffer::new(
+            self.base.clone(),
+            extent,
+            self.view_count as u32,
+            self.framebuffer.depth_buffer,
+            &self.layouts,
+            &self.fxaa_layout,
+            &self.present_layout
+        )?;
+        if self.attachment_loads.color_load_op() != vk::AttachmentLoadOp::CLEAR {
+            framebuffer.set_color_load(self.view_count as u32, self.attachment_loads.color_load_op())?;
+        }
+        self.depth_pyramid = DepthPyramid::new(self.base.clone(), &framebuffer, &self.hiz_layout)?;
+        //Custom shader pipelines stay compatible with the recreated render pass
+        framebuffer.material_pipelines = std::mem::take(&mut self.framebuffer.material_pipelines);
+        self.framebuffer = framebuffer;
+        self.descriptor_version += 1;
+        self.occlusion_valid = false;
+        self.depth_planes = None;
+        self.scene_color_valid = false;
+        Ok(())
+    }
+
+    /**
+        Set how the main render pass initializes its attachments (see `AttachmentLoads`).
+        Changing only clear values takes effect in the next frame; changing the color load operation
+        waits for the device to idle & recreates the render pass (pipelines & framebuffers are kept,
+        as load operations don't affect render pass compatibility).
+    */
+    pub fn set_attachment_loads(&mut self, loads: AttachmentLoads) -> Result<(), vk::Result> {
+        if loads.color_load_op() != self.attachment_loads.color_load_op() {
+            unsafe {self.base.device.device_wait_idle()?;}
+            self.framebuffer.set_color_load(self.view_count as u32, loads.color_load_op())?;
+        }
+        self.attachment_loads = loads;
+        Ok(())
+    }
+
+    pub fn attachment_loads(&self) -> AttachmentLoads {
+        self.attachment_loads
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    /**
+        Apply a preset's combination of render scale, texture filtering, FXAA & culling
+        (see `QualityPreset` for what each selects), validated against the device.
+        Its sample count is ignored, as it's fixed at creation (pass the preset to `with_options` instead):
+        anisotropy is clamped to `maxSamplerAnisotropy` (or disabled without `samplerAnisotropy`),
+        & the render scale is limited so the render extent fits `maxImageDimension2D`.
+        Individual settings can be overridden afterwards with their own setters.
+        Like `set_render_scale` & `set_texture_filtering`, this waits for the device to idle.
+    */
+    pub fn set_quality_preset(&mut self, preset: QualityPreset) -> Result<(), AllocationError> {
+        let settings = self.validated_quality(preset.settings());
+        self.set_render_scale(settings.render_scale)?;
+        self.set_texture_filtering(settings.texture_filtering)?;
+        self.set_fxaa(settings.fxaa);
+        self.set_culling(settings.culling);
+        Ok(())
+    }
+
+    ///Quality settings limited to what the device supports (see `set_quality_preset`).
+    pub fn validated_quality(&self, settings: QualitySettings) -> QualitySettings {
+        let limits = &self.base.physical_device_properties.limits;
+        let max_extent = BASE_RENDER_EXTENT.width.max(BASE_RENDER_EXTENT.height) as f32;
+        let anisotropy = settings.texture_filtering.anisotropy.filter(
+            |_| self.base.physical_device_features.sampler_anisotropy == vk::TRUE
+        ).map(|anisotropy| anisotropy.min(limits.max_sampler_anisotropy));
+        QualitySettings {
+            render_scale: settings.render_scale.min(
+                limits.max_image_dimension2_d as f32 / (max_extent * self.supersampling as f32)
+            ),
+            texture_filtering: TextureFiltering {anisotropy, ..settings.texture_filtering},
+            samples: supported_samples(&self.base, settings.samples, self.framebuffer.depth_buffer),
+            ..settings
+        }
+    }
+
+    ///Optional features of the device.
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            draw_indirect_count: self.base.draw_indirect_count,
+            draw_indirect_first_instance: self.base.draw_indirect_first_instance,
+            fragment_shader_barycentric: self.base.fragment_shader_barycentric,
+            wide_lines: self.base.wide_lines,
+            non_uniform_indexing: self.base.non_uniform_indexing,
+            async_compute: self.base.compute_queue.is_some()
+        }
+    }
+
+    ///Extent scenes are currently drawn at, including supersampling.
+    pub fn render_extent(&self) -> vk::Extent2D {
+        self.framebuffer.extent
+    }
+
+    ///Extent of the render scale before supersampling (that of images read back by `render_once_to_image`).
+    pub fn output_extent(&self) -> vk::Extent2D {
+        scaled_extent(self.render_scale, 1)
+    }
+
+    /**
+        Recompile the `shaders` directory and rebuild the built-in pipelines.
+        If any shader fails to compile, the current pipelines are kept
+        and the compiler messages are returned. Likewise, if any pipeline fails to build,
+        the current pipelines are kept & later pipelines still load the previous shaders.
+        Waits for the device to idle before swapping pipelines.
+        Shaders registered with `register_material_shader` are unaffected.
+    */
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_shaders(&mut self) -> Result<(), ShaderReloadError> {
+        let shaders = pipeline::compile_shaders().map_err(ShaderReloadError::Compile)?;
+        let mut pipelines = shaders.build_with(|| self.build_pipelines()).map_err(ShaderReloadError::Vulkan)?;
+        unsafe {self.base.device.device_wait_idle()}.map_err(ShaderReloadError::Vulkan)?;
+        //Swap the new pipelines in, so the replaced ones are destroyed with `pipelines`
+        for (pipeline, new) in std::iter::zip(&mut self.framebuffer.pipelines, &mut pipelines.builtin) {
+            std::mem::swap(pipeline, new);
+        }
+        std::mem::swap(&mut self.framebuffer.post_pipeline, &mut pipelines.post);
+        std::mem::swap(&mut self.cull_pipeline, &mut pipelines.cull);
+        std::mem::swap(&mut self.hiz_pipeline, &mut pipelines.hiz);
+        for (window, new) in std::iter::zip(&mut self.windows, &mut pipelines.present) {
+            std::mem::swap(&mut window.present_pipeline, new);
+        }
+        std::mem::swap(&mut self.framebuffer.line_pipeline, &mut pipelines.line);
+        std::mem::swap(&mut self.framebuffer.mesh_variants, &mut pipelines.mesh_variants);
+        pipelines.shadow = self.shadow_atlas.replace_pipelines(pipelines.shadow);
+        std::mem::swap(&mut self.framebuffer.wireframe_variants, &mut pipelines.wireframe_variants);
+        std::mem::swap(&mut self.framebuffer.highlight_variants, &mut pipelines.highlight_variants);
+        std::mem::swap(&mut self.framebuffer.backface_variants, &mut pipelines.backface_variants);
+        std::mem::swap(&mut self.framebuffer.coverage_variants, &mut pipelines.coverage_variants);
+        shaders.commit();
+        Ok(())
+    }
+
+    //Build every built-in pipeline, e.g. with reloaded shaders (see `reload_shaders`)
+    #[cfg(feature = "hot-reload")]
+    fn build_pipelines(&self) -> Result<BuiltinPipelines, vk::Result> {
+        let render_pass = self.framebuffer.render_pass;
+        let mut pipelines = BuiltinPipelines::new(self.base.clone());
+        for (pipeline, layout) in std::iter::zip(&mut pipelines.builtin, &self.layouts) {
+            *pipeline = (layout.create_pipeline)(layout, render_pass)?;
+        }
+        pipelines.post = (self.fxaa_layout.create_pipeline)(&self.fxaa_layout, self.framebuffer.post_render_pass)?;
+        pipelines.cull = (self.cull_layout.create_pipeline)(&self.cull_layout, vk::RenderPass::default())?;
+        pipelines.hiz = (self.hiz_layout.create_pipeline)(&self.hiz_layout, vk::RenderPass::default())?;
+        for window in &self.windows {
+            let pipeline = (self.present_layout.create_pipeline)(&self.present_layout, window.swapchain.render_pass)?;
+            pipelines.present.push(pipeline);
+        }
+        pipelines.line = pipeline::billboard::create_line_pipeline(&self.layouts[2], render_pass)?;
+        pipelines.mesh_variants = pipeline::mesh::create_variants(&self.layouts[0], render_pass)?;
+        pipelines.shadow = pipeline::shadow::create_pipelines(&self.layouts[0], self.shadow_atlas.render_pass())?;
+        if self.base.fragment_shader_barycentric {
+            pipelines.wireframe_variants = pipeline::mesh::create_wireframe_variants(&self.layouts[0], render_pass)?;
+        }
+        pipelines.highlight_variants = pipeline::mesh::create_highlight_variants(&self.layouts[0], render_pass)?;
+        pipelines.backface_variants = pipeline::mesh::create_backface_variants(&self.layouts[0], render_pass)?;
+        pipelines.coverage_variants = pipeline::mesh::create_coverage_variants(&self.layouts[0], render_pass)?;
+        Ok(pipelines)
+    }
+
+    /**
+        Set how scene & environment resources are shared between the transfer & graphics queues.
+        Applies to resources created after the call.
+    */
+    pub fn set_resource_sharing(&self, sharing: ResourceSharing) {
+        self.base.set_resource_sharing(sharing);
+        let concurrent = self.base.resource_sharing() == ResourceSharing::Concurrent;
+        self.transaction.borrow_mut().set_ownership_transfer(!concurrent);
+    }
+
+    ///Device memory currently held by the renderer, by category & by heap.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.base.memory_usage()
+    }
+
+    ///Current pipeline cache contents (see `with_pipeline_cache`).
+    pub fn pipeline_cache_data(&self) -> Result<Vec<u8>, vk::Result> {
+        self.base.pipeline_cache_data()
+    }
+
+    ///Number of views drawn each frame.
+    pub fn view_count(&self) -> usize {
+        self.view_count
+    }
+
+    ///The surface format & color space of the renderer's window's swapchain.
+    pub fn surface_format(&self) -> vk::SurfaceFormatKHR {
+        self.windows[0].swapchain.format
+    }
+
+    /**
+        Override the filtering of all scene textures.
+        This waits for the device to idle before replacing the sampler;
+        scene sets then rewrite their descriptor sets as each frame begins.
+        Avoid calling this every frame.
+    */
+    pub fn set_texture_filtering(&mut self, filtering: TextureFiltering) -> Result<(), vk::Result> {
+        let sampler = filtering.create_sampler(&self.base)?;
+        unsafe {
+            self.base.device.device_wait_idle()?;
+            self.base.device.destroy_sampler(self.texture_sampler, None);
+        }
+        self.texture_filtering = filtering;
+        self.texture_sampler = sampler;
+        self.texture_descriptor.sampler = sampler;
+        self.descriptor_version += 1;
+        Ok(())
+    }
+
+    pub fn texture_filtering(&self) -> TextureFiltering {
+        self.texture_filtering
+    }
+
+    /**
+        Set the mip LOD bias of scene textures, keeping the rest of the filtering.
+        A negative bias (e.g. -0.5 to -1) sharpens textures when a render scale below 1 is upscaled
+        (see `set_render_scale`), at the cost of some shimmering. Clamped to `maxSamplerLodBias`.
+        The texture sampler isn't immutable, so only the sampler is recreated (no layouts or pipelines),
+        but as with `set_texture_filtering` this waits for the device to idle
+        & scene sets rewrite their descriptor sets, so avoid calling it every frame.
+    */
+    pub fn set_texture_lod_bias(&mut self, bias: f32) -> Result<(), vk::Result> {
+        self.set_texture_filtering(TextureFiltering {lod_bias: bias, ..self.texture_filtering})
+    }
+
+    /**
+        Replace the DFG lookup table used for image-based specular lighting
+        (`R16G16B16A16_SFLOAT` texels, indexed by N·V horizontally & roughness vertically).
+        Like `set_texture_filtering`, this waits for the device to idle.
+        Panics if `data` doesn't hold exactly `extent` texels.
+    */
+    pub fn set_dfg_lut(&mut self, data: &[u8], extent: vk::Extent2D) -> Result<(), AllocationError> {
+        assert_eq!(
+            data.len(),
+            (extent.width * extent.height) as usize * DFG_TEXEL_SIZE,
+            "DFG lookup table size doesn't match its extent"
+        );
+        let (image, view, alloc) = create_dfg_lookup(
+            &self.base,
+            &mut self.transaction.borrow_mut(),
+            data,
+            extent
+        )?;
+        unsafe {
+            self.base.device.device_wait_idle()?;
+            self.base.device.destroy_image_view(self.dfg_lookup_view, None);
+            self.base.device.destroy_image(self.dfg_lookup, None);
+            self.base.free_memory(self.dfg_lookup_alloc);
+        }
+        self.dfg_lookup = image;
+        self.dfg_lookup_view = view;
+        self.dfg_lookup_alloc = alloc;
+        self.dfg_descriptor.image_view = view;
+        self.descriptor_version += 1;
+        Ok(())
+    }
+
+    /**
+        Register a custom shader pair (SPIR-V) for drawing meshes,
+        and assign it to scenes with `SceneSet::set_scene_shader`.
+        Fails with `ERROR_INVALID_SHADER_NV` if either shader isn't valid SPIR-V (e.g. a misaligned length).
+        Custom shaders must follow the same interface as `pbr.vert` & `pbr.frag`:
+        * Vertex inputs: position (location 0, vec3), normal (1, vec3), texture coordinates (2, vec2);
+          scenes drawn with custom shaders must use `VertexFormat::Full`.
+          The node transform is also an instance-rate input (5, mat4), valid with
+          `Capabilities::draw_indirect_first_instance` (see `pipeline::mesh::NODE_BINDING`)
+        * Descriptor set 0:
+          0. Camera uniform {mat4 view; mat4 projection; vec4 camera_pos; mat4 environment; uint scene_color_valid; uint ibl_specular; uint view_count; View views[MAX_VIEWS]; vec4 sky_top; vec4 sky_horizon; vec4 sky_bottom; vec4 environment_color;}
+          (`View` is {mat4 view; mat4 projection; vec4 camera_pos;}; the leading camera fields are view 0's)
+          (`environment_color` is the environment tint times intensity, see `SceneSet::set_environment_intensity`)
+          1. Meshes (storage buffer)
+          2. Materials (storage buffer)
+          3. Nodes (storage buffer)
+          4. Draw command extras (storage buffer, indexed by `gl_DrawID`)
+          5. Texture sampler
+          6. Textures (`MAX_TEXTURES` sampled images; indexing them non-uniformly within a draw,
+             with `nonuniformEXT`, requires `Capabilities::non_uniform_indexing`)
+          7. Point lights (storage buffer of {uint light_count; PointLight lights[];},
+             with `PointLight` {vec4 pos; vec4 color; vec4 shadow_rect; float intensity; float range; uint casts_shadow;})
+          8. Irradiance & pre-filtered environment cubemaps
+             (partially bound: unwritten with gradient environments, when `sky_top.w` is 1)
+          9. DFG lookup table
+          10. Previous frame's scene color (tonemapped, for transmission)
+          11. Point light shadow atlas (`sampler2DShadow`, tiles given by each light's `shadow_rect`)
+          12. User uniform (up to `MAX_USER_UNIFORM_SIZE` bytes, see `SceneSet::set_user_uniform`)
+          13. Morph deltas (storage buffer of {vec4 pos; vec4 normal;}, see `pbr.vert` for indexing)
+          14. Texture arrays (`MAX_TEXTURES` 2D array images); material texture indices with bit 31 set
+              refer to layer `(index >> 16) & 0x7FFF` of array `index & 0xFFFF` (see `TexturePacking`)
+          15. Cull statistics of the frame (storage buffer of {uint tested; uint visible; uint instances;},
+              see `scene_set::CullStats`)
+        * Vertex output: mirrored node flag (location 4, flat uint)
+        * Fragment output: color (location 0, alpha blended); custom shaders aren't drawn with alpha-to-coverage,
+          so should discard fragments of masked materials below their cutoff (see `scene::AlphaMode`)
+        * Views: with several views (see `with_views`), transform by `views[gl_ViewIndex]` (`GL_EXT_multiview`);
+          the scene color only holds view 0
+
+        The pipeline doesn't cull back faces, since mirrored nodes reverse triangle winding;
+        fragment shaders should discard fragments where `gl_FrontFacing` equals the mirrored flag.
+
+        Bindings unused by the shaders may be omitted.
+    */
+    pub fn register_material_shader(
+        &mut self,
+        name: &str,
+        vertex_spv: &[u8],
+        fragment_spv: &[u8]
+    ) -> Result<ShaderId, vk::Result> {
+        let read = |spv: &[u8]| ash::util::read_spv(&mut std::io::Cursor::new(spv))
+            .map_err(|_| vk::Result::ERROR_INVALID_SHADER_NV);
+        let shader = MaterialShader {
+            name: String::from(name),
+            vertex_code: read(vertex_spv)?,
+            fragment_code: read(fragment_spv)?
+        };
+        let pipeline = pipeline::mesh::create_pipeline_with_shaders(
+            &self.layouts[0],
+            self.framebuffer.render_pass,
+            &shader.vertex_code,
+            &shader.fragment_code
+        )?;
+        self.framebuffer.material_pipelines.push(pipeline);
+        self.material_shaders.push(shader);
+        Ok(ShaderId(self.material_shaders.len()))
+    }
+
+    ///Find a registered material shader by name.
+    pub fn material_shader(&self, name: &str) -> Option<ShaderId> {
+        self.material_shaders.iter().position(
+            |shader| shader.name == name
+        ).map(|i| ShaderId(i + 1))
+    }
+
+    fn mesh_pipeline(&self, scene: &DeviceScene) -> vk::Pipeline {
+        let (shader, vertex_format) = (scene.shader, scene.vertex_format);
+        match shader.0 {
+            0 if self.debug_view == DebugView::Backfaces => match vertex_format {
+                VertexFormat::Full => self.framebuffer.backface_variants[0],
+                VertexFormat::Packed => self.framebuffer.backface_variants[1]
+            },
+            //Masked materials (alpha tested by the flat shading variants instead)
+            0 if scene.masked && !self.flat_shading => match vertex_format {
+                VertexFormat::Full => self.framebuffer.coverage_variants[0],
+                VertexFormat::Packed => self.framebuffer.coverage_variants[1]
+            },
+            0 => match pipeline::mesh::variant_index(vertex_format, self.flat_shading) {
+                Some(i) => self.framebuffer.mesh_variants[i],
+                None => self.framebuffer.pipelines[0]
+            },
+            //Only assigned to scenes with full vertices (see `SceneSet::set_scene_shader`)
+            i => self.framebuffer.material_pipelines[i - 1]
+        }
+    }
+
+    ///Disable occlusion culling for the next frame (e.g. after a camera cut),
+    ///since the depth pyramid built from the previous frame no longer matches the view.
+    pub fn reset_occlusion(&mut self) {
+        self.occlusion_valid = false;
+    }
+
+    ///Timing of the present path in `draw` & counts of suboptimal & dropped frames.
+    pub fn present_statistics(&self) -> &PresentStats {
+        &self.present_stats
+    }
+
+    ///Enable or disable the FXAA post-processing pass.
+    pub fn set_fxaa(&mut self, enabled: bool) {
+        self.fxaa = enabled;
+    }
+
+    /**
+        Queue camera-facing quads to be drawn in the next frame, after meshes & the skybox.
+        Billboards are alpha blended in the order given, without sorting, & don't write depth.
+        At most `MAX_BILLBOARDS` are drawn each frame.
+    */
+    pub fn draw_billboards(&mut self, billboards: &[Billboard]) {
+        let count = billboards.len().min(MAX_BILLBOARDS - self.billboards.len());
+        self.billboards.extend_from_slice(&billboards[..count]);
+    }
+
+    /**
+        Queue line segments to be drawn in the next frame, after billboards, with the line style (see `set_line_style`).
+        Lines are depth tested without writing depth & alpha blended in the order given.
+        At most `MAX_DEBUG_LINES` are drawn each frame.
+    */
+    pub fn draw_lines(&mut self, lines: &[DebugLine]) {
+        let count = lines.len().min(MAX_DEBUG_LINES - self.lines.len());
+        self.lines.extend_from_slice(&lines[..count]);
+    }
+
+    /**
+        Set the width (in pixels) & depth bias of lines drawn with `draw_lines`.
+        The default bias (constant & slope of -1) pulls lines drawn on surfaces forward so they don't z-fight.
+        The width is clamped to the device's `lineWidthRange`, & to 1 without `wideLines` (see `capabilities`).
+    */
+    pub fn set_line_style(&mut self, width: f32, depth_bias: DepthBias) {
+        let [min_width, max_width] = self.base.physical_device_properties.limits.line_width_range;
+        let width = if self.base.wide_lines {width.clamp(min_width, max_width)} else {1.0};
+        self.line_style = (width, depth_bias);
+    }
+
+    pub fn line_style(&self) -> (f32, DepthBias) {
+        self.line_style
+    }
+
+    /**
+        Shade scenes using the default shaders with face normals (from screen-space derivatives)
+        instead of interpolated vertex normals, e.g. to inspect faceting or imported normals.
+        Scenes with custom material shaders are unaffected.
+    */
+    pub fn set_flat_shading(&mut self, enabled: bool) {
+        self.flat_shading = enabled;
+    }
+
+    /**
+        Select a diagnostic view (see `DebugView`), taking effect in the next frame.
+        Applies to scenes using the default shaders, replacing flat shading while active;
+        scenes with custom material shaders are unaffected.
+    */
+    pub fn set_debug_view(&mut self, view: DebugView) {
+        self.debug_view = view;
+    }
+
+    pub fn debug_view(&self) -> DebugView {
+        self.debug_view
+    }
+
+    /**
+        Draw triangle edges over shaded scenes, e.g. to inspect topology,
+        with a linear, alpha blended `color` & a `thickness` in pixels.
+        Edges are found from barycentric coordinates in the fragment shader,
+        so this fails with `ERROR_FEATURE_NOT_PRESENT` without `VK_KHR_fragment_shader_barycentric`
+        (see `Base::fragment_shader_barycentric`).
+        Every triangle drawn is outlined, including alpha-masked parts;
+        scenes with custom material shaders are outlined using the default vertex shader.
+        The style also applies to scenes outlined by `SceneSet::set_scene_wireframe`.
+    */
+    pub fn set_wireframe_overlay(
+        &mut self,
+        enabled: bool,
+        color: na::Vector4<f32>,
+        thickness: f32
+    ) -> Result<(), vk::Result> {
+        if enabled && !self.base.fragment_shader_barycentric {
+            return Err(vk::Result::ERROR_FEATURE_NOT_PRESENT);
+        }
+        self.wireframe_overlay = enabled;
+        self.wireframe_style = (color, thickness);
+        Ok(())
+    }
+
+    /**
+        Record custom draws (e.g. UI or debug overlays) into every frame's main render pass.
+        The hook runs while the frame is recorded, after meshes, the skybox & billboards,
+        & before the render pass ends (so before the scene color copy, FXAA & presentation).
+        Its contract:
+        * The render pass is in subpass 0, with the viewport & scissor covering `FrameContext::extent`.
+          The bound pipeline, descriptor sets, vertex & index buffers are unspecified:
+          bind your own, & don't end the render pass or record commands invalid inside it.
+        * Depth (if `FrameContext::depth_buffer`) holds the scene's depth, so hook draws can depth test against it.
+        * The command buffer is submitted after the hook returns: resources it uses must stay alive
+          & unmodified until the frame completes, once `FrameContext::frame` comes around again.
+    */
+    pub fn set_frame_hook(&mut self, hook: impl FnMut(vk::CommandBuffer, &FrameContext) + Send + 'static) {
+        self.frame_hook = Some(Box::new(hook));
+    }
+
+    pub fn clear_frame_hook(&mut self) {
+        self.frame_hook = None;
+    }
+
+    /**
+        Enable or disable the compute culling pass (enabled by default).
+        When disabled, every node is drawn (ignoring visibility & layers),
+        which helps tell culling bugs apart from rendering bugs.
+    */
+    pub fn set_culling(&mut self, enabled: bool) {
+        self.culling = enabled;
+        self.occlusion_valid = false;
+    }
+
+    /**
+        Submit the culling pass on a separate compute queue (`Base::compute_queue`), so culling a frame
+        overlaps the end of the previous frame's graphics work (disabled by default).
+        The frame's graphics submission waits for culling through a timeline semaphore,
+        & culling waits for the previous frame's depth pyramid through another when occlusion culling
+        (see `AsyncCompute` for the synchronization).
+        Without a second queue in the graphics family, culling stays on the graphics queue,
+        recorded in the frame's command buffer before drawing (check `async_compute`).
+        Disabling waits for the graphics queue to be idle.
+    */
+    pub fn set_async_compute(&mut self, enabled: bool) -> Result<(), vk::Result> {
+        if !enabled && self.async_compute.is_some() {
+            //Pending graphics submissions may wait on its semaphores
+            unsafe {
+                self.base.device.queue_wait_idle(self.base.graphics_queue)?;
+            }
+            self.async_compute = None;
+        } else if enabled && self.async_compute.is_none() && self.base.compute_queue.is_some() {
+            self.async_compute = Some(AsyncCompute::new(self.base.clone())?);
+        }
+        Ok(())
+    }
+
+    ///Whether culling is submitted on the compute queue (enabled & available).
+    pub fn async_compute(&self) -> bool {
+        self.async_compute.is_some()
+    }
+
+    /**
+        Make each drawn frame depend only on the scene set & renderer settings, not on previous frames,
+        e.g. for pixel-diff tests of `draw` (`render_once_to_image` already does this for its frame).
+        Disabled by default. This pins:
+        * Occlusion culling: the previous frame's depth pyramid isn't used, so nodes are only frustum culled.
+        * Transmission: the previous frame's scene color isn't sampled,
+          so transmissive materials blend instead (as in views other than view 0).
+
+        There is no auto-exposure, temporal anti-aliasing or time-based animation to pin;
+        features adding adaptive or temporal state should be pinned here.
+        Results still differ across GPUs & drivers (see `render_once_to_image`).
+    */
+    pub fn set_deterministic(&mut self, enabled: bool) {
+        self.deterministic = enabled;
+    }
+
+    /**
+        Limit the host-visible staging memory used for each frame's uploads (`None` for no limit, the default).
+        Uploads larger than the budget (e.g. a scene's textures) are split into several transfer submissions,
+        each waiting for the previous one, so the first frame after a large upload takes longer.
+    */
+    pub fn set_staging_budget(&mut self, budget: Option<usize>) {
+        self.transfer.set_staging_budget(budget);
+    }
+
+    /**
+        Preallocate each frame's host-visible staging buffer to at least `size` bytes,
+        e.g. the largest expected upload, so the first large upload doesn't reallocate staging memory.
+        Staging otherwise starts small & grows to fit each upload (it never shrinks without a staging budget,
+        while a budget below `size` shrinks it again on the next upload).
+    */
+    pub fn reserve_staging(&mut self, size: usize) -> Result<(), AllocationError> {
+        self.transfer.reserve_staging(size)
+    }
+
+    ///Size in bytes of each frame's staging buffer (see `reserve_staging`).
+    pub fn staging_capacity(&self) -> usize {
+        self.transfer.staging_capacity()
+    }
+
+    /**
+        Submit the uploads recorded so far (e.g. by `SceneSet::push_scene`) & block until they complete,
+        so uploaded resources can be read back or used without drawing a frame first.
+        Uploads are otherwise submitted by the next frame. Safe to call between frames:
+        the current frame's previous submission completes before its command buffer is reused.
+    */
+    pub fn flush_transfers(&mut self) -> Result<(), AllocationError> {
+        let frame = &self.framebuffer.frames[self.current_frame];
+        let mut transaction = self.transaction.borrow_mut();
+        unsafe {
+            self.base.device.wait_for_fences(
+                std::slice::from_ref(&frame.fence),
+                false,
+                TIMEOUT
+            )?;
+            let (transfer_semaphore, transfer_semaphore_value) = self.transfer.submit(
+                &transaction,
+                self.current_frame
+            )?;
+            //Make transfers visible to later submissions & acquire transferred images
+            let begin_info = vk::CommandBufferBeginInfo::builder()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+            self.base.device.begin_command_buffer(frame.command_buffer, &begin_info)?;
+            let memory_barrier = vk::MemoryBarrier2::builder()
+                .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+                .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                .dst_access_mask(vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE);
+            let dependency = vk::DependencyInfo::builder()
+                .memory_barriers(std::slice::from_ref(&memory_barrier))
+                .image_memory_barriers(&transaction.end_image_barriers);
+            self.base.device.cmd_pipeline_barrier2(frame.command_buffer, &dependency);
+            self.base.device.end_command_buffer(frame.command_buffer)?;
+            //Submit & wait
+            let wait_semaphore_info = vk::SemaphoreSubmitInfo::builder()
+                .semaphore(transfer_semaphore)
+                .value(transfer_semaphore_value)
+                .stage_mask(vk::PipelineStageFlags2::TRANSFER);
+            let command_buffer_info = vk::CommandBufferSubmitInfo::builder()
+                .command_buffer(frame.command_buffer);
+            let submit_info = vk::SubmitInfo2::builder()
+                .wait_semaphore_infos(std::slice::from_ref(&wait_semaphore_info))
+                .command_buffer_infos(std::slice::from_ref(&command_buffer_info));
+            self.base.device.reset_fences(std::slice::from_ref(&frame.fence))?;
+            self.base.device.queue_submit2(
+                self.base.graphics_queue,
+                std::slice::from_ref(&submit_info),
+                frame.fence
+            )?;
+            self.base.device.wait_for_fences(
+                std::slice::from_ref(&frame.fence),
+                false,
+                u64::MAX
+            )?;
+        }
+        transaction.clear();
+        Ok(())
+    }
+
+    /**
+        Wait for the current frame's previous submission, upload per-frame data
+        & record culling, drawing, the depth pyramid & post-processing.
+        With async compute, culling is submitted on the compute queue instead of recorded.
+        The command buffer is left open, and the returned color image is left in `COLOR_ATTACHMENT_OPTIMAL`;
+        the submission must wait on & signal the returned semaphores
+        (then call `AsyncCompute::graphics_submitted`).
+    */
+    fn record_frame(&mut self, scene_set: &SceneSet) -> Result<(vk::Image, FrameSemaphores), AllocationError> {
+        if self.deterministic {
+            self.occlusion_valid = false;
+            self.scene_color_valid = false;
+        }
         let frame = &self.framebuffer.frames[self.current_frame];
         let mut transaction = self.transaction.borrow_mut();
         unsafe {
-            //Acquire swapchain image
-            let mut swapchain_index = 0;
-            let mut swapchain_suboptimal = true;
-            while swapchain_suboptimal {
-                (swapchain_index, swapchain_suboptimal) = self.swapchain.loader.acquire_next_image(
-                    self.swapchain.swapchain,
-                    TIMEOUT,
-                    frame.semaphores[0],
-                    vk::Fence::null()
-                )?;
-                if swapchain_suboptimal {
-                    //Recreate swapchain
-                    self.base.device.queue_wait_idle(self.base.graphics_queue)?;
-                    self.swapchain = swapchain::Swapchain::new(
-                        self.base.clone(),
-                        Some(self.swapchain.swapchain)
-                    )?;
-                }
-            }
-            let swapchain_image = self.swapchain.images[swapchain_index as usize];
             //Wait for frame fence
+            let wait_start = Instant::now();
             self.base.device.wait_for_fences(
                 std::slice::from_ref(&frame.fence),
                 false,
                 TIMEOUT
             )?;
+            self.fence_wait = wait_start.elapsed();
             self.base.device.reset_fences(std::slice::from_ref(&frame.fence))?;
+            scene_set.begin_frame(self, self.current_frame);
             //Transactions
             //Update uniforms
-            let mut uniforms: [f32; 36] = [0.0; 36];
-            uniforms[0..16].copy_from_slice(scene_set.camera.view().as_slice());
-            uniforms[16..32].copy_from_slice(scene_set.camera.projection().as_slice());
-            uniforms[32..36].copy_from_slice(scene_set.camera.pos.to_homogeneous().as_slice());
+            let tint = na::Vector3::from(scene_set.environment_tint()) * scene_set.environment_intensity();
+            let uniform = CameraUniform {
+                view: scene_set.camera.view(),
+                projection: scene_set.camera.projection(),
+                camera_pos: scene_set.camera.pos.to_homogeneous(),
+                environment: scene_set.environment_rotation().inverse().to_homogeneous(),
+                scene_color_valid: self.scene_color_valid as u32,
+                ibl_specular: scene_set.ibl_specular() as u32,
+                view_count: self.view_count as u32,
+                padding: 0,
+                views: std::array::from_fn(|view| if view < self.view_count {
+                    ViewUniform::new(scene_set.view_camera(view))
+                } else {
+                    ViewUniform::zeroed()
+                }),
+                sky: scene_set.environment().gradient_uniform(),
+                environment_color: tint.push(0.0)
+            };
             transaction.buffer_write(
-                &uniforms,
+                std::slice::from_ref(&uniform),
                 scene_set.camera_buffer,
                 self.current_frame * scene_set.camera_uniform_size
             );
-            //Update lights
-            transaction.buffer_write(
-                &scene_set.lights,
-                scene_set.lights_buffer,
-                self.current_frame * MAX_LIGHTS * std::mem::size_of::<PointLight>()
-            );
+            //Update lights (count, then each light), assigning shadow atlas tiles to the first shadow-casting lights
+            //(lights past the capacity aren't drawn)
+            let mut casters = Vec::with_capacity(MAX_SHADOW_CASTERS);
+            let lights: Vec<_> = scene_set.lights.iter().take(scene_set.light_capacity()).map(|light| {
+                let tile = (
+                    light.casts_shadow && light.range > 0.0 && casters.len() < MAX_SHADOW_CASTERS
+                ).then(|| {
+                    casters.push(*light);
+                    casters.len() - 1
+                });
+                DeviceLight::new(light, tile.map(|tile| self.shadow_atlas.tile_rect(tile)))
+            }).collect();
+            let lights_offset = self.current_frame * scene_set.lights_size;
+            transaction.buffer_write(&[lights.len() as u32], scene_set.lights_buffer, lights_offset);
+            if !lights.is_empty() {
+                transaction.buffer_write(&lights, scene_set.lights_buffer, lights_offset + LIGHTS_HEADER_SIZE);
+            }
+            //Update user uniform
+            if !scene_set.user_uniform().is_empty() {
+                transaction.buffer_write(
+                    scene_set.user_uniform(),
+                    scene_set.user_buffer,
+                    self.current_frame * scene_set.user_uniform_size
+                );
+            }
+            //Update billboards
+            if !self.billboards.is_empty() {
+                transaction.buffer_write(
+                    &self.billboards,
+                    self.billboard_buffer,
+                    self.billboard_descriptors[self.current_frame].offset as usize
+                );
+            }
+            //Update debug lines
+            if !self.lines.is_empty() {
+                transaction.buffer_write(
+                    &self.lines,
+                    self.line_buffer,
+                    self.current_frame * MAX_DEBUG_LINES * std::mem::size_of::<DebugLine>()
+                );
+            }
             //Update scene dynamic data
             for scene in &scene_set.scenes {
                 //Nodes
                 transaction.buffer_write(
                     &scene.nodes,
-                    scene.buffers[5],
+                    scene.buffers[5].handle(),
                     self.current_frame * scene.buffer_sizes[5]
                 );
-                //Draw count
-                transaction.buffer_write::<u32>(
-                    std::slice::from_ref(&0),
-                    scene.buffers[8],
-                    self.current_frame * scene.buffer_sizes[8]
-                );
+                if self.culling {
+                    //Draw count
+                    transaction.fill_buffer(
+                        scene.buffers[8].handle(),
+                        self.current_frame * scene.buffer_sizes[8],
+                        std::mem::size_of::<u32>(),
+                        0
+                    );
+                    //Cull statistics
+                    transaction.fill_buffer(
+                        scene.buffers[10].handle(),
+                        self.current_frame * scene.buffer_sizes[10],
+                        scene.buffer_sizes[10],
+                        0
+                    );
+                } else {
+                    //Draw every node
+                    let draw_commands: Vec<_> = scene.nodes.iter().enumerate().map(|(i, node)| {
+                        let mut command = scene.mesh_commands[node.mesh as usize];
+                        if self.base.draw_indirect_first_instance {
+                            command.first_instance = i as u32;
+                        }
+                        command
+                    }).collect();
+                    let extras: Vec<[u32; 2]> = scene.nodes.iter().enumerate().map(
+                        |(i, node)| [i as u32, node.mesh]
+                    ).collect();
+                    transaction.buffer_write(
+                        &draw_commands,
+                        scene.buffers[6].handle(),
+                        self.current_frame * scene.buffer_sizes[6]
+                    );
+                    transaction.buffer_write(
+                        &extras,
+                        scene.buffers[7].handle(),
+                        self.current_frame * scene.buffer_sizes[7]
+                    );
+                    transaction.buffer_write::<u32>(
+                        std::slice::from_ref(&(scene.nodes.len() as u32)),
+                        scene.buffers[8].handle(),
+                        self.current_frame * scene.buffer_sizes[8]
+                    );
+                    let stats = CullStats {
+                        tested: scene.nodes.len() as u32,
+                        visible: scene.nodes.len() as u32,
+                        instances: draw_commands.iter().map(|command| command.instance_count).sum()
+                    };
+                    transaction.buffer_write(
+                        std::slice::from_ref(&stats),
+                        scene.buffers[10].handle(),
+                        self.current_frame * scene.buffer_sizes[10]
+                    );
+                }
             }
             //Transfer operations
             let (transfer_semaphore, transfer_semaphore_value) = self.transfer.submit(
                 &transaction,
                 self.current_frame
             )?;
+            let mut semaphores = FrameSemaphores {
+                waits: vec![*vk::SemaphoreSubmitInfo::builder()
+                    .semaphore(transfer_semaphore)
+                    .value(transfer_semaphore_value)
+                    .stage_mask(vk::PipelineStageFlags2::TRANSFER)
+                ],
+                signals: self.async_compute.as_ref().map(|compute| compute.graphics_signal()).into_iter().collect()
+            };
+            //Async compute culling
+            //(the semaphore waits make transfers & the previous depth pyramid visible, so no barriers are needed)
+            let async_cull = self.culling && self.async_compute.is_some();
+            if let Some(compute) = self.async_compute.as_ref().filter(|_| async_cull) {
+                let command_buffer = compute.begin(self.current_frame)?;
+                self.record_cull(command_buffer, scene_set);
+                let wait = self.async_compute.as_mut().unwrap().submit(
+                    self.current_frame,
+                    transfer_semaphore,
+                    transfer_semaphore_value,
+                    self.occlusion_valid
+                )?;
+                semaphores.waits.push(wait);
+            }
             //Record command buffer
             let begin_info = vk::CommandBufferBeginInfo::builder()
                 .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
             self.base.device.begin_command_buffer(frame.command_buffer, &begin_info)?;
             //Pipeline barrier
-            let memory_barrier = vk::MemoryBarrier2::builder()
-                .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
-                .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
-                .dst_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
-                .dst_access_mask(vk::AccessFlags2::SHADER_WRITE);
+            let memory_barriers = [
+                //Transfers
+                *vk::MemoryBarrier2::builder()
+                    .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+                    .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                    .dst_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+                    .dst_access_mask(vk::AccessFlags2::SHADER_WRITE),
+                //Transfers read by drawing & cull statistics readback
+                //(draw commands & statistics when culling is disabled, nodes as instance-rate vertices)
+                *vk::MemoryBarrier2::builder()
+                    .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+                    .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                    .dst_stage_mask(
+                        vk::PipelineStageFlags2::DRAW_INDIRECT
+                        | vk::PipelineStageFlags2::VERTEX_ATTRIBUTE_INPUT
+                        | vk::PipelineStageFlags2::VERTEX_SHADER
+                        | vk::PipelineStageFlags2::FRAGMENT_SHADER
+                        | vk::PipelineStageFlags2::COPY
+                    ).dst_access_mask(
+                        vk::AccessFlags2::INDIRECT_COMMAND_READ
+                        | vk::AccessFlags2::VERTEX_ATTRIBUTE_READ
+                        | vk::AccessFlags2::SHADER_READ
+                        | vk::AccessFlags2::TRANSFER_READ
+                    ),
+                //Previous frame's depth pyramid
+                *vk::MemoryBarrier2::builder()
+                    .src_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+                    .src_access_mask(vk::AccessFlags2::SHADER_WRITE)
+                    .dst_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+                    .dst_access_mask(vk::AccessFlags2::SHADER_READ)
+            ];
             let dependency = vk::DependencyInfo::builder()
-                .memory_barriers(std::slice::from_ref(&memory_barrier));
+                .memory_barriers(&memory_barriers);
             self.base.device.cmd_pipeline_barrier2(frame.command_buffer, &dependency);
             //Transfer barriers
             if transaction.end_image_barriers.len() > 0 {
@@ -299,33 +1752,8 @@ impl<'a> Renderer {
                 self.base.device.cmd_pipeline_barrier2(frame.command_buffer, &dependency);
             }
             //Compute culling
-            self.base.device.cmd_bind_pipeline(
-                frame.command_buffer,
-                vk::PipelineBindPoint::COMPUTE,
-                self.cull_pipeline
-            );
-            for (i, scene) in scene_set.scenes.iter().enumerate() {
-                self.base.device.cmd_push_constants(
-                    frame.command_buffer,
-                    self.cull_layout.pipeline_layout,
-                    vk::ShaderStageFlags::COMPUTE,
-                    0,
-                    &(scene.nodes.len() as u32).to_le_bytes()
-                );
-                self.base.device.cmd_bind_descriptor_sets(
-                    frame.command_buffer,
-                    vk::PipelineBindPoint::COMPUTE,
-                    self.cull_layout.pipeline_layout,
-                    0,
-                    std::slice::from_ref(&scene_set.cull_descriptors(i, self.current_frame)),
-                    &[]
-                );
-                self.base.device.cmd_dispatch(
-                    frame.command_buffer,
-                    ((scene.nodes.len() + 63) / 64) as u32,
-                    1,
-                    1
-                );
+            if self.culling && !async_cull {
+                self.record_cull(frame.command_buffer, scene_set);
             }
             //Pipeline barrier
             let memory_barrier = vk::MemoryBarrier2::builder()
@@ -333,214 +1761,1133 @@ impl<'a> Renderer {
                 .src_access_mask(vk::AccessFlags2::SHADER_WRITE)
                 .dst_stage_mask(vk::PipelineStageFlags2::ALL_GRAPHICS)
                 .dst_access_mask(vk::AccessFlags2::SHADER_READ);
+            let color_subresource_range = vk::ImageSubresourceRange::builder()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .base_mip_level(0)
+                .level_count(1)
+                .base_array_layer(0)
+                .layer_count(1);
+            //Scene color isn't sampled until written, but must be in the descriptor's layout
+            let scene_color_barrier = vk::ImageMemoryBarrier2::builder()
+                .src_stage_mask(vk::PipelineStageFlags2::NONE)
+                .src_access_mask(vk::AccessFlags2::NONE)
+                .dst_stage_mask(vk::PipelineStageFlags2::FRAGMENT_SHADER)
+                .dst_access_mask(vk::AccessFlags2::SHADER_READ)
+                .old_layout(vk::ImageLayout::UNDEFINED)
+                .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .src_queue_family_index(self.base.graphics_queue_family)
+                .dst_queue_family_index(self.base.graphics_queue_family)
+                .image(self.framebuffer.scene_color)
+                .subresource_range(*color_subresource_range);
             let dependency = vk::DependencyInfo::builder()
-                .memory_barriers(std::slice::from_ref(&memory_barrier));
+                .memory_barriers(std::slice::from_ref(&memory_barrier))
+                .image_memory_barriers(if self.scene_color_valid {
+                    &[]
+                } else {
+                    std::slice::from_ref(&scene_color_barrier)
+                });
             self.base.device.cmd_pipeline_barrier2(frame.command_buffer, &dependency);
+            //Shadow maps
+            let casters: Vec<_> = casters.iter().enumerate().collect();
+            self.shadow_atlas.record(
+                frame.command_buffer,
+                &self.layouts[0],
+                scene_set,
+                self.current_frame,
+                &casters
+            );
             //Drawing
             let render_area = vk::Rect2D::builder()
                 .offset(vk::Offset2D::default())
                 .extent(self.framebuffer.extent);
+            //Ignored by attachments which aren't cleared
+            let clear_color = match self.attachment_loads.color {
+                ColorLoad::Clear(color) => color,
+                ColorLoad::DontCare => [0.0; 4]
+            };
             let clear_values = [
-                vk::ClearValue {color: vk::ClearColorValue {float32: [0.0, 0.0, 0.0, 1.0]}}, //Color
-                vk::ClearValue {color: vk::ClearColorValue {float32: [0.0, 0.0, 0.0, 1.0]}}, //Resolve
-                vk::ClearValue {depth_stencil: *vk::ClearDepthStencilValue::builder().depth(1.0)} //Depth
+                vk::ClearValue {color: vk::ClearColorValue {float32: clear_color}}, //Color (multisampled only)
+                vk::ClearValue {color: vk::ClearColorValue {float32: clear_color}}, //Resolve
+                vk::ClearValue {depth_stencil: *vk::ClearDepthStencilValue::builder().depth(self.attachment_loads.clear_depth)} //Depth
             ];
             let begin_info = vk::RenderPassBeginInfo::builder()
                 .render_pass(self.framebuffer.render_pass)
                 .framebuffer(frame.framebuffer)
                 .render_area(*render_area)
-                .clear_values(&clear_values);
+                .clear_values(&clear_values[(self.framebuffer.samples == vk::SampleCountFlags::TYPE_1) as usize..]);
             self.base.device.cmd_begin_render_pass(
                 frame.command_buffer,
                 &begin_info,
                 vk::SubpassContents::INLINE
             );
-            //Draw meshes
-            self.base.device.cmd_bind_pipeline(
-                frame.command_buffer,
-                vk::PipelineBindPoint::GRAPHICS,
-                self.framebuffer.pipelines[0]
-            );
-            //Draw scenes
-            for (i, scene) in scene_set.scenes.iter().enumerate() {
+            set_viewport(&self.base.device, frame.command_buffer, self.framebuffer.extent);
+            //Draw scenes, then the wireframe overlay over them
+            let wireframe = |scene: &DeviceScene| self.base.fragment_shader_barycentric
+                && scene.wireframe.unwrap_or(self.wireframe_overlay);
+            let passes = if scene_set.scenes.iter().any(wireframe) {2} else {1};
+            for overlay in [false, true].into_iter().take(passes) {
+                if overlay {
+                    let (color, thickness) = self.wireframe_style;
+                    let constants: Vec<u8> = color.iter().chain(std::iter::once(&thickness)).flat_map(
+                        |x| x.to_le_bytes()
+                    ).collect();
+                    self.base.device.cmd_push_constants(
+                        frame.command_buffer,
+                        self.layouts[0].pipeline_layout,
+                        vk::ShaderStageFlags::FRAGMENT,
+                        0,
+                        &constants
+                    );
+                }
+                for (i, scene) in scene_set.scenes.iter().enumerate().filter(|(_, scene)| !overlay || wireframe(scene)) {
+                    let pipeline = if overlay {
+                        match scene.vertex_format {
+                            VertexFormat::Full => self.framebuffer.wireframe_variants[0],
+                            VertexFormat::Packed => self.framebuffer.wireframe_variants[1]
+                        }
+                    } else {
+                        self.mesh_pipeline(scene)
+                    };
+                    self.base.device.cmd_bind_pipeline(
+                        frame.command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        pipeline
+                    );
+                    self.base.device.cmd_set_depth_bias(
+                        frame.command_buffer,
+                        scene.depth_bias.constant,
+                        0.0,
+                        scene.depth_bias.slope
+                    );
+                    //Vertices & nodes (`pipeline::mesh::NODE_BINDING`)
+                    self.base.device.cmd_bind_vertex_buffers(
+                        frame.command_buffer,
+                        0,
+                        &[scene.buffers[0].handle(), scene.buffers[5].handle()],
+                        &[0, (self.current_frame * scene.buffer_sizes[5]) as u64]
+                    );
+                    self.base.device.cmd_bind_index_buffer(
+                        frame.command_buffer,
+                        scene.buffers[1].handle(),
+                        0,
+                        vk::IndexType::UINT16
+                    );
+                    self.base.device.cmd_bind_descriptor_sets(
+                        frame.command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        self.layouts[0].pipeline_layout,
+                        0,
+                        std::slice::from_ref(&scene_set.scene_descriptors(i, self.current_frame)),
+                        &[]
+                    );
+                    if self.draw_indirect_count {
+                        self.base.device.cmd_draw_indexed_indirect_count(
+                            frame.command_buffer,
+                            scene.buffers[6].handle(),
+                            (self.current_frame * scene.buffer_sizes[6]) as u64,
+                            scene.buffers[8].handle(),
+                            (self.current_frame * scene.buffer_sizes[8]) as u64,
+                            scene.node_capacity as u32,
+                            std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32
+                        );
+                    } else {
+                        self.base.device.cmd_draw_indexed_indirect(
+                            frame.command_buffer,
+                            scene.buffers[6].handle(),
+                            (self.current_frame * scene.buffer_sizes[6]) as u64,
+                            scene.nodes.len() as u32,
+                            std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32
+                        );
+                    }
+                }
+            }
+            //Draw skybox (it would cover everything without depth testing)
+            if self.framebuffer.depth_buffer {
+                self.base.device.cmd_bind_pipeline(
+                    frame.command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.framebuffer.pipelines[1]
+                );
+                self.base.device.cmd_bind_descriptor_sets(
+                    frame.command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.layouts[1].pipeline_layout,
+                    0,
+                    std::slice::from_ref(&scene_set.skybox_descriptors(self.current_frame)),
+                    &[]
+                );
                 self.base.device.cmd_bind_vertex_buffers(
                     frame.command_buffer,
                     0,
-                    std::slice::from_ref(&scene.buffers[0]),
+                    std::slice::from_ref(&self.skybox_vertex_buffer),
                     &[0]
                 );
-                self.base.device.cmd_bind_index_buffer(
+                self.base.device.cmd_draw(frame.command_buffer, 14, 1, 0, 0);
+            }
+            //Draw highlight outlines (after the skybox, which would cover them over the background)
+            if self.framebuffer.depth_buffer && !scene_set.highlights().is_empty() {
+                let extent = self.framebuffer.extent;
+                let outline = [
+                    2.0 / extent.width as f32,
+                    2.0 / extent.height as f32,
+                    scene_set.highlight_thickness()
+                ];
+                let constants: Vec<u8> = outline.iter().flat_map(|x| x.to_le_bytes()).collect();
+                self.base.device.cmd_push_constants(
+                    frame.command_buffer,
+                    self.layouts[0].pipeline_layout,
+                    vk::ShaderStageFlags::VERTEX,
+                    pipeline::mesh::SHADOW_PUSH_CONSTANT_OFFSET,
+                    &constants
+                );
+                self.base.device.cmd_set_depth_bias(frame.command_buffer, 0.0, 0.0, 0.0);
+                for highlight in scene_set.highlights() {
+                    let scene = &scene_set.scenes[highlight.scene];
+                    let pipeline = match scene.vertex_format {
+                        VertexFormat::Full => self.framebuffer.highlight_variants[0],
+                        VertexFormat::Packed => self.framebuffer.highlight_variants[1]
+                    };
+                    self.base.device.cmd_bind_pipeline(
+                        frame.command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        pipeline
+                    );
+                    let constants: Vec<u8> = highlight.color.iter().flat_map(|x| x.to_le_bytes()).collect();
+                    self.base.device.cmd_push_constants(
+                        frame.command_buffer,
+                        self.layouts[0].pipeline_layout,
+                        vk::ShaderStageFlags::FRAGMENT,
+                        0,
+                        &constants
+                    );
+                    //Vertices & nodes (`pipeline::mesh::NODE_BINDING`)
+                    self.base.device.cmd_bind_vertex_buffers(
+                        frame.command_buffer,
+                        0,
+                        &[scene.buffers[0].handle(), scene.buffers[5].handle()],
+                        &[0, (self.current_frame * scene.buffer_sizes[5]) as u64]
+                    );
+                    self.base.device.cmd_bind_index_buffer(
+                        frame.command_buffer,
+                        scene.buffers[1].handle(),
+                        0,
+                        vk::IndexType::UINT16
+                    );
+                    self.base.device.cmd_bind_descriptor_sets(
+                        frame.command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        self.layouts[0].pipeline_layout,
+                        0,
+                        std::slice::from_ref(&scene_set.scene_descriptors(highlight.scene, self.current_frame)),
+                        &[]
+                    );
+                    //One instance of each of the node's primitives, indexing its device node
+                    let device_nodes = scene.nodes.iter().zip(&scene.node_indices).enumerate()
+                        .filter(|(_, (node, &index))| index == highlight.node && node.visible());
+                    for (i, (node, _)) in device_nodes {
+                        let command = &scene.mesh_commands[node.mesh as usize];
+                        self.base.device.cmd_draw_indexed(
+                            frame.command_buffer,
+                            command.index_count,
+                            1,
+                            command.first_index,
+                            command.vertex_offset,
+                            i as u32
+                        );
+                    }
+                }
+            }
+            //Draw billboards
+            if !self.billboards.is_empty() {
+                self.base.device.cmd_bind_pipeline(
+                    frame.command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.framebuffer.pipelines[2]
+                );
+                self.base.device.cmd_bind_descriptor_sets(
                     frame.command_buffer,
-                    scene.buffers[1],
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.layouts[2].pipeline_layout,
                     0,
-                    vk::IndexType::UINT16
+                    std::slice::from_ref(&scene_set.billboard_descriptors(self.current_frame)),
+                    &[]
                 );
+                self.base.device.cmd_draw(frame.command_buffer, 4, self.billboards.len() as u32, 0, 0);
+            }
+            //Draw debug lines
+            if !self.lines.is_empty() {
+                let (width, depth_bias) = self.line_style;
+                self.base.device.cmd_bind_pipeline(
+                    frame.command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.framebuffer.line_pipeline
+                );
+                self.base.device.cmd_set_line_width(frame.command_buffer, width);
+                self.base.device.cmd_set_depth_bias(frame.command_buffer, depth_bias.constant, 0.0, depth_bias.slope);
                 self.base.device.cmd_bind_descriptor_sets(
                     frame.command_buffer,
                     vk::PipelineBindPoint::GRAPHICS,
-                    self.layouts[0].pipeline_layout,
+                    self.layouts[2].pipeline_layout,
                     0,
-                    std::slice::from_ref(&scene_set.scene_descriptors(i, self.current_frame)),
+                    std::slice::from_ref(&scene_set.billboard_descriptors(self.current_frame)),
                     &[]
                 );
-                /*
-                self.base.device.cmd_draw_indexed_indirect(
+                self.base.device.cmd_bind_vertex_buffers(
                     frame.command_buffer,
-                    scene.buffers[6],
                     0,
-                    scene.nodes.len() as u32,
-                    std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32
+                    std::slice::from_ref(&self.line_buffer),
+                    &[(self.current_frame * MAX_DEBUG_LINES * std::mem::size_of::<DebugLine>()) as u64]
+                );
+                self.base.device.cmd_draw(frame.command_buffer, 2, self.lines.len() as u32, 0, 0);
+            }
+            //Custom rendering
+            if let Some(hook) = &mut self.frame_hook {
+                let context = FrameContext {
+                    render_pass: self.framebuffer.render_pass,
+                    extent: self.framebuffer.extent,
+                    samples: self.framebuffer.samples,
+                    view_count: self.view_count,
+                    depth_buffer: self.framebuffer.depth_buffer,
+                    frame: self.current_frame,
+                    camera_descriptor: scene_set.camera_descriptor(self.current_frame)
+                };
+                hook(frame.command_buffer, &context);
+            }
+            self.base.device.cmd_end_render_pass(frame.command_buffer);
+            //Image transitions after the render pass
+            let mut images = ImageTracker::new(self.base.graphics_queue_family);
+            let resolve_image = images.track(frame.images[1], *color_subresource_range, ImageState::COLOR_ATTACHMENT);
+            //Copy scene color for the next frame's transmissive materials
+            if self.framebuffer.scene_color_copy {
+                //Previous scene color contents were sampled by this frame
+                let scene_color = images.track(
+                    self.framebuffer.scene_color,
+                    *color_subresource_range,
+                    ImageState::FRAGMENT_SAMPLED
+                );
+                images.transition(resolve_image, ImageState::COPY_SRC);
+                images.transition(scene_color, ImageState::COPY_DST);
+                images.record(&self.base.device, frame.command_buffer);
+                let subresource = vk::ImageSubresourceLayers::builder()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .mip_level(0)
+                    .base_array_layer(0)
+                    .layer_count(1);
+                let region = vk::ImageCopy2::builder()
+                    .src_subresource(*subresource)
+                    .dst_subresource(*subresource)
+                    .extent(vk::Extent3D {
+                        width: self.framebuffer.extent.width,
+                        height: self.framebuffer.extent.height,
+                        depth: 1
+                    });
+                let copy_info = vk::CopyImageInfo2::builder()
+                    .src_image(frame.images[1])
+                    .src_image_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                    .dst_image(self.framebuffer.scene_color)
+                    .dst_image_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .regions(std::slice::from_ref(&region));
+                self.base.device.cmd_copy_image2(frame.command_buffer, &copy_info);
+                images.transition(resolve_image, ImageState::COLOR_ATTACHMENT);
+                images.transition(scene_color, ImageState::FRAGMENT_SAMPLED);
+                images.record(&self.base.device, frame.command_buffer);
+                self.scene_color_valid = true;
+            }
+            //Depth pyramid
+            if self.framebuffer.depth_buffer {
+                let depth_subresource_range = vk::ImageSubresourceRange::builder()
+                    .aspect_mask(vk::ImageAspectFlags::DEPTH)
+                    .base_mip_level(0)
+                    .level_count(1)
+                    .base_array_layer(0)
+                    .layer_count(1);
+                let pyramid_subresource_range = vk::ImageSubresourceRange::builder()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .base_mip_level(0)
+                    .level_count(self.depth_pyramid.level_count)
+                    .base_array_layer(0)
+                    .layer_count(1);
+                let depth_image = images.track(frame.images[2], *depth_subresource_range, ImageState::DEPTH_ATTACHMENT);
+                //Previous depth pyramid contents were consumed by culling
+                let depth_pyramid = images.track(
+                    self.depth_pyramid.image,
+                    *pyramid_subresource_range,
+                    ImageState::COMPUTE_READ
                 );
-                */
-                self.base.device.cmd_draw_indexed_indirect_count(
+                images.transition(depth_image, ImageState::COMPUTE_SAMPLED);
+                images.discard(depth_pyramid, ImageState::COMPUTE_WRITE);
+                images.record(&self.base.device, frame.command_buffer);
+                self.base.device.cmd_bind_pipeline(
                     frame.command_buffer,
-                    scene.buffers[6],
-                    (self.current_frame * scene.buffer_sizes[6]) as u64,
-                    scene.buffers[8],
-                    (self.current_frame * scene.buffer_sizes[8]) as u64,
-                    scene.nodes.len() as u32,
-                    std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32
+                    vk::PipelineBindPoint::COMPUTE,
+                    self.hiz_pipeline
                 );
+                for level in 0..self.depth_pyramid.level_count {
+                    if level > 0 {
+                        //Wait for the previous level
+                        let memory_barrier = vk::MemoryBarrier2::builder()
+                            .src_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+                            .src_access_mask(vk::AccessFlags2::SHADER_WRITE)
+                            .dst_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+                            .dst_access_mask(vk::AccessFlags2::SHADER_READ);
+                        let dependency = vk::DependencyInfo::builder()
+                            .memory_barriers(std::slice::from_ref(&memory_barrier));
+                        self.base.device.cmd_pipeline_barrier2(frame.command_buffer, &dependency);
+                    }
+                    self.base.device.cmd_push_constants(
+                        frame.command_buffer,
+                        self.hiz_layout.pipeline_layout,
+                        vk::ShaderStageFlags::COMPUTE,
+                        0,
+                        &level.to_le_bytes()
+                    );
+                    self.base.device.cmd_bind_descriptor_sets(
+                        frame.command_buffer,
+                        vk::PipelineBindPoint::COMPUTE,
+                        self.hiz_layout.pipeline_layout,
+                        0,
+                        std::slice::from_ref(&self.depth_pyramid.build_descriptors(self.current_frame, level)),
+                        &[]
+                    );
+                    let extent = self.depth_pyramid.level_extent(level);
+                    self.base.device.cmd_dispatch(
+                        frame.command_buffer,
+                        extent.width.div_ceil(8),
+                        extent.height.div_ceil(8),
+                        1
+                    );
+                }
             }
-            //Draw skybox
-            self.base.device.cmd_bind_pipeline(
-                frame.command_buffer,
-                vk::PipelineBindPoint::GRAPHICS,
-                self.framebuffer.pipelines[1]
+            //FXAA
+            let color_image = if self.fxaa {
+                images.transition(resolve_image, ImageState::FRAGMENT_SAMPLED);
+                images.record(&self.base.device, frame.command_buffer);
+                //Fullscreen pass
+                let begin_info = vk::RenderPassBeginInfo::builder()
+                    .render_pass(self.framebuffer.post_render_pass)
+                    .framebuffer(frame.post_framebuffer)
+                    .render_area(*render_area);
+                self.base.device.cmd_begin_render_pass(
+                    frame.command_buffer,
+                    &begin_info,
+                    vk::SubpassContents::INLINE
+                );
+                set_viewport(&self.base.device, frame.command_buffer, self.framebuffer.extent);
+                self.base.device.cmd_bind_pipeline(
+                    frame.command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.framebuffer.post_pipeline
+                );
+                self.base.device.cmd_bind_descriptor_sets(
+                    frame.command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.fxaa_layout.pipeline_layout,
+                    0,
+                    std::slice::from_ref(&frame.post_descriptor_set),
+                    &[]
+                );
+                self.base.device.cmd_draw(frame.command_buffer, 3, 1, 0, 0);
+                self.base.device.cmd_end_render_pass(frame.command_buffer);
+                frame.images[3]
+            } else {
+                frame.images[1]
+            };
+            //Cull statistics readback
+            let memory_barrier = vk::MemoryBarrier2::builder()
+                .src_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+                .src_access_mask(vk::AccessFlags2::SHADER_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags2::COPY)
+                .dst_access_mask(vk::AccessFlags2::TRANSFER_READ);
+            let dependency = vk::DependencyInfo::builder()
+                .memory_barriers(std::slice::from_ref(&memory_barrier));
+            self.base.device.cmd_pipeline_barrier2(frame.command_buffer, &dependency);
+            for scene in &scene_set.scenes {
+                scene.record_cull_readback(frame.command_buffer, self.current_frame);
+            }
+            let memory_barrier = vk::MemoryBarrier2::builder()
+                .src_stage_mask(vk::PipelineStageFlags2::COPY)
+                .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags2::HOST)
+                .dst_access_mask(vk::AccessFlags2::HOST_READ);
+            let dependency = vk::DependencyInfo::builder()
+                .memory_barriers(std::slice::from_ref(&memory_barrier));
+            self.base.device.cmd_pipeline_barrier2(frame.command_buffer, &dependency);
+            transaction.clear();
+            self.billboards.clear();
+            self.lines.clear();
+            Ok((color_image, semaphores))
+        }
+    }
+
+    ///Record the culling dispatches of every scene.
+    unsafe fn record_cull(&self, command_buffer: vk::CommandBuffer, scene_set: &SceneSet) {
+        self.base.device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            self.cull_pipeline
+        );
+        for (i, scene) in scene_set.scenes.iter().enumerate() {
+            let mut constants: Vec<u8> = self.occlusion_view_projection.iter().flat_map(
+                |x| x.to_le_bytes()
+            ).collect();
+            for x in [
+                scene.nodes.len() as u32,
+                self.draw_indirect_count as u32,
+                self.occlusion_valid as u32,
+                scene_set.layer_mask()
+            ] {
+                constants.extend_from_slice(&x.to_le_bytes());
+            }
+            self.base.device.cmd_push_constants(
+                command_buffer,
+                self.cull_layout.pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                &constants
             );
             self.base.device.cmd_bind_descriptor_sets(
-                frame.command_buffer,
-                vk::PipelineBindPoint::GRAPHICS,
-                self.layouts[1].pipeline_layout,
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.cull_layout.pipeline_layout,
                 0,
-                std::slice::from_ref(&scene_set.skybox_descriptors(self.current_frame)),
+                std::slice::from_ref(&scene_set.cull_descriptors(i, self.current_frame)),
                 &[]
             );
-            self.base.device.cmd_bind_vertex_buffers(
-                frame.command_buffer,
-                0,
-                std::slice::from_ref(&self.skybox_vertex_buffer),
-                &[0]
+            self.base.device.cmd_dispatch(
+                command_buffer,
+                ((scene.nodes.len() + 63) / 64) as u32,
+                1,
+                1
             );
-            self.base.device.cmd_draw(frame.command_buffer, 14, 1, 0, 0);
-            self.base.device.cmd_end_render_pass(frame.command_buffer);
-            //Pre-blitting image transition
+        }
+    }
+
+    /**
+        Show a single scene without setting up a scene set, lit by a neutral procedural sky
+        (see `viewer::default_environment`). The camera starts 4 units along +Z, looking at the origin.
+        Draw the returned viewer with `draw(viewer.scene_set())`.
+    */
+    pub fn show(&self, scene: &Scene) -> Result<Viewer, AllocationError> {
+        Viewer::new(self, scene, viewer::default_environment())
+    }
+
+    ///Like `show`, lit by `environment` (e.g. cubemaps loaded with `Environment::new`).
+    pub fn show_with_environment(&self, scene: &Scene, environment: Environment) -> Result<Viewer, AllocationError> {
+        Viewer::new(self, scene, environment)
+    }
+
+    /**
+        Draw bound scenes & present them to the renderer's window.
+        The instructions proceed as follows:
+        1. Execute transfers
+        2. Acquire swapchain image
+        3. Record graphics command buffer
+            1. Update scene data
+            2. Draw scenes
+            3. Build depth pyramid for the next frame's occlusion culling
+            4. Apply FXAA (if enabled)
+            5. Copy the drawn image to the swapchain image
+
+        The drawn image is only blitted when it matches the swapchain image's format & extent
+        (an exact copy). Otherwise the present pass samples it, decoding sRGB to linear before
+        filtering, & writes the swapchain image, encoding sRGB if its format doesn't.
+        Blitting would filter in sRGB space on many drivers (darkening edges when scaling)
+        & leave linear values in UNORM swapchain images with an sRGB color space.
+    */
+    pub fn draw(&mut self, scene_set: &SceneSet) -> Result<(), AllocationError> {
+        let window_id = self.windows[0].window_id;
+        self.draw_to_windows(&[window_id], scene_set)
+    }
+
+    ///Draw bound scenes & present them to a window added with `add_window` (or the renderer's own).
+    pub fn draw_to_window(&mut self, window_id: u32, scene_set: &SceneSet) -> Result<(), AllocationError> {
+        self.draw_to_windows(&[window_id], scene_set)
+    }
+
+    /**
+        Draw bound scenes once & present the frame to each window (see `draw`), in one submission & present.
+        The drawn image is scaled to each window's swapchain.
+        To show different scene sets (or cameras) in each window, draw each with `draw_to_window`:
+        each draw is a frame of its own, sharing the framebuffer & frame resources.
+        Occlusion culling & transmission carry over from the previous frame only when it was presented
+        to the same first window, so alternating windows draws them without the previous frame's results.
+        Fails with `ERROR_SURFACE_LOST_KHR` if `window_ids` names a window that wasn't added.
+        Panics if `window_ids` is empty or repeats a window.
+    */
+    pub fn draw_to_windows(&mut self, window_ids: &[u32], scene_set: &SceneSet) -> Result<(), AllocationError> {
+        assert!(!window_ids.is_empty(), "At least one window must be drawn to");
+        let targets = window_ids.iter().map(|&id| self.window_index(id)).collect::<Result<Vec<_>, _>>()?;
+        assert!(
+            targets.iter().enumerate().all(|(i, target)| !targets[..i].contains(target)),
+            "Each window can only be drawn to once per frame"
+        );
+        if window_ids[0] != self.last_window {
+            //The previous frame may have shown another scene set or camera
+            self.occlusion_valid = false;
+            self.scene_color_valid = false;
+        }
+        let view_projection = scene_set.camera.projection() * scene_set.camera.view();
+        let mut suboptimal_targets = vec![]; //Recreated after presenting
+        unsafe {
+            //Acquire swapchain images
+            let acquire_start = Instant::now();
+            let mut swapchain_indices = Vec::with_capacity(targets.len());
+            for &target in &targets {
+                let window = &self.windows[target];
+                let (swapchain_index, swapchain_suboptimal) = window.swapchain.loader.acquire_next_image(
+                    window.swapchain.swapchain,
+                    TIMEOUT,
+                    window.semaphores[self.current_frame][0],
+                    vk::Fence::null()
+                ).inspect_err(|&e| self.present_stats.dropped += (e == vk::Result::ERROR_OUT_OF_DATE_KHR) as u64)?;
+                //A suboptimal image is still presented (consuming its acquire semaphore's signal) before recreating
+                if swapchain_suboptimal {
+                    self.present_stats.suboptimal += 1;
+                    suboptimal_targets.push(target);
+                }
+                swapchain_indices.push(swapchain_index);
+            }
+            let acquire = acquire_start.elapsed();
+            let (blit_image, mut semaphores) = self.record_frame(scene_set)?;
+            let frame = &self.framebuffer.frames[self.current_frame];
             let subresource_range = vk::ImageSubresourceRange::builder()
                 .aspect_mask(vk::ImageAspectFlags::COLOR)
                 .base_mip_level(0)
                 .level_count(1)
                 .base_array_layer(0)
                 .layer_count(1);
-            let image_barriers = [
-                //Resolve image
-                *vk::ImageMemoryBarrier2::builder()
-                    .src_stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
-                    .src_access_mask(vk::AccessFlags2::COLOR_ATTACHMENT_WRITE)
-                    .dst_stage_mask(vk::PipelineStageFlags2::BLIT)
-                    .dst_access_mask(vk::AccessFlags2::TRANSFER_READ)
-                    .old_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-                    .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
-                    .src_queue_family_index(self.base.graphics_queue_family)
-                    .dst_queue_family_index(self.base.graphics_queue_family)
-                    .image(frame.images[1])
-                    .subresource_range(*subresource_range),
-                //Swapchain image
-                *vk::ImageMemoryBarrier2::builder()
-                    .src_stage_mask(vk::PipelineStageFlags2::NONE)
-                    .src_access_mask(vk::AccessFlags2::NONE)
-                    .dst_stage_mask(vk::PipelineStageFlags2::BLIT)
-                    .dst_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
-                    .old_layout(vk::ImageLayout::UNDEFINED)
-                    .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
-                    .src_queue_family_index(self.base.graphics_queue_family)
-                    .dst_queue_family_index(self.base.graphics_queue_family)
-                    .image(swapchain_image)
-                    .subresource_range(*subresource_range)
-            ];
-            let dependency = vk::DependencyInfo::builder()
-                .image_memory_barriers(&image_barriers);
-            self.base.device.cmd_pipeline_barrier2(frame.command_buffer, &dependency);
-            //Blitting
-            let subresource_layers = vk::ImageSubresourceLayers::builder()
-                .aspect_mask(vk::ImageAspectFlags::COLOR)
-                .mip_level(0)
-                .base_array_layer(0)
-                .layer_count(1);
-            let regions = vk::ImageBlit2::builder()
-                .src_subresource(*subresource_layers)
-                .src_offsets([
-                    vk::Offset3D::default(),
-                    *vk::Offset3D::builder()
-                        .x(self.framebuffer.extent.width as i32)
-                        .y(self.framebuffer.extent.height as i32)
-                        .z(1)
-                ]).dst_subresource(*subresource_layers)
-                .dst_offsets([
-                    vk::Offset3D::default(),
-                    *vk::Offset3D::builder()
-                        .x(self.swapchain.extent.width as i32)
-                        .y(self.swapchain.extent.height as i32)
-                        .z(1)
-                ]);
-            let blit_info = vk::BlitImageInfo2::builder()
-                .src_image(frame.images[1])
-                .src_image_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
-                .dst_image(swapchain_image)
-                .dst_image_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
-                .regions(std::slice::from_ref(&regions))
-                .filter(vk::Filter::LINEAR);
-            self.base.device.cmd_blit_image2(frame.command_buffer, &blit_info);
-            //Transition swapchain image
-            let image_barrier = vk::ImageMemoryBarrier2::builder()
-                .src_stage_mask(vk::PipelineStageFlags2::BLIT)
-                .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
-                .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
-                .dst_access_mask(vk::AccessFlags2::NONE)
-                .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
-                .new_layout(vk::ImageLayout::PRESENT_SRC_KHR)
-                .src_queue_family_index(self.base.graphics_queue_family)
-                .dst_queue_family_index(self.base.graphics_queue_family)
-                .image(swapchain_image)
-                .subresource_range(*subresource_range);
-            let dependency = vk::DependencyInfo::builder()
-                .image_memory_barriers(std::slice::from_ref(&image_barrier));
-            self.base.device.cmd_pipeline_barrier2(frame.command_buffer, &dependency);
+            let mut images = ImageTracker::new(self.base.graphics_queue_family);
+            let drawn_image = images.track(blit_image, *subresource_range, ImageState::COLOR_ATTACHMENT);
+            for (&target, &swapchain_index) in targets.iter().zip(&swapchain_indices) {
+                let window = &self.windows[target];
+                let swapchain_image = window.swapchain.images[swapchain_index as usize];
+                let blit_compatible = window.blit_compatible(self.framebuffer.extent);
+                if blit_compatible {
+                    //Pre-blitting image transition
+                    let swapchain_image_index = images.track(swapchain_image, *subresource_range, ImageState::UNDEFINED);
+                    images.transition(drawn_image, ImageState::BLIT_SRC);
+                    images.transition(swapchain_image_index, ImageState::BLIT_DST);
+                    images.record(&self.base.device, frame.command_buffer);
+                    //Blitting
+                    let subresource_layers = vk::ImageSubresourceLayers::builder()
+                        .aspect_mask(vk::ImageAspectFlags::COLOR)
+                        .mip_level(0)
+                        .base_array_layer(0)
+                        .layer_count(1);
+                    let regions = vk::ImageBlit2::builder()
+                        .src_subresource(*subresource_layers)
+                        .src_offsets([
+                            vk::Offset3D::default(),
+                            *vk::Offset3D::builder()
+                                .x(self.framebuffer.extent.width as i32)
+                                .y(self.framebuffer.extent.height as i32)
+                                .z(1)
+                        ]).dst_subresource(*subresource_layers)
+                        .dst_offsets([
+                            vk::Offset3D::default(),
+                            *vk::Offset3D::builder()
+                                .x(window.swapchain.extent.width as i32)
+                                .y(window.swapchain.extent.height as i32)
+                                .z(1)
+                        ]);
+                    let blit_info = vk::BlitImageInfo2::builder()
+                        .src_image(blit_image)
+                        .src_image_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                        .dst_image(swapchain_image)
+                        .dst_image_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                        .regions(std::slice::from_ref(&regions))
+                        .filter(vk::Filter::NEAREST); //Same extent, so an exact copy
+                    self.base.device.cmd_blit_image2(frame.command_buffer, &blit_info);
+                    //Transition swapchain image
+                    images.transition(swapchain_image_index, ImageState::PRESENT);
+                    images.record(&self.base.device, frame.command_buffer);
+                } else {
+                    //Drawn image transition
+                    images.transition(drawn_image, ImageState::FRAGMENT_SAMPLED);
+                    images.record(&self.base.device, frame.command_buffer);
+                    //Present pass (leaves the swapchain image ready to present)
+                    let begin_info = vk::RenderPassBeginInfo::builder()
+                        .render_pass(window.swapchain.render_pass)
+                        .framebuffer(window.swapchain.framebuffers[swapchain_index as usize])
+                        .render_area(vk::Rect2D {offset: vk::Offset2D::default(), extent: window.swapchain.extent});
+                    self.base.device.cmd_begin_render_pass(
+                        frame.command_buffer,
+                        &begin_info,
+                        vk::SubpassContents::INLINE
+                    );
+                    set_viewport(&self.base.device, frame.command_buffer, window.swapchain.extent);
+                    self.base.device.cmd_bind_pipeline(
+                        frame.command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        window.present_pipeline
+                    );
+                    self.base.device.cmd_push_constants(
+                        frame.command_buffer,
+                        self.present_layout.pipeline_layout,
+                        vk::ShaderStageFlags::FRAGMENT,
+                        0,
+                        &(window.swapchain.encodes_srgb() as u32).to_le_bytes()
+                    );
+                    self.base.device.cmd_bind_descriptor_sets(
+                        frame.command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        self.present_layout.pipeline_layout,
+                        0,
+                        std::slice::from_ref(&frame.present_descriptor_sets[self.fxaa as usize]),
+                        &[]
+                    );
+                    self.base.device.cmd_draw(frame.command_buffer, 3, 1, 0, 0);
+                    self.base.device.cmd_end_render_pass(frame.command_buffer);
+                }
+                //Wait for the window's image & signal its presentation
+                let present_stage = if blit_compatible {
+                    vk::PipelineStageFlags2::BLIT
+                } else {
+                    vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT
+                };
+                semaphores.waits.push(*vk::SemaphoreSubmitInfo::builder()
+                    .semaphore(window.semaphores[self.current_frame][0])
+                    .stage_mask(present_stage)
+                );
+                semaphores.signals.push(*vk::SemaphoreSubmitInfo::builder()
+                    .semaphore(window.semaphores[self.current_frame][1])
+                    .stage_mask(present_stage)
+                );
+            }
             self.base.device.end_command_buffer(frame.command_buffer)?;
             //Submit to queue
-            let wait_semaphore_infos = [
-                *vk::SemaphoreSubmitInfo::builder()
-                    .semaphore(frame.semaphores[0])
-                    .stage_mask(vk::PipelineStageFlags2::BLIT),
-                *vk::SemaphoreSubmitInfo::builder()
-                    .semaphore(transfer_semaphore)
-                    .value(transfer_semaphore_value)
-                    .stage_mask(vk::PipelineStageFlags2::TRANSFER)
-            ];
             let command_buffer_info = vk::CommandBufferSubmitInfo::builder()
                 .command_buffer(frame.command_buffer);
-            let signal_semaphore_info = vk::SemaphoreSubmitInfo::builder()
-                .semaphore(frame.semaphores[1])
-                .stage_mask(vk::PipelineStageFlags2::BLIT);
             let submit_info = vk::SubmitInfo2::builder()
-                .wait_semaphore_infos(&wait_semaphore_infos)
+                .wait_semaphore_infos(&semaphores.waits)
                 .command_buffer_infos(std::slice::from_ref(&command_buffer_info))
-                .signal_semaphore_infos(std::slice::from_ref(&signal_semaphore_info));
+                .signal_semaphore_infos(&semaphores.signals);
             self.base.device.queue_submit2(
                 self.base.graphics_queue,
                 std::slice::from_ref(&submit_info),
                 frame.fence
             )?;
+            if let Some(compute) = &mut self.async_compute {
+                compute.graphics_submitted();
+            }
             //Presentation
+            let wait_semaphores: Vec<_> = targets.iter().map(
+                |&target| self.windows[target].semaphores[self.current_frame][1]
+            ).collect();
+            let swapchains: Vec<_> = targets.iter().map(|&target| self.windows[target].swapchain.swapchain).collect();
             let present_info = vk::PresentInfoKHR::builder()
-                .wait_semaphores(std::slice::from_ref(&frame.semaphores[1]))
-                .swapchains(std::slice::from_ref(&self.swapchain.swapchain))
-                .image_indices(std::slice::from_ref(&swapchain_index));
-            self.swapchain.loader.queue_present(self.base.graphics_queue, &present_info)?;
+                .wait_semaphores(&wait_semaphores)
+                .swapchains(&swapchains)
+                .image_indices(&swapchain_indices);
+            let present_start = Instant::now();
+            //Every window shares the device's swapchain functions
+            let present_suboptimal = self.windows[0].swapchain.loader.queue_present(self.base.graphics_queue, &present_info)
+                .inspect_err(|&e| self.present_stats.dropped += (e == vk::Result::ERROR_OUT_OF_DATE_KHR) as u64)?;
+            self.present_stats.suboptimal += present_suboptimal as u64;
+            self.present_stats.record(PresentTimes {
+                fence_wait: self.fence_wait,
+                acquire,
+                present: present_start.elapsed()
+            });
         }
         self.current_frame = (self.current_frame + 1) % self.framebuffer.frames.len();
-        transaction.clear();
+        self.last_window = window_ids[0];
+        self.occlusion_view_projection = view_projection;
+        //The depth pyramid only holds view 0
+        self.occlusion_valid = self.view_count == 1 && self.framebuffer.depth_buffer;
+        self.depth_planes = self.framebuffer.depth_buffer.then_some((scene_set.camera.near, scene_set.camera.far));
+        for target in suboptimal_targets {
+            self.recreate_swapchain(target)?;
+        }
         Ok(())
     }
+
+    /**
+        Draw bound scenes once (culling, meshes, skybox & post-processing) & read back the result,
+        blocking until the frame completes. Nothing is presented.
+        The image has the output extent (see `set_render_scale` & `set_supersampling`), independent of the window & swapchain.
+        Occlusion culling is disabled for the frame, so the result depends only on the scene set.
+
+        Intended for regression tests against reference images.
+        Results aren't bit-exact across GPUs & drivers (rasterization rules, filtering & transcendental precision vary),
+        so compare each channel with a small tolerance (e.g. ±2 of 255),
+        & allow a small fraction of pixels (e.g. 0.1%, mostly silhouette edges) to exceed it.
+
+        With several views, this returns view 0 (see `render_views_to_images`).
+    */
+    pub fn render_once_to_image(&mut self, scene_set: &SceneSet) -> Result<image::RgbaImage, AllocationError> {
+        self.render_once(scene_set, 1).map(|mut images| images.remove(0))
+    }
+
+    /**
+        Like `render_once_to_image`, but read back every view (see `with_views`).
+        Only view 0 is post-processed.
+    */
+    pub fn render_views_to_images(&mut self, scene_set: &SceneSet) -> Result<Vec<image::RgbaImage>, AllocationError> {
+        self.render_once(scene_set, self.view_count)
+    }
+
+    /**
+        Read back the depth of the last drawn frame's view 0, as linear view-space depth (distance along
+        the camera direction) in row-major order at the render extent (see `render_extent`).
+        Multisampled depth is resolved into a single-sampled image first, keeping each pixel's first sample.
+        `Camera::projection` maps view depth `z` to `d = far * (z - near) / ((far - near) * z)`,
+        so depth is linearized with `z = near * far / (far - d * (far - near))`
+        using the near & far planes the frame was drawn with (cleared pixels are at `far` with the default clear depth).
+        Waits for the device to be idle. Fails with `DepthCaptureError::NoDepthFrame` without a depth buffer
+        or a frame drawn since the framebuffer was recreated.
+    */
+    pub fn capture_depth(&mut self) -> Result<Vec<f32>, DepthCaptureError> {
+        let (near, far) = self.depth_planes.ok_or(DepthCaptureError::NoDepthFrame)?;
+        //The frame before the current one was drawn last
+        let frame_count = self.framebuffer.frames.len();
+        let resolve = DepthResolve::new(
+            self.base.clone(),
+            &self.framebuffer,
+            (self.current_frame + frame_count - 1) % frame_count
+        )?;
+        let extent = resolve.extent;
+        let size = (extent.width * extent.height) as usize * std::mem::size_of::<f32>();
+        //Readback buffer
+        let create_info = vk::BufferCreateInfo::builder()
+            .size(size as u64)
+            .usage(vk::BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let (buffers, allocation) = self.base.create_buffers(
+            std::slice::from_ref(&create_info),
+            vk::MemoryPropertyFlags::HOST_VISIBLE,
+            MemoryCategory::Staging
+        )?;
+        let buffer = buffers[0];
+        let result = unsafe {
+            //The depth pyramid build left the depth image sampled by compute shaders, as the resolve reads it
+            self.base.device.device_wait_idle().and_then(|_| self.base.submit_once(|command_buffer| {
+                resolve.record(command_buffer);
+                let region = vk::BufferImageCopy2::builder()
+                    .buffer_offset(0)
+                    .image_subresource(*vk::ImageSubresourceLayers::builder()
+                        .aspect_mask(vk::ImageAspectFlags::COLOR)
+                        .mip_level(0)
+                        .base_array_layer(0)
+                        .layer_count(1)
+                    ).image_extent(vk::Extent3D {width: extent.width, height: extent.height, depth: 1});
+                let copy_info = vk::CopyImageToBufferInfo2::builder()
+                    .src_image(resolve.image)
+                    .src_image_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                    .dst_buffer(buffer)
+                    .regions(std::slice::from_ref(&region));
+                self.base.device.cmd_copy_image_to_buffer2(command_buffer, &copy_info);
+                let memory_barrier = vk::MemoryBarrier2::builder()
+                    .src_stage_mask(vk::PipelineStageFlags2::COPY)
+                    .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                    .dst_stage_mask(vk::PipelineStageFlags2::HOST)
+                    .dst_access_mask(vk::AccessFlags2::HOST_READ);
+                let dependency = vk::DependencyInfo::builder()
+                    .memory_barriers(std::slice::from_ref(&memory_barrier));
+                self.base.device.cmd_pipeline_barrier2(command_buffer, &dependency);
+            })).and_then(|_| {
+                //Read depth
+                let ptr = self.base.device.map_memory(
+                    allocation,
+                    0,
+                    vk::WHOLE_SIZE,
+                    vk::MemoryMapFlags::empty()
+                )? as *const f32;
+                if !self.base.memory_properties(allocation).contains(vk::MemoryPropertyFlags::HOST_COHERENT) {
+                    let range = vk::MappedMemoryRange::builder()
+                        .memory(allocation)
+                        .offset(0)
+                        .size(vk::WHOLE_SIZE);
+                    self.base.device.invalidate_mapped_memory_ranges(std::slice::from_ref(&range))?;
+                }
+                let depth = std::slice::from_raw_parts(ptr, (extent.width * extent.height) as usize).iter().map(
+                    |d| near * far / (far - d * (far - near))
+                ).collect();
+                self.base.device.unmap_memory(allocation);
+                Ok(depth)
+            })
+        };
+        unsafe {
+            self.base.device.destroy_buffer(buffer, None);
+            self.base.free_memory(allocation);
+        }
+        result.map_err(DepthCaptureError::from)
+    }
+
+    ///Draw bound scenes once & read back the first `view_count` views.
+    fn render_once(&mut self, scene_set: &SceneSet, view_count: usize) -> Result<Vec<image::RgbaImage>, AllocationError> {
+        let extent = self.framebuffer.extent;
+        let size = (extent.width * extent.height * 4) as usize;
+        //Readback buffer
+        let create_info = vk::BufferCreateInfo::builder()
+            .size((view_count * size) as u64)
+            .usage(vk::BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let (buffers, allocation) = self.base.create_buffers(
+            std::slice::from_ref(&create_info),
+            vk::MemoryPropertyFlags::HOST_VISIBLE,
+            MemoryCategory::Staging
+        )?;
+        let buffer = buffers[0];
+        self.occlusion_valid = false;
+        self.scene_color_valid = false;
+        let result = self.record_frame(scene_set).and_then(
+            |(color_image, semaphores)| unsafe {
+                let frame = &self.framebuffer.frames[self.current_frame];
+                let subresource_range = vk::ImageSubresourceRange::builder()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .base_mip_level(0)
+                    .level_count(1)
+                    .base_array_layer(0)
+                    .layer_count(1);
+                let mut images = ImageTracker::new(self.base.graphics_queue_family);
+                let color_image_index = images.track(color_image, *subresource_range, ImageState::COLOR_ATTACHMENT);
+                images.transition(color_image_index, ImageState::COPY_SRC);
+                //Other views are read from the resolve image's remaining layers
+                if view_count > 1 {
+                    let view_range = vk::ImageSubresourceRange {
+                        base_array_layer: 1,
+                        layer_count: view_count as u32 - 1,
+                        ..*subresource_range
+                    };
+                    let views = images.track(frame.images[1], view_range, ImageState::COLOR_ATTACHMENT);
+                    images.transition(views, ImageState::COPY_SRC);
+                }
+                images.record(&self.base.device, frame.command_buffer);
+                //Copy to readback buffer
+                let region = vk::BufferImageCopy2::builder()
+                    .buffer_offset(0)
+                    .image_subresource(*vk::ImageSubresourceLayers::builder()
+                        .aspect_mask(vk::ImageAspectFlags::COLOR)
+                        .mip_level(0)
+                        .base_array_layer(0)
+                        .layer_count(1)
+                    ).image_extent(vk::Extent3D {width: extent.width, height: extent.height, depth: 1});
+                let copy_info = vk::CopyImageToBufferInfo2::builder()
+                    .src_image(color_image)
+                    .src_image_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                    .dst_buffer(buffer)
+                    .regions(std::slice::from_ref(&region));
+                self.base.device.cmd_copy_image_to_buffer2(frame.command_buffer, &copy_info);
+                if view_count > 1 {
+                    let region = vk::BufferImageCopy2::builder()
+                        .buffer_offset(size as u64)
+                        .image_subresource(*vk::ImageSubresourceLayers::builder()
+                            .aspect_mask(vk::ImageAspectFlags::COLOR)
+                            .mip_level(0)
+                            .base_array_layer(1)
+                            .layer_count(view_count as u32 - 1)
+                        ).image_extent(vk::Extent3D {width: extent.width, height: extent.height, depth: 1});
+                    let copy_info = vk::CopyImageToBufferInfo2::builder()
+                        .src_image(frame.images[1])
+                        .src_image_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                        .dst_buffer(buffer)
+                        .regions(std::slice::from_ref(&region));
+                    self.base.device.cmd_copy_image_to_buffer2(frame.command_buffer, &copy_info);
+                }
+                let memory_barrier = vk::MemoryBarrier2::builder()
+                    .src_stage_mask(vk::PipelineStageFlags2::COPY)
+                    .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                    .dst_stage_mask(vk::PipelineStageFlags2::HOST)
+                    .dst_access_mask(vk::AccessFlags2::HOST_READ);
+                let dependency = vk::DependencyInfo::builder()
+                    .memory_barriers(std::slice::from_ref(&memory_barrier));
+                self.base.device.cmd_pipeline_barrier2(frame.command_buffer, &dependency);
+                self.base.device.end_command_buffer(frame.command_buffer)?;
+                //Submit & wait
+                let command_buffer_info = vk::CommandBufferSubmitInfo::builder()
+                    .command_buffer(frame.command_buffer);
+                let submit_info = vk::SubmitInfo2::builder()
+                    .wait_semaphore_infos(&semaphores.waits)
+                    .command_buffer_infos(std::slice::from_ref(&command_buffer_info))
+                    .signal_semaphore_infos(&semaphores.signals);
+                self.base.device.queue_submit2(
+                    self.base.graphics_queue,
+                    std::slice::from_ref(&submit_info),
+                    frame.fence
+                )?;
+                if let Some(compute) = &mut self.async_compute {
+                    compute.graphics_submitted();
+                }
+                self.base.device.wait_for_fences(
+                    std::slice::from_ref(&frame.fence),
+                    false,
+                    u64::MAX
+                )?;
+                self.depth_planes = self.framebuffer.depth_buffer.then_some(
+                    (scene_set.camera.near, scene_set.camera.far)
+                );
+                //Read pixels (BGRA)
+                let ptr = self.base.device.map_memory(
+                    allocation,
+                    0,
+                    vk::WHOLE_SIZE,
+                    vk::MemoryMapFlags::empty()
+                )? as *const u8;
+                if !self.base.memory_properties(allocation).contains(vk::MemoryPropertyFlags::HOST_COHERENT) {
+                    let range = vk::MappedMemoryRange::builder()
+                        .memory(allocation)
+                        .offset(0)
+                        .size(vk::WHOLE_SIZE);
+                    self.base.device.invalidate_mapped_memory_ranges(std::slice::from_ref(&range))?;
+                }
+                let mut pixels = std::slice::from_raw_parts(ptr, view_count * size).to_vec();
+                self.base.device.unmap_memory(allocation);
+                for pixel in pixels.chunks_exact_mut(4) {
+                    pixel.swap(0, 2);
+                }
+                Ok(pixels.chunks_exact(size).map(|view| downsample(
+                    image::RgbaImage::from_raw(extent.width, extent.height, view.to_vec()).unwrap(),
+                    self.supersampling
+                )).collect())
+            }
+        );
+        unsafe {
+            self.base.device.destroy_buffer(buffer, None);
+            self.base.free_memory(allocation);
+        }
+        self.current_frame = (self.current_frame + 1) % self.framebuffer.frames.len();
+        self.occlusion_valid = false;
+        result
+    }
+}
+
+const DFG_TEXEL_SIZE: usize = 8; //R16G16B16A16_SFLOAT
+
+///Render extent at a render scale & supersampling factor.
+fn scaled_extent(scale: f32, supersampling: u32) -> vk::Extent2D {
+    vk::Extent2D {
+        width: ((BASE_RENDER_EXTENT.width as f32 * scale).round() as u32).max(1) * supersampling,
+        height: ((BASE_RENDER_EXTENT.height as f32 * scale).round() as u32).max(1) * supersampling
+    }
+}
+
+/**
+    Highest sample count up to `samples` usable for the color attachment & the depth attachment
+    (which the depth pyramid samples) of the main render pass.
+*/
+fn supported_samples(base: &Base, samples: vk::SampleCountFlags, depth_buffer: bool) -> vk::SampleCountFlags {
+    let limits = &base.physical_device_properties.limits;
+    let mut supported = limits.framebuffer_color_sample_counts;
+    if depth_buffer {
+        supported &= limits.framebuffer_depth_sample_counts & limits.sampled_image_depth_sample_counts;
+    }
+    //Sample count flags are single bits, each twice the previous count (keep the highest one requested)
+    let mut count = 1 << (31 - samples.as_raw().max(1).leading_zeros());
+    while count > 1 && !supported.contains(vk::SampleCountFlags::from_raw(count)) {
+        count >>= 1;
+    }
+    vk::SampleCountFlags::from_raw(count)
+}
+
+///Box filter an sRGB image by `factor` per axis, averaging color in linear space.
+fn downsample(image: image::RgbaImage, factor: u32) -> image::RgbaImage {
+    if factor == 1 {
+        return image;
+    }
+    let decode = |c: u8| {
+        let c = c as f32 / 255.0;
+        if c <= 0.04045 {c / 12.92} else {((c + 0.055) / 1.055).powf(2.4)}
+    };
+    let encode = |c: f32| {
+        let c = if c <= 0.0031308 {c * 12.92} else {1.055 * c.powf(1.0 / 2.4) - 0.055};
+        (c * 255.0).round().clamp(0.0, 255.0) as u8
+    };
+    let samples = (factor * factor) as f32;
+    image::RgbaImage::from_fn(image.width() / factor, image.height() / factor, |x, y| {
+        let mut sum = [0.0; 4];
+        for (i, j) in (0..factor).flat_map(|i| (0..factor).map(move |j| (i, j))) {
+            let pixel = image.get_pixel(x * factor + i, y * factor + j);
+            for c in 0..3 {
+                sum[c] += decode(pixel[c]);
+            }
+            sum[3] += pixel[3] as f32 / 255.0;
+        }
+        image::Rgba([
+            encode(sum[0] / samples),
+            encode(sum[1] / samples),
+            encode(sum[2] / samples),
+            (sum[3] / samples * 255.0).round() as u8
+        ])
+    })
+}
+
+///Set the viewport & scissor (dynamic in every graphics pipeline) to cover `extent`.
+unsafe fn set_viewport(device: &ash::Device, command_buffer: vk::CommandBuffer, extent: vk::Extent2D) {
+    let viewport = vk::Viewport::builder()
+        .width(extent.width as f32)
+        .height(extent.height as f32)
+        .min_depth(0.0)
+        .max_depth(1.0);
+    let scissor = vk::Rect2D::builder().extent(extent);
+    device.cmd_set_viewport(command_buffer, 0, std::slice::from_ref(&viewport));
+    device.cmd_set_scissor(command_buffer, 0, std::slice::from_ref(&scissor));
+}
+
+///Create & upload the DFG lookup image (R16G16B16A16_SFLOAT).
+fn create_dfg_lookup(
+    base: &Base,
+    transaction: &mut Transaction,
+    data: &[u8],
+    extent: vk::Extent2D
+) -> Result<(vk::Image, vk::ImageView, vk::DeviceMemory), AllocationError> {
+    let extent = vk::Extent3D::builder().width(extent.width).height(extent.height).depth(1);
+    let create_info = vk::ImageCreateInfo::builder()
+        .image_type(vk::ImageType::TYPE_2D)
+        .format(vk::Format::R16G16B16A16_SFLOAT)
+        .extent(*extent)
+        .mip_levels(1)
+        .array_layers(1)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE)
+        .initial_layout(vk::ImageLayout::UNDEFINED);
+    let (images, allocation) = base.create_images(
+        std::slice::from_ref(&create_info),
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        MemoryCategory::Environment
+    )?;
+    //Write to DFG lookup texture
+    let subresource_range = vk::ImageSubresourceRange::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(0)
+        .layer_count(1);
+    let subresource = vk::ImageSubresourceLayers::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .mip_level(0)
+        .base_array_layer(0)
+        .layer_count(1);
+    let region = vk::BufferImageCopy2::builder()
+        .buffer_offset(0)
+        .image_subresource(*subresource)
+        .image_offset(vk::Offset3D::default())
+        .image_extent(*extent);
+    transaction.image_write(
+        data,
+        images[0],
+        *subresource_range,
+        std::slice::from_ref(&region),
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+    );
+    //DGF lookup image view
+    let component_mapping = vk::ComponentMapping::builder()
+        .r(vk::ComponentSwizzle::IDENTITY)
+        .g(vk::ComponentSwizzle::IDENTITY)
+        .b(vk::ComponentSwizzle::IDENTITY)
+        .a(vk::ComponentSwizzle::IDENTITY);
+    let create_info = vk::ImageViewCreateInfo::builder()
+        .image(images[0])
+        .view_type(vk::ImageViewType::TYPE_2D)
+        .format(vk::Format::R16G16B16A16_SFLOAT)
+        .components(*component_mapping)
+        .subresource_range(*subresource_range);
+    let view = unsafe {
+        base.device.create_image_view(&create_info, None)?
+    };
+    Ok((images[0], view, allocation))
 }
 
 impl Drop for Renderer {
@@ -548,12 +2895,116 @@ impl Drop for Renderer {
         unsafe {
             self.base.device.device_wait_idle().unwrap();
             self.base.device.destroy_pipeline(self.cull_pipeline, None);
+            self.base.device.destroy_pipeline(self.hiz_pipeline, None);
             self.base.device.destroy_buffer(self.skybox_vertex_buffer, None);
-            self.base.device.free_memory(self.skybox_vertex_alloc, None);
+            self.base.free_memory(self.skybox_vertex_alloc);
+            self.base.device.destroy_buffer(self.billboard_buffer, None);
+            self.base.free_memory(self.billboard_alloc);
+            self.base.device.destroy_buffer(self.line_buffer, None);
+            self.base.free_memory(self.line_alloc);
             self.base.device.destroy_sampler(self.dfg_lookup_sampler, None);
             self.base.device.destroy_image_view(self.dfg_lookup_view, None);
             self.base.device.destroy_image(self.dfg_lookup, None);
-            self.base.device.free_memory(self.dfg_lookup_alloc, None);
+            self.base.free_memory(self.dfg_lookup_alloc);
+            self.base.device.destroy_sampler(self.texture_sampler, None);
         }
     }
 }
+
+/*
+    GPU tests, ignored by default as they need a Vulkan device & a video driver for SDL.
+    SDL must stay on one thread, so run them with `cargo test -- --ignored --test-threads=1`.
+*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Unit cube (half extent 1) with flat normals
+    const CUBE_OBJ: &str = "\
+v -1 -1 -1\nv 1 -1 -1\nv 1 1 -1\nv -1 1 -1\nv -1 -1 1\nv 1 -1 1\nv 1 1 1\nv -1 1 1
+vn 0 0 1\nvn 0 0 -1\nvn 1 0 0\nvn -1 0 0\nvn 0 1 0\nvn 0 -1 0
+f 5//1 6//1 7//1 8//1\nf 2//2 1//2 4//2 3//2\nf 6//3 2//3 3//3 7//3
+f 1//4 5//4 8//4 4//4\nf 8//5 7//5 3//5 4//5\nf 1//6 2//6 6//6 5//6
+";
+
+    ///Cube without a material, scaled by `scale` & moved to `pos`.
+    fn cube(pos: na::Vector3<f32>, scale: f32) -> Scene {
+        let path = std::env::temp_dir().join(format!("graphics-cube-{}.obj", std::process::id()));
+        std::fs::write(&path, CUBE_OBJ).unwrap();
+        let mut scene = Scene::load_obj(&path).unwrap();
+        scene.nodes[0].translation = pos.into();
+        scene.nodes[0].scale = na::Scale3::new(scale, scale, scale);
+        scene
+    }
+
+    fn with_renderer(test: impl FnOnce(&mut Renderer)) {
+        let sdl = sdl2::init().unwrap();
+        let video = sdl.video().unwrap();
+        let window = video.window("Test", 64, 64).vulkan().hidden().build().unwrap();
+        test(&mut Renderer::new(&window).unwrap());
+    }
+
+    #[test]
+    #[ignore]
+    fn material_less_cube_is_gray() {
+        with_renderer(|renderer| {
+            let viewer = renderer.show(&cube(na::Vector3::zeros(), 1.0)).unwrap();
+            let image = renderer.render_once_to_image(viewer.scene_set()).unwrap();
+            let [r, g, b, _] = image.get_pixel(image.width() / 2, image.height() / 2).0.map(i32::from);
+            //Lit by the neutral default sky, so the channels stay close (a red default would make `r` dominate)
+            assert!(r > 32, "Cube isn't lit: {:?}", (r, g, b));
+            assert!((r - g).abs() <= 16 && (r - b).abs() <= 32, "Cube isn't gray: {:?}", (r, g, b));
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn skybox_never_occludes() {
+        with_renderer(|renderer| {
+            //Small cube just in front of the far plane (64), under a uniformly blue sky
+            let blue = na::Vector3::new(0.0, 0.0, 1.0);
+            let environment = Environment::gradient(blue, blue, blue);
+            let mut viewer = renderer.show_with_environment(&cube(na::Vector3::new(0.0, 0.0, -60.0), 1.0), environment).unwrap();
+            viewer.camera_mut().pos = na::Point3::origin();
+            let image = renderer.render_once_to_image(viewer.scene_set()).unwrap();
+            let sky = image.get_pixel(0, 0).0;
+            let center = image.get_pixel(image.width() / 2, image.height() / 2).0;
+            //The cube reflects half the sky (its albedo), so it's clearly darker wherever it isn't covered
+            assert!(i32::from(center[2]) + 32 < i32::from(sky[2]), "Cube is covered by the skybox: {:?}", (center, sky));
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn pick_cube() {
+        with_renderer(|renderer| {
+            let scene = cube(na::Vector3::new(0.0, 0.0, -1.0), 0.5);
+            let viewer = renderer.show(&scene).unwrap();
+            //The front face is at z = -0.5
+            let hit = viewer.scene_set().pick(0, &scene, na::Point3::new(0.1, 0.2, 4.0), -na::Vector3::z_axis()).unwrap();
+            assert_eq!(hit.node, 0);
+            assert!((hit.distance - 4.5).abs() < 1e-5, "{}", hit.distance);
+            assert!((hit.position - na::Point3::new(0.1, 0.2, -0.5)).norm() < 1e-5);
+            //Past the cube's edge
+            assert!(viewer.scene_set().pick(0, &scene, na::Point3::new(0.6, 0.0, 4.0), -na::Vector3::z_axis()).is_none());
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn capture_cube_depth() {
+        with_renderer(|renderer| {
+            assert!(matches!(renderer.capture_depth(), Err(DepthCaptureError::NoDepthFrame)));
+            //The camera starts at z = 4, so the front face is 3 units away
+            let viewer = renderer.show(&cube(na::Vector3::zeros(), 1.0)).unwrap();
+            renderer.render_once_to_image(viewer.scene_set()).unwrap();
+            let depth = renderer.capture_depth().unwrap();
+            let extent = renderer.render_extent();
+            assert_eq!(depth.len(), (extent.width * extent.height) as usize);
+            let center = depth[(extent.height / 2 * extent.width + extent.width / 2) as usize];
+            assert!((center - 3.0).abs() < 1e-3, "{}", center);
+            let far = viewer.scene_set().camera.far;
+            assert!((depth[0] - far).abs() < 1e-2 * far, "{}", depth[0]);
+        });
+    }
+}
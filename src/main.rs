@@ -1,6 +1,5 @@
-use graphics::Renderer;
+use graphics::{Renderer, DebugView};
 use graphics::scene::Scene;
-use graphics::scene_set::SceneSet;
 use graphics::environment::Environment;
 
 struct Inputs {
@@ -22,7 +21,13 @@ fn main() {
     let sdl = sdl2::init().unwrap();
     let video = sdl.video().unwrap();
     let window = video.window("Graphics", 1024, 1024).vulkan().resizable().build().unwrap();
-    let mut renderer = Renderer::new(&window).expect("Renderer creation error");
+    //Pipeline cache (next to the executable)
+    let mut pipeline_cache_path = std::env::current_exe().unwrap();
+    pipeline_cache_path.pop();
+    pipeline_cache_path.push("pipeline-cache.bin");
+    let pipeline_cache = std::fs::read(&pipeline_cache_path).ok();
+    let mut renderer = Renderer::with_pipeline_cache(&window, 1, pipeline_cache.as_deref())
+        .expect("Renderer creation error");
     //Load scene
     let mut path = std::env::current_exe().unwrap();
     path.pop();
@@ -32,7 +37,8 @@ fn main() {
     //path.push("DamagedHelmet.glb");
     //path.push("SciFiHelmet.gltf");
     //path.push("bottle.glb");
-    let scene = Scene::load_gltf(path).unwrap();
+    let mut scene = Scene::load_gltf(path).unwrap();
+    scene.optimize();
     let environment = Environment::new(
         renderer.base.clone(),
         &mut renderer.transaction.borrow_mut(),
@@ -40,16 +46,14 @@ fn main() {
         include_bytes!("../assets/diffuse.ktx2"),
         include_bytes!("../assets/specular.ktx2")
     ).unwrap();
-    let mut scene_set = SceneSet::new(&renderer, environment).unwrap();
-    scene_set.push_scene(&scene, &renderer);
-    scene_set.camera.pos[2] = 4.0;
+    let mut viewer = renderer.show_with_environment(&scene, environment).unwrap();
     /*
-    scene_set.lights[0] = PointLight {
+    viewer.scene_set_mut().lights.push(PointLight {
         pos: [1.0, 0.0, 1.0, 0.0],
         color: [1.0, 1.0, 1.0, 1.0],
         intensity: 0.0,
         range: 8.0
-    };
+    });
     */
     //Event loop
     let mut event_pump = sdl.event_pump().unwrap();
@@ -89,6 +93,11 @@ fn main() {
                     sdl2::keyboard::Keycode::Down => inputs.pitch_down = true,
                     sdl2::keyboard::Keycode::Left => inputs.yaw_left = true,
                     sdl2::keyboard::Keycode::Right => inputs.yaw_right = true,
+                    //Debug view
+                    sdl2::keyboard::Keycode::B => renderer.set_debug_view(match renderer.debug_view() {
+                        DebugView::Shaded => DebugView::Backfaces,
+                        DebugView::Backfaces => DebugView::Shaded
+                    }),
                     //Quit
                     sdl2::keyboard::Keycode::Q => break 'main,
                     _ => ()
@@ -123,15 +132,19 @@ fn main() {
         if inputs.right {direction[1] += speed * delta.as_secs_f32();}
         if inputs.up {direction[2] += speed * delta.as_secs_f32();}
         if inputs.down {direction[2] -= speed * delta.as_secs_f32();}
-        scene_set.camera.locomote(direction[0], direction[1], direction[2]);
+        viewer.camera_mut().locomote(direction[0], direction[1], direction[2]);
         //Rotation
         let mut rotation = [0.0; 2];
         if inputs.pitch_up {rotation[0] += 1.0 * delta.as_secs_f32();}
         if inputs.pitch_down {rotation[0] -= 1.0 * delta.as_secs_f32();}
         if inputs.yaw_left {rotation[1] += 1.0 * delta.as_secs_f32();}
         if inputs.yaw_right {rotation[1] -= 1.0 * delta.as_secs_f32();}
-        scene_set.camera.rotate(rotation[0], rotation[1]);
+        viewer.camera_mut().rotate(rotation[0], rotation[1]);
         //Draw
-        renderer.draw(&scene_set).unwrap();
+        renderer.draw(viewer.scene_set()).unwrap();
+    }
+    //Save pipeline cache (best effort, e.g. the directory may be read-only)
+    if let Ok(data) = renderer.pipeline_cache_data() {
+        let _ = std::fs::write(pipeline_cache_path, data);
     }
 }
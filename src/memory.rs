@@ -0,0 +1,104 @@
+use ash::vk;
+
+///What a device memory allocation is used for.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MemoryCategory {
+    Scenes, //Scene geometry, textures & per-scene buffers
+    Environment, //Environment cubemaps, skybox & lookup tables
+    Framebuffer, //Render targets
+    Staging //Transfer staging buffers
+}
+
+///Device memory held in one memory heap.
+#[derive(Clone, Copy, Debug)]
+pub struct HeapUsage {
+    pub flags: vk::MemoryHeapFlags,
+    pub size: vk::DeviceSize, //Total heap size
+    pub allocated: vk::DeviceSize, //Allocated by the renderer
+    //Reported by `VK_EXT_memory_budget` (usage includes other processes)
+    pub budget: Option<vk::DeviceSize>,
+    pub usage: Option<vk::DeviceSize>
+}
+
+///Device memory held by the renderer.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryUsage {
+    //By category
+    pub scenes: vk::DeviceSize,
+    pub environment: vk::DeviceSize,
+    pub framebuffer: vk::DeviceSize,
+    pub staging: vk::DeviceSize,
+    //By heap
+    pub heaps: Vec<HeapUsage>,
+    pub allocation_count: u32,
+    pub max_allocation_count: u32 //`maxMemoryAllocationCount`
+}
+
+impl MemoryUsage {
+    ///Total device memory allocated by the renderer.
+    pub fn total(&self) -> vk::DeviceSize {
+        self.scenes + self.environment + self.framebuffer + self.staging
+    }
+}
+
+///Why no memory type could satisfy an allocation (see `AllocationError`).
+#[derive(Clone, Debug)]
+pub struct MemoryTypeError {
+    pub category: MemoryCategory,
+    pub properties: vk::MemoryPropertyFlags, //Requested property flags
+    pub supported_memory_types: u32, //Memory type bits allowed by every resource in the allocation
+    pub memory_types: Vec<vk::MemoryType>,
+    pub memory_heaps: Vec<vk::MemoryHeap>
+}
+
+impl std::fmt::Display for MemoryTypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(
+            f,
+            "No memory type for {:?} allocation with properties {:?} (supported types: {:#b})",
+            self.category,
+            self.properties,
+            self.supported_memory_types
+        )?;
+        for (i, memory_type) in self.memory_types.iter().enumerate() {
+            let supported = (self.supported_memory_types >> i) & 1 == 1;
+            writeln!(
+                f,
+                "  Type {}{}: heap {}, {:?}",
+                i,
+                if supported {""} else {" (unsupported)"},
+                memory_type.heap_index,
+                memory_type.property_flags
+            )?;
+        }
+        for (i, heap) in self.memory_heaps.iter().enumerate() {
+            writeln!(f, "  Heap {}: {} MiB, {:?}", i, heap.size >> 20, heap.flags)?;
+        }
+        Ok(())
+    }
+}
+
+///Failure of an operation which allocates device memory.
+#[derive(Clone, Debug)]
+pub enum AllocationError {
+    ///No memory type has the requested properties, even without `DEVICE_LOCAL`
+    NoMemoryType(Box<MemoryTypeError>),
+    Vulkan(vk::Result)
+}
+
+impl From<vk::Result> for AllocationError {
+    fn from(result: vk::Result) -> Self {
+        Self::Vulkan(result)
+    }
+}
+
+impl std::fmt::Display for AllocationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::NoMemoryType(error) => error.fmt(f),
+            Self::Vulkan(result) => result.fmt(f)
+        }
+    }
+}
+
+impl std::error::Error for AllocationError {}
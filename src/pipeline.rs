@@ -1,17 +1,137 @@
 use ash::vk;
 use super::base::Base;
-use std::rc::Rc;
+use std::sync::Arc;
 
 pub mod mesh;
 pub mod skybox;
+pub mod billboard;
 pub mod cull;
+pub mod fxaa;
+pub mod hiz;
+pub mod depth_resolve;
+pub mod shadow;
+pub mod present;
+
+//Shaders committed by `CompiledShaders::commit`, keyed by source file name
+//(global, since renderers may move to another thread)
+#[cfg(feature = "hot-reload")]
+static SHADER_OVERRIDES: std::sync::Mutex<std::collections::BTreeMap<String, Vec<u32>>> =
+    std::sync::Mutex::new(std::collections::BTreeMap::new());
+
+//Shaders pipelines are being built with on this thread, before they're committed (see `CompiledShaders::build_with`)
+#[cfg(feature = "hot-reload")]
+thread_local! {
+    static PENDING_SHADERS: std::cell::RefCell<Option<std::collections::BTreeMap<String, Vec<u32>>>> =
+        const {std::cell::RefCell::new(None)};
+}
+
+///Get the SPIR-V for a shader, preferring a runtime-compiled version if one exists.
+pub(crate) fn load_shader(name: &str, bundled: &[u8]) -> Vec<u32> {
+    #[cfg(feature = "hot-reload")]
+    {
+        //Pending shaders replace the committed ones entirely
+        let pending = PENDING_SHADERS.with(|pending| pending.borrow().as_ref().map(|shaders| shaders.get(name).cloned()));
+        if let Some(code) = pending.unwrap_or_else(|| SHADER_OVERRIDES.lock().unwrap().get(name).cloned()) {
+            return code;
+        }
+    }
+    #[cfg(not(feature = "hot-reload"))]
+    let _ = name;
+    ash::util::read_spv(&mut std::io::Cursor::new(bundled)).unwrap()
+}
+
+///Shaders compiled by `compile_shaders`, keyed by source file name.
+#[cfg(feature = "hot-reload")]
+pub(crate) struct CompiledShaders(std::collections::BTreeMap<String, Vec<u32>>);
+
+#[cfg(feature = "hot-reload")]
+impl CompiledShaders {
+    ///Build pipelines with these shaders on this thread, without committing them.
+    pub fn build_with<T>(&self, build: impl FnOnce() -> T) -> T {
+        PENDING_SHADERS.with(|pending| *pending.borrow_mut() = Some(self.0.clone()));
+        let result = build();
+        PENDING_SHADERS.with(|pending| *pending.borrow_mut() = None);
+        result
+    }
+
+    ///Load these shaders for every pipeline built from now on.
+    pub fn commit(self) {
+        *SHADER_OVERRIDES.lock().unwrap() = self.0;
+    }
+}
+
+/**
+    Compile every shader in the `shaders` source directory.
+    Fails with the compiler messages unless all shaders compile.
+    Nothing is replaced until the result is committed (see `CompiledShaders::commit`).
+*/
+#[cfg(feature = "hot-reload")]
+pub(crate) fn compile_shaders() -> Result<CompiledShaders, String> {
+    let shader_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("shaders");
+    let compiler = shaderc::Compiler::new().ok_or("Error creating shader compiler")?;
+    let mut compiled = std::collections::BTreeMap::new();
+    let mut errors = String::new();
+    for entry in shader_dir.read_dir().map_err(|e| e.to_string())? {
+        let filename = entry.map_err(|e| e.to_string())?.path();
+        let shader_type = match filename.extension().and_then(|e| e.to_str()) {
+            Some("vert") => shaderc::ShaderKind::Vertex,
+            Some("frag") => shaderc::ShaderKind::Fragment,
+            Some("comp") => shaderc::ShaderKind::Compute,
+            _ => continue
+        };
+        let name = filename.file_name().unwrap().to_string_lossy().into_owned();
+        let source_text = match std::fs::read_to_string(&filename) {
+            Ok(text) => text,
+            Err(e) => {
+                errors += &format!("{}: {}\n", name, e);
+                continue;
+            }
+        };
+        match compiler.compile_into_spirv(&source_text, shader_type, &name, "main", None) {
+            Ok(module) => {compiled.insert(name, module.as_binary().to_vec());},
+            Err(e) => errors += &format!("{}\n", e)
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(CompiledShaders(compiled))
+}
 
 pub struct PipelineLayout {
-    base: Rc<Base>,
+    base: Arc<Base>,
     pub samplers: Vec<vk::Sampler>,
     pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_counts: Vec<vk::DescriptorPoolSize>, //Descriptors of each type in one set (see `descriptor_counts`)
     pub pipeline_layout: vk::PipelineLayout,
-    pub create_pipeline: fn(&Self, vk::Extent2D, vk::RenderPass) -> Result<vk::Pipeline, vk::Result>
+    pub samples: vk::SampleCountFlags, //Rasterization samples of the pipelines (or the depth images read)
+    pub create_pipeline: fn(&Self, vk::RenderPass) -> Result<vk::Pipeline, vk::Result>
+}
+
+///Count the descriptors of each type in a descriptor set layout's bindings, e.g. to size descriptor pools.
+pub fn descriptor_counts(bindings: &[vk::DescriptorSetLayoutBinding]) -> Vec<vk::DescriptorPoolSize> {
+    let mut counts = vec![];
+    for binding in bindings {
+        let count = vk::DescriptorPoolSize {
+            ty: binding.descriptor_type,
+            descriptor_count: binding.descriptor_count
+        };
+        add_descriptor_counts(&mut counts, std::slice::from_ref(&count), 1);
+    }
+    counts
+}
+
+///Add `set_count` sets' worth of descriptor counts to pool sizes.
+pub fn add_descriptor_counts(pool_sizes: &mut Vec<vk::DescriptorPoolSize>, counts: &[vk::DescriptorPoolSize], set_count: u32) {
+    for count in counts {
+        match pool_sizes.iter_mut().find(|size| size.ty == count.ty) {
+            Some(size) => size.descriptor_count += set_count * count.descriptor_count,
+            None => pool_sizes.push(*vk::DescriptorPoolSize::builder()
+                .ty(count.ty)
+                .descriptor_count(set_count * count.descriptor_count)
+            )
+        }
+    }
 }
 
 impl Drop for PipelineLayout {
@@ -0,0 +1,288 @@
+use ash::vk;
+use crate::base::Base;
+use crate::scene::DebugLine;
+use super::PipelineLayout;
+use std::sync::Arc;
+
+pub fn create_layout(base: Arc<Base>, samples: vk::SampleCountFlags) -> Result<PipelineLayout, vk::Result> {
+    //Descriptor set layout
+    let bindings = [
+        //Camera
+        *vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::VERTEX),
+        //Billboards
+        *vk::DescriptorSetLayoutBinding::builder()
+            .binding(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::VERTEX)
+    ];
+    let create_info = vk::DescriptorSetLayoutCreateInfo::builder()
+        .bindings(&bindings);
+    let descriptor_set_layout = unsafe {
+        base.device.create_descriptor_set_layout(&create_info, None)?
+    };
+    //Pipeline layout
+    let create_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(std::slice::from_ref(&descriptor_set_layout));
+    let pipeline_layout = unsafe {
+        base.device.create_pipeline_layout(&create_info, None)?
+    };
+    Ok(PipelineLayout {
+        base,
+        samplers: vec![],
+        descriptor_set_layout,
+        descriptor_counts: super::descriptor_counts(&bindings),
+        pipeline_layout,
+        samples,
+        create_pipeline: create_pipeline
+    })
+}
+
+fn create_pipeline(
+    layout: &PipelineLayout,
+    render_pass: vk::RenderPass
+) -> Result<vk::Pipeline, vk::Result> {
+    let base = &layout.base;
+    //Pipeline
+    //Shaders
+    let code = super::load_shader("billboard.vert", include_bytes!("../../spv/billboard.vert.spv"));
+    let create_info = vk::ShaderModuleCreateInfo::builder().code(&code);
+    let vertex_shader = unsafe {
+        base.device.create_shader_module(&create_info, None)?
+    };
+    let code = super::load_shader("billboard.frag", include_bytes!("../../spv/billboard.frag.spv"));
+    let create_info = vk::ShaderModuleCreateInfo::builder().code(&code);
+    let fragment_shader = unsafe {
+        base.device.create_shader_module(&create_info, None)?
+    };
+    let shader_stages = [
+        *vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .module(vertex_shader)
+            .name(unsafe {std::ffi::CStr::from_bytes_with_nul_unchecked(b"main\0")}),
+        *vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::FRAGMENT)
+            .module(fragment_shader)
+            .name(unsafe {std::ffi::CStr::from_bytes_with_nul_unchecked(b"main\0")})
+    ];
+    //Fixed functions
+    //Vertex input (quads are expanded from instance data)
+    let vertex_input = vk::PipelineVertexInputStateCreateInfo::builder();
+    //Input assembly
+    let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_STRIP);
+    //Viewport (dynamic, set when drawing)
+    let viewport = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+    //Rasterization
+    let rasterization = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .line_width(1.0);
+    //Multisampling
+    let multisample = vk::PipelineMultisampleStateCreateInfo::builder()
+        .rasterization_samples(layout.samples);
+    //Depth stencil
+    let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true)
+        .depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL);
+    //Color blending
+    let color_blend_attachments = [
+        *vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ONE)
+            .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
+            .alpha_blend_op(vk::BlendOp::ADD)
+            .color_write_mask(vk::ColorComponentFlags::RGBA)
+    ];
+    let color_blend = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .attachments(&color_blend_attachments);
+    //Dynamic state
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder()
+        .dynamic_states(&dynamic_states);
+    //Create pipeline
+    let create_infos = [
+        *vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input)
+            .input_assembly_state(&input_assembly)
+            .viewport_state(&viewport)
+            .rasterization_state(&rasterization)
+            .multisample_state(&multisample)
+            .depth_stencil_state(&depth_stencil)
+            .color_blend_state(&color_blend)
+            .dynamic_state(&dynamic_state)
+            .layout(layout.pipeline_layout)
+            .render_pass(render_pass)
+            .subpass(0)
+    ];
+    let pipelines = match unsafe {
+        base.device.create_graphics_pipelines(
+            base.pipeline_cache,
+            &create_infos,
+            None
+        )
+    } {
+        Ok(v) => v,
+        Err(e) => {return Err(e.1);}
+    };
+    //Destroy shader modules
+    unsafe {
+        base.device.destroy_shader_module(vertex_shader, None);
+        base.device.destroy_shader_module(fragment_shader, None);
+    }
+    Ok(pipelines[0])
+}
+
+/**
+    Create the debug line pipeline, drawing `DebugLine`s (one instance of 2 vertices each)
+    with the billboard layout, of which it only reads the camera.
+    Line width & depth bias are dynamic (see `Renderer::set_line_style`).
+*/
+pub fn create_line_pipeline(
+    layout: &PipelineLayout,
+    render_pass: vk::RenderPass
+) -> Result<vk::Pipeline, vk::Result> {
+    let base = &layout.base;
+    //Pipeline
+    //Shaders
+    let code = super::load_shader("line.vert", include_bytes!("../../spv/line.vert.spv"));
+    let create_info = vk::ShaderModuleCreateInfo::builder().code(&code);
+    let vertex_shader = unsafe {
+        base.device.create_shader_module(&create_info, None)?
+    };
+    let code = super::load_shader("billboard.frag", include_bytes!("../../spv/billboard.frag.spv"));
+    let create_info = vk::ShaderModuleCreateInfo::builder().code(&code);
+    let fragment_shader = unsafe {
+        base.device.create_shader_module(&create_info, None)?
+    };
+    let shader_stages = [
+        *vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .module(vertex_shader)
+            .name(unsafe {std::ffi::CStr::from_bytes_with_nul_unchecked(b"main\0")}),
+        *vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::FRAGMENT)
+            .module(fragment_shader)
+            .name(unsafe {std::ffi::CStr::from_bytes_with_nul_unchecked(b"main\0")})
+    ];
+    //Fixed functions
+    //Vertex input (each instance is a line)
+    let binding = vk::VertexInputBindingDescription::builder()
+        .binding(0)
+        .stride(std::mem::size_of::<DebugLine>() as u32)
+        .input_rate(vk::VertexInputRate::INSTANCE);
+    let attributes = [
+        //Start
+        *vk::VertexInputAttributeDescription::builder()
+            .location(0)
+            .binding(0)
+            .format(vk::Format::R32G32B32_SFLOAT)
+            .offset(0),
+        //End
+        *vk::VertexInputAttributeDescription::builder()
+            .location(1)
+            .binding(0)
+            .format(vk::Format::R32G32B32_SFLOAT)
+            .offset(12),
+        //Color
+        *vk::VertexInputAttributeDescription::builder()
+            .location(2)
+            .binding(0)
+            .format(vk::Format::R32G32B32A32_SFLOAT)
+            .offset(24)
+    ];
+    let vertex_input = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_binding_descriptions(std::slice::from_ref(&binding))
+        .vertex_attribute_descriptions(&attributes);
+    //Input assembly
+    let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::LINE_LIST);
+    //Viewport (dynamic, set when drawing)
+    let viewport = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+    //Rasterization
+    let rasterization = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .depth_bias_enable(true) //Factors are dynamic
+        .line_width(1.0); //Dynamic
+    //Multisampling
+    let multisample = vk::PipelineMultisampleStateCreateInfo::builder()
+        .rasterization_samples(layout.samples);
+    //Depth stencil
+    let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true)
+        .depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL);
+    //Color blending
+    let color_blend_attachments = [
+        *vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ONE)
+            .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
+            .alpha_blend_op(vk::BlendOp::ADD)
+            .color_write_mask(vk::ColorComponentFlags::RGBA)
+    ];
+    let color_blend = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .attachments(&color_blend_attachments);
+    //Dynamic state
+    let dynamic_states = [
+        vk::DynamicState::VIEWPORT,
+        vk::DynamicState::SCISSOR,
+        vk::DynamicState::LINE_WIDTH,
+        vk::DynamicState::DEPTH_BIAS
+    ];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder()
+        .dynamic_states(&dynamic_states);
+    //Create pipeline
+    let create_infos = [
+        *vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input)
+            .input_assembly_state(&input_assembly)
+            .viewport_state(&viewport)
+            .rasterization_state(&rasterization)
+            .multisample_state(&multisample)
+            .depth_stencil_state(&depth_stencil)
+            .color_blend_state(&color_blend)
+            .dynamic_state(&dynamic_state)
+            .layout(layout.pipeline_layout)
+            .render_pass(render_pass)
+            .subpass(0)
+    ];
+    let pipelines = match unsafe {
+        base.device.create_graphics_pipelines(
+            base.pipeline_cache,
+            &create_infos,
+            None
+        )
+    } {
+        Ok(v) => v,
+        Err(e) => {return Err(e.1);}
+    };
+    //Destroy shader modules
+    unsafe {
+        base.device.destroy_shader_module(vertex_shader, None);
+        base.device.destroy_shader_module(fragment_shader, None);
+    }
+    Ok(pipelines[0])
+}
@@ -1,9 +1,22 @@
 use ash::vk;
 use crate::base::Base;
 use super::PipelineLayout;
-use std::rc::Rc;
+use std::sync::Arc;
 
-pub fn create_layout(base: Rc<Base>) -> Result<PipelineLayout, vk::Result> {
+pub fn create_layout(base: Arc<Base>) -> Result<PipelineLayout, vk::Result> {
+    //Depth pyramid sampler
+    let create_info = vk::SamplerCreateInfo::builder()
+        .mag_filter(vk::Filter::NEAREST)
+        .min_filter(vk::Filter::NEAREST)
+        .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .anisotropy_enable(false)
+        .max_lod(vk::LOD_CLAMP_NONE);
+    let sampler = unsafe {
+        base.device.create_sampler(&create_info, None)?
+    };
     //Descriptor set layout
     let bindings = [
         //Camera
@@ -47,6 +60,19 @@ pub fn create_layout(base: Rc<Base>) -> Result<PipelineLayout, vk::Result> {
             .binding(6)
             .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
             .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE),
+        //Depth pyramid
+        *vk::DescriptorSetLayoutBinding::builder()
+            .binding(7)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .immutable_samplers(std::slice::from_ref(&sampler)),
+        //Cull statistics
+        *vk::DescriptorSetLayoutBinding::builder()
+            .binding(8)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(1)
             .stage_flags(vk::ShaderStageFlags::COMPUTE)
     ];
     let create_info = vk::DescriptorSetLayoutCreateInfo::builder()
@@ -55,9 +81,10 @@ pub fn create_layout(base: Rc<Base>) -> Result<PipelineLayout, vk::Result> {
         base.device.create_descriptor_set_layout(&create_info, None)?
     };
     //Pipeline layout
+    //[occlusion view-projection, node count, compact, occlusion, layer mask]
     let push_constant = vk::PushConstantRange::builder()
         .stage_flags(vk::ShaderStageFlags::COMPUTE)
-        .size(std::mem::size_of::<u32>() as u32);
+        .size((std::mem::size_of::<[f32; 16]>() + 4 * std::mem::size_of::<u32>()) as u32);
     let create_info = vk::PipelineLayoutCreateInfo::builder()
         .set_layouts(std::slice::from_ref(&descriptor_set_layout))
         .push_constant_ranges(std::slice::from_ref(&push_constant));
@@ -66,31 +93,40 @@ pub fn create_layout(base: Rc<Base>) -> Result<PipelineLayout, vk::Result> {
     };
     Ok(PipelineLayout {
         base,
-        samplers: vec![],
+        samplers: vec![sampler],
         descriptor_set_layout,
+        descriptor_counts: super::descriptor_counts(&bindings),
         pipeline_layout,
+        samples: vk::SampleCountFlags::TYPE_1,
         create_pipeline: create_pipeline
     })
 }
 
 fn create_pipeline(
     layout: &PipelineLayout,
-    _extent: vk::Extent2D,
     _render_pass: vk::RenderPass
 ) -> Result<vk::Pipeline, vk::Result> {
     let base = &layout.base;
     //Shaders
-    let code = ash::util::read_spv(
-        &mut std::io::Cursor::new(include_bytes!("../../spv/cull.comp.spv"))
-    ).unwrap();
+    let code = super::load_shader("cull.comp", include_bytes!("../../spv/cull.comp.spv"));
     let create_info = vk::ShaderModuleCreateInfo::builder().code(&code);
     let shader = unsafe {
         base.device.create_shader_module(&create_info, None)?
     };
+    //`FIRST_INSTANCE`: draw commands start at their node's instance, for the instance-rate node binding
+    let first_instance = (base.draw_indirect_first_instance as vk::Bool32).to_ne_bytes();
+    let map_entry = vk::SpecializationMapEntry::builder()
+        .constant_id(0)
+        .offset(0)
+        .size(std::mem::size_of::<vk::Bool32>());
+    let specialization = vk::SpecializationInfo::builder()
+        .map_entries(std::slice::from_ref(&map_entry))
+        .data(&first_instance);
     let shader_stage = *vk::PipelineShaderStageCreateInfo::builder()
         .stage(vk::ShaderStageFlags::COMPUTE)
         .module(shader)
-        .name(unsafe {std::ffi::CStr::from_bytes_with_nul_unchecked(b"main\0")});
+        .name(unsafe {std::ffi::CStr::from_bytes_with_nul_unchecked(b"main\0")})
+        .specialization_info(&specialization);
     let create_info = vk::ComputePipelineCreateInfo::builder()
         .stage(shader_stage)
         .layout(layout.pipeline_layout);
@@ -0,0 +1,112 @@
+use ash::vk;
+use crate::base::Base;
+use super::PipelineLayout;
+use std::sync::Arc;
+
+pub fn create_layout(base: Arc<Base>, samples: vk::SampleCountFlags) -> Result<PipelineLayout, vk::Result> {
+    //Sampler
+    let create_info = vk::SamplerCreateInfo::builder()
+        .mag_filter(vk::Filter::NEAREST)
+        .min_filter(vk::Filter::NEAREST)
+        .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .anisotropy_enable(false);
+    let sampler = unsafe {
+        base.device.create_sampler(&create_info, None)?
+    };
+    //Descriptor set layout
+    let bindings = [
+        //Multisampled depth image
+        *vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .immutable_samplers(std::slice::from_ref(&sampler)),
+        //Single-sampled depth image
+        *vk::DescriptorSetLayoutBinding::builder()
+            .binding(1)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .immutable_samplers(std::slice::from_ref(&sampler)),
+        //Resolved image
+        *vk::DescriptorSetLayoutBinding::builder()
+            .binding(2)
+            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+    ];
+    //Only the depth binding matching the sample count is written
+    let binding_flags = [
+        vk::DescriptorBindingFlags::PARTIALLY_BOUND,
+        vk::DescriptorBindingFlags::PARTIALLY_BOUND,
+        vk::DescriptorBindingFlags::empty()
+    ];
+    let mut binding_flags_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder()
+        .binding_flags(&binding_flags);
+    let create_info = vk::DescriptorSetLayoutCreateInfo::builder()
+        .bindings(&bindings)
+        .push_next(&mut binding_flags_info);
+    let descriptor_set_layout = unsafe {
+        base.device.create_descriptor_set_layout(&create_info, None)?
+    };
+    //Pipeline layout
+    let create_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(std::slice::from_ref(&descriptor_set_layout));
+    let pipeline_layout = unsafe {
+        base.device.create_pipeline_layout(&create_info, None)?
+    };
+    Ok(PipelineLayout {
+        base,
+        samplers: vec![sampler],
+        descriptor_set_layout,
+        descriptor_counts: super::descriptor_counts(&bindings),
+        pipeline_layout,
+        samples,
+        create_pipeline
+    })
+}
+
+fn create_pipeline(
+    layout: &PipelineLayout,
+    _render_pass: vk::RenderPass
+) -> Result<vk::Pipeline, vk::Result> {
+    let base = &layout.base;
+    //Shaders
+    let code = super::load_shader("depth_resolve.comp", include_bytes!("../../spv/depth_resolve.comp.spv"));
+    let create_info = vk::ShaderModuleCreateInfo::builder().code(&code);
+    let shader = unsafe {
+        base.device.create_shader_module(&create_info, None)?
+    };
+    //Specialization
+    let multisampled = (layout.samples != vk::SampleCountFlags::TYPE_1) as u32;
+    let map_entry = vk::SpecializationMapEntry::builder()
+        .constant_id(0)
+        .offset(0)
+        .size(std::mem::size_of::<u32>());
+    let multisampled_bytes = multisampled.to_le_bytes();
+    let specialization = vk::SpecializationInfo::builder()
+        .map_entries(std::slice::from_ref(&map_entry))
+        .data(&multisampled_bytes);
+    let shader_stage = *vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::COMPUTE)
+        .module(shader)
+        .name(c"main")
+        .specialization_info(&specialization);
+    let create_info = vk::ComputePipelineCreateInfo::builder()
+        .stage(shader_stage)
+        .layout(layout.pipeline_layout);
+    let pipelines = unsafe {base.device.create_compute_pipelines(
+        base.pipeline_cache,
+        std::slice::from_ref(&create_info),
+        None
+    )};
+    //Destroy shader modules
+    unsafe {
+        base.device.destroy_shader_module(shader, None);
+    }
+    pipelines.map(|pipelines| pipelines[0]).map_err(|e| e.1)
+}
@@ -0,0 +1,124 @@
+use ash::vk;
+use crate::base::Base;
+use super::PipelineLayout;
+use std::sync::Arc;
+
+pub fn create_layout(base: Arc<Base>, samples: vk::SampleCountFlags) -> Result<PipelineLayout, vk::Result> {
+    //Sampler
+    let create_info = vk::SamplerCreateInfo::builder()
+        .mag_filter(vk::Filter::NEAREST)
+        .min_filter(vk::Filter::NEAREST)
+        .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .anisotropy_enable(false);
+    let sampler = unsafe {
+        base.device.create_sampler(&create_info, None)?
+    };
+    //Descriptor set layout
+    let bindings = [
+        //Multisampled depth image
+        *vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .immutable_samplers(std::slice::from_ref(&sampler)),
+        //Source level
+        *vk::DescriptorSetLayoutBinding::builder()
+            .binding(1)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .immutable_samplers(std::slice::from_ref(&sampler)),
+        //Destination level
+        *vk::DescriptorSetLayoutBinding::builder()
+            .binding(2)
+            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE),
+        //Single-sampled depth image
+        *vk::DescriptorSetLayoutBinding::builder()
+            .binding(3)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .immutable_samplers(std::slice::from_ref(&sampler))
+    ];
+    //Only the depth binding matching the sample count is written
+    let mut binding_flags = [vk::DescriptorBindingFlags::empty(); 4];
+    binding_flags[0] = vk::DescriptorBindingFlags::PARTIALLY_BOUND;
+    binding_flags[3] = vk::DescriptorBindingFlags::PARTIALLY_BOUND;
+    let mut binding_flags_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder()
+        .binding_flags(&binding_flags);
+    let create_info = vk::DescriptorSetLayoutCreateInfo::builder()
+        .bindings(&bindings)
+        .push_next(&mut binding_flags_info);
+    let descriptor_set_layout = unsafe {
+        base.device.create_descriptor_set_layout(&create_info, None)?
+    };
+    //Pipeline layout
+    let push_constant = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        .size(std::mem::size_of::<u32>() as u32);
+    let create_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(std::slice::from_ref(&descriptor_set_layout))
+        .push_constant_ranges(std::slice::from_ref(&push_constant));
+    let pipeline_layout = unsafe {
+        base.device.create_pipeline_layout(&create_info, None)?
+    };
+    Ok(PipelineLayout {
+        base,
+        samplers: vec![sampler],
+        descriptor_set_layout,
+        descriptor_counts: super::descriptor_counts(&bindings),
+        pipeline_layout,
+        samples,
+        create_pipeline: create_pipeline
+    })
+}
+
+fn create_pipeline(
+    layout: &PipelineLayout,
+    _render_pass: vk::RenderPass
+) -> Result<vk::Pipeline, vk::Result> {
+    let base = &layout.base;
+    //Shaders
+    let code = super::load_shader("hiz.comp", include_bytes!("../../spv/hiz.comp.spv"));
+    let create_info = vk::ShaderModuleCreateInfo::builder().code(&code);
+    let shader = unsafe {
+        base.device.create_shader_module(&create_info, None)?
+    };
+    //Specialization
+    let multisampled = (layout.samples != vk::SampleCountFlags::TYPE_1) as u32;
+    let map_entry = vk::SpecializationMapEntry::builder()
+        .constant_id(0)
+        .offset(0)
+        .size(std::mem::size_of::<u32>());
+    let multisampled_bytes = multisampled.to_le_bytes();
+    let specialization = vk::SpecializationInfo::builder()
+        .map_entries(std::slice::from_ref(&map_entry))
+        .data(&multisampled_bytes);
+    let shader_stage = *vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::COMPUTE)
+        .module(shader)
+        .name(unsafe {std::ffi::CStr::from_bytes_with_nul_unchecked(b"main\0")})
+        .specialization_info(&specialization);
+    let create_info = vk::ComputePipelineCreateInfo::builder()
+        .stage(shader_stage)
+        .layout(layout.pipeline_layout);
+    let pipelines = match unsafe {base.device.create_compute_pipelines(
+        base.pipeline_cache,
+        std::slice::from_ref(&create_info),
+        None
+    )} {
+        Ok(v) => v,
+        Err(e) => {return Err(e.1);}
+    };
+    //Destroy shader modules
+    unsafe {
+        base.device.destroy_shader_module(shader, None);
+    }
+    Ok(pipelines[0])
+}
@@ -1,20 +1,33 @@
 use ash::vk;
 use crate::base::Base;
-use crate::{SAMPLE_COUNT, MAX_TEXTURES};
-use crate::scene::Vertex;
+use crate::MAX_TEXTURES;
+use crate::scene::{Vertex, VertexFormat};
+use crate::device_scene::{PackedVertex, DeviceNode};
 use super::PipelineLayout;
-use std::rc::Rc;
+use std::sync::Arc;
 
-pub fn create_layout(base: Rc<Base>) -> Result<PipelineLayout, vk::Result> {
-    //Sampler
+///Offset of the shadow pipelines' vertex push constants, after the fragment push constants.
+pub const SHADOW_PUSH_CONSTANT_OFFSET: u32 = 32;
+
+/**
+    Vertex binding of the frame's nodes (`DeviceNode`) at instance rate, bound with the scene's vertices.
+    It coexists with the storage buffer lookup of indirect draws: there's still one draw per device node
+    (`gl_DrawID` indexes its draw extra), & with `Base::draw_indirect_first_instance` each draw's only instance
+    is its device node, so `gl_InstanceIndex` is the node index & instance attributes are that node's.
+    Otherwise indirect draws start at instance 0, so only `gl_DrawID` identifies the node
+    & `pbr.vert` reads transforms from the node storage buffer instead.
+*/
+pub const NODE_BINDING: u32 = 1;
+
+pub fn create_layout(base: Arc<Base>, samples: vk::SampleCountFlags) -> Result<PipelineLayout, vk::Result> {
+    //Scene color sampler
     let create_info = vk::SamplerCreateInfo::builder()
-        .mag_filter(vk::Filter::NEAREST)
-        .min_filter(vk::Filter::NEAREST)
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
         .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
-        .address_mode_u(vk::SamplerAddressMode::REPEAT)
-        .address_mode_v(vk::SamplerAddressMode::REPEAT)
-        .address_mode_w(vk::SamplerAddressMode::REPEAT)
-        .anisotropy_enable(false);
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
     let sampler = unsafe {
         base.device.create_sampler(&create_info, None)?
     };
@@ -50,13 +63,12 @@ pub fn create_layout(base: Rc<Base>) -> Result<PipelineLayout, vk::Result> {
             .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
             .descriptor_count(1)
             .stage_flags(vk::ShaderStageFlags::VERTEX),
-        //Sampler
+        //Sampler (not immutable, so texture filtering can change without recreating the layout)
         *vk::DescriptorSetLayoutBinding::builder()
             .binding(5)
             .descriptor_type(vk::DescriptorType::SAMPLER)
             .descriptor_count(1)
-            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
-            .immutable_samplers(std::slice::from_ref(&sampler)),
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT),
         //Textures
         *vk::DescriptorSetLayoutBinding::builder()
             .binding(6)
@@ -80,16 +92,72 @@ pub fn create_layout(base: Rc<Base>) -> Result<PipelineLayout, vk::Result> {
             .binding(9)
             .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
             .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+        //Scene color (for transmission)
+        *vk::DescriptorSetLayoutBinding::builder()
+            .binding(10)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
             .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .immutable_samplers(std::slice::from_ref(&sampler)),
+        //Shadow atlas
+        *vk::DescriptorSetLayoutBinding::builder()
+            .binding(11)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+        //User uniform
+        *vk::DescriptorSetLayoutBinding::builder()
+            .binding(12)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT),
+        //Morph deltas
+        *vk::DescriptorSetLayoutBinding::builder()
+            .binding(13)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::VERTEX),
+        //Texture arrays
+        *vk::DescriptorSetLayoutBinding::builder()
+            .binding(14)
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .descriptor_count(MAX_TEXTURES as u32)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+        //Cull statistics
+        *vk::DescriptorSetLayoutBinding::builder()
+            .binding(15)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
     ];
+    //Cubemaps are unwritten with gradient environments
+    let mut binding_flags = [vk::DescriptorBindingFlags::empty(); 16];
+    binding_flags[8] = vk::DescriptorBindingFlags::PARTIALLY_BOUND;
+    let mut binding_flags_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder()
+        .binding_flags(&binding_flags);
     let create_info = vk::DescriptorSetLayoutCreateInfo::builder()
-        .bindings(&bindings);
+        .bindings(&bindings)
+        .push_next(&mut binding_flags_info);
     let descriptor_set_layout = unsafe {
         base.device.create_descriptor_set_layout(&create_info, None)?
     };
     //Pipeline layout
+    let push_constants = [
+        //Wireframe overlay edge style: color & thickness
+        *vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .offset(0)
+            .size(std::mem::size_of::<[f32; 5]>() as u32),
+        //Shadow map face view-projection
+        *vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::VERTEX)
+            .offset(SHADOW_PUSH_CONSTANT_OFFSET)
+            .size(std::mem::size_of::<[f32; 16]>() as u32)
+    ];
     let create_info = vk::PipelineLayoutCreateInfo::builder()
-        .set_layouts(std::slice::from_ref(&descriptor_set_layout));
+        .set_layouts(std::slice::from_ref(&descriptor_set_layout))
+        .push_constant_ranges(&push_constants);
     let pipeline_layout = unsafe {
         base.device.create_pipeline_layout(&create_info, None)?
     };
@@ -97,34 +165,305 @@ pub fn create_layout(base: Rc<Base>) -> Result<PipelineLayout, vk::Result> {
         base,
         samplers: vec![sampler],
         descriptor_set_layout,
+        descriptor_counts: super::descriptor_counts(&bindings),
         pipeline_layout,
+        samples,
         create_pipeline: create_pipeline
     })
 }
 
 fn create_pipeline(
     layout: &PipelineLayout,
-    extent: vk::Extent2D,
     render_pass: vk::RenderPass
+) -> Result<vk::Pipeline, vk::Result> {
+    create_default_variant(layout, render_pass, VertexFormat::Full, false)
+}
+
+///Default pipeline variants other than the base pipeline: [vertex format, flat shading]
+pub const VARIANTS: [(VertexFormat, bool); 3] = [
+    (VertexFormat::Full, true),
+    (VertexFormat::Packed, false),
+    (VertexFormat::Packed, true)
+];
+
+///Index of a default pipeline variant in `VARIANTS`, or `None` for the base pipeline.
+pub fn variant_index(vertex_format: VertexFormat, flat_shading: bool) -> Option<usize> {
+    VARIANTS.iter().position(|&variant| variant == (vertex_format, flat_shading))
+}
+
+///Create every pipeline in `VARIANTS`.
+pub fn create_variants(
+    layout: &PipelineLayout,
+    render_pass: vk::RenderPass
+) -> Result<[vk::Pipeline; VARIANTS.len()], vk::Result> {
+    let mut pipelines = [vk::Pipeline::null(); VARIANTS.len()];
+    for (i, (vertex_format, flat_shading)) in VARIANTS.into_iter().enumerate() {
+        match create_default_variant(layout, render_pass, vertex_format, flat_shading) {
+            Ok(pipeline) => pipelines[i] = pipeline,
+            Err(e) => {
+                for pipeline in &pipelines[..i] {
+                    unsafe {layout.base.device.destroy_pipeline(*pipeline, None);}
+                }
+                return Err(e);
+            }
+        }
+    }
+    Ok(pipelines)
+}
+
+/**
+    Create a pipeline with the default shaders, specialized for the vertex format
+    (`PACKED_VERTICES` in `pbr.vert`) & face normals (`FLAT_SHADING` in `pbr.frag`).
+*/
+fn create_default_variant(
+    layout: &PipelineLayout,
+    render_pass: vk::RenderPass,
+    vertex_format: VertexFormat,
+    flat_shading: bool
+) -> Result<vk::Pipeline, vk::Result> {
+    let vertex_code = super::load_shader("pbr.vert", include_bytes!("../../spv/pbr.vert.spv"));
+    let fragment_code = super::load_shader("pbr.frag", include_bytes!("../../spv/pbr.frag.spv"));
+    let vertex_data = vertex_constants(&layout.base, vertex_format);
+    let flat_shading = (flat_shading as vk::Bool32).to_ne_bytes();
+    let map_entries = bool_map_entries();
+    let specializations = [
+        *vk::SpecializationInfo::builder()
+            .map_entries(&map_entries)
+            .data(&vertex_data),
+        *vk::SpecializationInfo::builder()
+            .map_entries(&map_entries[..1])
+            .data(&flat_shading)
+    ];
+    create_pipeline_variant(
+        layout,
+        render_pass,
+        [&vertex_code, &fragment_code],
+        vertex_format,
+        Some(&specializations),
+        false,
+        false
+    )
+}
+
+/**
+    Create the wireframe overlay pipelines for each vertex format: [full, packed].
+    These draw triangle edges (`wireframe.frag`, using barycentric coordinates) over shaded meshes,
+    so require `Base::fragment_shader_barycentric`.
+*/
+pub fn create_wireframe_variants(
+    layout: &PipelineLayout,
+    render_pass: vk::RenderPass
+) -> Result<[vk::Pipeline; 2], vk::Result> {
+    let vertex_code = super::load_shader("pbr.vert", include_bytes!("../../spv/pbr.vert.spv"));
+    let fragment_code = super::load_shader("wireframe.frag", include_bytes!("../../spv/wireframe.frag.spv"));
+    create_overlay_variants(layout, render_pass, [&vertex_code, &fragment_code])
+}
+
+/**
+    Create the selection highlight pipeline for each vertex format (see `SceneSet::set_highlight`).
+    Highlighted nodes are drawn again as an inverted hull:
+    back faces pushed out along the screen-space normal, depth tested without depth writes,
+    so only a band of constant width in pixels around the silhouette is visible.
+*/
+pub fn create_highlight_variants(
+    layout: &PipelineLayout,
+    render_pass: vk::RenderPass
+) -> Result<[vk::Pipeline; 2], vk::Result> {
+    let vertex_code = super::load_shader("highlight.vert", include_bytes!("../../spv/highlight.vert.spv"));
+    let fragment_code = super::load_shader("highlight.frag", include_bytes!("../../spv/highlight.frag.spv"));
+    create_overlay_variants(layout, render_pass, [&vertex_code, &fragment_code])
+}
+
+/**
+    Create the back-face debug pipeline for each vertex format (see `DebugView::Backfaces`):
+    the default shaders with `BACKFACES` specialized in `pbr.frag`, so back faces
+    (which the fragment shader otherwise discards, as every mesh pipeline disables culling)
+    are drawn in a flat tint while front faces are shaded as usual.
+*/
+pub fn create_backface_variants(
+    layout: &PipelineLayout,
+    render_pass: vk::RenderPass
+) -> Result<[vk::Pipeline; 2], vk::Result> {
+    let vertex_code = super::load_shader("pbr.vert", include_bytes!("../../spv/pbr.vert.spv"));
+    let fragment_code = super::load_shader("pbr.frag", include_bytes!("../../spv/pbr.frag.spv"));
+    let map_entries = bool_map_entries();
+    //FLAT_SHADING & BACKFACES
+    let fragment_data: Vec<u8> = [vk::FALSE, vk::TRUE].iter().flat_map(|b| b.to_ne_bytes()).collect();
+    let mut pipelines = [vk::Pipeline::null(); 2];
+    for (i, vertex_format) in [VertexFormat::Full, VertexFormat::Packed].into_iter().enumerate() {
+        let vertex_data = vertex_constants(&layout.base, vertex_format);
+        let specializations = [
+            *vk::SpecializationInfo::builder()
+                .map_entries(&map_entries)
+                .data(&vertex_data),
+            *vk::SpecializationInfo::builder()
+                .map_entries(&map_entries)
+                .data(&fragment_data)
+        ];
+        match create_pipeline_variant(
+            layout,
+            render_pass,
+            [&vertex_code, &fragment_code],
+            vertex_format,
+            Some(&specializations),
+            false,
+            false
+        ) {
+            Ok(pipeline) => pipelines[i] = pipeline,
+            Err(e) => {
+                for pipeline in &pipelines[..i] {
+                    unsafe {layout.base.device.destroy_pipeline(*pipeline, None);}
+                }
+                return Err(e);
+            }
+        }
+    }
+    Ok(pipelines)
+}
+
+/**
+    Create the alpha-to-coverage pipeline for each vertex format, drawing scenes with masked materials
+    (see `AlphaMode::Mask`): the default shaders with `ALPHA_TO_COVERAGE` specialized in `pbr.frag`,
+    which outputs masked materials' coverage as alpha (other materials output alpha 1, so are fully covered).
+    Blending is disabled, so transmission without a scene color falls back to partial coverage.
+    Flat shading isn't specialized: with it enabled, masked materials fall back to alpha testing.
+*/
+pub fn create_coverage_variants(
+    layout: &PipelineLayout,
+    render_pass: vk::RenderPass
+) -> Result<[vk::Pipeline; 2], vk::Result> {
+    let vertex_code = super::load_shader("pbr.vert", include_bytes!("../../spv/pbr.vert.spv"));
+    let fragment_code = super::load_shader("pbr.frag", include_bytes!("../../spv/pbr.frag.spv"));
+    let map_entries = bool_map_entries();
+    let coverage_entry = vk::SpecializationMapEntry::builder()
+        .constant_id(2)
+        .offset(0)
+        .size(std::mem::size_of::<vk::Bool32>());
+    let fragment_data = vk::TRUE.to_ne_bytes();
+    let mut pipelines = [vk::Pipeline::null(); 2];
+    for (i, vertex_format) in [VertexFormat::Full, VertexFormat::Packed].into_iter().enumerate() {
+        let vertex_data = vertex_constants(&layout.base, vertex_format);
+        let specializations = [
+            *vk::SpecializationInfo::builder()
+                .map_entries(&map_entries)
+                .data(&vertex_data),
+            *vk::SpecializationInfo::builder()
+                .map_entries(std::slice::from_ref(&coverage_entry))
+                .data(&fragment_data)
+        ];
+        match create_pipeline_variant(
+            layout,
+            render_pass,
+            [&vertex_code, &fragment_code],
+            vertex_format,
+            Some(&specializations),
+            false,
+            true
+        ) {
+            Ok(pipeline) => pipelines[i] = pipeline,
+            Err(e) => {
+                for pipeline in &pipelines[..i] {
+                    unsafe {layout.base.device.destroy_pipeline(*pipeline, None);}
+                }
+                return Err(e);
+            }
+        }
+    }
+    Ok(pipelines)
+}
+
+//Specialization of constant IDs 0 & 1 as consecutive booleans
+fn bool_map_entries() -> [vk::SpecializationMapEntry; 2] {
+    let size = std::mem::size_of::<vk::Bool32>();
+    [0, 1].map(|i| *vk::SpecializationMapEntry::builder()
+        .constant_id(i)
+        .offset(i * size as u32)
+        .size(size)
+    )
+}
+
+//`pbr.vert`'s `PACKED_VERTICES` & `INSTANCED_TRANSFORMS` (whenever draws start at their node's instance)
+fn vertex_constants(base: &Base, vertex_format: VertexFormat) -> Vec<u8> {
+    [vertex_format == VertexFormat::Packed, base.draw_indirect_first_instance].iter().flat_map(
+        |&b| (b as vk::Bool32).to_ne_bytes()
+    ).collect()
+}
+
+//Overlay pipelines for each vertex format, with the vertex shader's `PACKED_VERTICES` specialized
+fn create_overlay_variants(
+    layout: &PipelineLayout,
+    render_pass: vk::RenderPass,
+    [vertex_code, fragment_code]: [&[u32]; 2]
+) -> Result<[vk::Pipeline; 2], vk::Result> {
+    let map_entry = vk::SpecializationMapEntry::builder()
+        .constant_id(0)
+        .offset(0)
+        .size(std::mem::size_of::<vk::Bool32>());
+    let mut pipelines = [vk::Pipeline::null(); 2];
+    for (i, vertex_format) in [VertexFormat::Full, VertexFormat::Packed].into_iter().enumerate() {
+        let packed = ((vertex_format == VertexFormat::Packed) as vk::Bool32).to_ne_bytes();
+        let specializations = [
+            *vk::SpecializationInfo::builder()
+                .map_entries(std::slice::from_ref(&map_entry))
+                .data(&packed),
+            vk::SpecializationInfo::default()
+        ];
+        match create_pipeline_variant(
+            layout,
+            render_pass,
+            [vertex_code, fragment_code],
+            vertex_format,
+            Some(&specializations),
+            true,
+            false
+        ) {
+            Ok(pipeline) => pipelines[i] = pipeline,
+            Err(e) => {
+                for pipeline in &pipelines[..i] {
+                    unsafe {layout.base.device.destroy_pipeline(*pipeline, None);}
+                }
+                return Err(e);
+            }
+        }
+    }
+    Ok(pipelines)
+}
+
+/**
+    Create a mesh pipeline with custom shaders (SPIR-V words).
+    Vertex shaders may read the vertex attributes at locations 0-2 (see `Vertex`),
+    & their node's transform as a `mat4` at locations 5-8 (see `NODE_BINDING`).
+*/
+pub fn create_pipeline_with_shaders(
+    layout: &PipelineLayout,
+    render_pass: vk::RenderPass,
+    vertex_code: &[u32],
+    fragment_code: &[u32]
+) -> Result<vk::Pipeline, vk::Result> {
+    create_pipeline_variant(layout, render_pass, [vertex_code, fragment_code], VertexFormat::Full, None, false, false)
+}
+
+fn create_pipeline_variant(
+    layout: &PipelineLayout,
+    render_pass: vk::RenderPass,
+    code: [&[u32]; 2], //Vertex & fragment stages
+    vertex_format: VertexFormat,
+    specializations: Option<&[vk::SpecializationInfo; 2]>, //Vertex & fragment stages
+    overlay: bool, //Drawn over the same meshes' depth, without writing it
+    alpha_to_coverage: bool //Output alpha sets sample coverage instead of blending
 ) -> Result<vk::Pipeline, vk::Result> {
     let base = &layout.base;
     //Pipeline
     //Shaders
-    let code = ash::util::read_spv(
-        &mut std::io::Cursor::new(include_bytes!("../../spv/pbr.vert.spv"))
-    ).unwrap();
-    let create_info = vk::ShaderModuleCreateInfo::builder().code(&code);
+    let create_info = vk::ShaderModuleCreateInfo::builder().code(code[0]);
     let vertex_shader = unsafe {
         base.device.create_shader_module(&create_info, None)?
     };
-    let code = ash::util::read_spv(
-        &mut std::io::Cursor::new(include_bytes!("../../spv/pbr.frag.spv"))
-    ).unwrap();
-    let create_info = vk::ShaderModuleCreateInfo::builder().code(&code);
+    let create_info = vk::ShaderModuleCreateInfo::builder().code(code[1]);
     let fragment_shader = unsafe {
         base.device.create_shader_module(&create_info, None)?
     };
-    let shader_stages = [
+    let mut shader_stages = [
         *vk::PipelineShaderStageCreateInfo::builder()
             .stage(vk::ShaderStageFlags::VERTEX)
             .module(vertex_shader)
@@ -134,15 +473,33 @@ fn create_pipeline(
             .module(fragment_shader)
             .name(unsafe {std::ffi::CStr::from_bytes_with_nul_unchecked(b"main\0")})
     ];
+    if let Some(specializations) = specializations {
+        shader_stages[0].p_specialization_info = &specializations[0];
+        shader_stages[1].p_specialization_info = &specializations[1];
+    }
     //Fixed functions
     //Vertex input
     let vertex_bindings = [
         *vk::VertexInputBindingDescription::builder()
             .binding(0)
-            .stride(std::mem::size_of::<Vertex>() as u32)
-            .input_rate(vk::VertexInputRate::VERTEX)
+            .stride(match vertex_format {
+                VertexFormat::Full => std::mem::size_of::<Vertex>(),
+                VertexFormat::Packed => std::mem::size_of::<PackedVertex>()
+            } as u32)
+            .input_rate(vk::VertexInputRate::VERTEX),
+        *vk::VertexInputBindingDescription::builder()
+            .binding(NODE_BINDING)
+            .stride(std::mem::size_of::<DeviceNode>() as u32)
+            .input_rate(vk::VertexInputRate::INSTANCE)
     ];
-    let vertex_attributes = [
+    //Node transform columns (`DeviceNode::transform`)
+    let node_attributes = [0, 1, 2, 3].map(|i| *vk::VertexInputAttributeDescription::builder()
+        .location(5 + i)
+        .binding(NODE_BINDING)
+        .format(vk::Format::R32G32B32A32_SFLOAT)
+        .offset(16 * i)
+    );
+    let full_attributes = [
         //Position
         *vk::VertexInputAttributeDescription::builder()
             .location(0)
@@ -162,46 +519,62 @@ fn create_pipeline(
             .format(vk::Format::R32G32_SFLOAT)
             .offset(24)
     ];
+    let packed_attributes = [
+        //Position
+        *vk::VertexInputAttributeDescription::builder()
+            .location(0)
+            .binding(0)
+            .format(vk::Format::R32G32B32_SFLOAT)
+            .offset(0),
+        //Normal (octahedral encoding)
+        *vk::VertexInputAttributeDescription::builder()
+            .location(1)
+            .binding(0)
+            .format(vk::Format::R16G16_SNORM)
+            .offset(12),
+        //Texture coordinates
+        *vk::VertexInputAttributeDescription::builder()
+            .location(2)
+            .binding(0)
+            .format(vk::Format::R16G16_SFLOAT)
+            .offset(16)
+    ];
+    let vertex_attributes: Vec<_> = match vertex_format {
+        VertexFormat::Full => &full_attributes,
+        VertexFormat::Packed => &packed_attributes
+    }.iter().chain(&node_attributes).copied().collect();
     let vertex_input = vk::PipelineVertexInputStateCreateInfo::builder()
         .vertex_binding_descriptions(&vertex_bindings)
         .vertex_attribute_descriptions(&vertex_attributes);
     //Input assembly
     let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::builder()
         .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
-    //Viewport
-    let viewports = [
-        *vk::Viewport::builder()
-            .width(extent.width as f32)
-            .height(extent.height as f32)
-            .min_depth(0.0)
-            .max_depth(1.0)
-    ];
-    let scissors = [
-        *vk::Rect2D::builder().extent(extent)
-    ];
+    //Viewport (dynamic, set when drawing)
     let viewport = vk::PipelineViewportStateCreateInfo::builder()
-        .viewport_count(viewports.len() as u32).viewports(&viewports)
-        .scissor_count(scissors.len() as u32).scissors(&scissors);
+        .viewport_count(1)
+        .scissor_count(1);
     //Rasterization
     let rasterization = vk::PipelineRasterizationStateCreateInfo::builder()
         .polygon_mode(vk::PolygonMode::FILL)
-        .cull_mode(vk::CullModeFlags::BACK)
+        .cull_mode(vk::CullModeFlags::NONE) //Culled by the fragment shader, as mirrored nodes reverse winding
         .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .depth_bias_enable(true) //Factors are dynamic
         .line_width(1.0);
     //Multisampling
     let multisample = vk::PipelineMultisampleStateCreateInfo::builder()
-        .rasterization_samples(SAMPLE_COUNT);
+        .rasterization_samples(layout.samples)
+        .alpha_to_coverage_enable(alpha_to_coverage);
     //Depth stencil
     let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::builder()
         .depth_test_enable(true)
-        .depth_write_enable(true)
-        .depth_compare_op(vk::CompareOp::LESS);
+        .depth_write_enable(!overlay)
+        .depth_compare_op(if overlay {vk::CompareOp::LESS_OR_EQUAL} else {vk::CompareOp::LESS});
     //Color blending
     let color_blend_attachments = [
         *vk::PipelineColorBlendAttachmentState::builder()
-            .blend_enable(false)
+            .blend_enable(!alpha_to_coverage) //Opaque output has alpha 1; transmission falls back to alpha blending
             .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
-            .dst_color_blend_factor(vk::BlendFactor::DST_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
             .color_blend_op(vk::BlendOp::ADD)
             .src_alpha_blend_factor(vk::BlendFactor::ONE)
             .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
@@ -211,6 +584,10 @@ fn create_pipeline(
     let color_blend = vk::PipelineColorBlendStateCreateInfo::builder()
         .logic_op_enable(false)
         .attachments(&color_blend_attachments);
+    //Dynamic state
+    let dynamic_states = [vk::DynamicState::DEPTH_BIAS, vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder()
+        .dynamic_states(&dynamic_states);
     //Create pipeline
     let create_infos = [
         *vk::GraphicsPipelineCreateInfo::builder()
@@ -222,6 +599,7 @@ fn create_pipeline(
             .multisample_state(&multisample)
             .depth_stencil_state(&depth_stencil)
             .color_blend_state(&color_blend)
+            .dynamic_state(&dynamic_state)
             .layout(layout.pipeline_layout)
             .render_pass(render_pass)
             .subpass(0)
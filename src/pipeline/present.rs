@@ -0,0 +1,149 @@
+use ash::vk;
+use crate::base::Base;
+use super::PipelineLayout;
+use std::sync::Arc;
+
+/**
+    Present pass: draws the drawn image into a swapchain image of any format & extent.
+    Sampling decodes sRGB before filtering, and the shader encodes sRGB when the swapchain format doesn't
+    (a `u32` fragment push constant), unlike blits which some drivers filter in sRGB space.
+*/
+pub fn create_layout(base: Arc<Base>) -> Result<PipelineLayout, vk::Result> {
+    //Sampler
+    let create_info = vk::SamplerCreateInfo::builder()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .anisotropy_enable(false);
+    let sampler = unsafe {
+        base.device.create_sampler(&create_info, None)?
+    };
+    //Descriptor set layout
+    let bindings = [
+        //Drawn image (resolve or post-processing target)
+        *vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .immutable_samplers(std::slice::from_ref(&sampler))
+    ];
+    let create_info = vk::DescriptorSetLayoutCreateInfo::builder()
+        .bindings(&bindings);
+    let descriptor_set_layout = unsafe {
+        base.device.create_descriptor_set_layout(&create_info, None)?
+    };
+    //Pipeline layout
+    let push_constant_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+        .offset(0)
+        .size(4);
+    let create_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(std::slice::from_ref(&descriptor_set_layout))
+        .push_constant_ranges(std::slice::from_ref(&push_constant_range));
+    let pipeline_layout = unsafe {
+        base.device.create_pipeline_layout(&create_info, None)?
+    };
+    Ok(PipelineLayout {
+        base,
+        samplers: vec![sampler],
+        descriptor_set_layout,
+        descriptor_counts: super::descriptor_counts(&bindings),
+        pipeline_layout,
+        samples: vk::SampleCountFlags::TYPE_1,
+        create_pipeline: create_pipeline
+    })
+}
+
+fn create_pipeline(
+    layout: &PipelineLayout,
+    render_pass: vk::RenderPass
+) -> Result<vk::Pipeline, vk::Result> {
+    let base = &layout.base;
+    //Pipeline
+    //Shaders
+    let code = super::load_shader("fxaa.vert", include_bytes!("../../spv/fxaa.vert.spv"));
+    let create_info = vk::ShaderModuleCreateInfo::builder().code(&code);
+    let vertex_shader = unsafe {
+        base.device.create_shader_module(&create_info, None)?
+    };
+    let code = super::load_shader("present.frag", include_bytes!("../../spv/present.frag.spv"));
+    let create_info = vk::ShaderModuleCreateInfo::builder().code(&code);
+    let fragment_shader = unsafe {
+        base.device.create_shader_module(&create_info, None)?
+    };
+    let shader_stages = [
+        *vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .module(vertex_shader)
+            .name(unsafe {std::ffi::CStr::from_bytes_with_nul_unchecked(b"main\0")}),
+        *vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::FRAGMENT)
+            .module(fragment_shader)
+            .name(unsafe {std::ffi::CStr::from_bytes_with_nul_unchecked(b"main\0")})
+    ];
+    //Fixed functions
+    //Vertex input (fullscreen triangle is generated in the vertex shader shared with FXAA)
+    let vertex_input = vk::PipelineVertexInputStateCreateInfo::builder();
+    //Input assembly
+    let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+    //Viewport (dynamic, set when drawing)
+    let viewport = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+    //Rasterization
+    let rasterization = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .line_width(1.0);
+    //Multisampling
+    let multisample = vk::PipelineMultisampleStateCreateInfo::builder()
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+    //Color blending
+    let color_blend_attachments = [
+        *vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(false)
+            .color_write_mask(vk::ColorComponentFlags::RGBA)
+    ];
+    let color_blend = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .attachments(&color_blend_attachments);
+    //Dynamic state
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder()
+        .dynamic_states(&dynamic_states);
+    //Create pipeline
+    let create_infos = [
+        *vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input)
+            .input_assembly_state(&input_assembly)
+            .viewport_state(&viewport)
+            .rasterization_state(&rasterization)
+            .multisample_state(&multisample)
+            .color_blend_state(&color_blend)
+            .dynamic_state(&dynamic_state)
+            .layout(layout.pipeline_layout)
+            .render_pass(render_pass)
+            .subpass(0)
+    ];
+    let pipelines = match unsafe {base.device.create_graphics_pipelines(
+        base.pipeline_cache,
+        &create_infos,
+        None
+    )} {
+        Ok(v) => v,
+        Err(e) => {return Err(e.1);}
+    };
+    //Destroy shader modules
+    unsafe {
+        base.device.destroy_shader_module(vertex_shader, None);
+        base.device.destroy_shader_module(fragment_shader, None);
+    }
+    Ok(pipelines[0])
+}
@@ -0,0 +1,112 @@
+use ash::vk;
+use crate::scene::{Vertex, VertexFormat};
+use crate::device_scene::PackedVertex;
+use super::PipelineLayout;
+
+/**
+    Create the shadow map pipelines for each vertex format: [full, packed].
+    These use the mesh pipeline layout (so scene descriptor sets can be bound as is),
+    draw depth only with one instance per node (`firstInstance` is the node index)
+    & take the face's view-projection matrix as a vertex push constant.
+*/
+pub fn create_pipelines(
+    layout: &PipelineLayout,
+    render_pass: vk::RenderPass
+) -> Result<[vk::Pipeline; 2], vk::Result> {
+    let base = &layout.base;
+    //Shaders
+    let code = super::load_shader("shadow.vert", include_bytes!("../../spv/shadow.vert.spv"));
+    let create_info = vk::ShaderModuleCreateInfo::builder().code(&code);
+    let vertex_shader = unsafe {
+        base.device.create_shader_module(&create_info, None)?
+    };
+    let shader_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vertex_shader)
+        .name(unsafe {std::ffi::CStr::from_bytes_with_nul_unchecked(b"main\0")});
+    //Fixed functions
+    //Vertex input (position only, at the start of both vertex formats)
+    let vertex_bindings = [VertexFormat::Full, VertexFormat::Packed].map(
+        |vertex_format| *vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(match vertex_format {
+                VertexFormat::Full => std::mem::size_of::<Vertex>(),
+                VertexFormat::Packed => std::mem::size_of::<PackedVertex>()
+            } as u32)
+            .input_rate(vk::VertexInputRate::VERTEX)
+    );
+    let vertex_attribute = vk::VertexInputAttributeDescription::builder()
+        .location(0)
+        .binding(0)
+        .format(vk::Format::R32G32B32_SFLOAT)
+        .offset(0);
+    let vertex_inputs = vertex_bindings.each_ref().map(
+        |binding| *vk::PipelineVertexInputStateCreateInfo::builder()
+            .vertex_binding_descriptions(std::slice::from_ref(binding))
+            .vertex_attribute_descriptions(std::slice::from_ref(&vertex_attribute))
+    );
+    //Input assembly
+    let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+    //Viewport (dynamic, one face of the atlas at a time)
+    let viewport = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+    //Rasterization
+    let rasterization = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .depth_bias_enable(true)
+        .depth_bias_constant_factor(1.5)
+        .depth_bias_slope_factor(1.75)
+        .line_width(1.0);
+    //Multisampling
+    let multisample = vk::PipelineMultisampleStateCreateInfo::builder()
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+    //Depth stencil
+    let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true)
+        .depth_write_enable(true)
+        .depth_compare_op(vk::CompareOp::LESS);
+    //Color blending (no color attachments)
+    let color_blend = vk::PipelineColorBlendStateCreateInfo::builder();
+    //Dynamic state
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder()
+        .dynamic_states(&dynamic_states);
+    //Create pipelines
+    let create_infos = vertex_inputs.each_ref().map(
+        |vertex_input| *vk::GraphicsPipelineCreateInfo::builder()
+            .stages(std::slice::from_ref(&shader_stage))
+            .vertex_input_state(vertex_input)
+            .input_assembly_state(&input_assembly)
+            .viewport_state(&viewport)
+            .rasterization_state(&rasterization)
+            .multisample_state(&multisample)
+            .depth_stencil_state(&depth_stencil)
+            .color_blend_state(&color_blend)
+            .dynamic_state(&dynamic_state)
+            .layout(layout.pipeline_layout)
+            .render_pass(render_pass)
+            .subpass(0)
+    );
+    let result = unsafe {base.device.create_graphics_pipelines(
+        base.pipeline_cache,
+        &create_infos,
+        None
+    )};
+    //Destroy shader module
+    unsafe {
+        base.device.destroy_shader_module(vertex_shader, None);
+    }
+    match result {
+        Ok(pipelines) => Ok([pipelines[0], pipelines[1]]),
+        Err((pipelines, e)) => {
+            for pipeline in pipelines {
+                unsafe {base.device.destroy_pipeline(pipeline, None);}
+            }
+            Err(e)
+        }
+    }
+}
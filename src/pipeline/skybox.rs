@@ -1,10 +1,9 @@
 use ash::vk;
-use crate::{SAMPLE_COUNT};
 use crate::base::Base;
 use super::PipelineLayout;
-use std::rc::Rc;
+use std::sync::Arc;
 
-pub fn create_layout(base: Rc<Base>) -> Result<PipelineLayout, vk::Result> {
+pub fn create_layout(base: Arc<Base>, samples: vk::SampleCountFlags) -> Result<PipelineLayout, vk::Result> {
     //Descriptor set layout
     let bindings = [
         //Camera
@@ -20,8 +19,13 @@ pub fn create_layout(base: Rc<Base>) -> Result<PipelineLayout, vk::Result> {
             .descriptor_count(1)
             .stage_flags(vk::ShaderStageFlags::FRAGMENT)
     ];
+    //The image is unwritten with gradient environments
+    let binding_flags = [vk::DescriptorBindingFlags::empty(), vk::DescriptorBindingFlags::PARTIALLY_BOUND];
+    let mut binding_flags_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder()
+        .binding_flags(&binding_flags);
     let create_info = vk::DescriptorSetLayoutCreateInfo::builder()
-        .bindings(&bindings);
+        .bindings(&bindings)
+        .push_next(&mut binding_flags_info);
     let descriptor_set_layout = unsafe {
         base.device.create_descriptor_set_layout(&create_info, None)?
     };
@@ -35,29 +39,26 @@ pub fn create_layout(base: Rc<Base>) -> Result<PipelineLayout, vk::Result> {
         base,
         samplers: vec![],
         descriptor_set_layout,
+        descriptor_counts: super::descriptor_counts(&bindings),
         pipeline_layout,
+        samples,
         create_pipeline: create_pipeline
     })
 }
 
 fn create_pipeline(
     layout: &PipelineLayout,
-    extent: vk::Extent2D,
     render_pass: vk::RenderPass
 ) -> Result<vk::Pipeline, vk::Result> {
     let base = &layout.base;
     //Pipeline
     //Shaders
-    let code = ash::util::read_spv(
-        &mut std::io::Cursor::new(include_bytes!("../../spv/skybox.vert.spv"))
-    ).unwrap();
+    let code = super::load_shader("skybox.vert", include_bytes!("../../spv/skybox.vert.spv"));
     let create_info = vk::ShaderModuleCreateInfo::builder().code(&code);
     let vertex_shader = unsafe {
         base.device.create_shader_module(&create_info, None)?
     };
-    let code = ash::util::read_spv(
-        &mut std::io::Cursor::new(include_bytes!("../../spv/skybox.frag.spv"))
-    ).unwrap();
+    let code = super::load_shader("skybox.frag", include_bytes!("../../spv/skybox.frag.spv"));
     let create_info = vk::ShaderModuleCreateInfo::builder().code(&code);
     let fragment_shader = unsafe {
         base.device.create_shader_module(&create_info, None)?
@@ -89,20 +90,10 @@ fn create_pipeline(
     //Input assembly
     let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::builder()
         .topology(vk::PrimitiveTopology::TRIANGLE_STRIP);
-    //Viewport
-    let viewports = [
-        *vk::Viewport::builder()
-            .width(extent.width as f32)
-            .height(extent.height as f32)
-            .min_depth(0.0)
-            .max_depth(1.0)
-    ];
-    let scissors = [
-        *vk::Rect2D::builder().extent(extent)
-    ];
+    //Viewport (dynamic, set when drawing)
     let viewport = vk::PipelineViewportStateCreateInfo::builder()
-        .viewport_count(viewports.len() as u32).viewports(&viewports)
-        .scissor_count(scissors.len() as u32).scissors(&scissors);
+        .viewport_count(1)
+        .scissor_count(1);
     //Rasterization
     let rasterization = vk::PipelineRasterizationStateCreateInfo::builder()
         .polygon_mode(vk::PolygonMode::FILL)
@@ -111,8 +102,10 @@ fn create_pipeline(
         .line_width(1.0);
     //Multisampling
     let multisample = vk::PipelineMultisampleStateCreateInfo::builder()
-        .rasterization_samples(SAMPLE_COUNT);
+        .rasterization_samples(layout.samples);
     //Depth stencil
+    //The skybox is on the far plane (depth 1, see `skybox.vert`), so it only passes where the
+    //cleared depth is untouched: any geometry in front of the far plane occludes it
     let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::builder()
         .depth_test_enable(true)
         .depth_write_enable(false)
@@ -132,6 +125,10 @@ fn create_pipeline(
     let color_blend = vk::PipelineColorBlendStateCreateInfo::builder()
         .logic_op_enable(false)
         .attachments(&color_blend_attachments);
+    //Dynamic state
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder()
+        .dynamic_states(&dynamic_states);
     //Create pipeline
     let create_infos = [
         *vk::GraphicsPipelineCreateInfo::builder()
@@ -143,6 +140,7 @@ fn create_pipeline(
             .multisample_state(&multisample)
             .depth_stencil_state(&depth_stencil)
             .color_blend_state(&color_blend)
+            .dynamic_state(&dynamic_state)
             .layout(layout.pipeline_layout)
             .render_pass(render_pass)
             .subpass(0)
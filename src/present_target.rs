@@ -0,0 +1,122 @@
+use ash::vk;
+use crate::{FRAME_COUNT, COLOR_FORMAT};
+use crate::base::Base;
+use crate::pipeline::PipelineLayout;
+use crate::swapchain::Swapchain;
+use std::sync::Arc;
+
+/**
+    A window a renderer presents to: its swapchain, present pipeline & per-frame semaphores.
+    The renderer's first window presents to the base's surface; windows added later own theirs.
+*/
+pub struct PresentTarget {
+    base: Arc<Base>,
+    pub window_id: u32, //SDL window ID
+    pub swapchain: Swapchain,
+    pub present_pipeline: vk::Pipeline, //Compatible with the swapchain's present pass
+    /*
+        Semaphores of each frame:
+        1. Swapchain image acquired
+        2. Presentation
+    */
+    pub semaphores: [[vk::Semaphore; 2]; FRAME_COUNT],
+    //Declared after the swapchain, so it's destroyed after it
+    surface: Surface
+}
+
+struct Surface {
+    base: Arc<Base>,
+    surface: vk::SurfaceKHR,
+    owned: bool //Otherwise, the base's surface
+}
+
+impl PresentTarget {
+    /**
+        Create the swapchain & present pipeline of a window's surface.
+        An owned surface is destroyed with the target (including when creation fails).
+    */
+    pub fn new(
+        base: Arc<Base>,
+        window_id: u32,
+        surface: vk::SurfaceKHR,
+        owned: bool,
+        surface_format: vk::SurfaceFormatKHR,
+        composite_alpha: vk::CompositeAlphaFlagsKHR,
+        present_layout: &PipelineLayout
+    ) -> Result<Self, vk::Result> {
+        let surface = Surface {base: base.clone(), surface, owned};
+        let swapchain = Swapchain::new(base.clone(), surface.surface, None, surface_format, composite_alpha)?;
+        let present_pipeline = (present_layout.create_pipeline)(present_layout, swapchain.render_pass)?;
+        //Semaphores stay null until created, so dropping the target destroys only those which exist
+        let semaphores = [[vk::Semaphore::null(); 2]; FRAME_COUNT];
+        let mut target = Self {base, window_id, swapchain, present_pipeline, semaphores, surface};
+        for semaphore in target.semaphores.iter_mut().flatten() {
+            *semaphore = unsafe {target.base.device.create_semaphore(&vk::SemaphoreCreateInfo::default(), None)}?;
+        }
+        Ok(target)
+    }
+
+    pub fn surface(&self) -> vk::SurfaceKHR {
+        self.surface.surface
+    }
+
+    /**
+        Recreate the swapchain (e.g. after a resize); the device must be idle.
+        The present pipeline is only rebuilt if the format changed, as viewports are dynamic.
+    */
+    pub fn recreate_swapchain(
+        &mut self,
+        surface_format: vk::SurfaceFormatKHR,
+        composite_alpha: vk::CompositeAlphaFlagsKHR,
+        present_layout: &PipelineLayout
+    ) -> Result<(), vk::Result> {
+        let old_format = self.swapchain.format.format;
+        self.swapchain = Swapchain::new(
+            self.base.clone(),
+            self.surface.surface,
+            Some(self.swapchain.swapchain),
+            surface_format,
+            composite_alpha
+        )?;
+        if self.swapchain.format.format != old_format {
+            self.recreate_present_pipeline(present_layout)?;
+        }
+        Ok(())
+    }
+
+    ///Rebuild the present pipeline (e.g. after the swapchain format changed); the device must be idle.
+    pub fn recreate_present_pipeline(&mut self, present_layout: &PipelineLayout) -> Result<(), vk::Result> {
+        let present_pipeline = (present_layout.create_pipeline)(present_layout, self.swapchain.render_pass)?;
+        unsafe {
+            self.base.device.destroy_pipeline(std::mem::replace(&mut self.present_pipeline, present_pipeline), None);
+        }
+        Ok(())
+    }
+
+    /**
+        Whether an image drawn at `extent` is bit-compatible with swapchain images (same format & extent),
+        so it can be blitted without filtering or conversion.
+    */
+    pub fn blit_compatible(&self, extent: vk::Extent2D) -> bool {
+        self.swapchain.format.format == COLOR_FORMAT && self.swapchain.extent == extent
+    }
+}
+
+impl Drop for PresentTarget {
+    fn drop(&mut self) {
+        unsafe {
+            self.base.device.destroy_pipeline(self.present_pipeline, None);
+            for semaphore in self.semaphores.into_iter().flatten() {
+                self.base.device.destroy_semaphore(semaphore, None);
+            }
+        }
+    }
+}
+
+impl Drop for Surface {
+    fn drop(&mut self) {
+        if self.owned {
+            unsafe {self.base.surface_loader.destroy_surface(self.surface, None);}
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use ash::vk;
+use super::sampler::TextureFiltering;
+
+/**
+    Renderer settings chosen together by a `QualityPreset` (see `Renderer::set_quality_preset`).
+    The sample count only applies at creation (see `RendererOptions::quality`).
+*/
+#[derive(Clone, Copy)]
+pub struct QualitySettings {
+    pub render_scale: f32, //See `Renderer::set_render_scale`
+    pub texture_filtering: TextureFiltering, //Anisotropy is clamped to the device limit when applied
+    pub fxaa: bool,
+    pub culling: bool,
+    pub samples: vk::SampleCountFlags //Lowered to what the device supports when applied
+}
+
+/**
+    Combinations of quality settings, from fastest to best looking.
+    Each selects (render scale, texture filtering, FXAA, culling, MSAA samples):
+    * Low: 0.5, bilinear without mip blending (nearest mips), -0.5 LOD bias to offset the upscale, off, on, 1
+    * Medium: 0.75, bilinear (nearest mips), no LOD bias, on, on, 1
+    * High: 1, trilinear with 8x anisotropy, no LOD bias, off, on, 4
+    * Ultra: 1.5 (supersampled), trilinear with 16x anisotropy, no LOD bias, off, on, 8
+
+    Culling is always enabled, as disabling it only helps debugging.
+    FXAA is paired with single sampling, as it would only blur edges MSAA already resolved.
+*/
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum QualityPreset {
+    Low,
+    Medium,
+    High,
+    Ultra
+}
+
+impl QualityPreset {
+    pub fn settings(self) -> QualitySettings {
+        let (render_scale, mipmap_mode, anisotropy, lod_bias, fxaa, samples) = match self {
+            Self::Low => (0.5, vk::SamplerMipmapMode::NEAREST, None, -0.5, false, vk::SampleCountFlags::TYPE_1),
+            Self::Medium => (0.75, vk::SamplerMipmapMode::NEAREST, None, 0.0, true, vk::SampleCountFlags::TYPE_1),
+            Self::High => (1.0, vk::SamplerMipmapMode::LINEAR, Some(8.0), 0.0, false, vk::SampleCountFlags::TYPE_4),
+            Self::Ultra => (1.5, vk::SamplerMipmapMode::LINEAR, Some(16.0), 0.0, false, vk::SampleCountFlags::TYPE_8)
+        };
+        QualitySettings {
+            render_scale,
+            texture_filtering: TextureFiltering {
+                filter: vk::Filter::LINEAR,
+                mipmap_mode,
+                anisotropy,
+                lod_bias
+            },
+            fxaa,
+            culling: true,
+            samples
+        }
+    }
+}
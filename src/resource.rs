@@ -0,0 +1,108 @@
+use ash::vk;
+use super::base::Base;
+use std::sync::Arc;
+
+/*
+    Owned Vulkan handles, destroyed when dropped.
+    Each keeps its `Base` alive, so the device outlives it.
+    Struct fields drop in declaration order,
+    so declare views before their images & resources before their allocation.
+*/
+
+pub struct Buffer {
+    base: Arc<Base>,
+    handle: vk::Buffer
+}
+
+impl Buffer {
+    ///Take ownership of a buffer.
+    pub fn new(base: Arc<Base>, handle: vk::Buffer) -> Self {
+        Self {base, handle}
+    }
+
+    pub fn handle(&self) -> vk::Buffer {
+        self.handle
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        unsafe {
+            self.base.device.destroy_buffer(self.handle, None);
+        }
+    }
+}
+
+pub struct Image {
+    base: Arc<Base>,
+    handle: vk::Image
+}
+
+impl Image {
+    ///Take ownership of an image.
+    pub fn new(base: Arc<Base>, handle: vk::Image) -> Self {
+        Self {base, handle}
+    }
+
+    pub fn handle(&self) -> vk::Image {
+        self.handle
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        unsafe {
+            self.base.device.destroy_image(self.handle, None);
+        }
+    }
+}
+
+pub struct ImageView {
+    base: Arc<Base>,
+    handle: vk::ImageView
+}
+
+impl ImageView {
+    ///Create an image view.
+    pub fn new(base: Arc<Base>, create_info: &vk::ImageViewCreateInfo) -> Result<Self, vk::Result> {
+        let handle = unsafe {base.device.create_image_view(create_info, None)?};
+        Ok(Self {base, handle})
+    }
+
+    pub fn handle(&self) -> vk::ImageView {
+        self.handle
+    }
+}
+
+impl Drop for ImageView {
+    fn drop(&mut self) {
+        unsafe {
+            self.base.device.destroy_image_view(self.handle, None);
+        }
+    }
+}
+
+///Device memory from `Base::create_buffers` or `Base::create_images` (implicitly unmapped when freed).
+pub struct Allocation {
+    base: Arc<Base>,
+    handle: vk::DeviceMemory
+}
+
+impl Allocation {
+    ///Take ownership of an allocation.
+    pub fn new(base: Arc<Base>, handle: vk::DeviceMemory) -> Self {
+        Self {base, handle}
+    }
+
+    pub fn handle(&self) -> vk::DeviceMemory {
+        self.handle
+    }
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        unsafe {
+            self.base.free_memory(self.handle);
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use ash::vk;
+use super::base::Base;
+
+///Filtering applied to all scene textures.
+#[derive(Clone, Copy)]
+pub struct TextureFiltering {
+    pub filter: vk::Filter, //Magnification & minification filter
+    pub mipmap_mode: vk::SamplerMipmapMode, //`LINEAR` for trilinear filtering
+    pub anisotropy: Option<f32>, //Maximum anisotropy (clamped to the device limit)
+    pub lod_bias: f32 //Negative values sharpen (clamped to the device limit)
+}
+
+impl Default for TextureFiltering {
+    fn default() -> Self {
+        Self {
+            filter: vk::Filter::NEAREST,
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            anisotropy: None,
+            lod_bias: 0.0
+        }
+    }
+}
+
+impl TextureFiltering {
+    ///Create a repeating sampler with these settings.
+    ///Anisotropy is ignored if the device doesn't support it.
+    pub(crate) fn create_sampler(&self, base: &Base) -> Result<vk::Sampler, vk::Result> {
+        let limits = &base.physical_device_properties.limits;
+        let anisotropy = self.anisotropy.filter(
+            |_| base.physical_device_features.sampler_anisotropy == vk::TRUE
+        ).map(|a| a.clamp(1.0, limits.max_sampler_anisotropy));
+        let create_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(self.filter)
+            .min_filter(self.filter)
+            .mipmap_mode(self.mipmap_mode)
+            .address_mode_u(vk::SamplerAddressMode::REPEAT)
+            .address_mode_v(vk::SamplerAddressMode::REPEAT)
+            .address_mode_w(vk::SamplerAddressMode::REPEAT)
+            .mip_lod_bias(self.lod_bias.clamp(-limits.max_sampler_lod_bias, limits.max_sampler_lod_bias))
+            .anisotropy_enable(anisotropy.is_some())
+            .max_anisotropy(anisotropy.unwrap_or(1.0))
+            .max_lod(vk::LOD_CLAMP_NONE);
+        unsafe {base.device.create_sampler(&create_info, None)}
+    }
+}
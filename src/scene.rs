@@ -1,5 +1,16 @@
 use nalgebra as na;
 use nalgebra::geometry as na_geo;
+use ash::vk;
+use super::camera::Camera;
+use super::animation::{Animation, Channel, AnimatedProperty, Interpolation};
+use super::MAX_MORPH_TARGETS;
+use gltf::animation::util::ReadOutputs;
+use std::collections::HashMap;
+
+const WELD_EPSILON: f32 = 1e-5;
+const MAX_PRIMITIVE_VERTICES: usize = 65536; //Addressed by 16-bit indices (see `split_primitive`)
+///Base color of primitives without a material (linear, like glTF base color factors).
+pub const DEFAULT_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
 
 #[repr(C)]
 #[derive(Copy, Clone, Default)]
@@ -9,16 +20,109 @@ pub struct Vertex {
     pub tex: na::Vector2<f32>
 }
 
+/**
+    Layout of a scene's vertices in device memory.
+    * `Full`: f32 position, normal & texture coordinates (32 bytes per vertex).
+    * `Packed`: f32 position, octahedral-encoded 16-bit normal & f16 texture coordinates
+      (20 bytes per vertex, 37.5% less vertex memory & bandwidth).
+      Normals stay within 0.004° of the source.
+      Texture coordinates in [0, 1] stay within half a texel of 1024-texel textures,
+      with precision halving at each power of two beyond (e.g. heavily tiled textures).
+      Only the default shaders read packed vertices.
+*/
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum VertexFormat {
+    #[default]
+    Full,
+    Packed
+}
+
+/**
+    How a scene's textures are bound when it's uploaded.
+    * `Separate`: one image per texture in the `MAX_TEXTURES` array of sampled images.
+      Any mix of sizes, but at most `MAX_TEXTURES` textures (including the default texture).
+    * `Arrays`: textures of the same size & color space share a 2D array image (one layer each),
+      so only distinct sizes count towards `MAX_TEXTURES` (layers are limited by the device, at least 256).
+      Each sample decodes an array & layer index, and texture sets of mixed sizes gain little.
+*/
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum TexturePacking {
+    #[default]
+    Separate,
+    Arrays
+}
+
+/**
+    Origin subtracted from vertex positions on import (see `Scene::load_gltf_rebased`).
+    * `None`: positions are kept as authored.
+    * `Centroid`: the average of every mesh's positions.
+    * `Point`: a given point, e.g. the camera's position in the asset's coordinates.
+*/
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum OriginRebase {
+    #[default]
+    None,
+    Centroid,
+    Point(na::Point3<f64>)
+}
+
+/**
+    Up axis of an asset's coordinate system (see `ImportOptions::up_axis`).
+    * `YUp`: already in world space conventions (right-handed, Y up), as the glTF specification requires.
+    * `ZUp`: right-handed with Z up, as exported by some Z-up tools without conversion;
+      rotated by -90° about X, so +Z becomes +Y & +Y becomes -Z.
+*/
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum UpAxis {
+    #[default]
+    YUp,
+    ZUp
+}
+
+impl UpAxis {
+    ///Rotation from the asset's coordinates to world space.
+    pub fn rotation(self) -> na::UnitQuaternion<f32> {
+        match self {
+            Self::YUp => na::UnitQuaternion::identity(),
+            Self::ZUp => na::UnitQuaternion::from_axis_angle(&na::Vector3::x_axis(), -std::f32::consts::FRAC_PI_2)
+        }
+    }
+}
+
+///Options of `Scene::load_gltf_with`.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct ImportOptions {
+    pub rebase: OriginRebase, //See `Scene::load_gltf_rebased`
+    pub up_axis: UpAxis //Applied to root nodes through `Scene::root_transform`
+}
+
+/**
+    Displacements of a primitive's vertices (glTF morph target), one per vertex.
+    Targets are blended by their node's weights (see `Node::weights`).
+*/
+#[derive(Clone, Default)]
+pub struct MorphTarget {
+    pub positions: Vec<na::Vector3<f32>>,
+    pub normals: Vec<na::Vector3<f32>>
+}
+
 #[derive(Clone)]
 pub struct Primitive {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u16>,
-    pub material: u32
+    pub material: u32,
+    pub morph_targets: Vec<MorphTarget> //At most `MAX_MORPH_TARGETS`
 }
 
 #[derive(Clone)]
 pub struct Mesh {
-    pub primitives: Vec<Primitive>
+    pub primitives: Vec<Primitive>,
+    /**
+        Index of the imported primitive (glTF primitive, or OBJ material group) each of `primitives` comes from.
+        Imported primitives with more vertices than 16-bit indices address are split into consecutive primitives
+        sharing an index, so `primitives` can be longer than the source mesh's primitive list.
+    */
+    pub source_primitives: Vec<u32>
 }
 
 #[derive(Clone)]
@@ -27,7 +131,50 @@ pub struct Node {
     pub children: Vec<u32>,
     pub translation: na_geo::Translation3<f32>,
     pub rotation: na_geo::Rotation3<f32>,
-    pub scale: na_geo::Scale3<f32>
+    pub scale: na_geo::Scale3<f32>,
+    //Morph target weights of the mesh's primitives, applied by `SceneSet::update_scene`.
+    //Culling bounds assume weights in [0, 1].
+    pub weights: Vec<f32>
+}
+
+///Vertex & index counts before & after `Scene::optimize`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct OptimizeReport {
+    pub vertices_before: usize,
+    pub vertices_after: usize,
+    pub indices_before: usize,
+    pub indices_after: usize
+}
+
+impl std::fmt::Display for OptimizeReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Vertices: {} -> {}, indices: {} -> {}",
+            self.vertices_before,
+            self.vertices_after,
+            self.indices_before,
+            self.indices_after
+        )
+    }
+}
+
+///Failure to load an OBJ file or its MTL libraries & textures (see `Scene::load_obj`).
+#[derive(Debug)]
+pub enum ObjError {
+    Io(std::path::PathBuf, std::io::Error),
+    Image(std::path::PathBuf, image::ImageError),
+    Parse {path: std::path::PathBuf, line: usize, message: String}
+}
+
+impl std::fmt::Display for ObjError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            Self::Image(path, e) => write!(f, "{}: {}", path.display(), e),
+            Self::Parse {path, line, message} => write!(f, "{}:{}: {}", path.display(), line, message)
+        }
+    }
 }
 
 #[repr(C, align(16))]
@@ -37,24 +184,167 @@ pub struct Material {
     pub color_texture: u32,
     pub metal_rough_texture: u32,
     pub metal_factor: f32,
-    pub rough_factor: f32
+    pub rough_factor: f32,
+    pub height_texture: u32, //Parallax occlusion mapping (white is highest)
+    pub height_scale: f32, //Parallax depth in texture coordinates, 0 disables parallax
+    pub emissive_texture: u32,
+    pub emissive_strength: f32, //KHR_materials_emissive_strength (unclamped emissive multiplier)
+    pub emissive: na::Vector4<f32>, //Emissive factor (w unused)
+    pub transmission_texture: u32,
+    pub transmission_factor: f32, //KHR_materials_transmission (0 is opaque)
+    pub ior: f32, //Index of refraction (KHR_materials_ior), also giving the dielectric F0
+    //KHR_materials_clearcoat: specular layer over the base (0 disables it)
+    pub clearcoat_factor: f32,
+    pub clearcoat_texture: u32, //Factor in the red channel
+    pub clearcoat_roughness: f32,
+    pub clearcoat_roughness_texture: u32, //Roughness in the green channel
+    pub workflow: MaterialWorkflow, //Source workflow (ignored by shaders, which only use metallic-roughness)
+    //KHR_materials_specular: dielectric F0 is ((ior - 1) / (ior + 1))² tinted by the color & scaled by the factor
+    pub specular_color: na::Vector4<f32>, //Linear F0 color factor (w unused)
+    pub specular_color_texture: u32, //sRGB, in the RGB channels
+    pub specular_factor: f32,
+    pub specular_texture: u32, //Factor in the alpha channel
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32 //Coverage threshold of the color's alpha with `AlphaMode::Mask`
+}
+
+/**
+    How a material's color alpha (factor times texture) is used.
+    Masked materials are cutouts (e.g. foliage): scenes containing them are drawn with alpha-to-coverage,
+    turning alpha around the cutoff into MSAA sample coverage, so edges are antialiased by the resolve
+    without sorting. glTF `BLEND` materials are imported as opaque.
+*/
+#[repr(u32)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum AlphaMode {
+    #[default]
+    Opaque,
+    Mask
+}
+
+/**
+    Workflow a material was authored in. Specular-glossiness materials
+    (KHR_materials_pbrSpecularGlossiness) are converted to metallic-roughness on import:
+    factors are converted exactly, while a specular-glossiness texture becomes a new metallic-roughness
+    texture solved against the diffuse factor (the diffuse texture is used as the base color as is).
+*/
+#[repr(u32)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum MaterialWorkflow {
+    #[default]
+    MetallicRoughness,
+    SpecularGlossiness
 }
 
-#[repr(C, align(16))]
 #[derive(Copy, Clone, Default)]
 pub struct PointLight {
     pub pos: na::Vector4<f32>,
     pub color: na::Vector4<f32>,
     pub intensity: f32,
-    pub range: f32
+    pub range: f32,
+    //Rendered into the shadow atlas (the first `MAX_SHADOW_CASTERS` such lights with a range)
+    pub casts_shadow: bool
 }
 
+///Camera-facing quad (e.g. a particle or impostor), drawn with `Renderer::draw_billboards`.
+#[repr(C, align(16))]
+#[derive(Copy, Clone, Default)]
+pub struct Billboard {
+    pub pos: na::Vector3<f32>, //Center
+    pub size: f32, //Width & height in world units
+    pub color: na::Vector4<f32> //Linear color, alpha blended
+}
+
+///Line segment drawn with `Renderer::draw_lines` (e.g. for debugging bounds, normals or paths).
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct DebugLine {
+    pub start: na::Vector3<f32>,
+    pub end: na::Vector3<f32>,
+    pub color: na::Vector4<f32> //Linear color, alpha blended
+}
+
+/**
+    World space is right-handed with Y up (see `Camera`); assets with other conventions
+    can be converted on import (see `UpAxis`).
+
+    Color-space conventions:
+    * Color factors (`Material::color`, `Material::emissive`, light colors) are linear, as in glTF.
+    * Base color & emissive textures are sRGB encoded & decoded to linear when sampled,
+      other textures (metallic-roughness, height, transmission) hold linear data.
+    * Shading is linear; the tonemapped result is encoded to sRGB by the framebuffer format.
+*/
 #[derive(Clone)]
 pub struct Scene {
     pub nodes: Vec<Node>,
     pub meshes: Vec<Mesh>,
     pub materials: Vec<Material>,
-    pub textures: Vec<image::RgbaImage> //TODO: Custom image format
+    pub textures: Vec<image::RgbaImage>, //TODO: Custom image format
+    pub vertex_format: VertexFormat, //Applied when the scene is uploaded
+    pub texture_packing: TexturePacking, //Applied when the scene is uploaded
+    pub animations: Vec<Animation>, //Played with `animation::Animator`
+    pub root_transform: na_geo::Affine3<f32>, //Applied to root nodes (e.g. the offset of rebased imports)
+    pub warnings: Vec<String> //Problems worked around while loading (e.g. missing images replaced by white textures)
+}
+
+impl Primitive {
+    /**
+        Weld vertices whose attributes are equal within `epsilon` & rebuild the index buffer.
+        Each vertex is welded to the first kept vertex within `epsilon` of it (so chains of close vertices
+        aren't collapsed into one), found among the kept vertices in the neighbouring position cells.
+        Unindexed primitives become indexed, and unreferenced vertices are removed.
+        The primitive is left unchanged if the welded vertices don't fit 16-bit indices
+        or if it has morph targets.
+    */
+    pub fn weld(&mut self, epsilon: f32) {
+        if !self.morph_targets.is_empty() {
+            return;
+        }
+        let indices: Vec<usize> = if self.indices.is_empty() {
+            (0..self.vertices.len()).collect()
+        } else {
+            self.indices.iter().map(|&i| i as usize).collect()
+        };
+        //Kept vertices by position cell, so vertices within `epsilon` are in the same or a neighbouring cell
+        let cell = |pos: &na::Vector3<f32>| pos.map(|x| (x / epsilon).floor() as i64);
+        let close = |a: &Vertex, b: &Vertex| (a.pos - b.pos).amax() <= epsilon
+            && (a.normal - b.normal).amax() <= epsilon
+            && (a.tex - b.tex).amax() <= epsilon;
+        let mut cells = HashMap::<na::Vector3<i64>, Vec<usize>>::new();
+        let mut remap: Vec<Option<usize>> = vec![None; self.vertices.len()];
+        let mut vertices = Vec::<Vertex>::new();
+        for &i in &indices {
+            if remap[i].is_some() {
+                continue;
+            }
+            let vertex = &self.vertices[i];
+            let center = cell(&vertex.pos);
+            let neighbours = (0..27).map(|n| center + na::Vector3::new(n % 3 - 1, n / 3 % 3 - 1, n / 9 - 1));
+            let welded = neighbours.filter_map(|neighbour| cells.get(&neighbour)).flatten().copied().filter(
+                |&kept| close(&vertices[kept], vertex)
+            ).min();
+            remap[i] = Some(welded.unwrap_or_else(|| {
+                vertices.push(*vertex);
+                cells.entry(center).or_default().push(vertices.len() - 1);
+                vertices.len() - 1
+            }));
+        }
+        if vertices.len() > u16::MAX as usize + 1 {
+            return;
+        }
+        self.indices = indices.iter().map(|&i| remap[i].unwrap() as u16).collect();
+        self.vertices = vertices;
+    }
+}
+
+impl Mesh {
+    ///Mesh of imported primitives, each split into one or more primitives (see `source_primitives`).
+    fn from_split(split_primitives: Vec<Vec<Primitive>>) -> Self {
+        let source_primitives = split_primitives.iter().enumerate().flat_map(
+            |(i, primitives)| std::iter::repeat_n(i as u32, primitives.len())
+        ).collect();
+        Self {primitives: split_primitives.into_iter().flatten().collect(), source_primitives}
+    }
 }
 
 impl Node {
@@ -79,7 +369,7 @@ impl Scene {
         //Traverse scene graph
         let mut stack: Vec<(usize, na_geo::Affine3<f32>)> = root_mask.iter().enumerate().filter_map(
             |(i, b)| if *b {
-                Some((i, self.nodes[i].matrix()))
+                Some((i, self.root_transform * self.nodes[i].matrix()))
             } else {None}
         ).collect();
         let mut result = vec![na_geo::Affine3::<f32>::identity(); self.nodes.len()];
@@ -96,8 +386,249 @@ impl Scene {
         result
     }
 
+    ///Weld duplicate vertices in every primitive (see `Primitive::weld`).
+    pub fn optimize(&mut self) -> OptimizeReport {
+        let mut report = OptimizeReport::default();
+        for primitive in self.meshes.iter_mut().flat_map(|mesh| &mut mesh.primitives) {
+            report.vertices_before += primitive.vertices.len();
+            report.indices_before += primitive.indices.len();
+            primitive.weld(WELD_EPSILON);
+            report.vertices_after += primitive.vertices.len();
+            report.indices_after += primitive.indices.len();
+        }
+        report
+    }
+
+    /**
+        Describe the scene for debugging imports: the node hierarchy (indented by depth) with local & world transforms,
+        meshes with their primitives' vertex, index & morph target counts & materials,
+        then materials with their factors & textures, and texture dimensions.
+        Primitives whose normals are all zero (which render black) are flagged.
+        Nodes deeper than `max_depth` (0 lists only root nodes) are summarized by their count.
+    */
+    pub fn debug_dump(&self, max_depth: Option<usize>) -> String {
+        use std::fmt::Write;
+        let mut out = String::new();
+        let world = self.transformations();
+        let mut root_mask = vec![true; self.nodes.len()];
+        for node in &self.nodes {
+            for child in &node.children {
+                root_mask[*child as usize] = false;
+            }
+        }
+        //Node hierarchy (writing to a `String` can't fail)
+        let _ = writeln!(out, "Nodes: {}", self.nodes.len());
+        let mut stack: Vec<(usize, usize)> = root_mask.iter().enumerate().rev().filter_map(
+            |(i, b)| if *b {Some((i, 0))} else {None}
+        ).collect();
+        while let Some((index, depth)) = stack.pop() {
+            let node = &self.nodes[index];
+            let indent = 2 * (depth + 1);
+            let (roll, pitch, yaw) = node.rotation.euler_angles();
+            let position = world[index].transform_point(&na::Point3::origin());
+            let _ = writeln!(
+                out,
+                "{:indent$}Node {}: translation {:?}, rotation (degrees) {:?}, scale {:?}, world position {:?}",
+                "",
+                index,
+                node.translation.vector.as_slice(),
+                [roll, pitch, yaw].map(f32::to_degrees),
+                node.scale.vector.as_slice(),
+                position.coords.as_slice()
+            );
+            if let Some(mesh) = node.mesh {
+                let primitives = self.meshes.get(mesh as usize).map_or(0, |mesh| mesh.primitives.len());
+                let _ = writeln!(out, "{:indent$}  Mesh {} ({} primitives), weights {:?}", "", mesh, primitives, node.weights);
+            }
+            if max_depth.is_some_and(|max_depth| depth >= max_depth) {
+                if !node.children.is_empty() {
+                    let _ = writeln!(out, "{:indent$}  ({} children not shown)", "", node.children.len());
+                }
+            } else {
+                stack.extend(node.children.iter().rev().map(|&child| (child as usize, depth + 1)));
+            }
+        }
+        //Meshes
+        let _ = writeln!(out, "Meshes: {}", self.meshes.len());
+        for (i, mesh) in self.meshes.iter().enumerate() {
+            let _ = writeln!(out, "  Mesh {}:", i);
+            for (j, primitive) in mesh.primitives.iter().enumerate() {
+                let missing_normals = !primitive.vertices.is_empty()
+                    && primitive.vertices.iter().all(|vertex| vertex.normal == na::Vector3::zeros());
+                let _ = writeln!(
+                    out,
+                    "    Primitive {}: {} vertices, {} indices, {} morph targets, material {}{}{}",
+                    j,
+                    primitive.vertices.len(),
+                    primitive.indices.len(),
+                    primitive.morph_targets.len(),
+                    primitive.material,
+                    if primitive.material == 0 {" (default)"} else {""},
+                    if missing_normals {", NO NORMALS"} else {""}
+                );
+            }
+        }
+        //Materials (texture 0 is the default white texture)
+        let _ = writeln!(out, "Materials: {}", self.materials.len());
+        for (i, material) in self.materials.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "  Material {}: color {:?}, metal {}, rough {}, emissive {:?} x {}, transmission {}, ior {}, {:?}, {:?} ({})",
+                i,
+                material.color.as_slice(),
+                material.metal_factor,
+                material.rough_factor,
+                &material.emissive.as_slice()[..3],
+                material.emissive_strength,
+                material.transmission_factor,
+                material.ior,
+                material.workflow,
+                material.alpha_mode,
+                material.alpha_cutoff
+            );
+            let textures = [
+                ("color", material.color_texture),
+                ("metal_rough", material.metal_rough_texture),
+                ("height", material.height_texture),
+                ("emissive", material.emissive_texture),
+                ("transmission", material.transmission_texture),
+                ("clearcoat", material.clearcoat_texture),
+                ("clearcoat_roughness", material.clearcoat_roughness_texture),
+                ("specular_color", material.specular_color_texture),
+                ("specular", material.specular_texture)
+            ];
+            for (name, texture) in textures.into_iter().filter(|(_, texture)| *texture != 0) {
+                let _ = writeln!(out, "    {} texture {}", name, texture);
+            }
+        }
+        //Textures
+        let _ = writeln!(out, "Textures: {}", self.textures.len());
+        for (i, texture) in self.textures.iter().enumerate() {
+            let _ = writeln!(out, "  Texture {}: {}x{}", i, texture.width(), texture.height());
+        }
+        let _ = writeln!(out, "Animations: {}", self.animations.len());
+        out
+    }
+
+    ///Assign a height map to a material, adding the image to the scene's textures.
+    pub fn set_height_map(&mut self, material: usize, image: image::RgbaImage, scale: f32) {
+        self.textures.push(image);
+        self.materials[material].height_texture = (self.textures.len() - 1) as u32;
+        self.materials[material].height_scale = scale;
+    }
+
+    ///Set the base color (linear) of primitives without a material.
+    pub fn set_default_color(&mut self, color: na::Vector4<f32>) {
+        self.materials[0].color = color;
+    }
+
+    ///Whether a texture holds sRGB-encoded color (base color or emission) rather than linear data.
+    pub fn is_color_texture(&self, texture: u32) -> bool {
+        self.materials.iter().any(
+            |material| material.color_texture == texture
+                || material.emissive_texture == texture
+                || material.specular_color_texture == texture
+        )
+    }
+
+    /**
+        Rasterize the scene on the CPU (no Vulkan), e.g. for previews & smoke tests without a GPU.
+        Triangles are flat shaded with a headlight & their material's base color (ignoring textures),
+        depth tested & encoded as sRGB; nothing is culled.
+        The camera's aspect ratio is used as is, so should match `extent`.
+    */
+    pub fn rasterize_preview(&self, camera: &Camera, extent: vk::Extent2D) -> image::RgbaImage {
+        let (width, height) = (extent.width as usize, extent.height as usize);
+        let mut image = image::RgbaImage::from_pixel(extent.width, extent.height, image::Rgba([0, 0, 0, 255]));
+        let mut depth = vec![1.0f32; width * height];
+        let view_projection = camera.projection() * camera.view();
+        for (node, transform) in std::iter::zip(&self.nodes, self.transformations()) {
+            let Some(mesh) = node.mesh else {continue};
+            for primitive in &self.meshes[mesh as usize].primitives {
+                let color = self.materials[primitive.material as usize].color;
+                let world: Vec<na::Point3<f32>> = primitive.vertices.iter().map(
+                    |vertex| transform * na::Point3::from(vertex.pos)
+                ).collect();
+                for triangle in primitive.indices.chunks_exact(3) {
+                    let [a, b, c] = [0, 1, 2].map(|i| world[triangle[i] as usize]);
+                    //Headlight shading from the face normal
+                    let normal = (b - a).cross(&(c - a));
+                    let to_camera = camera.pos - a;
+                    let shade = match normal.norm() * to_camera.norm() {
+                        n if n > 0.0 => 0.2 + 0.8 * (normal.dot(&to_camera) / n).abs(),
+                        _ => 0.2
+                    };
+                    let rgba = image::Rgba([
+                        encode_srgb(color.x * shade),
+                        encode_srgb(color.y * shade),
+                        encode_srgb(color.z * shade),
+                        255
+                    ]);
+                    //Clip against the near plane, then triangulate the polygon as a fan
+                    let clip = clip_near([a, b, c].map(|p| view_projection * p.to_homogeneous()));
+                    let screen: Vec<na::Vector3<f32>> = clip.iter().map(|p| na::Vector3::new(
+                        (p.x / p.w + 1.0) * 0.5 * width as f32,
+                        (p.y / p.w + 1.0) * 0.5 * height as f32,
+                        p.z / p.w
+                    )).collect();
+                    for i in 2..screen.len() {
+                        rasterize_triangle(
+                            [screen[0], screen[i - 1], screen[i]],
+                            &mut depth,
+                            &mut image,
+                            rgba
+                        );
+                    }
+                }
+            }
+        }
+        image
+    }
+
+    ///Load a glTF file on a separate thread.
+    ///A `Scene` is plain CPU data (no Vulkan handles), so it is `Send` and can be
+    ///handed back to the rendering thread, where it is uploaded with `SceneSet::push_scene`.
+    pub fn load_gltf_async<P>(path: P) -> std::thread::JoinHandle<gltf::Result<Self>>
+    where P: AsRef<std::path::Path> + Send + 'static {
+        std::thread::spawn(move || Self::load_gltf(path))
+    }
+
+    /**
+        Load a glTF file & the buffers & images it references.
+        Images which are missing or fail to decode are replaced by white textures
+        & reported in `warnings`, rather than failing the whole import.
+        Point & line primitives fail it. Triangle strips & fans are converted to lists.
+    */
     pub fn load_gltf<P: AsRef<std::path::Path>>(path: P) -> gltf::Result<Self> {
-        let (document, buffers, images) = gltf::import(path)?;
+        Self::load_gltf_rebased(path, OriginRebase::None)
+    }
+
+    /**
+        Load a glTF file, rebasing vertex positions onto `rebase`'s origin to keep precision
+        in large-world assets authored far from the origin.
+        Positions are read as f64, offset by the origin (in each mesh's space) & only then
+        converted to f32, while `root_transform` translates the scene back by the offset.
+        This is exact when mesh nodes only translate, as in exports baking world positions
+        into vertices; rotated or scaled mesh nodes move by the difference of the offset
+        & its transformed value.
+        The offset itself is stored in f32, so keep the camera near the rebased origin
+        (e.g. rebase onto the camera's position) to keep precision while drawing.
+    */
+    pub fn load_gltf_rebased<P: AsRef<std::path::Path>>(path: P, rebase: OriginRebase) -> gltf::Result<Self> {
+        Self::load_gltf_with(path, ImportOptions {rebase, ..ImportOptions::default()})
+    }
+
+    /**
+        Load a glTF file with import options: origin rebasing (see `load_gltf_rebased`)
+        & conversion from another up axis, which rotates root nodes through `root_transform`
+        (after the rebasing offset).
+    */
+    pub fn load_gltf_with<P: AsRef<std::path::Path>>(path: P, options: ImportOptions) -> gltf::Result<Self> {
+        let rebase = options.rebase;
+        let (document, buffers, images, warnings) = import_gltf(path.as_ref())?;
+        //Raw JSON for extensions unsupported by the gltf crate
+        let json = raw_json(path.as_ref());
+        check_modes(&document).map_err(gltf::Error::Io)?;
         //Nodes
         let nodes: Vec<Node> = document.nodes().map(|node| {
             let (translation, rotation, scale) = node.transform().decomposed();
@@ -111,15 +642,30 @@ impl Scene {
                 rotation: na::UnitQuaternion::from_quaternion(
                     na::Quaternion::<f32>::from(rotation)
                 ).into(),
-                scale: scale.into()
+                scale: scale.into(),
+                weights: node.weights().or(node.mesh().and_then(|mesh| mesh.weights())).unwrap_or(&[]).to_vec()
             }
         }).collect();
+        //Origin offset, accumulated in f64
+        let offset = match rebase {
+            OriginRebase::None => na::Vector3::<f64>::zeros(),
+            OriginRebase::Centroid => {
+                let (sum, count) = document.meshes().flat_map(|mesh| mesh.primitives()).filter_map(
+                    |primitive| primitive.get(&gltf::Semantic::Positions)
+                ).fold((na::Vector3::<f64>::zeros(), 0), |(sum, count), accessor| (
+                    read_vec3_f64(&accessor, &buffers).iter().fold(sum, |sum, pos| sum + pos),
+                    count + accessor.count()
+                ));
+                if count > 0 {sum / count as f64} else {sum}
+            },
+            OriginRebase::Point(point) => point.coords
+        };
         //Meshes
         let meshes: Vec<Mesh> = document.meshes().map(|mesh| {
-            let primitives: Vec<Primitive> = mesh.primitives().map(|primitive| {
-                let mut indices = Vec::<u16>::new();
+            let split_primitives: Vec<Vec<Primitive>> = mesh.primitives().map(|primitive| {
                 //Indices
-                if let Some(accessor) = primitive.indices() {
+                let indices = primitive.indices().map(|accessor| {
+                    let mut indices = Vec::<u32>::new();
                     let view = accessor.view().unwrap();
                     let buffer = view.buffer();
                     let data = &buffers[buffer.index()];
@@ -131,17 +677,19 @@ impl Scene {
                     for i in 0..accessor.count() {
                         let offset = offset + i * stride;
                         let value = match accessor.size() {
+                            1 => data[offset] as u32,
                             size @ 2 => u16::from_le_bytes(
                                 data[offset..offset + size].try_into().unwrap()
-                            ),
+                            ) as u32,
                             size @ 4 => u32::from_le_bytes(
                                 data[offset..offset + size].try_into().unwrap()
-                            ) as u16,
+                            ),
                             _ => panic!("Unknown index type size")
                         };
                         indices.push(value);
                     }
-                }
+                    indices
+                });
                 //Vertex attributes
                 //TODO: Additional texture coordinates
                 //TODO: Fill missing attributes
@@ -151,26 +699,9 @@ impl Scene {
                 for (semantic, accessor) in primitive.attributes() {
                     match semantic {
                         gltf::Semantic::Positions => {
-                            let view = accessor.view().unwrap();
-                            let buffer = view.buffer();
-                            let data = &buffers[buffer.index()];
-                            let offset = view.offset() + accessor.offset();
-                            let stride = match view.stride() {
-                                Some(s) => s,
-                                None => accessor.size()
-                            };
-                            for i in 0..accessor.count() {
-                                let mut element = na::Vector3::<f32>::zeros();
-                                let offset = offset + i * stride;
-                                for j in 0..std::cmp::max(accessor.dimensions().multiplicity(), 3) {
-                                    let scalar_size = accessor.data_type().size();
-                                    let offset = offset + j * scalar_size;
-                                    element[j] = f32::from_le_bytes(
-                                        data[offset..offset + scalar_size].try_into().unwrap()
-                                    );
-                                }
-                                positions.push(element);
-                            }
+                            positions = read_vec3_f64(&accessor, &buffers).iter().map(
+                                |pos| (pos - offset).cast::<f32>()
+                            ).collect();
                         },
                         gltf::Semantic::Normals => {
                             let view = accessor.view().unwrap();
@@ -234,27 +765,56 @@ impl Scene {
                         tex: texcoords[i],
                     }
                 ).collect();
+                //Morph targets (missing attributes don't move)
+                let morph_targets: Vec<MorphTarget> = primitive.morph_targets().take(MAX_MORPH_TARGETS).map(
+                    |target| MorphTarget {
+                        positions: match target.positions() {
+                            Some(accessor) => read_vec3(&accessor, &buffers),
+                            None => vec![na::Vector3::zeros(); vertices.len()]
+                        },
+                        normals: match target.normals() {
+                            Some(accessor) => read_vec3(&accessor, &buffers),
+                            None => vec![na::Vector3::zeros(); vertices.len()]
+                        }
+                    }
+                ).collect();
                 //Material
                 let material = match primitive.material().index() {
                     Some(x) => x as u32 + 1,
                     None => 0
                 };
-                Primitive {vertices, indices, material}
+                //Non-indexed primitives get sequential indices, & strips & fans become lists
+                let indices = triangle_list(
+                    primitive.mode(),
+                    indices.unwrap_or_else(|| (0..vertices.len() as u32).collect())
+                );
+                split_primitive(vertices, morph_targets, &indices, material)
             }).collect();
-            Mesh {primitives}
+            Mesh::from_split(split_primitives)
         }).collect();
+        //Textures
+        let mut textures = vec![default_texture()];
+        textures.append(&mut document.textures().map(|texture| {
+            images[texture.source().index()].clone().unwrap_or_else(default_texture)
+        }).collect());
         //Materials
-        let default_material = Material {
-            color: na::Vector4::<f32>::new(1.0, 0.0, 0.0, 1.0),
-            color_texture: 0,
-            metal_rough_texture: 0,
-            metal_factor: 0.0,
-            rough_factor: 0.0
-        };
-        let mut materials = vec![default_material];
+        let mut materials = vec![default_material()];
         materials.append(&mut document.materials().map(|material| {
             let pbr = material.pbr_metallic_roughness();
-            Material {
+            let (height_texture, height_scale) = height_map(material.extras());
+            let emissive_strength = material.index().and_then(
+                |i| json["materials"][i]["extensions"]["KHR_materials_emissive_strength"]["emissiveStrength"].as_f64()
+            ).unwrap_or(1.0) as f32;
+            let [r, g, b] = material.emissive_factor();
+            let transmission = material.transmission();
+            let clearcoat = material.index().map_or(
+                &serde_json::Value::Null,
+                |i| &json["materials"][i]["extensions"]["KHR_materials_clearcoat"]
+            );
+            let clearcoat_texture = |name: &str| clearcoat[name]["index"].as_u64().map_or(0, |i| i as u32 + 1);
+            let specular = material.specular();
+            let [specular_r, specular_g, specular_b] = specular.as_ref().map_or([1.0; 3], |s| s.specular_color_factor());
+            let mut result = Material {
                 color: pbr.base_color_factor().into(),
                 color_texture: match pbr.base_color_texture() {
                     Some(info) => info.texture().index() + 1,
@@ -265,36 +825,1210 @@ impl Scene {
                     None => 0
                 } as u32,
                 metal_factor: pbr.metallic_factor(),
-                rough_factor: pbr.roughness_factor()
+                rough_factor: pbr.roughness_factor(),
+                height_texture,
+                height_scale,
+                emissive_texture: match material.emissive_texture() {
+                    Some(info) => info.texture().index() + 1,
+                    None => 0
+                } as u32,
+                emissive_strength,
+                emissive: na::Vector4::new(r, g, b, 0.0),
+                transmission_texture: match transmission.as_ref().and_then(|t| t.transmission_texture()) {
+                    Some(info) => info.texture().index() + 1,
+                    None => 0
+                } as u32,
+                transmission_factor: transmission.as_ref().map_or(0.0, |t| t.transmission_factor()),
+                ior: material.ior().unwrap_or(1.5),
+                clearcoat_factor: clearcoat["clearcoatFactor"].as_f64().unwrap_or(0.0) as f32,
+                clearcoat_texture: clearcoat_texture("clearcoatTexture"),
+                clearcoat_roughness: clearcoat["clearcoatRoughnessFactor"].as_f64().unwrap_or(0.0) as f32,
+                clearcoat_roughness_texture: clearcoat_texture("clearcoatRoughnessTexture"),
+                workflow: MaterialWorkflow::MetallicRoughness,
+                specular_color: na::Vector4::new(specular_r, specular_g, specular_b, 0.0),
+                specular_color_texture: match specular.as_ref().and_then(|s| s.specular_color_texture()) {
+                    Some(info) => info.texture().index() + 1,
+                    None => 0
+                } as u32,
+                specular_factor: specular.as_ref().map_or(1.0, |s| s.specular_factor()),
+                specular_texture: match specular.as_ref().and_then(|s| s.specular_texture()) {
+                    Some(info) => info.texture().index() + 1,
+                    None => 0
+                } as u32,
+                alpha_mode: match material.alpha_mode() {
+                    gltf::material::AlphaMode::Mask => AlphaMode::Mask,
+                    gltf::material::AlphaMode::Opaque | gltf::material::AlphaMode::Blend => AlphaMode::Opaque
+                },
+                alpha_cutoff: material.alpha_cutoff().unwrap_or(0.5)
+            };
+            //Specular-glossiness is converted to metallic-roughness, keeping a single shading model
+            if let Some(spec_gloss) = material.pbr_specular_glossiness() {
+                let diffuse = na::Vector4::from(spec_gloss.diffuse_factor());
+                let specular = na::Vector3::from(spec_gloss.specular_factor());
+                let glossiness = spec_gloss.glossiness_factor();
+                let (color, metallic, roughness) = specular_glossiness_factors(&diffuse, &specular, glossiness);
+                result.color = color;
+                result.color_texture = spec_gloss.diffuse_texture().map_or(0, |info| info.texture().index() as u32 + 1);
+                (result.metal_rough_texture, result.metal_factor, result.rough_factor) =
+                    match spec_gloss.specular_glossiness_texture() {
+                        //Per-texel conversion, with the factors folded into a new texture
+                        Some(info) => {
+                            textures.push(specular_glossiness_texture(
+                                &textures[info.texture().index() + 1],
+                                &diffuse.xyz(),
+                                &specular,
+                                glossiness
+                            ));
+                            (textures.len() as u32 - 1, 1.0, 1.0)
+                        },
+                        None => (0, metallic, roughness)
+                    };
+                result.workflow = MaterialWorkflow::SpecularGlossiness;
             }
+            result
         }).collect());
+        //Animations (channels without readable keyframes are skipped)
+        let animations = document.animations().map(|animation| Animation {
+            name: animation.name().map(str::to_owned),
+            channels: animation.channels().filter_map(|channel| {
+                let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+                let times: Vec<f32> = reader.read_inputs()?.collect();
+                let (property, values) = match reader.read_outputs()? {
+                    ReadOutputs::Translations(values) => (AnimatedProperty::Translation, values.flatten().collect()),
+                    ReadOutputs::Rotations(values) => (AnimatedProperty::Rotation, values.into_f32().flatten().collect()),
+                    ReadOutputs::Scales(values) => (AnimatedProperty::Scale, values.flatten().collect()),
+                    ReadOutputs::MorphTargetWeights(values) => (AnimatedProperty::Weights, values.into_f32().collect())
+                };
+                Some(Channel {
+                    node: channel.target().node().index() as u32,
+                    property,
+                    interpolation: match channel.sampler().interpolation() {
+                        gltf::animation::Interpolation::Linear => Interpolation::Linear,
+                        gltf::animation::Interpolation::Step => Interpolation::Step,
+                        gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline
+                    },
+                    times,
+                    values
+                })
+            }).collect()
+        }).collect();
+        Ok(Self {
+            nodes,
+            meshes,
+            materials,
+            textures,
+            vertex_format: VertexFormat::Full,
+            texture_packing: TexturePacking::Separate,
+            animations,
+            root_transform: na_geo::Affine3::from_matrix_unchecked(
+                options.up_axis.rotation().to_homogeneous()
+                * na_geo::Translation3::from(offset.cast::<f32>()).to_homogeneous()
+            ),
+            warnings
+        })
+    }
+
+    /**
+        Load a Wavefront OBJ file & the MTL libraries it references.
+        * Each object (`o`) or group (`g`) becomes a mesh with its own node,
+          split into a primitive per material (`usemtl`).
+        * Polygons are triangulated as fans, so should be convex.
+        * Faces without normals get smooth normals, averaged over the faces sharing each position.
+        * Materials use the diffuse color (`Kd`, taken as linear), dissolve (`d` or `Tr`)
+          & diffuse map (`map_Kd`, loaded relative to the MTL file); other statements are ignored.
+        * Texture coordinates are flipped vertically, as OBJ's origin is the bottom-left corner.
+    */
+    pub fn load_obj<P: AsRef<std::path::Path>>(path: P) -> Result<Self, ObjError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path).map_err(|e| ObjError::Io(path.to_path_buf(), e))?;
+        let directory = path.parent().unwrap_or(std::path::Path::new(""));
+        let mut positions = Vec::<na::Vector3<f32>>::new();
+        let mut normals = Vec::<na::Vector3<f32>>::new();
+        let mut texcoords = Vec::<na::Vector2<f32>>::new();
+        let mut library = ObjLibrary {
+            materials: vec![default_material()],
+            textures: vec![default_texture()],
+            material_names: HashMap::new(),
+            texture_paths: HashMap::new()
+        };
+        //Triangles of each object, by material
+        let mut objects: Vec<Vec<(u32, Vec<[ObjCorner; 3]>)>> = vec![vec![]];
+        let mut material = 0;
+        for (i, line) in source.lines().enumerate() {
+            let error = |message: String| ObjError::Parse {path: path.to_path_buf(), line: i + 1, message};
+            let line = line.split('#').next().unwrap();
+            let mut tokens = line.split_whitespace();
+            match tokens.next() {
+                Some("v") => {
+                    let [x, y, z] = parse_floats(&mut tokens, 3).map_err(error)?;
+                    positions.push(na::Vector3::new(x, y, z));
+                },
+                Some("vn") => {
+                    let [x, y, z] = parse_floats(&mut tokens, 3).map_err(error)?;
+                    normals.push(na::Vector3::new(x, y, z));
+                },
+                Some("vt") => {
+                    let [u, v] = parse_floats(&mut tokens, 1).map_err(error)?;
+                    texcoords.push(na::Vector2::new(u, 1.0 - v));
+                },
+                Some("f") => {
+                    let corners = tokens.map(|token| parse_corner(
+                        token,
+                        [positions.len(), texcoords.len(), normals.len()]
+                    )).collect::<Result<Vec<_>, _>>().map_err(error)?;
+                    if corners.len() < 3 {
+                        return Err(error(String::from("Face with fewer than 3 vertices")));
+                    }
+                    let object = objects.last_mut().unwrap();
+                    let triangles = match object.iter_mut().find(|(m, _)| *m == material) {
+                        Some((_, triangles)) => triangles,
+                        None => {
+                            object.push((material, vec![]));
+                            &mut object.last_mut().unwrap().1
+                        }
+                    };
+                    triangles.extend((1..corners.len() - 1).map(
+                        |j| [corners[0], corners[j], corners[j + 1]]
+                    ));
+                },
+                Some("o" | "g") if !objects.last().unwrap().is_empty() => objects.push(vec![]),
+                Some("usemtl") => {
+                    let name = tokens.collect::<Vec<_>>().join(" ");
+                    material = library.material_names.get(&name).copied().unwrap_or(0);
+                },
+                Some("mtllib") => {
+                    for name in tokens {
+                        library.load_mtl(&directory.join(name))?;
+                    }
+                },
+                _ => ()
+            }
+        }
+        //Smooth normals for faces without them
+        let mut generated_normals = vec![na::Vector3::<f32>::zeros(); positions.len()];
+        for triangle in objects.iter().flatten().flat_map(|(_, triangles)| triangles) {
+            if triangle.iter().all(|corner| corner.2.is_some()) {
+                continue;
+            }
+            let [a, b, c] = triangle.map(|corner| positions[corner.0]);
+            let normal = (b - a).cross(&(c - a)); //Area weighted
+            for corner in triangle {
+                generated_normals[corner.0] += normal;
+            }
+        }
+        for normal in &mut generated_normals {
+            *normal = normal.try_normalize(1e-12).unwrap_or_else(na::Vector3::zeros);
+        }
+        //Meshes, splitting primitives to fit 16-bit indices
+        let meshes: Vec<Mesh> = objects.iter().filter(|object| !object.is_empty()).map(|object| {
+            let split_primitives = object.iter().map(|(material, triangles)| {
+                let mut primitives = Vec::<Primitive>::new();
+                let mut vertices = Vec::<Vertex>::new();
+                let mut indices = Vec::<u16>::new();
+                let mut remap = HashMap::<ObjCorner, u16>::new();
+                for triangle in triangles {
+                    if vertices.len() + 3 > u16::MAX as usize + 1 {
+                        primitives.push(Primitive {
+                            vertices: std::mem::take(&mut vertices),
+                            indices: std::mem::take(&mut indices),
+                            material: *material,
+                            morph_targets: vec![]
+                        });
+                        remap.clear();
+                    }
+                    for &corner in triangle {
+                        let index = *remap.entry(corner).or_insert_with(|| {
+                            let (position, texcoord, normal) = corner;
+                            vertices.push(Vertex {
+                                pos: positions[position],
+                                normal: normal.map_or(generated_normals[position], |n| normals[n]),
+                                tex: texcoord.map_or(na::Vector2::zeros(), |t| texcoords[t])
+                            });
+                            (vertices.len() - 1) as u16
+                        });
+                        indices.push(index);
+                    }
+                }
+                primitives.push(Primitive {vertices, indices, material: *material, morph_targets: vec![]});
+                primitives
+            }).collect();
+            Mesh::from_split(split_primitives)
+        }).collect();
+        //A root node for each mesh
+        let nodes = (0..meshes.len()).map(|mesh| Node {
+            mesh: Some(mesh as u32),
+            children: vec![],
+            translation: na_geo::Translation3::identity(),
+            rotation: na_geo::Rotation3::identity(),
+            scale: na_geo::Scale3::identity(),
+            weights: vec![]
+        }).collect();
+        Ok(Self {
+            nodes,
+            meshes,
+            materials: library.materials,
+            textures: library.textures,
+            vertex_format: VertexFormat::Full,
+            texture_packing: TexturePacking::Separate,
+            animations: vec![],
+            root_transform: na_geo::Affine3::identity(),
+            warnings: vec![]
+        })
+    }
+
+    /**
+        Write the scene as a binary glTF (GLB) file, e.g. to inspect the result of import processing
+        (welding, generated normals, `optimize`) in another viewer. Exported are:
+        * Nodes, with `root_transform` as an extra root node unless it's the identity.
+        * Primitives' positions, normals, texture coordinates, 16-bit indices & morph targets.
+        * Materials' metallic-roughness factors & textures, emissive (with KHR_materials_emissive_strength),
+          transmission (KHR_materials_transmission), IOR (KHR_materials_ior) & alpha mode.
+        * Textures as embedded PNG images, except the default texture.
+
+        Height maps, clearcoat, specular & animations aren't exported.
+    */
+    pub fn export_glb<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        let mut writer = GlbWriter::default();
+        //Meshes
+        let meshes: Vec<serde_json::Value> = self.meshes.iter().map(|mesh| {
+            let primitives: Vec<serde_json::Value> = mesh.primitives.iter().map(|primitive| {
+                let vertices = &primitive.vertices;
+                let mut value = serde_json::json!({
+                    "attributes": {
+                        "POSITION": writer.float_accessor(vertices.iter().map(|vertex| vertex.pos.as_slice()), 3, true),
+                        "NORMAL": writer.float_accessor(vertices.iter().map(|vertex| vertex.normal.as_slice()), 3, false),
+                        "TEXCOORD_0": writer.float_accessor(vertices.iter().map(|vertex| vertex.tex.as_slice()), 2, false)
+                    },
+                    "material": primitive.material
+                });
+                if !primitive.indices.is_empty() {
+                    value["indices"] = writer.index_accessor(&primitive.indices).into();
+                }
+                if !primitive.morph_targets.is_empty() {
+                    value["targets"] = primitive.morph_targets.iter().map(|target| {
+                        let mut attributes = serde_json::json!({
+                            "POSITION": writer.float_accessor(target.positions.iter().map(|pos| pos.as_slice()), 3, true)
+                        });
+                        if !target.normals.is_empty() {
+                            attributes["NORMAL"] = writer.float_accessor(
+                                target.normals.iter().map(|normal| normal.as_slice()),
+                                3,
+                                false
+                            ).into();
+                        }
+                        attributes
+                    }).collect();
+                }
+                value
+            }).collect();
+            serde_json::json!({"primitives": primitives})
+        }).collect();
         //Textures
-        let default_texture = image::RgbaImage::from_pixel(
-            1, 1, image::Rgba([255, 255, 255, 255])
+        let mut images = Vec::<serde_json::Value>::new();
+        for texture in self.textures.iter().skip(1) {
+            let mut png = Vec::<u8>::new();
+            texture.write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png)
+                .map_err(std::io::Error::other)?;
+            images.push(serde_json::json!({"bufferView": writer.view(&png, None), "mimeType": "image/png"}));
+        }
+        let textures: Vec<serde_json::Value> = (0..images.len()).map(|i| serde_json::json!({"source": i})).collect();
+        //Materials (texture 0 is the default texture, so isn't referenced)
+        let mut extensions_used = Vec::<String>::new();
+        let materials: Vec<serde_json::Value> = self.materials.iter().map(|material| {
+            let texture = |index: u32| serde_json::json!({"index": index - 1});
+            let mut pbr = serde_json::json!({
+                "baseColorFactor": material.color.as_slice(),
+                "metallicFactor": material.metal_factor,
+                "roughnessFactor": material.rough_factor
+            });
+            if material.color_texture != 0 {
+                pbr["baseColorTexture"] = texture(material.color_texture);
+            }
+            if material.metal_rough_texture != 0 {
+                pbr["metallicRoughnessTexture"] = texture(material.metal_rough_texture);
+            }
+            let mut value = serde_json::json!({
+                "pbrMetallicRoughness": pbr,
+                "emissiveFactor": &material.emissive.as_slice()[..3]
+            });
+            if material.emissive_texture != 0 {
+                value["emissiveTexture"] = texture(material.emissive_texture);
+            }
+            if material.alpha_mode == AlphaMode::Mask {
+                value["alphaMode"] = "MASK".into();
+                value["alphaCutoff"] = material.alpha_cutoff.into();
+            }
+            let mut extensions = serde_json::Map::new();
+            if material.emissive_strength != 1.0 {
+                extensions.insert(
+                    "KHR_materials_emissive_strength".into(),
+                    serde_json::json!({"emissiveStrength": material.emissive_strength})
+                );
+            }
+            if material.transmission_factor > 0.0 {
+                let mut transmission = serde_json::json!({"transmissionFactor": material.transmission_factor});
+                if material.transmission_texture != 0 {
+                    transmission["transmissionTexture"] = texture(material.transmission_texture);
+                }
+                extensions.insert("KHR_materials_transmission".into(), transmission);
+            }
+            if material.ior != 1.5 {
+                extensions.insert("KHR_materials_ior".into(), serde_json::json!({"ior": material.ior}));
+            }
+            for name in extensions.keys() {
+                if !extensions_used.contains(name) {
+                    extensions_used.push(name.clone());
+                }
+            }
+            if !extensions.is_empty() {
+                value["extensions"] = extensions.into();
+            }
+            value
+        }).collect();
+        //Nodes
+        let mut nodes: Vec<serde_json::Value> = self.nodes.iter().map(|node| {
+            let rotation = na::UnitQuaternion::from_rotation_matrix(&node.rotation);
+            let mut value = serde_json::json!({
+                "translation": node.translation.vector.as_slice(),
+                "rotation": [rotation.i, rotation.j, rotation.k, rotation.w],
+                "scale": node.scale.vector.as_slice()
+            });
+            if let Some(mesh) = node.mesh {
+                value["mesh"] = mesh.into();
+            }
+            if !node.children.is_empty() {
+                value["children"] = node.children.clone().into();
+            }
+            if !node.weights.is_empty() {
+                value["weights"] = node.weights.clone().into();
+            }
+            value
+        }).collect();
+        let mut roots: Vec<usize> = (0..self.nodes.len()).filter(
+            |&i| !self.nodes.iter().any(|node| node.children.contains(&(i as u32)))
+        ).collect();
+        if self.root_transform.matrix() != &na::Matrix4::identity() {
+            nodes.push(serde_json::json!({
+                "matrix": self.root_transform.matrix().as_slice(),
+                "children": roots
+            }));
+            roots = vec![nodes.len() - 1];
+        }
+        //Document
+        let mut document = serde_json::json!({
+            "asset": {"version": "2.0", "generator": "graphics"},
+            "scene": 0,
+            "scenes": [{"nodes": roots}],
+            "nodes": nodes,
+            "meshes": meshes,
+            "materials": materials,
+            "textures": textures,
+            "images": images,
+            "accessors": writer.accessors,
+            "bufferViews": writer.buffer_views
+        });
+        if !extensions_used.is_empty() {
+            document["extensionsUsed"] = extensions_used.into();
+        }
+        if !writer.binary.is_empty() {
+            document["buffers"] = serde_json::json!([{"byteLength": writer.binary.len()}]);
+        }
+        //Top-level arrays must not be empty
+        document.as_object_mut().unwrap().retain(|_, value| value.as_array().is_none_or(|array| !array.is_empty()));
+        //Header, JSON chunk (padded with spaces) & binary chunk (padded with zeros)
+        let mut json = serde_json::to_vec(&document)?;
+        json.resize((json.len() + 3) & !3, b' ');
+        let mut binary = writer.binary;
+        binary.resize((binary.len() + 3) & !3, 0);
+        let mut glb = Vec::<u8>::with_capacity(12 + 8 + json.len() + 8 + binary.len());
+        let length = 12 + 8 + json.len() + if binary.is_empty() {0} else {8 + binary.len()};
+        for word in [0x46546C67, 2, length as u32, json.len() as u32, 0x4E4F534A] {
+            glb.extend_from_slice(&u32::to_le_bytes(word));
+        }
+        glb.extend_from_slice(&json);
+        if !binary.is_empty() {
+            for word in [binary.len() as u32, 0x004E4942] {
+                glb.extend_from_slice(&u32::to_le_bytes(word));
+            }
+            glb.extend_from_slice(&binary);
+        }
+        std::fs::write(path, glb)
+    }
+}
+
+///Face vertex of an OBJ file: position, texture coordinate & normal indices.
+type ObjCorner = (usize, Option<usize>, Option<usize>);
+
+///Materials & textures loaded from MTL libraries, by name & path.
+struct ObjLibrary {
+    materials: Vec<Material>,
+    textures: Vec<image::RgbaImage>,
+    material_names: HashMap<String, u32>,
+    texture_paths: HashMap<std::path::PathBuf, u32>
+}
+
+impl ObjLibrary {
+    fn load_mtl(&mut self, path: &std::path::Path) -> Result<(), ObjError> {
+        let source = std::fs::read_to_string(path).map_err(|e| ObjError::Io(path.to_path_buf(), e))?;
+        let directory = path.parent().unwrap_or(std::path::Path::new(""));
+        let mut current: Option<usize> = None;
+        for (i, line) in source.lines().enumerate() {
+            let error = |message: String| ObjError::Parse {path: path.to_path_buf(), line: i + 1, message};
+            let line = line.split('#').next().unwrap();
+            let mut tokens = line.split_whitespace();
+            let statement = tokens.next();
+            if statement == Some("newmtl") {
+                let name = tokens.collect::<Vec<_>>().join(" ");
+                self.materials.push(default_material());
+                self.material_names.insert(name, (self.materials.len() - 1) as u32);
+                current = Some(self.materials.len() - 1);
+                continue;
+            }
+            let (Some(statement), Some(material)) = (statement, current) else {continue};
+            match statement {
+                "Kd" => {
+                    let [r, g, b] = parse_floats(&mut tokens, 3).map_err(error)?;
+                    let alpha = self.materials[material].color.w;
+                    self.materials[material].color = na::Vector4::new(r, g, b, alpha);
+                },
+                "d" => {
+                    let [d] = parse_floats(&mut tokens, 1).map_err(error)?;
+                    self.materials[material].color.w = d;
+                },
+                "Tr" => {
+                    let [transparency] = parse_floats(&mut tokens, 1).map_err(error)?;
+                    self.materials[material].color.w = 1.0 - transparency;
+                },
+                "map_Kd" => {
+                    //Options precede the file name
+                    let Some(name) = tokens.last() else {
+                        return Err(error(String::from("Missing texture file name")));
+                    };
+                    let texture_path = directory.join(name);
+                    let texture = match self.texture_paths.get(&texture_path) {
+                        Some(&texture) => texture,
+                        None => {
+                            let image = image::open(&texture_path).map_err(
+                                |e| ObjError::Image(texture_path.clone(), e)
+                            )?;
+                            self.textures.push(image.into_rgba8());
+                            let texture = (self.textures.len() - 1) as u32;
+                            self.texture_paths.insert(texture_path, texture);
+                            texture
+                        }
+                    };
+                    self.materials[material].color_texture = texture;
+                },
+                _ => ()
+            }
+        }
+        Ok(())
+    }
+}
+
+///Parse at least `required` & at most `N` floats (missing ones are 0).
+fn parse_floats<'a, const N: usize>(
+    tokens: &mut impl Iterator<Item = &'a str>,
+    required: usize
+) -> Result<[f32; N], String> {
+    let mut values = [0.0; N];
+    let mut count = 0;
+    for (value, token) in values.iter_mut().zip(tokens) {
+        *value = token.parse().map_err(|_| format!("Invalid number \"{}\"", token))?;
+        count += 1;
+    }
+    if count < required {
+        return Err(format!("Expected {} numbers, found {}", required, count));
+    }
+    Ok(values)
+}
+
+/**
+    Parse a face vertex (`v`, `v/vt`, `v//vn` or `v/vt/vn`),
+    resolving 1-based & negative (relative) indices against the element counts so far.
+*/
+fn parse_corner(token: &str, counts: [usize; 3]) -> Result<ObjCorner, String> {
+    let mut indices = [None; 3];
+    for (j, part) in token.split('/').enumerate() {
+        if j >= 3 {
+            return Err(format!("Invalid face vertex \"{}\"", token));
+        }
+        if part.is_empty() {
+            continue;
+        }
+        let index: isize = part.parse().map_err(|_| format!("Invalid index \"{}\"", part))?;
+        let resolved = match index {
+            1.. => index - 1,
+            ..=-1 => counts[j] as isize + index,
+            0 => return Err(String::from("Invalid index 0"))
+        };
+        if resolved < 0 || resolved as usize >= counts[j] {
+            return Err(format!("Index {} out of range", index));
+        }
+        indices[j] = Some(resolved as usize);
+    }
+    match indices {
+        [Some(position), texcoord, normal] => Ok((position, texcoord, normal)),
+        _ => Err(format!("Face vertex \"{}\" without a position", token))
+    }
+}
+
+///Material of primitives without one (index 0 of every scene).
+fn default_material() -> Material {
+    Material {
+        color: DEFAULT_COLOR.into(),
+        color_texture: 0,
+        metal_rough_texture: 0,
+        metal_factor: 0.0,
+        rough_factor: 1.0,
+        height_texture: 0,
+        height_scale: 0.0,
+        emissive_texture: 0,
+        emissive_strength: 1.0,
+        emissive: na::Vector4::zeros(),
+        transmission_texture: 0,
+        transmission_factor: 0.0,
+        ior: 1.5,
+        clearcoat_factor: 0.0,
+        clearcoat_texture: 0,
+        clearcoat_roughness: 0.0,
+        clearcoat_roughness_texture: 0,
+        workflow: MaterialWorkflow::MetallicRoughness,
+        specular_color: na::Vector4::new(1.0, 1.0, 1.0, 0.0),
+        specular_color_texture: 0,
+        specular_factor: 1.0,
+        specular_texture: 0,
+        alpha_mode: AlphaMode::Opaque,
+        alpha_cutoff: 0.5
+    }
+}
+
+///White texture sampled by materials without a texture (index 0 of every scene).
+fn default_texture() -> image::RgbaImage {
+    image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 255]))
+}
+
+///Clip a clip-space triangle against the near plane (z >= 0), giving a convex polygon.
+fn clip_near(triangle: [na::Vector4<f32>; 3]) -> Vec<na::Vector4<f32>> {
+    let mut polygon = Vec::with_capacity(4);
+    for i in 0..3 {
+        let (p, q) = (triangle[i], triangle[(i + 1) % 3]);
+        if p.z >= 0.0 {
+            polygon.push(p);
+        }
+        if (p.z >= 0.0) != (q.z >= 0.0) {
+            polygon.push(p + (q - p) * (p.z / (p.z - q.z)));
+        }
+    }
+    polygon
+}
+
+///Depth test & fill a screen-space triangle ([x, y] in pixels, z in [0, 1]).
+fn rasterize_triangle(
+    triangle: [na::Vector3<f32>; 3],
+    depth: &mut [f32],
+    image: &mut image::RgbaImage,
+    color: image::Rgba<u8>
+) {
+    let [a, b, c] = triangle;
+    let edge = |p: na::Vector3<f32>, q: na::Vector3<f32>, x: f32, y: f32|
+        (q.x - p.x) * (y - p.y) - (q.y - p.y) * (x - p.x);
+    let area = edge(a, b, c.x, c.y);
+    if area == 0.0 {
+        return;
+    }
+    let (width, height) = image.dimensions();
+    let x_min = a.x.min(b.x).min(c.x).floor().max(0.0) as u32;
+    let y_min = a.y.min(b.y).min(c.y).floor().max(0.0) as u32;
+    let x_max = (a.x.max(b.x).max(c.x).ceil().max(0.0) as u32).min(width);
+    let y_max = (a.y.max(b.y).max(c.y).ceil().max(0.0) as u32).min(height);
+    for y in y_min..y_max {
+        for x in x_min..x_max {
+            //Barycentric coordinates of the pixel center (either winding)
+            let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+            let w = [edge(b, c, px, py) / area, edge(c, a, px, py) / area, edge(a, b, px, py) / area];
+            if w.iter().any(|&w| w < 0.0) {
+                continue;
+            }
+            //Screen-space depth is affine, so interpolates linearly
+            let z = w[0] * a.z + w[1] * b.z + w[2] * c.z;
+            let i = (y * width + x) as usize;
+            if z < depth[i] && z <= 1.0 {
+                depth[i] = z;
+                image.put_pixel(x, y, color);
+            }
+        }
+    }
+}
+
+///Decode an 8-bit sRGB color channel to linear.
+fn decode_srgb(encoded: u8) -> f32 {
+    let encoded = encoded as f32 / 255.0;
+    if encoded <= 0.04045 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+const DIELECTRIC_SPECULAR: f32 = 0.04;
+
+///Perceived brightness of a linear color.
+fn perceived_brightness(color: &na::Vector3<f32>) -> f32 {
+    (0.299 * color.x * color.x + 0.587 * color.y * color.y + 0.114 * color.z * color.z).sqrt()
+}
+
+///Metalness reproducing a diffuse & specular color (from the glTF specular-glossiness conversion).
+fn solve_metallic(diffuse: &na::Vector3<f32>, specular: &na::Vector3<f32>) -> f32 {
+    let specular_brightness = perceived_brightness(specular);
+    if specular_brightness < DIELECTRIC_SPECULAR {
+        return 0.0;
+    }
+    let one_minus_specular_strength = 1.0 - specular.max();
+    let a = DIELECTRIC_SPECULAR;
+    let b = perceived_brightness(diffuse) * one_minus_specular_strength / (1.0 - DIELECTRIC_SPECULAR)
+        + specular_brightness - 2.0 * DIELECTRIC_SPECULAR;
+    let c = DIELECTRIC_SPECULAR - specular_brightness;
+    let discriminant = (b * b - 4.0 * a * c).max(0.0);
+    ((-b + discriminant.sqrt()) / (2.0 * a)).clamp(0.0, 1.0)
+}
+
+///Base color, metalness & roughness equivalent to specular-glossiness factors.
+fn specular_glossiness_factors(
+    diffuse: &na::Vector4<f32>,
+    specular: &na::Vector3<f32>,
+    glossiness: f32
+) -> (na::Vector4<f32>, f32, f32) {
+    let metallic = solve_metallic(&diffuse.xyz(), specular);
+    let one_minus_specular_strength = 1.0 - specular.max();
+    let from_diffuse = diffuse.xyz() * (
+        one_minus_specular_strength / (1.0 - DIELECTRIC_SPECULAR) / (1.0 - metallic).max(1e-4)
+    );
+    let from_specular = (specular - na::Vector3::repeat(DIELECTRIC_SPECULAR * (1.0 - metallic)))
+        / metallic.max(1e-4);
+    let color = from_diffuse.lerp(&from_specular, metallic * metallic).map(|x| x.clamp(0.0, 1.0));
+    (color.push(diffuse.w), metallic, 1.0 - glossiness)
+}
+
+/**
+    Metallic-roughness texture (roughness in green, metalness in blue) equivalent to
+    a specular-glossiness texture (sRGB specular, linear glossiness in alpha) scaled by its factors.
+*/
+fn specular_glossiness_texture(
+    image: &image::RgbaImage,
+    diffuse: &na::Vector3<f32>,
+    specular_factor: &na::Vector3<f32>,
+    glossiness_factor: f32
+) -> image::RgbaImage {
+    image::RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+        let [r, g, b, a] = image.get_pixel(x, y).0;
+        let specular = na::Vector3::new(decode_srgb(r), decode_srgb(g), decode_srgb(b)).component_mul(specular_factor);
+        let roughness = 1.0 - a as f32 / 255.0 * glossiness_factor;
+        let metallic = solve_metallic(diffuse, &specular);
+        image::Rgba([255, (roughness * 255.0).round() as u8, (metallic * 255.0).round() as u8, 255])
+    })
+}
+
+///Encode a linear color channel as 8-bit sRGB.
+fn encode_srgb(linear: f32) -> u8 {
+    let linear = linear.clamp(0.0, 1.0);
+    let encoded = if linear <= 0.0031308 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
+
+/**
+    Read a height map from material extras, as there is no standard glTF extension for one:
+    `"extras": {"heightTexture": {"index": 0, "scale": 0.05}}`
+    Returns the texture index (offset for the default texture) & scale.
+*/
+fn height_map(extras: &gltf::json::Extras) -> (u32, f32) {
+    let Some(extras) = extras else {return (0, 0.0)};
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(extras.get()) else {return (0, 0.0)};
+    let info = &value["heightTexture"];
+    match info["index"].as_u64() {
+        Some(index) => (index as u32 + 1, info["scale"].as_f64().unwrap_or(0.05) as f32),
+        None => (0, 0.0)
+    }
+}
+
+/**
+    Read a VEC3 float accessor (e.g. morph target displacements).
+    Elements are zero without a buffer view (sparse values are not applied).
+*/
+fn read_vec3(accessor: &gltf::Accessor, buffers: &[gltf::buffer::Data]) -> Vec<na::Vector3<f32>> {
+    read_vec3_f64(accessor, buffers).iter().map(|element| element.cast()).collect()
+}
+
+///Read a VEC3 float accessor into f64, so offsets can be applied before rounding to f32.
+fn read_vec3_f64(accessor: &gltf::Accessor, buffers: &[gltf::buffer::Data]) -> Vec<na::Vector3<f64>> {
+    let Some(view) = accessor.view() else {return vec![na::Vector3::zeros(); accessor.count()]};
+    let data = &buffers[view.buffer().index()];
+    let offset = view.offset() + accessor.offset();
+    let stride = view.stride().unwrap_or(accessor.size());
+    (0..accessor.count()).map(|i| {
+        let offset = offset + i * stride;
+        na::Vector3::from_fn(|j, _| f32::from_le_bytes(
+            data[offset + 4 * j..offset + 4 * j + 4].try_into().unwrap()
+        ) as f64)
+    }).collect()
+}
+
+///Read the JSON of a glTF or GLB file (`Null` if it can't be read).
+///glTF document, buffers, decoded images & import warnings (see `import_gltf`).
+type GltfImport = (gltf::Document, Vec<gltf::buffer::Data>, Vec<Option<image::RgbaImage>>, Vec<String>);
+
+/**
+    Import a glTF file & its resources, with images decoded to RGBA (`None` where one failed, with a warning).
+    This tries `gltf::import` first, then, if it fails, resolves resources here so that images
+    which are missing or fail to decode don't fail the import. Buffers are still required.
+*/
+fn import_gltf(path: &std::path::Path) -> gltf::Result<GltfImport> {
+    if let Ok((document, buffers, images)) = gltf::import(path) {
+        let images = images.into_iter().map(|image| Some(rgba_image(image))).collect();
+        return Ok((document, buffers, images, vec![]));
+    }
+    let base = path.parent().unwrap_or_else(|| std::path::Path::new("./"));
+    let gltf::Gltf {document, mut blob} = gltf::Gltf::open(path)?;
+    //Buffers, padded to 4 bytes like `gltf::import`
+    let buffers = document.buffers().map(|buffer| {
+        let mut data = match buffer.source() {
+            gltf::buffer::Source::Bin => blob.take().ok_or(gltf::Error::MissingBlob)?,
+            gltf::buffer::Source::Uri(uri) => read_uri(base, uri).map_err(gltf::Error::Io)?
+        };
+        if data.len() < buffer.length() {
+            return Err(gltf::Error::BufferLength {
+                buffer: buffer.index(),
+                expected: buffer.length(),
+                actual: data.len()
+            });
+        }
+        data.resize(data.len().next_multiple_of(4), 0);
+        Ok(gltf::buffer::Data(data))
+    }).collect::<gltf::Result<Vec<_>>>()?;
+    //Images, with the format guessed from their contents
+    let mut warnings = vec![];
+    let images = document.images().map(|image| {
+        let (name, encoded) = match image.source() {
+            gltf::image::Source::View {view, ..} => (
+                format!("image {}", image.index()),
+                Ok(buffers[view.buffer().index()][view.offset()..view.offset() + view.length()].to_vec())
+            ),
+            gltf::image::Source::Uri {uri, ..} => (
+                format!("image {} ({})", image.index(), uri.get(..64).unwrap_or(uri)),
+                read_uri(base, uri)
+            )
+        };
+        let decoded = encoded.map_err(|e| e.to_string()).and_then(
+            |data| image::load_from_memory(&data).map_err(|e| e.to_string())
         );
-        let mut textures = vec![default_texture];
-        textures.append(&mut document.textures().map(|texture| {
-            /*
-                GLTF imports texture images using the `image` library;
-                the reported image format maps directly to the original `image` format types.
-            */
-            let image = &images[texture.source().index()];
-            match image.format {
-                gltf::image::Format::R8G8B8 => image::DynamicImage::ImageRgb8(
-                    image::RgbImage::from_raw(
-                        image.width,
-                        image.height,
-                        image.pixels.clone()
-                    ).unwrap()
-                ).into_rgba8(),
-                gltf::image::Format::R8G8B8A8 => image::RgbaImage::from_raw(
-                    image.width,
-                    image.height,
-                    image.pixels.clone()
-                ).unwrap(),
-                _ => panic!("Unsupported image format")
+        match decoded {
+            Ok(decoded) => Some(decoded.into_rgba8()),
+            Err(e) => {
+                warnings.push(format!("Couldn't load {}, using a white texture: {}", name, e));
+                None
             }
-        }).collect());
-        Ok(Self {nodes, meshes, materials, textures})
+        }
+    }).collect();
+    Ok((document, buffers, images, warnings))
+}
+
+///Convert an image decoded by `gltf::import`, whose formats map directly to the `image` library's.
+fn rgba_image(image: gltf::image::Data) -> image::RgbaImage {
+    match image.format {
+        gltf::image::Format::R8G8B8 => image::DynamicImage::ImageRgb8(
+            image::RgbImage::from_raw(image.width, image.height, image.pixels).unwrap()
+        ).into_rgba8(),
+        gltf::image::Format::R8G8B8A8 => image::RgbaImage::from_raw(image.width, image.height, image.pixels).unwrap(),
+        _ => panic!("Unsupported image format")
+    }
+}
+
+///Fail on primitives which aren't triangles (points & lines), as only triangle lists are drawn.
+fn check_modes(document: &gltf::Document) -> std::io::Result<()> {
+    for mesh in document.meshes() {
+        for primitive in mesh.primitives() {
+            use gltf::mesh::Mode;
+            if !matches!(primitive.mode(), Mode::Triangles | Mode::TriangleStrip | Mode::TriangleFan) {
+                return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, format!(
+                    "Mesh {} primitive {} has mode {:?}, only triangles are supported",
+                    mesh.index(),
+                    primitive.index(),
+                    primitive.mode()
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/**
+    Convert a glTF primitive's indices to a triangle list, keeping the winding of strip & fan triangles.
+    Other modes are rejected by `check_modes`.
+*/
+fn triangle_list(mode: gltf::mesh::Mode, indices: Vec<u32>) -> Vec<u32> {
+    use gltf::mesh::Mode;
+    let triangle_count = indices.len().saturating_sub(2);
+    match mode {
+        //Odd triangles swap their first 2 vertices
+        Mode::TriangleStrip => (0..triangle_count).flat_map(|i| if i % 2 == 0 {
+            [indices[i], indices[i + 1], indices[i + 2]]
+        } else {
+            [indices[i + 1], indices[i], indices[i + 2]]
+        }).collect(),
+        Mode::TriangleFan => (0..triangle_count).flat_map(
+            |i| [indices[0], indices[i + 1], indices[i + 2]]
+        ).collect(),
+        _ => indices
+    }
+}
+
+/**
+    A primitive drawing a triangle list of `vertices` (& their morph targets),
+    or consecutive primitives of at most `MAX_PRIMITIVE_VERTICES` vertices each
+    when the vertices don't fit 16-bit indices (see `Mesh::source_primitives`).
+*/
+fn split_primitive(
+    vertices: Vec<Vertex>,
+    morph_targets: Vec<MorphTarget>,
+    indices: &[u32],
+    material: u32
+) -> Vec<Primitive> {
+    if vertices.len() <= MAX_PRIMITIVE_VERTICES {
+        let indices = indices.iter().map(|&i| i as u16).collect();
+        return vec![Primitive {vertices, indices, material, morph_targets}];
+    }
+    let empty = || Primitive {
+        vertices: vec![],
+        indices: vec![],
+        material,
+        morph_targets: vec![MorphTarget::default(); morph_targets.len()]
+    };
+    let mut primitives = vec![empty()];
+    let mut remap = HashMap::<u32, u16>::new();
+    for triangle in indices.chunks_exact(3) {
+        if remap.len() + 3 > MAX_PRIMITIVE_VERTICES {
+            primitives.push(empty());
+            remap.clear();
+        }
+        let primitive = primitives.last_mut().unwrap();
+        for &i in triangle {
+            let index = *remap.entry(i).or_insert_with(|| {
+                primitive.vertices.push(vertices[i as usize]);
+                for (split, target) in primitive.morph_targets.iter_mut().zip(&morph_targets) {
+                    split.positions.push(target.positions[i as usize]);
+                    split.normals.push(target.normals[i as usize]);
+                }
+                (primitive.vertices.len() - 1) as u16
+            });
+            primitive.indices.push(index);
+        }
+    }
+    primitives
+}
+
+///Read a glTF URI: a base64 data URI, a `file:` URI or a percent-encoded path relative to `base`.
+fn read_uri(base: &std::path::Path, uri: &str) -> std::io::Result<Vec<u8>> {
+    let invalid = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string());
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, encoded) = data.split_once(";base64,").ok_or_else(|| invalid("Unsupported data URI"))?;
+        return decode_base64(encoded).ok_or_else(|| invalid("Invalid base64 data"));
+    }
+    let path = match uri.strip_prefix("file://").or_else(|| uri.strip_prefix("file:")) {
+        Some(path) => std::path::PathBuf::from(path),
+        None if uri.contains(':') => return Err(invalid("Unsupported URI scheme")),
+        None => {
+            //Percent decoding
+            let mut bytes = Vec::with_capacity(uri.len());
+            let mut rest = uri.as_bytes();
+            while let Some((&byte, tail)) = rest.split_first() {
+                let escaped = tail.get(..2).filter(|_| byte == b'%').and_then(
+                    |hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()
+                );
+                match escaped {
+                    Some(escaped) => {
+                        bytes.push(escaped);
+                        rest = &tail[2..];
+                    },
+                    None => {
+                        bytes.push(byte);
+                        rest = tail;
+                    }
+                }
+            }
+            base.join(String::from_utf8_lossy(&bytes).as_ref())
+        }
+    };
+    std::fs::read(path)
+}
+
+///Decode standard or URL-safe base64, ignoring padding.
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut count) = (0u32, 0);
+    for c in text.bytes().filter(|&c| c != b'=') {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return None
+        };
+        bits = (bits << 6 | value as u32) & 0xFFFF;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+fn raw_json(path: &std::path::Path) -> serde_json::Value {
+    let Ok(data) = std::fs::read(path) else {return serde_json::Value::Null};
+    let json = if data.starts_with(b"glTF") {
+        match gltf::Glb::from_slice(&data) {
+            Ok(glb) => serde_json::from_slice(&glb.json),
+            Err(_) => return serde_json::Value::Null
+        }
+    } else {
+        serde_json::from_slice(&data)
+    };
+    json.unwrap_or(serde_json::Value::Null)
+}
+
+///Binary chunk, buffer views & accessors of a GLB file being written (see `Scene::export_glb`).
+#[derive(Default)]
+struct GlbWriter {
+    binary: Vec<u8>,
+    buffer_views: Vec<serde_json::Value>,
+    accessors: Vec<serde_json::Value>
+}
+
+impl GlbWriter {
+    const ARRAY_BUFFER: u32 = 34962;
+    const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+    ///Append data to the binary chunk (4-byte aligned) as a buffer view. Returns the view's index.
+    fn view(&mut self, data: &[u8], target: Option<u32>) -> usize {
+        self.binary.resize((self.binary.len() + 3) & !3, 0);
+        let mut view = serde_json::json!({"buffer": 0, "byteOffset": self.binary.len(), "byteLength": data.len()});
+        if let Some(target) = target {
+            view["target"] = target.into();
+        }
+        self.binary.extend_from_slice(data);
+        self.buffer_views.push(view);
+        self.buffer_views.len() - 1
+    }
+
+    ///Accessor of float vectors (VEC2 or VEC3), with bounds if required (positions). Returns its index.
+    fn float_accessor<'a>(
+        &mut self,
+        vectors: impl Iterator<Item = &'a [f32]>,
+        width: usize,
+        bounds: bool
+    ) -> usize {
+        let mut data = Vec::<u8>::new();
+        let (mut min, mut max) = (vec![f32::INFINITY; width], vec![f32::NEG_INFINITY; width]);
+        let mut count = 0;
+        for vector in vectors {
+            for (i, &x) in vector.iter().enumerate() {
+                min[i] = min[i].min(x);
+                max[i] = max[i].max(x);
+                data.extend_from_slice(&x.to_le_bytes());
+            }
+            count += 1;
+        }
+        let view = self.view(&data, Some(Self::ARRAY_BUFFER));
+        let mut accessor = serde_json::json!({
+            "bufferView": view,
+            "componentType": 5126, //FLOAT
+            "count": count,
+            "type": if width == 2 {"VEC2"} else {"VEC3"}
+        });
+        if bounds {
+            accessor["min"] = min.into();
+            accessor["max"] = max.into();
+        }
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    ///Accessor of 16-bit indices. Returns its index.
+    fn index_accessor(&mut self, indices: &[u16]) -> usize {
+        let data: Vec<u8> = indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+        let view = self.view(&data, Some(Self::ELEMENT_ARRAY_BUFFER));
+        self.accessors.push(serde_json::json!({
+            "bufferView": view,
+            "componentType": 5123, //UNSIGNED_SHORT
+            "count": indices.len(),
+            "type": "SCALAR"
+        }));
+        self.accessors.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///Write a glTF file & its buffer to a new temporary directory, returning the file's path.
+    fn write_gltf(name: &str, json: serde_json::Value, buffer: &[u8]) -> std::path::PathBuf {
+        let directory = std::env::temp_dir().join(format!("graphics-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("buffer.bin"), buffer).unwrap();
+        let path = directory.join("scene.gltf");
+        std::fs::write(&path, json.to_string()).unwrap();
+        path
+    }
+
+    #[test]
+    fn non_indexed_primitives() {
+        //A quad as a triangle list of 6 vertices, then as a fan of 4
+        let positions: [[f32; 3]; 10] = [
+            [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]
+        ];
+        let buffer: Vec<u8> = positions.iter().flatten().flat_map(|x| x.to_le_bytes()).collect();
+        let accessor = |view: usize, count: usize| serde_json::json!({
+            "bufferView": view,
+            "componentType": 5126,
+            "count": count,
+            "type": "VEC3",
+            "min": [0.0, 0.0, 0.0],
+            "max": [1.0, 1.0, 0.0]
+        });
+        let path = write_gltf("non-indexed", serde_json::json!({
+            "asset": {"version": "2.0"},
+            "scenes": [{"nodes": [0]}],
+            "nodes": [{"mesh": 0}],
+            "meshes": [{"primitives": [
+                {"attributes": {"POSITION": 0}, "mode": 4},
+                {"attributes": {"POSITION": 1}, "mode": 6}
+            ]}],
+            "buffers": [{"uri": "buffer.bin", "byteLength": buffer.len()}],
+            "bufferViews": [
+                {"buffer": 0, "byteOffset": 0, "byteLength": 72},
+                {"buffer": 0, "byteOffset": 72, "byteLength": 48}
+            ],
+            "accessors": [accessor(0, 6), accessor(1, 4)]
+        }), &buffer);
+        let scene = Scene::load_gltf(path).unwrap();
+        let mesh = &scene.meshes[0];
+        assert_eq!(mesh.source_primitives, [0, 1]);
+        assert_eq!(mesh.primitives[0].vertices.len(), 6);
+        assert_eq!(mesh.primitives[0].indices, [0, 1, 2, 3, 4, 5]);
+        assert_eq!(mesh.primitives[1].vertices.len(), 4);
+        assert_eq!(mesh.primitives[1].indices, [0, 1, 2, 0, 2, 3]);
+    }
+
+    fn vertex(x: f32, y: f32) -> Vertex {
+        Vertex {pos: na::Vector3::new(x, y, 0.0), normal: na::Vector3::z(), tex: na::Vector2::new(x, y)}
+    }
+
+    #[test]
+    fn weld_quad() {
+        let mut primitive = Primitive {
+            vertices: [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 0.0), (1.0, 1.0), (0.0, 1.0)].map(
+                |(x, y)| vertex(x, y)
+            ).to_vec(),
+            indices: vec![],
+            material: 0,
+            morph_targets: vec![]
+        };
+        primitive.weld(WELD_EPSILON);
+        assert_eq!(primitive.vertices.len(), 4);
+        assert_eq!(primitive.indices, [0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn weld_across_cells() {
+        //Within epsilon, on either side of a cell boundary (at 0)
+        let mut primitive = Primitive {
+            vertices: vec![vertex(-0.25 * WELD_EPSILON, 0.0), vertex(0.25 * WELD_EPSILON, 0.0), vertex(1.0, 0.0)],
+            indices: vec![0, 2, 1],
+            material: 0,
+            morph_targets: vec![]
+        };
+        primitive.weld(WELD_EPSILON);
+        assert_eq!(primitive.vertices.len(), 2);
+        assert_eq!(primitive.indices, [0, 1, 0]);
+    }
+
+    #[test]
+    fn z_up_import() {
+        //Box 2 units tall along +Z, on a node 3 units along +Z
+        let positions: Vec<[f32; 3]> = (0..8).map(|i| [
+            if i & 1 == 0 {-0.5} else {0.5},
+            if i & 2 == 0 {-0.5} else {0.5},
+            if i & 4 == 0 {0.0} else {2.0}
+        ]).collect();
+        let indices: [u16; 36] = [
+            0, 2, 1, 1, 2, 3, 4, 5, 6, 5, 7, 6, 0, 1, 4, 1, 5, 4,
+            2, 6, 3, 3, 6, 7, 0, 4, 2, 2, 4, 6, 1, 3, 5, 3, 7, 5
+        ];
+        let buffer: Vec<u8> = positions.iter().flatten().flat_map(|x| x.to_le_bytes())
+            .chain(indices.iter().flat_map(|i| i.to_le_bytes())).collect();
+        let path = write_gltf("z-up", serde_json::json!({
+            "asset": {"version": "2.0"},
+            "scenes": [{"nodes": [0]}],
+            "nodes": [{"mesh": 0, "translation": [0.0, 0.0, 3.0]}],
+            "meshes": [{"primitives": [{"attributes": {"POSITION": 0}, "indices": 1}]}],
+            "buffers": [{"uri": "buffer.bin", "byteLength": buffer.len()}],
+            "bufferViews": [
+                {"buffer": 0, "byteOffset": 0, "byteLength": 96},
+                {"buffer": 0, "byteOffset": 96, "byteLength": 72}
+            ],
+            "accessors": [
+                {
+                    "bufferView": 0,
+                    "componentType": 5126,
+                    "count": 8,
+                    "type": "VEC3",
+                    "min": [-0.5, -0.5, 0.0],
+                    "max": [0.5, 0.5, 2.0]
+                },
+                {"bufferView": 1, "componentType": 5123, "count": 36, "type": "SCALAR"}
+            ]
+        }), &buffer);
+        let scene = Scene::load_gltf_with(path, ImportOptions {up_axis: UpAxis::ZUp, ..ImportOptions::default()}).unwrap();
+        let transform = scene.transformations()[0];
+        let world: Vec<na::Point3<f32>> = scene.meshes[0].primitives[0].vertices.iter().map(
+            |vertex| transform.transform_point(&vertex.pos.into())
+        ).collect();
+        let lower = world.iter().fold(na::Point3::from([f32::MAX; 3]), |lower, p| lower.inf(p));
+        let upper = world.iter().fold(na::Point3::from([f32::MIN; 3]), |upper, p| upper.sup(p));
+        //The box stands along +Y, its node's +Z offset included
+        assert!((lower - na::Point3::new(-0.5, 3.0, -0.5)).norm() < 1e-5, "{:?}", lower);
+        assert!((upper - na::Point3::new(0.5, 5.0, 0.5)).norm() < 1e-5, "{:?}", upper);
+    }
+
+    #[test]
+    fn strip_winding() {
+        assert_eq!(triangle_list(gltf::mesh::Mode::TriangleStrip, vec![0, 1, 2, 3, 4]), [0, 1, 2, 2, 1, 3, 2, 3, 4]);
+    }
+
+    #[test]
+    fn split_large_primitives() {
+        //One triangle more than fits 16-bit indices
+        let triangle_count = MAX_PRIMITIVE_VERTICES / 3 + 1;
+        let vertices: Vec<Vertex> = (0..triangle_count * 3).map(|i| Vertex {
+            pos: na::Vector3::new(i as f32, 0.0, 0.0),
+            normal: na::Vector3::z(),
+            tex: na::Vector2::zeros()
+        }).collect();
+        let indices: Vec<u32> = (0..vertices.len() as u32).collect();
+        let primitives = split_primitive(vertices, vec![], &indices, 0);
+        assert_eq!(primitives.len(), 2);
+        assert_eq!(primitives[0].indices.len(), (triangle_count - 1) * 3);
+        assert_eq!(primitives[1].indices, [0, 1, 2]);
+        assert_eq!(primitives[1].vertices[0].pos.x, ((triangle_count - 1) * 3) as f32);
+        let mesh = Mesh::from_split(vec![primitives]);
+        assert_eq!(mesh.source_primitives, [0, 0]);
     }
 }
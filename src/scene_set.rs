@@ -1,59 +1,224 @@
 use ash::vk;
-use super::Renderer;
+use nalgebra as na;
+use super::{Renderer, ShaderId, DepthBias};
 use super::camera::Camera;
-use super::{FRAME_COUNT, MAX_TEXTURES, MAX_LIGHTS};
+use super::{FRAME_COUNT, DEFAULT_LIGHT_CAPACITY, MAX_VIEWS, MAX_USER_UNIFORM_SIZE};
 use super::base::Base;
+use super::memory::{MemoryCategory, AllocationError};
 use super::device_scene::DeviceScene;
 use super::environment::Environment;
-use super::scene::{Scene, PointLight};
-use std::rc::Rc;
+use super::pipeline::add_descriptor_counts;
+use super::scene::{Scene, PointLight, VertexFormat};
+use std::sync::Arc;
+use std::cell::RefCell;
 
-const UNIFORM_SIZE: usize = 2 * 64 + 16;
+const POOL_SCENE_COUNT: usize = 8; //Scenes per descriptor pool
+
+///Nearest intersection of a ray with scene geometry.
+pub struct Hit {
+    pub node: usize, //Index of the hit node in the source scene
+    pub position: na::Point3<f32>, //World-space position of the hit
+    pub distance: f32, //Distance along the ray
+    pub uv: na::Vector2<f32> //Barycentric coordinates within the hit triangle
+}
+
+///Screen-space rectangle in pixels (origin at the top-left corner).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ScreenRect {
+    pub min: na::Point2<f32>,
+    pub max: na::Point2<f32>
+}
+
+///Outlined scene node (see `SceneSet::set_highlight`).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Highlight {
+    pub scene: usize,
+    pub node: usize,
+    pub color: [f32; 4]
+}
+
+/**
+    Culling statistics of a scene in one frame (see `SceneSet::last_cull_stats`).
+    The cull shader accumulates them in a per-frame storage buffer, bound to mesh pipelines
+    (descriptor 15 of the custom shader interface) with the std430 layout
+    `{uint tested; uint visible; uint instances;}` (12 bytes), matching this struct.
+    Without culling, every node counts as tested & visible.
+*/
+#[repr(C)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct CullStats {
+    pub tested: u32, //Device nodes (per primitive) considered
+    pub visible: u32, //Device nodes passing culling
+    pub instances: u32 //Instances drawn by the visible nodes' commands
+}
+
+///Bytes before the lights in each frame's light buffer slice: the light count, padded to the lights' alignment.
+pub const LIGHTS_HEADER_SIZE: usize = 16;
+
+///Point light as laid out in the light buffer, with its shadow atlas tile.
+#[repr(C, align(16))]
+#[derive(Copy, Clone, Default)]
+pub struct DeviceLight {
+    pub pos: na::Vector4<f32>,
+    pub color: na::Vector4<f32>,
+    pub shadow_rect: [f32; 4], //Atlas UV offset & face size of the light's tile (see `ShadowAtlas::tile_rect`)
+    pub intensity: f32,
+    pub range: f32,
+    pub casts_shadow: u32
+}
+
+/**
+    Camera uniform as laid out (std140) in each frame's uniform buffer slice,
+    sizing the slice & uploaded whole by the renderer (see `Renderer::register_material_shader`).
+*/
+#[repr(C, align(16))]
+#[derive(Copy, Clone)]
+pub(crate) struct CameraUniform {
+    pub view: na::Matrix4<f32>,
+    pub projection: na::Matrix4<f32>,
+    pub camera_pos: na::Vector4<f32>,
+    pub environment: na::Matrix4<f32>, //World to environment sampling direction
+    pub scene_color_valid: u32,
+    pub ibl_specular: u32,
+    pub view_count: u32,
+    pub padding: u32,
+    pub views: [ViewUniform; MAX_VIEWS], //Only the first `view_count` are set
+    pub sky: [na::Vector4<f32>; 3], //Top, horizon & bottom colors of gradient environments
+    pub environment_color: na::Vector4<f32> //Tint times intensity
+}
+
+///Camera of one view in `CameraUniform`.
+#[repr(C, align(16))]
+#[derive(Copy, Clone)]
+pub(crate) struct ViewUniform {
+    pub view: na::Matrix4<f32>,
+    pub projection: na::Matrix4<f32>,
+    pub pos: na::Vector4<f32>
+}
+
+//Matches the std140 layout declared by the shaders
+const _: () = assert!(
+    std::mem::size_of::<CameraUniform>() == 3 * 64 + 2 * 16 + MAX_VIEWS * (2 * 64 + 16) + 4 * 16
+        && std::mem::align_of::<CameraUniform>() == 16
+        && std::mem::size_of::<ViewUniform>() == 2 * 64 + 16
+);
+
+impl ViewUniform {
+    pub fn new(camera: &Camera) -> Self {
+        Self {
+            view: camera.view(),
+            projection: camera.projection(),
+            pos: camera.pos.to_homogeneous()
+        }
+    }
+
+    pub fn zeroed() -> Self {
+        Self {
+            view: na::Matrix4::zeros(),
+            projection: na::Matrix4::zeros(),
+            pos: na::Vector4::zeros()
+        }
+    }
+}
+
+impl DeviceLight {
+    pub fn new(light: &PointLight, shadow_rect: Option<[f32; 4]>) -> Self {
+        Self {
+            pos: light.pos,
+            color: light.color,
+            shadow_rect: shadow_rect.unwrap_or_default(),
+            intensity: light.intensity,
+            range: light.range,
+            casts_shadow: shadow_rect.is_some() as u32
+        }
+    }
+}
+
+///Resources replaced while still in use by in-flight frames.
+#[derive(Default)]
+struct Retirement {
+    //Retired device scenes & the frames which may still use them
+    scenes: Vec<(DeviceScene, [bool; FRAME_COUNT])>,
+    //Scenes & the frames whose descriptor sets are out of date
+    descriptors: Vec<(usize, [bool; FRAME_COUNT])>,
+    //Retired environments & the frames which may still use them
+    environments: Vec<(Environment, [bool; FRAME_COUNT])>,
+    //Frames whose environment descriptors are out of date
+    environment_frames: [bool; FRAME_COUNT],
+    //Renderer descriptor version written to each frame's descriptor sets
+    descriptor_versions: [u64; FRAME_COUNT]
+}
 
 pub struct SceneSet {
-    base: Rc<Base>,
+    base: Arc<Base>,
     pub camera: Camera,
-    descriptor_pool: vk::DescriptorPool,
-    //Descriptor sets: [scenes, skybox]
-    pub descriptor_sets: Vec<vk::DescriptorSet>,
+    pub extra_cameras: Vec<Camera>, //Cameras of views 1 onwards (see `Renderer::with_views`)
+    environment_rotation: na::UnitQuaternion<f32>,
+    environment_intensity: f32,
+    environment_tint: [f32; 3], //Linear
+    layer_mask: u32,
+    ibl_specular: bool,
+    retain_scenes: bool, //Keep a copy of each uploaded scene (see `set_scene_retention`)
+    highlights: Vec<Highlight>,
+    highlight_thickness: f32, //In pixels
+    //Descriptor pools for each frame, added as existing pools fill up
+    descriptor_pools: [Vec<vk::DescriptorPool>; FRAME_COUNT],
+    pool_sizes: Vec<vk::DescriptorPoolSize>, //Counted from the descriptor set layouts (see `create_descriptor_pool`)
+    //Descriptor sets: [scenes: [frames: [pbr, cull]]]
+    scene_descriptor_sets: Vec<[[vk::DescriptorSet; 2]; FRAME_COUNT]>,
+    skybox_descriptor_sets: [vk::DescriptorSet; FRAME_COUNT],
+    billboard_descriptor_sets: [vk::DescriptorSet; FRAME_COUNT],
     pub scenes: Vec<DeviceScene>,
-    pub environment: Environment,
-    pub lights: [PointLight; MAX_LIGHTS],
+    environment: Environment, //Replaced through `set_environment` so in-flight frames keep the old one
+    pub lights: Vec<PointLight>, //Drawn lights (only the first `light_capacity`)
+    light_capacity: usize,
+    pub lights_size: usize, //Size of each frame's light buffer slice
     pub camera_uniform_size: usize,
+    pub user_uniform_size: usize, //Size of each frame's user uniform slice
+    user_uniform: Vec<u8>, //Uploaded to the current frame's slice in each frame
     pub lights_buffer: vk::Buffer,
     pub camera_buffer: vk::Buffer,
+    pub user_buffer: vk::Buffer,
     buffer_alloc: vk::DeviceMemory,
-    buffer_descriptors: [vk::DescriptorBufferInfo; 2 * FRAME_COUNT]
+    buffer_descriptors: [vk::DescriptorBufferInfo; 3 * FRAME_COUNT],
+    retirement: RefCell<Retirement>
 }
 
 impl SceneSet {
     pub fn new(
         renderer: &Renderer,
         environment: Environment
-    ) -> Result<SceneSet, vk::Result> {
+    ) -> Result<SceneSet, AllocationError> {
+        Self::with_light_capacity(renderer, environment, DEFAULT_LIGHT_CAPACITY)
+    }
+
+    /**
+        Create a scene set drawing up to `light_capacity` point lights (`DEFAULT_LIGHT_CAPACITY` with `new`).
+        Each frame slot stores the light count & `light_capacity` lights of 64 bytes,
+        which must fit the device's `maxStorageBufferRange` (failing with `ERROR_TOO_MANY_OBJECTS` otherwise).
+        Shaders only evaluate the lights in `lights`, so unused capacity costs memory but no shading time.
+    */
+    pub fn with_light_capacity(
+        renderer: &Renderer,
+        environment: Environment,
+        light_capacity: usize
+    ) -> Result<SceneSet, AllocationError> {
         let base = renderer.base.clone();
-        let pool_sizes = [
-            *vk::DescriptorPoolSize::builder()
-                .ty(vk::DescriptorType::SAMPLER)
-                .descriptor_count(FRAME_COUNT as u32),
-            *vk::DescriptorPoolSize::builder()
-                .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                .descriptor_count(4 * FRAME_COUNT as u32)
-        ];
-        //Layouts: [mesh, skybox]
-        let create_info = vk::DescriptorPoolCreateInfo::builder()
-            .max_sets(4 * FRAME_COUNT as u32)
-            .pool_sizes(&pool_sizes);
-        let descriptor_pool = unsafe {
-            base.device.create_descriptor_pool(&create_info, None)
-        }?;
+        let limits = &base.physical_device_properties.limits;
         //Buffers
-        let lights = [PointLight::default(); MAX_LIGHTS];
-        let alignment = base.physical_device_properties.limits.min_uniform_buffer_offset_alignment as usize;
-        let uniform_size = (UNIFORM_SIZE + alignment - 1) & !(alignment - 1);
+        let storage_alignment = limits.min_storage_buffer_offset_alignment as usize;
+        let lights_size = light_capacity.checked_mul(std::mem::size_of::<DeviceLight>())
+            .and_then(|size| size.checked_add(LIGHTS_HEADER_SIZE + storage_alignment - 1))
+            .map(|size| size & !(storage_alignment - 1))
+            .filter(|&size| size <= limits.max_storage_buffer_range as usize)
+            .ok_or(vk::Result::ERROR_TOO_MANY_OBJECTS)?;
+        let alignment = limits.min_uniform_buffer_offset_alignment as usize;
+        let uniform_size = (std::mem::size_of::<CameraUniform>() + alignment - 1) & !(alignment - 1);
+        let user_uniform_size = (MAX_USER_UNIFORM_SIZE + alignment - 1) & !(alignment - 1);
         let buffer_sizes = [
-            MAX_LIGHTS * std::mem::size_of::<PointLight>(),
-            uniform_size
+            lights_size,
+            uniform_size,
+            user_uniform_size
         ];
         let create_infos = [
             //Lights
@@ -65,15 +230,23 @@ impl SceneSet {
             *vk::BufferCreateInfo::builder()
                 .size((FRAME_COUNT * buffer_sizes[1]) as u64)
                 .usage(vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::TRANSFER_DST)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE),
+            //User uniform
+            *vk::BufferCreateInfo::builder()
+                .size((FRAME_COUNT * buffer_sizes[2]) as u64)
+                .usage(vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::TRANSFER_DST)
                 .sharing_mode(vk::SharingMode::EXCLUSIVE)
         ];
         let (buffers, buffer_alloc) = base.create_buffers(
             &create_infos,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            MemoryCategory::Scenes
         )?;
+        //User uniform reads as zeros until set
+        renderer.transaction.borrow_mut().fill_buffer(buffers[2], 0, FRAME_COUNT * buffer_sizes[2], 0);
         //Buffer descriptors
-        let mut buffer_descriptors = [vk::DescriptorBufferInfo::default(); 2 * FRAME_COUNT];
-        for b in 0..2 {
+        let mut buffer_descriptors = [vk::DescriptorBufferInfo::default(); 3 * FRAME_COUNT];
+        for b in 0..3 {
             let buffer = buffers[b];
             let size = buffer_sizes[b];
             for f in 0..FRAME_COUNT {
@@ -83,288 +256,907 @@ impl SceneSet {
                     .range(size as u64);
             }
         }
-        Ok(Self {
+        let mut scene_set = Self {
             base,
             camera: Camera::new(),
-            descriptor_pool,
-            descriptor_sets: vec![],
+            extra_cameras: vec![Camera::new(); renderer.view_count() - 1],
+            environment_rotation: na::UnitQuaternion::identity(),
+            environment_intensity: 1.0,
+            environment_tint: [1.0; 3],
+            layer_mask: !0,
+            ibl_specular: true,
+            retain_scenes: false,
+            highlights: vec![],
+            highlight_thickness: 2.0,
+            descriptor_pools: Default::default(),
+            pool_sizes: vec![],
+            scene_descriptor_sets: vec![],
+            skybox_descriptor_sets: [vk::DescriptorSet::null(); FRAME_COUNT],
+            billboard_descriptor_sets: [vk::DescriptorSet::null(); FRAME_COUNT],
             scenes: vec![],
             environment,
-            lights,
+            lights: Vec::with_capacity(light_capacity),
+            light_capacity,
+            lights_size,
             camera_uniform_size: uniform_size,
+            user_uniform_size,
+            user_uniform: vec![],
             lights_buffer: buffers[0],
             camera_buffer: buffers[1],
+            user_buffer: buffers[2],
             buffer_alloc,
-            buffer_descriptors
-        })
-    }
-
-    fn recreate_descriptors(&mut self, renderer: &Renderer) -> Result<(), vk::Result> {
+            buffer_descriptors,
+            retirement: RefCell::new(Retirement {
+                descriptor_versions: [renderer.descriptor_version; FRAME_COUNT],
+                ..Retirement::default()
+            })
+        };
+        //Descriptors in each pool, for `POOL_SCENE_COUNT` scenes (PBR & culling sets), a skybox & billboards
+        for (layout, set_count) in [
+            (&renderer.layouts[0], POOL_SCENE_COUNT),
+            (&renderer.cull_layout, POOL_SCENE_COUNT),
+            (&renderer.layouts[1], 1),
+            (&renderer.layouts[2], 1)
+        ] {
+            add_descriptor_counts(&mut scene_set.pool_sizes, &layout.descriptor_counts, set_count as u32);
+        }
+        //Skybox & billboard descriptor sets
+        let mut writes = Vec::<vk::WriteDescriptorSet>::new();
+        for frame in 0..FRAME_COUNT {
+            let descriptor_sets = scene_set.allocate_descriptor_sets(
+                frame,
+                &[renderer.layouts[1].descriptor_set_layout, renderer.layouts[2].descriptor_set_layout]
+            )?;
+            scene_set.skybox_descriptor_sets[frame] = descriptor_sets[0];
+            scene_set.billboard_descriptor_sets[frame] = descriptor_sets[1];
+        }
+        for frame in 0..FRAME_COUNT {
+            let descriptor_set = scene_set.skybox_descriptor_sets[frame];
+            //Camera
+            writes.push(*vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(0)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .buffer_info(std::slice::from_ref(
+                    &scene_set.buffer_descriptors[FRAME_COUNT + frame]
+                ))
+            );
+            //Skybox image (left unwritten for gradient environments, as the binding is partially bound)
+            if let Some(descriptors) = scene_set.environment.descriptors() {
+                writes.push(*vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(1)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(std::slice::from_ref(&descriptors[0]))
+                );
+            }
+            let descriptor_set = scene_set.billboard_descriptor_sets[frame];
+            writes.extend_from_slice(&[
+                //Camera
+                *vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(0)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .buffer_info(std::slice::from_ref(
+                        &scene_set.buffer_descriptors[FRAME_COUNT + frame]
+                    )),
+                //Billboards
+                *vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(1)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(std::slice::from_ref(&renderer.billboard_descriptors[frame]))
+            ]);
+        }
+        debug_assert!(descriptors_cover(
+            &[&renderer.layouts[1].descriptor_counts, &renderer.layouts[2].descriptor_counts],
+            FRAME_COUNT as u32,
+            &writes
+        ));
         unsafe {
-            self.base.device.queue_wait_idle(self.base.graphics_queue)?;
-            self.base.device.destroy_descriptor_pool(self.descriptor_pool, None);
+            scene_set.base.device.update_descriptor_sets(&writes, &[]);
         }
-        //Create pool
-        let pbr_set_count = FRAME_COUNT * self.scenes.len();
-        let cull_set_count = FRAME_COUNT * self.scenes.len();
-        let env_set_count = FRAME_COUNT;
-        //TODO: Automatic pool size counting
-        let pool_sizes = [
-            *vk::DescriptorPoolSize::builder()
-                .ty(vk::DescriptorType::UNIFORM_BUFFER)
-                .descriptor_count((pbr_set_count + cull_set_count + env_set_count) as u32),
-            *vk::DescriptorPoolSize::builder()
-                .ty(vk::DescriptorType::STORAGE_BUFFER)
-                .descriptor_count((5 * pbr_set_count + 6 * cull_set_count) as u32),
-            *vk::DescriptorPoolSize::builder()
-                .ty(vk::DescriptorType::SAMPLER)
-                .descriptor_count(pbr_set_count as u32),
-            *vk::DescriptorPoolSize::builder()
-                .ty(vk::DescriptorType::SAMPLED_IMAGE)
-                .descriptor_count((pbr_set_count * MAX_TEXTURES) as u32),
-            *vk::DescriptorPoolSize::builder()
-                .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                .descriptor_count(3 * pbr_set_count as u32 + env_set_count as u32)
-        ];
+        Ok(scene_set)
+    }
+
+    ///Create a descriptor pool with room for `POOL_SCENE_COUNT` scenes, a skybox & billboards (single frame).
+    ///The descriptor counts are summed from the layouts' bindings, so they follow binding changes.
+    fn create_descriptor_pool(&self) -> Result<vk::DescriptorPool, vk::Result> {
         let create_info = vk::DescriptorPoolCreateInfo::builder()
-            .max_sets((pbr_set_count + cull_set_count + env_set_count) as u32)
-            .pool_sizes(&pool_sizes);
-        self.descriptor_pool = unsafe {
+            .max_sets((2 * POOL_SCENE_COUNT + 2) as u32)
+            .pool_sizes(&self.pool_sizes);
+        unsafe {
             self.base.device.create_descriptor_pool(&create_info, None)
-        }?;
-        //Allocate descriptor sets
-        let layouts: Vec<vk::DescriptorSetLayout> = [
-            std::iter::repeat(renderer.layouts[0].descriptor_set_layout).take(pbr_set_count),
-            std::iter::repeat(renderer.cull_layout.descriptor_set_layout).take(cull_set_count),
-            std::iter::repeat(renderer.layouts[1].descriptor_set_layout).take(env_set_count)
-        ].into_iter().flatten().collect();
+        }
+    }
+
+    ///Allocate descriptor sets for a frame, adding a new pool if the current one is full.
+    fn allocate_descriptor_sets(
+        &mut self,
+        frame: usize,
+        layouts: &[vk::DescriptorSetLayout]
+    ) -> Result<Vec<vk::DescriptorSet>, vk::Result> {
+        if let Some(&descriptor_pool) = self.descriptor_pools[frame].last() {
+            let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(descriptor_pool)
+                .set_layouts(layouts);
+            match unsafe {self.base.device.allocate_descriptor_sets(&allocate_info)} {
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL) => (),
+                result => {return result;}
+            }
+        }
+        let descriptor_pool = self.create_descriptor_pool()?;
+        self.descriptor_pools[frame].push(descriptor_pool);
         let allocate_info = vk::DescriptorSetAllocateInfo::builder()
-            .descriptor_pool(self.descriptor_pool)
-            .set_layouts(&layouts);
-        self.descriptor_sets = unsafe {
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(layouts);
+        unsafe {
             self.base.device.allocate_descriptor_sets(&allocate_info)
-        }?;
+        }
+    }
 
-        //Update descriptor sets
-        //[scenes: [frames: [items: []]], skybox: [frames: []]]
-        let mut writes = Vec::<vk::WriteDescriptorSet>::new();
+    ///Descriptor writes for one frame of a scene's PBR & culling descriptor sets.
+    fn scene_writes(
+        &self,
+        renderer: &Renderer,
+        index: usize,
+        frame: usize
+    ) -> Vec<vk::WriteDescriptorSet> {
+        let scene = &self.scenes[index];
         //PBR pipeline
-        for (i, scene) in self.scenes.iter().enumerate() {
-            //Per-frame descriptor writes
-            for frame in 0..FRAME_COUNT {
-                let descriptor_set = self.descriptor_sets[FRAME_COUNT * i + frame];
-                writes.extend_from_slice(&[
-                    //Uniforms
-                    *vk::WriteDescriptorSet::builder()
-                        .dst_set(descriptor_set)
-                        .dst_binding(0)
-                        .dst_array_element(0)
-                        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-                        .buffer_info(std::slice::from_ref(
-                            &self.buffer_descriptors[FRAME_COUNT + frame]
-                        )),
-                    //Meshes
-                    *vk::WriteDescriptorSet::builder()
-                        .dst_set(descriptor_set)
-                        .dst_binding(1)
-                        .dst_array_element(0)
-                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-                        .buffer_info(std::slice::from_ref(
-                            &scene.buffer_descriptors[0]
-                        )),
-                    //Materials
-                    *vk::WriteDescriptorSet::builder()
-                        .dst_set(descriptor_set)
-                        .dst_binding(2)
-                        .dst_array_element(0)
-                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-                        .buffer_info(std::slice::from_ref(
-                            &scene.buffer_descriptors[1]
-                        )),
-                    //Nodes
-                    *vk::WriteDescriptorSet::builder()
-                        .dst_set(descriptor_set)
-                        .dst_binding(3)
-                        .dst_array_element(0)
-                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-                        .buffer_info(std::slice::from_ref(
-                            &scene.buffer_descriptors[3 + frame]
-                        )),
-                    //Draw command extras
-                    *vk::WriteDescriptorSet::builder()
-                        .dst_set(descriptor_set)
-                        .dst_binding(4)
-                        .dst_array_element(0)
-                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-                        .buffer_info(std::slice::from_ref(
-                            &scene.buffer_descriptors[3 + 2 * FRAME_COUNT + frame]
-                        )),
-                    //Textures
-                    *vk::WriteDescriptorSet::builder()
-                        .dst_set(descriptor_set)
-                        .dst_binding(6)
-                        .dst_array_element(0)
-                        .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
-                        .image_info(&scene.image_descriptors),
-                    //Lights
-                    *vk::WriteDescriptorSet::builder()
-                        .dst_set(descriptor_set)
-                        .dst_binding(7)
-                        .dst_array_element(0)
-                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-                        .buffer_info(std::slice::from_ref(
-                            &self.buffer_descriptors[frame]
-                        )),
-                    //Cubemaps
-                    *vk::WriteDescriptorSet::builder()
-                        .dst_set(descriptor_set)
-                        .dst_binding(8)
-                        .dst_array_element(0)
-                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                        .image_info(&self.environment.descriptors[1..=2]),
-                    //DFG lookup
-                    *vk::WriteDescriptorSet::builder()
-                        .dst_set(descriptor_set)
-                        .dst_binding(9)
-                        .dst_array_element(0)
-                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                        .image_info(std::slice::from_ref(&renderer.dfg_descriptor))
-                ])
-            }
+        let descriptor_set = self.scene_descriptors(index, frame);
+        let mut writes = vec![
+            //Uniforms
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(0)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .buffer_info(std::slice::from_ref(
+                    &self.buffer_descriptors[FRAME_COUNT + frame]
+                )),
+            //Meshes
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(1)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(std::slice::from_ref(
+                    &scene.buffer_descriptors[0]
+                )),
+            //Materials
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(2)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(std::slice::from_ref(
+                    &scene.buffer_descriptors[1]
+                )),
+            //Nodes
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(3)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(std::slice::from_ref(
+                    &scene.buffer_descriptors[3 + frame]
+                )),
+            //Draw command extras
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(4)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(std::slice::from_ref(
+                    &scene.buffer_descriptors[3 + 2 * FRAME_COUNT + frame]
+                )),
+            //Sampler
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(5)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .image_info(std::slice::from_ref(&renderer.texture_descriptor)),
+            //Textures
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(6)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .image_info(&scene.image_descriptors),
+            //Lights
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(7)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(std::slice::from_ref(
+                    &self.buffer_descriptors[frame]
+                )),
+            //DFG lookup
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(9)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(std::slice::from_ref(&renderer.dfg_descriptor)),
+            //Scene color
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(10)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(std::slice::from_ref(&renderer.framebuffer.scene_color_descriptor)),
+            //Shadow atlas
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(11)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(std::slice::from_ref(&renderer.shadow_atlas.descriptor)),
+            //User uniform
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(12)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .buffer_info(std::slice::from_ref(
+                    &self.buffer_descriptors[2 * FRAME_COUNT + frame]
+                )),
+            //Morph deltas
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(13)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(std::slice::from_ref(
+                    &scene.buffer_descriptors[3 + 4 * FRAME_COUNT]
+                )),
+            //Texture arrays
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(14)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .image_info(&scene.array_descriptors),
+            //Cull statistics
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(15)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(std::slice::from_ref(
+                    &scene.buffer_descriptors[4 + 4 * FRAME_COUNT + frame]
+                ))
+        ];
+        //Cubemaps (partially bound, so unwritten for gradient environments)
+        if let Some(descriptors) = self.environment.descriptors() {
+            writes.push(*vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(8)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(&descriptors[1..=2])
+            );
         }
-
+        let pbr_writes = writes.len();
         //Compute culling pipeline
-        for (i, scene) in self.scenes.iter().enumerate() {
-            //Per-frame descriptor writes
-            for frame in 0..FRAME_COUNT {
-                let descriptor_set = self.descriptor_sets[pbr_set_count + FRAME_COUNT * i + frame];
-                writes.extend_from_slice(&[
-                    //Uniforms
-                    *vk::WriteDescriptorSet::builder()
-                        .dst_set(descriptor_set)
-                        .dst_binding(0)
-                        .dst_array_element(0)
-                        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-                        .buffer_info(std::slice::from_ref(
-                            &self.buffer_descriptors[FRAME_COUNT + frame]
-                        )),
-                    //Meshes
-                    *vk::WriteDescriptorSet::builder()
-                        .dst_set(descriptor_set)
-                        .dst_binding(1)
-                        .dst_array_element(0)
-                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-                        .buffer_info(std::slice::from_ref(
-                            &scene.buffer_descriptors[0]
-                        )),
-                    //Mesh draw commands
-                    *vk::WriteDescriptorSet::builder()
-                        .dst_set(descriptor_set)
-                        .dst_binding(2)
-                        .dst_array_element(0)
-                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-                        .buffer_info(std::slice::from_ref(
-                            &scene.buffer_descriptors[2]
-                        )),
-                    //Nodes
-                    *vk::WriteDescriptorSet::builder()
-                        .dst_set(descriptor_set)
-                        .dst_binding(3)
-                        .dst_array_element(0)
-                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-                        .buffer_info(std::slice::from_ref(
-                            &scene.buffer_descriptors[3 + frame]
-                        )),
-                    //Draw commands
-                    *vk::WriteDescriptorSet::builder()
-                        .dst_set(descriptor_set)
-                        .dst_binding(4)
-                        .dst_array_element(0)
-                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-                        .buffer_info(std::slice::from_ref(
-                            &scene.buffer_descriptors[3 + FRAME_COUNT + frame]
-                        )),
-                    //Draw command extras
-                    *vk::WriteDescriptorSet::builder()
-                        .dst_set(descriptor_set)
-                        .dst_binding(5)
-                        .dst_array_element(0)
-                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-                        .buffer_info(std::slice::from_ref(
-                            &scene.buffer_descriptors[3 + 2 * FRAME_COUNT + frame]
-                        )),
-                    //Draw count
-                    *vk::WriteDescriptorSet::builder()
-                        .dst_set(descriptor_set)
-                        .dst_binding(6)
-                        .dst_array_element(0)
-                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-                        .buffer_info(std::slice::from_ref(
-                            &scene.buffer_descriptors[3 + 3 * FRAME_COUNT + frame]
-                        ))
-                ])
-            }
-        }
-
-        //Skybox pipeline
-        //Camera
-        writes.extend((0..FRAME_COUNT).map(
-            |frame| *vk::WriteDescriptorSet::builder()
-                .dst_set(self.descriptor_sets[pbr_set_count + cull_set_count + frame])
+        let descriptor_set = self.cull_descriptors(index, frame);
+        writes.extend_from_slice(&[
+            //Uniforms
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
                 .dst_binding(0)
                 .dst_array_element(0)
                 .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-                .buffer_info(
-                    std::slice::from_ref(&self.buffer_descriptors[FRAME_COUNT + frame])
-                )
-        ));
-        //Skybox image
-        writes.extend((0..FRAME_COUNT).map(
-            |frame| *vk::WriteDescriptorSet::builder()
-                .dst_set(self.descriptor_sets[pbr_set_count + cull_set_count + frame])
+                .buffer_info(std::slice::from_ref(
+                    &self.buffer_descriptors[FRAME_COUNT + frame]
+                )),
+            //Meshes
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
                 .dst_binding(1)
                 .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(std::slice::from_ref(
+                    &scene.buffer_descriptors[0]
+                )),
+            //Mesh draw commands
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(2)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(std::slice::from_ref(
+                    &scene.buffer_descriptors[2]
+                )),
+            //Nodes
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(3)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(std::slice::from_ref(
+                    &scene.buffer_descriptors[3 + frame]
+                )),
+            //Draw commands
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(4)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(std::slice::from_ref(
+                    &scene.buffer_descriptors[3 + FRAME_COUNT + frame]
+                )),
+            //Draw command extras
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(5)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(std::slice::from_ref(
+                    &scene.buffer_descriptors[3 + 2 * FRAME_COUNT + frame]
+                )),
+            //Draw count
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(6)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(std::slice::from_ref(
+                    &scene.buffer_descriptors[3 + 3 * FRAME_COUNT + frame]
+                )),
+            //Depth pyramid
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(7)
+                .dst_array_element(0)
                 .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                .image_info(std::slice::from_ref(&self.environment.descriptors[0]))
-        ));
+                .image_info(std::slice::from_ref(&renderer.depth_pyramid.descriptor)),
+            //Cull statistics
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(8)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(std::slice::from_ref(
+                    &scene.buffer_descriptors[4 + 4 * FRAME_COUNT + frame]
+                ))
+        ]);
+        debug_assert!(descriptors_cover(&[&renderer.layouts[0].descriptor_counts], 1, &writes[..pbr_writes]));
+        debug_assert!(descriptors_cover(&[&renderer.cull_layout.descriptor_counts], 1, &writes[pbr_writes..]));
+        writes
+    }
 
-        unsafe {
-            self.base.device.update_descriptor_sets(&writes, &[]);
-        }
-        Ok(())
+    /**
+        Keep a copy of each scene uploaded from now on (by `push_scene`, `update_scene`, `replace_scene`
+        & `reserve_nodes`), so CPU-side queries like `pick` don't need the caller to hold the scene
+        (see `source_scene`). Off by default, as it doubles the memory held for scene data on the host
+        & copies the scene on each update.
+    */
+    pub fn set_scene_retention(&mut self, enabled: bool) {
+        self.retain_scenes = enabled;
+    }
+
+    ///The scene a device scene was last uploaded from, if retained (see `set_scene_retention`).
+    pub fn source_scene(&self, index: usize) -> Option<&Scene> {
+        self.scenes[index].source.as_deref()
+    }
+
+    fn retained(&self, scene: &Scene) -> Option<Arc<Scene>> {
+        self.retain_scenes.then(|| Arc::new(scene.clone()))
     }
 
-    pub fn push_scene(&mut self, scene: &Scene, renderer: &Renderer) -> usize {
+    /**
+        Upload a scene, returning its index.
+        Fails if its textures don't fit `MAX_TEXTURES` images (see `DeviceScene::new`).
+    */
+    pub fn push_scene(&mut self, scene: &Scene, renderer: &Renderer) -> Result<usize, AllocationError> {
         let index = self.scenes.len();
         let mut transaction = renderer.transaction.borrow_mut();
-        self.scenes.push(DeviceScene::new(
+        let mut device_scene = DeviceScene::new(
             self.base.clone(),
             &mut transaction,
-            scene
-        ).unwrap());
-        self.recreate_descriptors(renderer).unwrap();
-        index
+            scene,
+            0
+        )?;
+        device_scene.source = self.retained(scene);
+        //Descriptor sets
+        //New sets aren't used by in-flight frames, so they can be written immediately
+        let layouts = [
+            renderer.layouts[0].descriptor_set_layout,
+            renderer.cull_layout.descriptor_set_layout
+        ];
+        let mut descriptor_sets = [[vk::DescriptorSet::null(); 2]; FRAME_COUNT];
+        for (frame, sets) in descriptor_sets.iter_mut().enumerate() {
+            match self.allocate_descriptor_sets(frame, &layouts) {
+                Ok(allocated) => sets.copy_from_slice(&allocated),
+                Err(result) => {
+                    //Pending uploads still write to the scene's buffers, so it's released like a replaced scene
+                    self.retirement.borrow_mut().scenes.push((device_scene, [true; FRAME_COUNT]));
+                    return Err(result.into());
+                }
+            }
+        }
+        self.scenes.push(device_scene);
+        self.scene_descriptor_sets.push(descriptor_sets);
+        let writes: Vec<vk::WriteDescriptorSet> = (0..FRAME_COUNT).flat_map(
+            |frame| self.scene_writes(renderer, index, frame)
+        ).collect();
+        unsafe {
+            self.base.device.update_descriptor_sets(&writes, &[]);
+        }
+        Ok(index)
     }
 
     pub fn update_scene(&mut self, scene: &Scene, index: usize) {
         self.scenes[index].update(scene);
+        if self.retain_scenes {
+            self.scenes[index].source = self.retained(scene);
+        }
+    }
+
+    ///The camera of a view (`camera` for view 0).
+    pub fn view_camera(&self, view: usize) -> &Camera {
+        match view {
+            0 => &self.camera,
+            i => &self.extra_cameras[i - 1]
+        }
+    }
+
+    ///Rotate the environment (skybox & image-based lighting) relative to the world.
+    pub fn set_environment_rotation(&mut self, rotation: na::UnitQuaternion<f32>) {
+        self.environment_rotation = rotation;
+    }
+
+    pub fn environment_rotation(&self) -> na::UnitQuaternion<f32> {
+        self.environment_rotation
+    }
+
+    /**
+        Scale the environment's radiance (skybox & image-based lighting), e.g. to fade to night (1 by default).
+        Applied through the per-frame uniform, so it can change every frame.
+    */
+    pub fn set_environment_intensity(&mut self, intensity: f32) {
+        self.environment_intensity = intensity;
+    }
+
+    pub fn environment_intensity(&self) -> f32 {
+        self.environment_intensity
+    }
+
+    ///Tint the environment's radiance by a linear color (white by default), along with its intensity.
+    pub fn set_environment_tint(&mut self, tint: [f32; 3]) {
+        self.environment_tint = tint;
+    }
+
+    pub fn environment_tint(&self) -> [f32; 3] {
+        self.environment_tint
+    }
+
+    ///Enable or disable the specular term of image-based lighting (enabled by default).
+    pub fn set_ibl_specular(&mut self, enabled: bool) {
+        self.ibl_specular = enabled;
+    }
+
+    pub fn ibl_specular(&self) -> bool {
+        self.ibl_specular
+    }
+
+    /**
+        Set the user uniform, bound at binding 12 of scene descriptor sets for custom shaders
+        (see `Renderer::register_material_shader`). It is uploaded with every following frame,
+        so frames in flight keep the data they were recorded with.
+        `T` should match the shader's std140 block, e.g. with `#[repr(C, align(16))]`.
+        Panics if `T` is larger than `MAX_USER_UNIFORM_SIZE` or aligned to more than 16 bytes.
+    */
+    pub fn set_user_uniform<T: Copy>(&mut self, data: &T) {
+        assert!(std::mem::size_of::<T>() <= MAX_USER_UNIFORM_SIZE, "User uniform larger than MAX_USER_UNIFORM_SIZE");
+        assert!(std::mem::align_of::<T>() <= 16, "User uniform aligned to more than 16 bytes");
+        let bytes = unsafe {
+            std::slice::from_raw_parts(data as *const T as *const u8, std::mem::size_of::<T>())
+        };
+        self.user_uniform = bytes.to_vec();
+    }
+
+    ///Bytes of the user uniform (empty until `set_user_uniform` is called).
+    pub fn user_uniform(&self) -> &[u8] {
+        &self.user_uniform
+    }
+
+    /**
+        Only draw (& pick) nodes whose layer's bit is set in `mask`.
+        All layers are drawn by default.
+    */
+    pub fn set_layer_mask(&mut self, mask: u32) {
+        self.layer_mask = mask;
+    }
+
+    pub fn layer_mask(&self) -> u32 {
+        self.layer_mask
+    }
+
+    ///Assign every device node drawn for a source scene node to a layer (0-31).
+    pub fn set_node_layer(&mut self, index: usize, node: usize, layer: u32) {
+        let scene = &mut self.scenes[index];
+        for (device_node, &i) in std::iter::zip(&mut scene.nodes, &scene.node_indices) {
+            if i == node {
+                device_node.set_layer(layer);
+            }
+        }
+    }
+
+    /**
+        Show or hide a single primitive of a scene node's mesh (e.g. a removable part),
+        leaving the node's other primitives drawn.
+        Returns false (changing nothing) if the node has no such primitive.
+    */
+    pub fn set_primitive_visible(&mut self, index: usize, node: usize, primitive: usize, visible: bool) -> bool {
+        let scene = &mut self.scenes[index];
+        match scene.primitive_node_index(node, primitive) {
+            Some(device_node) => {
+                scene.nodes[device_node].set_visible(visible);
+                true
+            },
+            None => false
+        }
+    }
+
+    /**
+        Draw a scene's meshes with a shader registered using `Renderer::register_material_shader`.
+        Fails with `ERROR_FORMAT_NOT_SUPPORTED` (keeping the scene's shader) if the scene uses `VertexFormat::Packed`,
+        as custom shaders take full vertices. Replacing the scene resets its shader to the default.
+    */
+    pub fn set_scene_shader(&mut self, index: usize, shader: ShaderId) -> Result<(), vk::Result> {
+        let scene = &mut self.scenes[index];
+        if shader != ShaderId::DEFAULT && scene.vertex_format != VertexFormat::Full {
+            return Err(vk::Result::ERROR_FORMAT_NOT_SUPPORTED);
+        }
+        scene.shader = shader;
+        Ok(())
+    }
+
+    ///Offset the depth of a scene's meshes, e.g. to draw decals over coplanar geometry without z-fighting.
+    pub fn set_scene_depth_bias(&mut self, index: usize, depth_bias: DepthBias) {
+        self.scenes[index].depth_bias = depth_bias;
+    }
+
+    /**
+        Override the renderer's wireframe overlay for a scene (see `Renderer::set_wireframe_overlay`):
+        `Some(true)` outlines the scene's triangles even with the overlay disabled,
+        `Some(false)` draws the scene solid only, & `None` follows the renderer.
+        Outlining still requires `VK_KHR_fragment_shader_barycentric`, otherwise scenes are drawn solid.
+    */
+    pub fn set_scene_wireframe(&mut self, index: usize, wireframe: Option<bool>) {
+        self.scenes[index].wireframe = wireframe;
+    }
+
+    /**
+        Outline a scene node in a linear, alpha blended `color`, or remove its outline with `None`.
+        After the scenes are drawn, the node's primitives are drawn again as an inverted hull
+        (back faces pushed out by the outline width), so the outline is occluded by nearer meshes
+        & needs no stencil aspect in the depth buffer. Hidden primitives aren't outlined,
+        & nothing is outlined without a depth buffer (see `Renderer::without_depth_buffer`).
+    */
+    pub fn set_highlight(&mut self, scene: usize, node: usize, color: Option<[f32; 4]>) {
+        assert!(scene < self.scenes.len());
+        self.highlights.retain(|highlight| (highlight.scene, highlight.node) != (scene, node));
+        if let Some(color) = color {
+            self.highlights.push(Highlight {scene, node, color});
+        }
+    }
+
+    pub fn highlights(&self) -> &[Highlight] {
+        &self.highlights
+    }
+
+    ///Set the width of highlight outlines in pixels (2 by default).
+    pub fn set_highlight_thickness(&mut self, thickness: f32) {
+        self.highlight_thickness = thickness;
+    }
+
+    pub fn highlight_thickness(&self) -> f32 {
+        self.highlight_thickness
+    }
+
+    ///Number of `lights` drawn, further lights are ignored (see `with_light_capacity`).
+    pub fn light_capacity(&self) -> usize {
+        self.light_capacity
+    }
+
+    /**
+        Replace a scene entirely (unlike `update_scene`, the node count may change).
+        The scene keeps its node capacity (see `reserve_nodes`), which doubles when exceeded.
+        This doesn't wait for the device to idle:
+        the previous device scene is kept alive until every frame which may use it has completed,
+        and the scene's descriptor sets are rewritten as each frame begins.
+        Fails like `push_scene`, keeping the previous scene.
+    */
+    pub fn replace_scene(
+        &mut self,
+        index: usize,
+        scene: &Scene,
+        renderer: &Renderer
+    ) -> Result<(), AllocationError> {
+        let capacity = self.scenes[index].node_capacity;
+        self.rebuild_scene(index, scene, renderer, capacity)
+    }
+
+    /**
+        Make room for at least `capacity` device nodes (one per node primitive) in a scene's
+        node, draw command & extras buffers, which are otherwise sized to the scene's node count.
+        Growing the buffers re-uploads the scene from `scene` like `replace_scene`.
+    */
+    pub fn reserve_nodes(
+        &mut self,
+        index: usize,
+        capacity: usize,
+        scene: &Scene,
+        renderer: &Renderer
+    ) -> Result<(), AllocationError> {
+        if capacity <= self.scenes[index].node_capacity {
+            return Ok(());
+        }
+        self.rebuild_scene(index, scene, renderer, capacity)
+    }
+
+    ///Replace a scene's device scene, growing its node capacity if `scene` exceeds it.
+    fn rebuild_scene(
+        &mut self,
+        index: usize,
+        scene: &Scene,
+        renderer: &Renderer,
+        capacity: usize
+    ) -> Result<(), AllocationError> {
+        let node_count = DeviceScene::node_count(scene);
+        let capacity = if node_count > capacity && capacity > 0 {
+            //Exceeded: grow geometrically, so repeated growth doesn't re-upload every time
+            node_count.max(2 * capacity)
+        } else {
+            capacity
+        };
+        let mut transaction = renderer.transaction.borrow_mut();
+        let mut device_scene = DeviceScene::new(self.base.clone(), &mut transaction, scene, capacity)?;
+        device_scene.source = self.retained(scene);
+        let old_scene = std::mem::replace(&mut self.scenes[index], device_scene);
+        let mut retirement = self.retirement.borrow_mut();
+        retirement.scenes.push((old_scene, [true; FRAME_COUNT]));
+        retirement.descriptors.retain(|(i, _)| *i != index);
+        retirement.descriptors.push((index, [true; FRAME_COUNT]));
+        Ok(())
+    }
+
+    /**
+        Replace the environment (skybox & image-based lighting), e.g. for time of day changes.
+        Like `replace_scene`, this doesn't wait for the device to idle:
+        the previous environment is kept alive until every frame which may use it has completed,
+        and the environment descriptors are rewritten as each frame begins.
+    */
+    pub fn set_environment(&mut self, environment: Environment) {
+        let old_environment = std::mem::replace(&mut self.environment, environment);
+        let mut retirement = self.retirement.borrow_mut();
+        retirement.environments.push((old_environment, [true; FRAME_COUNT]));
+        retirement.environment_frames = [true; FRAME_COUNT];
+    }
+
+    pub fn environment(&self) -> &Environment {
+        &self.environment
+    }
+
+    /**
+        Prepare the resources of a frame for recording.
+        Must be called once the frame's previous submission has completed.
+        Rewrites the frame's out-of-date descriptor sets
+        and releases retired scenes & environments no longer used by any frame.
+    */
+    pub(crate) fn begin_frame(&self, renderer: &Renderer, frame: usize) {
+        let mut retirement = self.retirement.borrow_mut();
+        //Descriptor sets
+        let mut writes = Vec::<vk::WriteDescriptorSet>::new();
+        let environment_changed = retirement.environment_frames[frame];
+        if environment_changed || retirement.descriptor_versions[frame] != renderer.descriptor_version {
+            for index in 0..self.scenes.len() {
+                writes.extend(self.scene_writes(renderer, index, frame));
+            }
+            retirement.descriptor_versions[frame] = renderer.descriptor_version;
+        }
+        if environment_changed {
+            //Skybox image
+            if let Some(descriptors) = self.environment.descriptors() {
+                writes.push(*vk::WriteDescriptorSet::builder()
+                    .dst_set(self.skybox_descriptor_sets[frame])
+                    .dst_binding(1)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(std::slice::from_ref(&descriptors[0]))
+                );
+            }
+            retirement.environment_frames[frame] = false;
+        }
+        for (index, frames) in &mut retirement.descriptors {
+            if frames[frame] {
+                writes.extend(self.scene_writes(renderer, *index, frame));
+                frames[frame] = false;
+            }
+        }
+        retirement.descriptors.retain(|(_, frames)| frames.contains(&true));
+        if !writes.is_empty() {
+            unsafe {
+                self.base.device.update_descriptor_sets(&writes, &[]);
+            }
+        }
+        //Retired scenes
+        for (_, frames) in &mut retirement.scenes {
+            frames[frame] = false;
+        }
+        retirement.scenes.retain(|(_, frames)| frames.contains(&true));
+        //Retired environments
+        for (_, frames) in &mut retirement.environments {
+            frames[frame] = false;
+        }
+        retirement.environments.retain(|(_, frames)| frames.contains(&true));
+        //Cull statistics of the frame's previous submission
+        for scene in &self.scenes {
+            scene.read_cull_readback(frame);
+        }
+    }
+
+    /**
+        Number of a scene's nodes (per primitive) which passed culling in the last completed frame.
+        Read back without stalling, so this lags the current frame by up to `FRAME_COUNT` frames.
+    */
+    pub fn last_cull_count(&self, index: usize) -> u32 {
+        self.scenes[index].cull_count()
+    }
+
+    /**
+        Cull statistics of a scene in the last completed frame, e.g. for adaptive resolution or LOD.
+        Each frame has its own statistics buffer, read back without stalling like `last_cull_count`.
+    */
+    pub fn last_cull_stats(&self, index: usize) -> CullStats {
+        self.scenes[index].cull_stats()
+    }
+
+    /**
+        Find the nearest intersection of a world-space ray with the geometry of a scene.
+        Candidate nodes are first selected by their bounding boxes,
+        then tested against the triangles of `source`,
+        which must be the scene the device scene was created from
+        (e.g. `source_scene` when scenes are retained); `None` if its meshes don't match.
+    */
+    pub fn pick(
+        &self,
+        index: usize,
+        source: &Scene,
+        origin: na::Point3<f32>,
+        direction: na::UnitVector3<f32>
+    ) -> Option<Hit> {
+        let scene = &self.scenes[index];
+        let mut nearest: Option<Hit> = None;
+        for (device_node, &node) in std::iter::zip(&scene.nodes, &scene.node_indices) {
+            if !device_node.visible() || self.layer_mask & (1 << device_node.layer()) == 0 {
+                continue;
+            }
+            //Object-space ray
+            //(The direction is left unnormalized so distances stay in world units)
+            let local_origin = device_node.inverse_transform.transform_point(&origin);
+            let local_direction = device_node.inverse_transform.transform_vector(&direction);
+            //Broad phase
+            let mesh = &scene.meshes[device_node.mesh as usize];
+            let Some(distance) = ray_box_intersection(
+                &local_origin,
+                &local_direction,
+                &mesh.lower_bounds.xyz(),
+                &mesh.upper_bounds.xyz()
+            ) else {continue};
+            if nearest.as_ref().is_some_and(|hit| hit.distance < distance) {
+                continue;
+            }
+            //Narrow phase
+            let mesh_index = source.nodes.get(node)?.mesh? as usize;
+            let primitive_index = (device_node.mesh as usize).checked_sub(*scene.mesh_offsets.get(mesh_index)?)?;
+            let primitive = source.meshes.get(mesh_index)?.primitives.get(primitive_index)?;
+            for triangle in primitive.indices.chunks_exact(3) {
+                let vertex = |i: usize| primitive.vertices.get(triangle[i] as usize).map(|vertex| vertex.pos);
+                let (a, b, c) = (vertex(0)?, vertex(1)?, vertex(2)?);
+                let Some((distance, uv)) = ray_triangle_intersection(
+                    &local_origin,
+                    &local_direction,
+                    &a, &b, &c
+                ) else {continue};
+                if nearest.as_ref().is_some_and(|hit| hit.distance <= distance) {
+                    continue;
+                }
+                nearest = Some(Hit {
+                    node,
+                    position: origin + distance * direction.into_inner(),
+                    distance,
+                    uv
+                });
+            }
+        }
+        nearest
+    }
+
+    /**
+        Screen-space bounds of a source scene node's meshes (e.g. to place labels or selection boxes),
+        seen by `camera` in a viewport of `extent` pixels, regardless of visibility & layers.
+        Each bounding box is clipped against the near plane, so nodes crossing it are bounded
+        by their part in front of the camera. The result is clamped to the viewport;
+        `None` if the node has no mesh, or is entirely behind the camera or outside the viewport.
+    */
+    pub fn node_screen_rect(&self, scene: usize, node: usize, extent: vk::Extent2D) -> Option<ScreenRect> {
+        let scene = &self.scenes[scene];
+        let view_projection = self.camera.projection() * self.camera.view();
+        let mut min = na::Point2::new(f32::MAX, f32::MAX);
+        let mut max = na::Point2::new(f32::MIN, f32::MIN);
+        for (device_node, _) in std::iter::zip(&scene.nodes, &scene.node_indices).filter(|(_, &i)| i == node) {
+            let mesh = &scene.meshes[device_node.mesh as usize];
+            let transform = view_projection * device_node.transform;
+            //Clip-space corners (bit i selects the upper bound of axis i)
+            let corners: [na::Vector4<f32>; 8] = std::array::from_fn(|i| transform * na::Vector4::new(
+                if i & 1 == 0 {mesh.lower_bounds.x} else {mesh.upper_bounds.x},
+                if i & 2 == 0 {mesh.lower_bounds.y} else {mesh.upper_bounds.y},
+                if i & 4 == 0 {mesh.lower_bounds.z} else {mesh.upper_bounds.z},
+                1.0
+            ));
+            //Corners in front of the near plane (z >= 0 in clip space) & edge crossings
+            let mut points: Vec<na::Vector4<f32>> = corners.iter().filter(|corner| corner.z >= 0.0).copied().collect();
+            for i in 0..8 {
+                for axis in [1, 2, 4] {
+                    let (a, b) = (corners[i], corners[i | axis]);
+                    if i & axis == 0 && (a.z >= 0.0) != (b.z >= 0.0) {
+                        points.push(a.lerp(&b, a.z / (a.z - b.z)));
+                    }
+                }
+            }
+            for point in points.iter().filter(|point| point.w > 0.0) {
+                let ndc = point.xy() / point.w;
+                let pixel = na::Point2::new(
+                    (ndc.x + 1.0) * 0.5 * extent.width as f32,
+                    (ndc.y + 1.0) * 0.5 * extent.height as f32
+                );
+                min = min.inf(&pixel);
+                max = max.sup(&pixel);
+            }
+        }
+        //Clamp to the viewport
+        let min = min.sup(&na::Point2::origin());
+        let max = max.inf(&na::Point2::new(extent.width as f32, extent.height as f32));
+        (min.x <= max.x && min.y <= max.y).then_some(ScreenRect {min, max})
     }
 
     pub fn scene_descriptors(&self, scene: usize, frame: usize) -> vk::DescriptorSet {
         assert!(scene < self.scenes.len());
         assert!(frame < FRAME_COUNT);
-        self.descriptor_sets[scene * FRAME_COUNT + frame]
+        self.scene_descriptor_sets[scene][frame][0]
     }
 
     pub fn cull_descriptors(&self, scene: usize, frame: usize) -> vk::DescriptorSet {
         assert!(scene < self.scenes.len());
         assert!(frame < FRAME_COUNT);
-        self.descriptor_sets[(self.scenes.len() + scene) * FRAME_COUNT + frame]
+        self.scene_descriptor_sets[scene][frame][1]
+    }
+
+    ///A frame's camera uniform buffer range (layout in `Renderer::register_material_shader`).
+    pub fn camera_descriptor(&self, frame: usize) -> vk::DescriptorBufferInfo {
+        assert!(frame < FRAME_COUNT);
+        self.buffer_descriptors[FRAME_COUNT + frame]
+    }
+
+    pub fn billboard_descriptors(&self, frame: usize) -> vk::DescriptorSet {
+        assert!(frame < FRAME_COUNT);
+        self.billboard_descriptor_sets[frame]
     }
 
     pub fn skybox_descriptors(&self, frame: usize) -> vk::DescriptorSet {
         assert!(frame < FRAME_COUNT);
-        self.descriptor_sets[2 * self.scenes.len() * FRAME_COUNT + frame]
+        self.skybox_descriptor_sets[frame]
     }
 }
 
@@ -372,10 +1164,112 @@ impl Drop for SceneSet {
     fn drop(&mut self) {
         unsafe {
             self.base.device.device_wait_idle().unwrap();
-            self.base.device.destroy_descriptor_pool(self.descriptor_pool, None);
+            for descriptor_pool in self.descriptor_pools.iter().flatten() {
+                self.base.device.destroy_descriptor_pool(*descriptor_pool, None);
+            }
             self.base.device.destroy_buffer(self.lights_buffer, None);
             self.base.device.destroy_buffer(self.camera_buffer, None);
-            self.base.device.free_memory(self.buffer_alloc, None);
+            self.base.device.destroy_buffer(self.user_buffer, None);
+            self.base.free_memory(self.buffer_alloc);
         }
     }
 }
+
+///Ray-AABB slab test returning the entry distance.
+fn ray_box_intersection(
+    origin: &na::Point3<f32>,
+    direction: &na::Vector3<f32>,
+    lower: &na::Vector3<f32>,
+    upper: &na::Vector3<f32>
+) -> Option<f32> {
+    let mut near = 0.0f32;
+    let mut far = f32::MAX;
+    for i in 0..3 {
+        let inverse = 1.0 / direction[i];
+        let t0 = (lower[i] - origin[i]) * inverse;
+        let t1 = (upper[i] - origin[i]) * inverse;
+        near = near.max(t0.min(t1));
+        far = far.min(t0.max(t1));
+    }
+    if near <= far {Some(near)} else {None}
+}
+
+///Möller-Trumbore ray-triangle test returning the distance & barycentric coordinates.
+fn ray_triangle_intersection(
+    origin: &na::Point3<f32>,
+    direction: &na::Vector3<f32>,
+    a: &na::Vector3<f32>,
+    b: &na::Vector3<f32>,
+    c: &na::Vector3<f32>
+) -> Option<(f32, na::Vector2<f32>)> {
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = direction.cross(&edge2);
+    let determinant = edge1.dot(&p);
+    //Relative to the determinant's largest magnitude (for a ray in the triangle's plane), so small triangles still hit
+    if determinant.abs() <= f32::EPSILON * edge1.norm() * edge2.norm() * direction.norm() {
+        return None;
+    }
+    let inverse = 1.0 / determinant;
+    let s = origin.coords - a;
+    let u = s.dot(&p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(&edge1);
+    let v = direction.dot(&q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let distance = edge2.dot(&q) * inverse;
+    if distance < 0.0 {
+        return None;
+    }
+    Some((distance, na::Vector2::new(u, v)))
+}
+
+//Whether `set_count` sets of each layout have room for every descriptor written
+fn descriptors_cover(layout_counts: &[&[vk::DescriptorPoolSize]], set_count: u32, writes: &[vk::WriteDescriptorSet]) -> bool {
+    let mut available = vec![];
+    for counts in layout_counts {
+        add_descriptor_counts(&mut available, counts, set_count);
+    }
+    let mut written = vec![];
+    for write in writes {
+        let count = vk::DescriptorPoolSize {
+            ty: write.descriptor_type,
+            descriptor_count: write.descriptor_count
+        };
+        add_descriptor_counts(&mut written, std::slice::from_ref(&count), 1);
+    }
+    written.iter().all(|write| available.iter().any(
+        |size| size.ty == write.ty && size.descriptor_count >= write.descriptor_count
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ray_hits_triangle() {
+        let [a, b, c] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]].map(na::Vector3::from);
+        let down = -na::Vector3::z();
+        let (distance, uv) = ray_triangle_intersection(&na::Point3::new(0.25, 0.5, 2.0), &down, &a, &b, &c).unwrap();
+        assert!((distance - 2.0).abs() < 1e-6);
+        assert!((uv - na::Vector2::new(0.25, 0.5)).norm() < 1e-6);
+        //Outside the triangle, behind the origin & parallel to the plane
+        assert!(ray_triangle_intersection(&na::Point3::new(0.75, 0.75, 2.0), &down, &a, &b, &c).is_none());
+        assert!(ray_triangle_intersection(&na::Point3::new(0.25, 0.25, -1.0), &down, &a, &b, &c).is_none());
+        assert!(ray_triangle_intersection(&na::Point3::new(-1.0, 0.25, 0.0), &na::Vector3::x(), &a, &b, &c).is_none());
+    }
+
+    #[test]
+    fn ray_hits_small_triangle() {
+        //Millimetre-scale edges, whose determinant is below f32::EPSILON
+        let [a, b, c] = [[0.0, 0.0, 0.0], [1e-4, 0.0, 0.0], [0.0, 1e-4, 0.0]].map(na::Vector3::from);
+        let origin = na::Point3::new(2.5e-5, 2.5e-5, 1.0);
+        let (distance, _) = ray_triangle_intersection(&origin, &-na::Vector3::z(), &a, &b, &c).unwrap();
+        assert!((distance - 1.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,342 @@
+use ash::vk;
+use nalgebra as na;
+use super::DEPTH_FORMAT;
+use super::base::Base;
+use super::camera::Camera;
+use super::memory::{MemoryCategory, AllocationError};
+use super::pipeline::{self, PipelineLayout};
+use super::resource::{Image, ImageView, Allocation};
+use super::scene::{PointLight, VertexFormat};
+use super::scene_set::SceneSet;
+use std::sync::Arc;
+
+pub const SHADOW_FACE_SIZE: u32 = 512; //Width & height of each cube face in the atlas
+pub const MAX_SHADOW_CASTERS: usize = 8; //Lights with a tile in the atlas
+const TILE_COLUMNS: u32 = 2; //Tiles per atlas row (each tile is 3 x 2 faces)
+const SHADOW_NEAR: f32 = 0.05; //Near plane of each face (must match `pbr.frag`)
+
+//Cube face directions & up vectors (must match `pbr.frag`)
+const FACES: [([f32; 3], [f32; 3]); 6] = [
+    ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ([-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+    ([0.0, -1.0, 0.0], [0.0, 0.0, 1.0]),
+    ([0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+    ([0.0, 0.0, -1.0], [0.0, 1.0, 0.0])
+];
+
+/**
+    Point light shadow maps sharing one depth image.
+    Each shadow-casting light is assigned a tile of 3 x 2 faces (one per axis direction),
+    redrawn every frame before the scene.
+*/
+pub struct ShadowAtlas {
+    base: Arc<Base>,
+    pub extent: vk::Extent2D,
+    _view: ImageView,
+    _image: Image,
+    _allocation: Allocation,
+    sampler: vk::Sampler, //Depth comparison
+    pub descriptor: vk::DescriptorImageInfo,
+    render_pass: vk::RenderPass,
+    framebuffer: vk::Framebuffer,
+    pub pipelines: [vk::Pipeline; 2], //For each vertex format: [full, packed]
+    initialized: bool //Transitioned to its sampled layout at least once
+}
+
+impl ShadowAtlas {
+    pub fn new(base: Arc<Base>, mesh_layout: &PipelineLayout) -> Result<Self, AllocationError> {
+        let tile_rows = (MAX_SHADOW_CASTERS as u32).div_ceil(TILE_COLUMNS);
+        let extent = vk::Extent2D {
+            width: TILE_COLUMNS * 3 * SHADOW_FACE_SIZE,
+            height: tile_rows * 2 * SHADOW_FACE_SIZE
+        };
+        //Image
+        let create_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(DEPTH_FORMAT)
+            .extent(vk::Extent3D {width: extent.width, height: extent.height, depth: 1})
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+        let (images, allocation) = base.create_images(
+            std::slice::from_ref(&create_info),
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            MemoryCategory::Framebuffer
+        )?;
+        let allocation = Allocation::new(base.clone(), allocation);
+        let image = Image::new(base.clone(), images[0]);
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::DEPTH)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1);
+        let create_info = vk::ImageViewCreateInfo::builder()
+            .image(image.handle())
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(DEPTH_FORMAT)
+            .components(vk::ComponentMapping::default())
+            .subresource_range(*subresource_range);
+        let view = ImageView::new(base.clone(), &create_info)?;
+        //Comparison sampler (bilinear percentage-closer filtering)
+        let create_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .compare_enable(true)
+            .compare_op(vk::CompareOp::LESS_OR_EQUAL);
+        let sampler = unsafe {base.device.create_sampler(&create_info, None)?};
+        let descriptor = *vk::DescriptorImageInfo::builder()
+            .sampler(sampler)
+            .image_view(view.handle())
+            .image_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL);
+        //Render pass
+        let attachment = vk::AttachmentDescription::builder()
+            .format(DEPTH_FORMAT)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL);
+        let reference = vk::AttachmentReference::builder()
+            .attachment(0)
+            .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+        let subpass = vk::SubpassDescription::builder()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .depth_stencil_attachment(&reference);
+        let dependencies = [
+            //Previous frame's sampling
+            *vk::SubpassDependency::builder()
+                .src_subpass(vk::SUBPASS_EXTERNAL)
+                .dst_subpass(0)
+                .src_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .src_access_mask(vk::AccessFlags::empty())
+                .dst_stage_mask(
+                    vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+                ).dst_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE),
+            //This frame's sampling
+            *vk::SubpassDependency::builder()
+                .src_subpass(0)
+                .dst_subpass(vk::SUBPASS_EXTERNAL)
+                .src_stage_mask(
+                    vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+                ).src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ)
+        ];
+        let create_info = vk::RenderPassCreateInfo::builder()
+            .attachments(std::slice::from_ref(&attachment))
+            .subpasses(std::slice::from_ref(&subpass))
+            .dependencies(&dependencies);
+        let render_pass = unsafe {
+            base.device.create_render_pass(&create_info, None)?
+        };
+        //Framebuffer
+        let attachment = view.handle();
+        let create_info = vk::FramebufferCreateInfo::builder()
+            .render_pass(render_pass)
+            .attachments(std::slice::from_ref(&attachment))
+            .width(extent.width)
+            .height(extent.height)
+            .layers(1);
+        let framebuffer = unsafe {
+            base.device.create_framebuffer(&create_info, None)?
+        };
+        let pipelines = pipeline::shadow::create_pipelines(mesh_layout, render_pass)?;
+        Ok(Self {
+            base,
+            extent,
+            _view: view,
+            _image: image,
+            _allocation: allocation,
+            sampler,
+            descriptor,
+            render_pass,
+            framebuffer,
+            pipelines,
+            initialized: false
+        })
+    }
+
+    ///Rebuild the shadow pipelines (e.g. after recompiling shaders), returning the old ones.
+    #[cfg(feature = "hot-reload")]
+    pub fn replace_pipelines(&mut self, pipelines: [vk::Pipeline; 2]) -> [vk::Pipeline; 2] {
+        std::mem::replace(&mut self.pipelines, pipelines)
+    }
+
+    #[cfg(feature = "hot-reload")]
+    pub fn render_pass(&self) -> vk::RenderPass {
+        self.render_pass
+    }
+
+    ///Atlas UV offset of a tile & size of one of its faces: [u, v, width, height].
+    pub fn tile_rect(&self, tile: usize) -> [f32; 4] {
+        let tile = tile as u32;
+        let (column, row) = (tile % TILE_COLUMNS, tile / TILE_COLUMNS);
+        [
+            (column * 3 * SHADOW_FACE_SIZE) as f32 / self.extent.width as f32,
+            (row * 2 * SHADOW_FACE_SIZE) as f32 / self.extent.height as f32,
+            SHADOW_FACE_SIZE as f32 / self.extent.width as f32,
+            SHADOW_FACE_SIZE as f32 / self.extent.height as f32
+        ]
+    }
+
+    ///View-projection of a light's cube face, with 90° field of view out to the light's range.
+    fn face_view_projection(light: &PointLight, face: usize) -> na::Matrix4<f32> {
+        let (dir, up) = FACES[face];
+        let camera = Camera {
+            pos: na::Point3::from(light.pos.xyz()),
+            dir: na::Unit::new_normalize(dir.into()),
+            up: na::Unit::new_normalize(up.into()),
+            fov: std::f32::consts::FRAC_PI_2,
+            aspect: 1.0,
+            near: SHADOW_NEAR,
+            far: light.range
+        };
+        camera.projection() * camera.view()
+    }
+
+    /**
+        Record drawing every node of every scene into the tiles of shadow-casting lights,
+        given as (tile, light) pairs.
+        Nodes aren't culled by the camera, only by each light's range.
+        Does nothing once the atlas is initialized if no light casts shadows.
+    */
+    pub fn record(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        mesh_layout: &PipelineLayout,
+        scene_set: &SceneSet,
+        frame: usize,
+        casters: &[(usize, &PointLight)]
+    ) {
+        if casters.is_empty() && self.initialized {
+            return;
+        }
+        self.initialized = true;
+        let device = &self.base.device;
+        let clear_value = vk::ClearValue {
+            depth_stencil: *vk::ClearDepthStencilValue::builder().depth(1.0)
+        };
+        let begin_info = vk::RenderPassBeginInfo::builder()
+            .render_pass(self.render_pass)
+            .framebuffer(self.framebuffer)
+            .render_area(vk::Rect2D {offset: vk::Offset2D::default(), extent: self.extent})
+            .clear_values(std::slice::from_ref(&clear_value));
+        unsafe {
+            device.cmd_begin_render_pass(command_buffer, &begin_info, vk::SubpassContents::INLINE);
+            for &(tile, light) in casters {
+                let tile = tile as u32;
+                let origin = (
+                    tile % TILE_COLUMNS * 3 * SHADOW_FACE_SIZE,
+                    tile / TILE_COLUMNS * 2 * SHADOW_FACE_SIZE
+                );
+                for face in 0..FACES.len() {
+                    //Face viewport
+                    let offset = vk::Offset2D {
+                        x: (origin.0 + face as u32 % 3 * SHADOW_FACE_SIZE) as i32,
+                        y: (origin.1 + face as u32 / 3 * SHADOW_FACE_SIZE) as i32
+                    };
+                    let viewport = vk::Viewport::builder()
+                        .x(offset.x as f32)
+                        .y(offset.y as f32)
+                        .width(SHADOW_FACE_SIZE as f32)
+                        .height(SHADOW_FACE_SIZE as f32)
+                        .min_depth(0.0)
+                        .max_depth(1.0);
+                    let scissor = vk::Rect2D {
+                        offset,
+                        extent: vk::Extent2D {width: SHADOW_FACE_SIZE, height: SHADOW_FACE_SIZE}
+                    };
+                    device.cmd_set_viewport(command_buffer, 0, std::slice::from_ref(&viewport));
+                    device.cmd_set_scissor(command_buffer, 0, std::slice::from_ref(&scissor));
+                    let constants: Vec<u8> = Self::face_view_projection(light, face).iter().flat_map(
+                        |x| x.to_le_bytes()
+                    ).collect();
+                    device.cmd_push_constants(
+                        command_buffer,
+                        mesh_layout.pipeline_layout,
+                        vk::ShaderStageFlags::VERTEX,
+                        pipeline::mesh::SHADOW_PUSH_CONSTANT_OFFSET,
+                        &constants
+                    );
+                    for (i, scene) in scene_set.scenes.iter().enumerate() {
+                        let pipeline = match scene.vertex_format {
+                            VertexFormat::Full => self.pipelines[0],
+                            VertexFormat::Packed => self.pipelines[1]
+                        };
+                        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+                        device.cmd_bind_vertex_buffers(
+                            command_buffer,
+                            0,
+                            std::slice::from_ref(&scene.buffers[0].handle()),
+                            &[0]
+                        );
+                        device.cmd_bind_index_buffer(
+                            command_buffer,
+                            scene.buffers[1].handle(),
+                            0,
+                            vk::IndexType::UINT16
+                        );
+                        device.cmd_bind_descriptor_sets(
+                            command_buffer,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            mesh_layout.pipeline_layout,
+                            0,
+                            std::slice::from_ref(&scene_set.scene_descriptors(i, frame)),
+                            &[]
+                        );
+                        for (n, node) in scene.nodes.iter().enumerate() {
+                            //Skip hidden nodes & nodes out of the light's range (bounding sphere test)
+                            if !node.visible() || scene_set.layer_mask() & (1 << node.layer()) == 0 {
+                                continue;
+                            }
+                            let mesh = &scene.meshes[node.mesh as usize];
+                            let center = node.transform * mesh.bounding_sphere.xyz().push(1.0);
+                            let scale = (0..3).map(
+                                |i| node.transform.fixed_view::<3, 1>(0, i).norm()
+                            ).fold(0.0, f32::max);
+                            let radius = scale * mesh.bounding_sphere.w;
+                            if (center.xyz() - light.pos.xyz()).norm() > light.range + radius {
+                                continue;
+                            }
+                            let command = &scene.mesh_commands[node.mesh as usize];
+                            device.cmd_draw_indexed(
+                                command_buffer,
+                                command.index_count,
+                                1,
+                                command.first_index,
+                                command.vertex_offset,
+                                n as u32
+                            );
+                        }
+                    }
+                }
+            }
+            device.cmd_end_render_pass(command_buffer);
+        }
+    }
+}
+
+impl Drop for ShadowAtlas {
+    fn drop(&mut self) {
+        unsafe {
+            for pipeline in self.pipelines {
+                self.base.device.destroy_pipeline(pipeline, None);
+            }
+            self.base.device.destroy_framebuffer(self.framebuffer, None);
+            self.base.device.destroy_render_pass(self.render_pass, None);
+            self.base.device.destroy_sampler(self.sampler, None);
+        }
+    }
+}
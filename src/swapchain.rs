@@ -2,23 +2,34 @@ use ash::vk;
 use ash::extensions::khr;
 use crate::FRAME_COUNT;
 use crate::base::Base;
-use std::rc::Rc;
+use std::sync::Arc;
 
 pub struct Swapchain {
-    _base: Rc<Base>,
+    base: Arc<Base>,
     pub extent: vk::Extent2D,
+    pub format: vk::SurfaceFormatKHR,
+    pub composite_alpha: vk::CompositeAlphaFlagsKHR,
     pub loader: khr::Swapchain,
     pub swapchain: vk::SwapchainKHR,
-    pub images: Vec<vk::Image>
+    pub images: Vec<vk::Image>,
+    pub image_views: Vec<vk::ImageView>,
+    //Present pass drawing the drawn image into a swapchain image (when it can't be blitted)
+    pub render_pass: vk::RenderPass,
+    pub framebuffers: Vec<vk::Framebuffer>
 }
 
 impl Swapchain {
-    pub fn new(base: Rc<Base>, old_swapchain: Option<vk::SwapchainKHR>)
-        -> Result<Self, vk::Result> {
+    pub fn new(
+        base: Arc<Base>,
+        surface: vk::SurfaceKHR,
+        old_swapchain: Option<vk::SwapchainKHR>,
+        preferred_format: vk::SurfaceFormatKHR,
+        preferred_composite_alpha: vk::CompositeAlphaFlagsKHR
+    ) -> Result<Self, vk::Result> {
         let surface_capabilities = unsafe {
             base.surface_loader.get_physical_device_surface_capabilities(
                 base.physical_device,
-                base.surface
+                surface
             )
         }?;
         let extent = if surface_capabilities.current_extent.width == u32::MAX
@@ -27,24 +38,165 @@ impl Swapchain {
         } else {
             surface_capabilities.current_extent
         };
+        let format = Self::choose_format(&base, surface, preferred_format)?;
+        let composite_alpha = Self::choose_composite_alpha(
+            surface_capabilities.supported_composite_alpha,
+            preferred_composite_alpha
+        );
         let create_info = vk::SwapchainCreateInfoKHR::builder()
-            .surface(base.surface)
+            .surface(surface)
             .min_image_count((FRAME_COUNT as u32).max(surface_capabilities.min_image_count))
-            .image_format(vk::Format::B8G8R8A8_SRGB)
-            .image_color_space(vk::ColorSpaceKHR::SRGB_NONLINEAR)
+            .image_format(format.format)
+            .image_color_space(format.color_space)
             .image_extent(extent)
             .image_array_layers(1)
-            .image_usage(vk::ImageUsageFlags::TRANSFER_DST)
+            .image_usage(vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::COLOR_ATTACHMENT)
             .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
             .pre_transform(vk::SurfaceTransformFlagsKHR::IDENTITY)
-            .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
+            .composite_alpha(composite_alpha)
             .present_mode(vk::PresentModeKHR::FIFO)
             .old_swapchain(if let Some(sc) = old_swapchain {sc} else {vk::SwapchainKHR::null()});
         let loader = khr::Swapchain::new(&base.instance, &base.device);
-        unsafe {
+        let (swapchain, images) = unsafe {
             let swapchain = loader.create_swapchain(&create_info, None)?;
-            let images = loader.get_swapchain_images(swapchain)?;
-            Ok(Self {_base: base, extent, loader, swapchain, images})
+            (swapchain, loader.get_swapchain_images(swapchain)?)
+        };
+        //Present render pass
+        let attachment = vk::AttachmentDescription::builder()
+            .format(format.format)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(vk::ImageLayout::PRESENT_SRC_KHR);
+        let reference = vk::AttachmentReference::builder()
+            .attachment(0)
+            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        let subpass = vk::SubpassDescription::builder()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(std::slice::from_ref(&reference));
+        //Image acquisition (the acquire semaphore is waited on at color attachment output)
+        let dependency = vk::SubpassDependency::builder()
+            .src_subpass(vk::SUBPASS_EXTERNAL)
+            .dst_subpass(0)
+            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .src_access_mask(vk::AccessFlags::empty())
+            .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE);
+        let create_info = vk::RenderPassCreateInfo::builder()
+            .attachments(std::slice::from_ref(&attachment))
+            .subpasses(std::slice::from_ref(&subpass))
+            .dependencies(std::slice::from_ref(&dependency));
+        let render_pass = unsafe {
+            base.device.create_render_pass(&create_info, None)?
+        };
+        //Image views & framebuffers
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1);
+        let image_views = images.iter().map(|&image| {
+            let create_info = vk::ImageViewCreateInfo::builder()
+                .image(image)
+                .view_type(vk::ImageViewType::TYPE_2D)
+                .format(format.format)
+                .components(vk::ComponentMapping::default())
+                .subresource_range(*subresource_range);
+            unsafe {base.device.create_image_view(&create_info, None)}
+        }).collect::<Result<Vec<_>, _>>()?;
+        let framebuffers = image_views.iter().map(|image_view| {
+            let create_info = vk::FramebufferCreateInfo::builder()
+                .render_pass(render_pass)
+                .attachments(std::slice::from_ref(image_view))
+                .width(extent.width)
+                .height(extent.height)
+                .layers(1);
+            unsafe {base.device.create_framebuffer(&create_info, None)}
+        }).collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            base,
+            extent,
+            format,
+            composite_alpha,
+            loader,
+            swapchain,
+            images,
+            image_views,
+            render_pass,
+            framebuffers
+        })
+    }
+
+    /**
+        Whether the present pass must encode sRGB itself:
+        the color space expects sRGB-encoded values, but the format doesn't encode on write.
+    */
+    pub fn encodes_srgb(&self) -> bool {
+        let srgb_format = matches!(
+            self.format.format,
+            vk::Format::B8G8R8A8_SRGB | vk::Format::R8G8B8A8_SRGB | vk::Format::A8B8G8R8_SRGB_PACK32
+        );
+        self.format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR && !srgb_format
+    }
+
+    /**
+        Choose a surface format, in order of preference:
+        1. The preferred format & color space
+        2. Any format with the preferred color space
+        3. The first available format
+
+        Only formats which can be rendered to are considered,
+        since the drawn image is drawn into the swapchain image by the present pass
+        (or blitted when the formats & extents match).
+    */
+    fn choose_format(
+        base: &Base,
+        surface: vk::SurfaceKHR,
+        preferred: vk::SurfaceFormatKHR
+    ) -> Result<vk::SurfaceFormatKHR, vk::Result> {
+        let formats: Vec<_> = unsafe {
+            base.surface_loader.get_physical_device_surface_formats(
+                base.physical_device,
+                surface
+            )
+        }?.into_iter().filter(|surface_format| {
+            let properties = unsafe {
+                base.instance.get_physical_device_format_properties(
+                    base.physical_device,
+                    surface_format.format
+                )
+            };
+            properties.optimal_tiling_features.contains(vk::FormatFeatureFlags::COLOR_ATTACHMENT)
+        }).collect();
+        formats.iter().find(
+            |f| f.format == preferred.format && f.color_space == preferred.color_space
+        ).or_else(|| formats.iter().find(
+            |f| f.color_space == preferred.color_space
+        )).or_else(|| formats.first())
+            .copied()
+            .ok_or(vk::Result::ERROR_FORMAT_NOT_SUPPORTED)
+    }
+
+    /**
+        Choose how presented images are composited, in order of preference:
+        1. The preferred mode
+        2. `OPAQUE`
+        3. The lowest supported mode (surfaces support at least one)
+    */
+    fn choose_composite_alpha(
+        supported: vk::CompositeAlphaFlagsKHR,
+        preferred: vk::CompositeAlphaFlagsKHR
+    ) -> vk::CompositeAlphaFlagsKHR {
+        if supported.contains(preferred) {
+            preferred
+        } else if supported.contains(vk::CompositeAlphaFlagsKHR::OPAQUE) {
+            vk::CompositeAlphaFlagsKHR::OPAQUE
+        } else {
+            vk::CompositeAlphaFlagsKHR::from_raw(supported.as_raw() & supported.as_raw().wrapping_neg())
         }
     }
 }
@@ -52,6 +204,13 @@ impl Swapchain {
 impl Drop for Swapchain {
     fn drop(&mut self) {
         unsafe {
+            for framebuffer in &self.framebuffers {
+                self.base.device.destroy_framebuffer(*framebuffer, None);
+            }
+            for image_view in &self.image_views {
+                self.base.device.destroy_image_view(*image_view, None);
+            }
+            self.base.device.destroy_render_pass(self.render_pass, None);
             self.loader.destroy_swapchain(self.swapchain, None);
         }
     }
@@ -1,35 +1,43 @@
 use ash::vk;
 use super::FRAME_COUNT;
 use super::base::Base;
+use super::memory::{MemoryCategory, AllocationError};
 use transaction::Transaction;
-use std::rc::Rc;
+use std::sync::Arc;
 
 const TIMEOUT: u64 = 2_000_000_000;
+const STAGING_ALIGNMENT: usize = 16; //Covers texel block sizes of compressed formats
+pub const MIN_STAGING_SIZE: usize = 64;
 
 mod arena;
 pub mod transaction;
 
 ///Host-visible staging memory
 struct Staging {
-    base: Rc<Base>,
+    base: Arc<Base>,
     pub buffer: vk::Buffer,
     pub alloc: vk::DeviceMemory,
     pub size: usize,
-    pub ptr: *mut u8
+    pub ptr: *mut u8,
+    pub coherent: bool //Otherwise writes must be flushed
 }
 
+//The mapping is only accessed through its owner
+unsafe impl Send for Staging {}
+
 pub struct Transfer {
-    base: Rc<Base>,
+    base: Arc<Base>,
     queue: vk::Queue,
     command_pool: vk::CommandPool,
     staging: [Staging; FRAME_COUNT],
     command_buffers: [vk::CommandBuffer; FRAME_COUNT],
     semaphores: [vk::Semaphore; FRAME_COUNT],
-    counts: [u64; FRAME_COUNT]
+    counts: [u64; FRAME_COUNT],
+    staging_budget: Option<usize> //Maximum staging size per submission
 }
 
 impl Staging {
-    fn new(base: Rc<Base>, size: usize) -> Result<Self, vk::Result> {
+    fn new(base: Arc<Base>, size: usize) -> Result<Self, AllocationError> {
         assert!(size > 0);
         let create_info = vk::BufferCreateInfo::builder()
             .size(size as u64)
@@ -37,8 +45,11 @@ impl Staging {
             .sharing_mode(vk::SharingMode::EXCLUSIVE);
         let (buffers, alloc) = base.create_buffers(
             std::slice::from_ref(&create_info),
-            vk::MemoryPropertyFlags::HOST_VISIBLE
+            vk::MemoryPropertyFlags::HOST_VISIBLE,
+            MemoryCategory::Staging
         )?;
+        let coherent = base.memory_properties(alloc)
+            .contains(vk::MemoryPropertyFlags::HOST_COHERENT);
         let ptr = unsafe {
             base.device.map_memory(
                 alloc,
@@ -52,7 +63,8 @@ impl Staging {
             buffer: buffers[0],
             alloc,
             size,
-            ptr
+            ptr,
+            coherent
         })
     }
 }
@@ -62,13 +74,14 @@ impl Drop for Staging {
         unsafe {
             self.base.device.unmap_memory(self.alloc);
             self.base.device.destroy_buffer(self.buffer, None);
-            self.base.device.free_memory(self.alloc, None);
+            self.base.free_memory(self.alloc);
         }
     }
 }
 
 impl Transfer {
-    pub fn new(base: Rc<Base>) -> Result<Transfer, vk::Result> {
+    ///Create the transfer queue's resources, with each frame's staging buffer preallocated to `staging_size` bytes.
+    pub fn new(base: Arc<Base>, staging_size: usize) -> Result<Transfer, AllocationError> {
         unsafe {
             //Queue
             let queue = base.device.get_device_queue(base.transfer_queue_family, 0);
@@ -79,7 +92,9 @@ impl Transfer {
             let command_pool = base.device.create_command_pool(&create_info, None)?;
             //Staging
             let counts = [0; FRAME_COUNT];
-            let staging = counts.map(|_| Staging::new(base.clone(), 64).unwrap());
+            let staging: Vec<_> = (0..FRAME_COUNT).map(
+                |_| Staging::new(base.clone(), staging_size.max(MIN_STAGING_SIZE))
+            ).collect::<Result<_, _>>()?;
             //Command buffers
             let create_info = vk::CommandBufferAllocateInfo::builder()
                 .command_pool(command_pool)
@@ -99,94 +114,243 @@ impl Transfer {
                 base,
                 queue,
                 command_pool,
-                staging,
+                staging: staging.try_into().ok().unwrap(),
                 command_buffers: command_buffers.try_into().unwrap(),
                 semaphores,
-                counts
+                counts,
+                staging_budget: None
             })
         }
     }
 
+    /**
+        Limit the staging memory used by each frame's uploads (`None` for no limit).
+        Larger transactions are split into several submissions,
+        each waiting for the previous one to complete before reusing the staging buffer.
+        A single write larger than the budget is staged on its own.
+    */
+    pub fn set_staging_budget(&mut self, budget: Option<usize>) {
+        self.staging_budget = budget;
+    }
+
+    /**
+        Grow each frame's staging buffer to at least `size` bytes (e.g. the largest expected upload),
+        waiting for transfers still using a replaced buffer. Staging larger than the budget is shrunk by the next upload.
+    */
+    pub fn reserve_staging(&mut self, size: usize) -> Result<(), AllocationError> {
+        for frame in 0..FRAME_COUNT {
+            if self.staging[frame].size < size {
+                let wait_info = vk::SemaphoreWaitInfo::builder()
+                    .semaphores(std::slice::from_ref(&self.semaphores[frame]))
+                    .values(std::slice::from_ref(&self.counts[frame]));
+                unsafe {self.base.device.wait_semaphores(&wait_info, TIMEOUT)?;}
+                self.staging[frame] = Staging::new(self.base.clone(), size)?;
+            }
+        }
+        Ok(())
+    }
+
+    ///Size of each frame's staging buffer in bytes (the smallest, if they differ).
+    pub fn staging_capacity(&self) -> usize {
+        self.staging.iter().map(|staging| staging.size).min().unwrap()
+    }
+
     pub fn submit(
         &mut self,
         transaction: &Transaction,
         frame: usize
-    ) -> Result<(vk::Semaphore, u64), vk::Result> {
-        unsafe {
-            //Wait for previous transfer
-            let wait_info = vk::SemaphoreWaitInfo::builder()
-                .semaphores(std::slice::from_ref(&self.semaphores[frame]))
-                .values(std::slice::from_ref(&self.counts[frame]));
-            self.base.device.wait_semaphores(&wait_info, TIMEOUT)?;
-            //Write to mapped memory
-            if self.staging[frame].size < transaction.arena.size() {
-                self.staging[frame] = Staging::new(self.base.clone(), transaction.arena.size())?;
+    ) -> Result<(vk::Semaphore, u64), AllocationError> {
+        //Source ranges of each copy in recording order (buffers, then images)
+        let ranges: Vec<(usize, usize)> = transaction.buffer_transfers.iter().map(
+            |transfer| (transfer.src_offset, transfer.size)
+        ).chain(transaction.image_transfers.iter().map(
+            |transfer| (transfer.src_offset, transfer.size)
+        )).collect();
+        //Split into chunks fitting the staging budget
+        let budget = self.staging_budget.unwrap_or(usize::MAX);
+        let mut chunk_starts = vec![0];
+        let mut chunk_size = 0;
+        for (i, &(_, size)) in ranges.iter().enumerate() {
+            let size = size.next_multiple_of(STAGING_ALIGNMENT);
+            if chunk_size > 0 && chunk_size + size > budget {
+                chunk_starts.push(i);
+                chunk_size = 0;
+            }
+            chunk_size += size;
+        }
+        for (i, &start) in chunk_starts.iter().enumerate() {
+            let end = chunk_starts.get(i + 1).copied().unwrap_or(ranges.len());
+            unsafe {
+                self.submit_chunk(
+                    transaction,
+                    frame,
+                    &ranges,
+                    start..end,
+                    i == 0,
+                    i == chunk_starts.len() - 1
+                )?;
             }
-            transaction.arena.ptr().copy_to_nonoverlapping(
-                self.staging[frame].ptr,
-                transaction.arena.size()
+        }
+        Ok((self.semaphores[frame], self.counts[frame]))
+    }
+
+    /**
+        Stage & submit the copies in `chunk` (indices into `ranges`).
+        Start barriers are recorded with the first chunk & end barriers with the last.
+    */
+    unsafe fn submit_chunk(
+        &mut self,
+        transaction: &Transaction,
+        frame: usize,
+        ranges: &[(usize, usize)],
+        chunk: std::ops::Range<usize>,
+        first: bool,
+        last: bool
+    ) -> Result<(), AllocationError> {
+        //Wait for previous transfer
+        let wait_info = vk::SemaphoreWaitInfo::builder()
+            .semaphores(std::slice::from_ref(&self.semaphores[frame]))
+            .values(std::slice::from_ref(&self.counts[frame]));
+        self.base.device.wait_semaphores(&wait_info, TIMEOUT)?;
+        //Staging offsets
+        let mut offsets = Vec::with_capacity(chunk.len());
+        let mut size = 0;
+        for &(_, range_size) in &ranges[chunk.clone()] {
+            offsets.push(size);
+            size += range_size.next_multiple_of(STAGING_ALIGNMENT);
+        }
+        //Grow staging, or shrink it to the budget
+        let staging_size = self.staging[frame].size;
+        if staging_size < size || staging_size > size.max(self.staging_budget.unwrap_or(usize::MAX)) {
+            self.staging[frame] = Staging::new(self.base.clone(), size.max(MIN_STAGING_SIZE))?;
+        }
+        //Write to mapped memory
+        for (&(src_offset, range_size), &offset) in std::iter::zip(&ranges[chunk.clone()], &offsets) {
+            transaction.arena.ptr().add(src_offset).copy_to_nonoverlapping(
+                self.staging[frame].ptr.add(offset),
+                range_size
             );
-            //Record command buffer
-            let begin_info = vk::CommandBufferBeginInfo::builder()
-                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
-            self.base.device.begin_command_buffer(self.command_buffers[frame], &begin_info)?;
-            //Copy buffers
-            for transfer in &transaction.buffer_transfers {
-                let region = vk::BufferCopy::builder()
-                    .src_offset(transfer.src_offset as u64)
-                    .dst_offset(transfer.dst_offset as u64)
-                    .size(transfer.size as u64);
-                self.base.device.cmd_copy_buffer(
+        }
+        if !self.staging[frame].coherent && size > 0 {
+            //Flushed ranges must be multiples of the atom size (or reach the end of the memory)
+            let atom_size = self.base.physical_device_properties.limits.non_coherent_atom_size;
+            let size = (size as u64).div_ceil(atom_size) * atom_size;
+            let range = vk::MappedMemoryRange::builder()
+                .memory(self.staging[frame].alloc)
+                .offset(0)
+                .size(if size < self.staging[frame].size as u64 {size} else {vk::WHOLE_SIZE});
+            self.base.device.flush_mapped_memory_ranges(std::slice::from_ref(&range))?;
+        }
+        //Record command buffer
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        self.base.device.begin_command_buffer(self.command_buffers[frame], &begin_info)?;
+        //Fills & clears (with the first chunk)
+        if first && !(transaction.buffer_fills.is_empty() && transaction.image_clears.is_empty()) {
+            for fill in &transaction.buffer_fills {
+                self.base.device.cmd_fill_buffer(
                     self.command_buffers[frame],
-                    self.staging[frame].buffer,
-                    transfer.dst,
-                    std::slice::from_ref(&region)
+                    fill.dst,
+                    fill.offset as u64,
+                    fill.size as u64,
+                    fill.value
                 );
             }
-            //Copy images
-            //Start barriers
-            if transaction.start_image_barriers.len() > 0 {
+            if !transaction.start_image_barriers.is_empty() {
                 let dependency = vk::DependencyInfo::builder()
                     .image_memory_barriers(&transaction.start_image_barriers);
                 self.base.device.cmd_pipeline_barrier2(self.command_buffers[frame], &dependency);
             }
-            //Copies
-            for transfer in &transaction.image_transfers {
-                let copy = vk::CopyBufferToImageInfo2::builder()
-                    .src_buffer(self.staging[frame].buffer)
-                    .dst_image(transfer.dst)
-                    .dst_image_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
-                    .regions(&transaction.regions[
-                        transfer.region_offset..(transfer.region_offset + transfer.region_count)
-                    ]);
-                self.base.device.cmd_copy_buffer_to_image2(self.command_buffers[frame], &copy);
+            for clear in &transaction.image_clears {
+                self.base.device.cmd_clear_color_image(
+                    self.command_buffers[frame],
+                    clear.dst,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &clear.color,
+                    std::slice::from_ref(&clear.subresource_range)
+                );
             }
-            //End barriers
-            if transaction.end_image_barriers.len() > 0 {
-                let dependency = vk::DependencyInfo::builder()
-                    .image_memory_barriers(&transaction.end_image_barriers);
-                self.base.device.cmd_pipeline_barrier2(self.command_buffers[frame], &dependency);
+            //Copies may overwrite filled or cleared ranges
+            let memory_barrier = vk::MemoryBarrier2::builder()
+                .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+                .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+                .dst_access_mask(vk::AccessFlags2::TRANSFER_WRITE);
+            let dependency = vk::DependencyInfo::builder()
+                .memory_barriers(std::slice::from_ref(&memory_barrier));
+            self.base.device.cmd_pipeline_barrier2(self.command_buffers[frame], &dependency);
+        }
+        //Copy buffers
+        let buffer_count = transaction.buffer_transfers.len();
+        for (i, &offset) in std::iter::zip(chunk.clone(), &offsets) {
+            if i >= buffer_count {
+                break;
             }
-            self.base.device.end_command_buffer(self.command_buffers[frame])?;
-            //Submit to queue
-            self.counts[frame] += 1;
-            let command_info = vk::CommandBufferSubmitInfo::builder()
-                .command_buffer(self.command_buffers[frame]);
-            let signal_info = vk::SemaphoreSubmitInfo::builder()
-                .semaphore(self.semaphores[frame])
-                .value(self.counts[frame])
-                .stage_mask(vk::PipelineStageFlags2::ALL_TRANSFER)
-                .device_index(0);
-            let submit_info = vk::SubmitInfo2::builder()
-                .command_buffer_infos(std::slice::from_ref(&command_info))
-                .signal_semaphore_infos(std::slice::from_ref(&signal_info));
-            self.base.device.queue_submit2(
-                self.queue,
-                std::slice::from_ref(&submit_info),
-                vk::Fence::null()
-            )?;
+            let transfer = &transaction.buffer_transfers[i];
+            let region = vk::BufferCopy::builder()
+                .src_offset(offset as u64)
+                .dst_offset(transfer.dst_offset as u64)
+                .size(transfer.size as u64);
+            self.base.device.cmd_copy_buffer(
+                self.command_buffers[frame],
+                self.staging[frame].buffer,
+                transfer.dst,
+                std::slice::from_ref(&region)
+            );
         }
-        Ok((self.semaphores[frame], self.counts[frame]))
+        //Copy images
+        //Start barriers (already recorded with clears)
+        if first && transaction.image_clears.is_empty() && transaction.start_image_barriers.len() > 0 {
+            let dependency = vk::DependencyInfo::builder()
+                .image_memory_barriers(&transaction.start_image_barriers);
+            self.base.device.cmd_pipeline_barrier2(self.command_buffers[frame], &dependency);
+        }
+        //Copies
+        for (i, &offset) in std::iter::zip(chunk, &offsets) {
+            if i < buffer_count {
+                continue;
+            }
+            let transfer = &transaction.image_transfers[i - buffer_count];
+            //Regions relative to the staged copy
+            let regions: Vec<_> = transaction.regions[
+                transfer.region_offset..(transfer.region_offset + transfer.region_count)
+            ].iter().map(|region| {
+                let mut region = *region;
+                region.buffer_offset = region.buffer_offset - transfer.src_offset as u64 + offset as u64;
+                region
+            }).collect();
+            let copy = vk::CopyBufferToImageInfo2::builder()
+                .src_buffer(self.staging[frame].buffer)
+                .dst_image(transfer.dst)
+                .dst_image_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                .regions(&regions);
+            self.base.device.cmd_copy_buffer_to_image2(self.command_buffers[frame], &copy);
+        }
+        //End barriers
+        if last && transaction.end_image_barriers.len() > 0 {
+            let dependency = vk::DependencyInfo::builder()
+                .image_memory_barriers(&transaction.end_image_barriers);
+            self.base.device.cmd_pipeline_barrier2(self.command_buffers[frame], &dependency);
+        }
+        self.base.device.end_command_buffer(self.command_buffers[frame])?;
+        //Submit to queue
+        self.counts[frame] += 1;
+        let command_info = vk::CommandBufferSubmitInfo::builder()
+            .command_buffer(self.command_buffers[frame]);
+        let signal_info = vk::SemaphoreSubmitInfo::builder()
+            .semaphore(self.semaphores[frame])
+            .value(self.counts[frame])
+            .stage_mask(vk::PipelineStageFlags2::ALL_TRANSFER)
+            .device_index(0);
+        let submit_info = vk::SubmitInfo2::builder()
+            .command_buffer_infos(std::slice::from_ref(&command_info))
+            .signal_semaphore_infos(std::slice::from_ref(&signal_info));
+        self.base.device.queue_submit2(
+            self.queue,
+            std::slice::from_ref(&submit_info),
+            vk::Fence::null()
+        )?;
+        Ok(())
     }
 }
 
@@ -8,6 +8,9 @@ pub struct Arena {
     layout: Layout //Capacity & alignment
 }
 
+//The arena uniquely owns its allocation
+unsafe impl Send for Arena {}
+
 impl Arena {
     pub fn new(size: usize) -> Self {
         let layout = Layout::from_size_align(size, ALIGNMENT).unwrap();
@@ -9,6 +9,8 @@ pub struct BufferTransfer {
 }
 
 pub struct ImageTransfer {
+    pub src_offset: usize,
+    pub size: usize,
     pub dst: vk::Image,
     pub subresource_range: vk::ImageSubresourceRange,
     pub region_offset: usize,
@@ -16,20 +18,39 @@ pub struct ImageTransfer {
     pub layout: vk::ImageLayout
 }
 
+pub struct BufferFill {
+    pub dst: vk::Buffer,
+    pub offset: usize,
+    pub size: usize,
+    pub value: u32
+}
+
+pub struct ImageClear {
+    pub dst: vk::Image,
+    pub color: vk::ClearColorValue,
+    pub subresource_range: vk::ImageSubresourceRange
+}
+
 pub struct Transaction {
     src_queue_family: u32,
     dst_queue_family: u32,
+    ownership_transfer: bool, //Whether image barriers transfer queue family ownership
     pub arena: Arena,
     //Buffers
     pub buffer_transfers: Vec<BufferTransfer>,
+    pub buffer_fills: Vec<BufferFill>,
     //buffer_barriers: Vec<vk::BufferMemoryBarrier2>,
     //Images
     pub image_transfers: Vec<ImageTransfer>,
+    pub image_clears: Vec<ImageClear>,
     pub regions: Vec<vk::BufferImageCopy2>,
     pub start_image_barriers: Vec<vk::ImageMemoryBarrier2>,
     pub end_image_barriers: Vec<vk::ImageMemoryBarrier2>
 }
 
+//Recorded Vulkan structures have no `p_next` chains
+unsafe impl Send for Transaction {}
+
 impl Transaction {
     pub fn new(
         src_queue_family: u32,
@@ -38,16 +59,24 @@ impl Transaction {
         Self {
             src_queue_family,
             dst_queue_family,
+            ownership_transfer: true,
             arena: Arena::new(0),
             buffer_transfers: vec![],
+            buffer_fills: vec![],
             //buffer_barriers: vec![],
             image_transfers: vec![],
+            image_clears: vec![],
             regions: vec![],
             start_image_barriers: vec![],
             end_image_barriers: vec![]
         }
     }
 
+    ///Disable ownership transfers for images created with concurrent sharing.
+    pub fn set_ownership_transfer(&mut self, ownership_transfer: bool) {
+        self.ownership_transfer = ownership_transfer;
+    }
+
     pub fn buffer_write<T>(
         &mut self,
         src: &[T],
@@ -64,6 +93,27 @@ impl Transaction {
         });
     }
 
+    /**
+        Fill `size` bytes of `dst` with a repeated 32-bit value, without staging host data.
+        Fills are recorded before the transaction's copies.
+        `offset` & `size` must be multiples of 4.
+    */
+    pub fn fill_buffer(
+        &mut self,
+        dst: vk::Buffer,
+        offset: usize,
+        size: usize,
+        value: u32
+    ) {
+        assert!(offset.is_multiple_of(4) && size.is_multiple_of(4), "Buffer fills must be aligned to 4 bytes");
+        self.buffer_fills.push(BufferFill {
+            dst,
+            offset,
+            size,
+            value
+        });
+    }
+
     pub fn image_write<T>(
         &mut self,
         src: &[T],
@@ -80,12 +130,48 @@ impl Transaction {
             self.regions.push(new_region);
         }
         self.image_transfers.push(ImageTransfer {
+            src_offset,
+            size: std::mem::size_of::<T>() * src.len(),
             dst,
             subresource_range,
             region_offset,
             region_count: regions.len(),
             layout
         });
+        self.push_image_barriers(dst, subresource_range, layout);
+    }
+
+    /**
+        Clear a color image to `color`, without staging host data, & transition it to `layout`.
+        Clears are recorded before the transaction's copies.
+    */
+    pub fn clear_image(
+        &mut self,
+        dst: vk::Image,
+        color: vk::ClearColorValue,
+        subresource_range: vk::ImageSubresourceRange,
+        layout: vk::ImageLayout
+    ) {
+        self.image_clears.push(ImageClear {
+            dst,
+            color,
+            subresource_range
+        });
+        self.push_image_barriers(dst, subresource_range, layout);
+    }
+
+    ///Barriers transitioning `dst` for transfer writes, then to `layout` (& the graphics queue family).
+    fn push_image_barriers(
+        &mut self,
+        dst: vk::Image,
+        subresource_range: vk::ImageSubresourceRange,
+        layout: vk::ImageLayout
+    ) {
+        let (src_queue_family, dst_queue_family) = if self.ownership_transfer {
+            (self.src_queue_family, self.dst_queue_family)
+        } else {
+            (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED)
+        };
         self.start_image_barriers.push(*vk::ImageMemoryBarrier2::builder()
             .src_stage_mask(vk::PipelineStageFlags2::NONE)
             .src_access_mask(vk::AccessFlags2::NONE)
@@ -93,16 +179,16 @@ impl Transaction {
             .dst_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
             .old_layout(vk::ImageLayout::UNDEFINED)
             .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
-            .src_queue_family_index(self.src_queue_family)
-            .dst_queue_family_index(self.src_queue_family)
+            .src_queue_family_index(src_queue_family)
+            .dst_queue_family_index(src_queue_family)
             .image(dst)
             .subresource_range(subresource_range)
         );
         self.end_image_barriers.push(*vk::ImageMemoryBarrier2::builder()
             .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
             .new_layout(layout)
-            .src_queue_family_index(self.src_queue_family)
-            .dst_queue_family_index(self.dst_queue_family)
+            .src_queue_family_index(src_queue_family)
+            .dst_queue_family_index(dst_queue_family)
             .image(dst)
             .subresource_range(subresource_range)
         );
@@ -111,8 +197,10 @@ impl Transaction {
     pub fn clear(&mut self) {
         self.arena.clear();
         self.buffer_transfers.clear();
+        self.buffer_fills.clear();
         //self.buffer_barriers.clear();
         self.image_transfers.clear();
+        self.image_clears.clear();
         self.start_image_barriers.clear();
         self.end_image_barriers.clear();
         self.regions.clear();
@@ -0,0 +1,60 @@
+use nalgebra as na;
+use super::Renderer;
+use super::camera::Camera;
+use super::environment::Environment;
+use super::scene::Scene;
+use super::scene_set::SceneSet;
+use super::memory::AllocationError;
+
+/**
+    A single scene shown by a renderer (see `Renderer::show`), managing the scene set behind it.
+    Draw it with `Renderer::draw(viewer.scene_set())`;
+    the scene set remains accessible for anything the viewer doesn't cover.
+*/
+pub struct Viewer {
+    scene_set: SceneSet
+}
+
+impl Viewer {
+    pub(crate) fn new(renderer: &Renderer, scene: &Scene, environment: Environment) -> Result<Self, AllocationError> {
+        let mut scene_set = SceneSet::new(renderer, environment)?;
+        scene_set.push_scene(scene, renderer)?;
+        scene_set.camera.pos[2] = 4.0;
+        Ok(Self {scene_set})
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.scene_set.camera
+    }
+
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.scene_set.camera
+    }
+
+    ///Upload changes to the shown scene (e.g. after `Animator::update`), which must have the same structure.
+    pub fn update(&mut self, scene: &Scene) {
+        self.scene_set.update_scene(scene, 0);
+    }
+
+    ///Show a different scene, freeing the previous one once in-flight frames are done with it.
+    pub fn replace(&mut self, scene: &Scene, renderer: &Renderer) -> Result<(), AllocationError> {
+        self.scene_set.replace_scene(0, scene, renderer)
+    }
+
+    pub fn scene_set(&self) -> &SceneSet {
+        &self.scene_set
+    }
+
+    pub fn scene_set_mut(&mut self) -> &mut SceneSet {
+        &mut self.scene_set
+    }
+}
+
+///Environment of `Renderer::show`: a neutral procedural sky, so no cubemaps need loading.
+pub fn default_environment() -> Environment {
+    Environment::gradient(
+        na::Vector3::new(0.5, 0.6, 0.8),
+        na::Vector3::new(0.8, 0.8, 0.8),
+        na::Vector3::new(0.3, 0.3, 0.3)
+    )
+}